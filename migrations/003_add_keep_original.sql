@@ -0,0 +1,2 @@
+-- Allow jobs to keep the downloaded source file alongside the processed output
+ALTER TABLE jobs ADD COLUMN keep_original BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Per-job processing overrides (JSON) and the job a re-process was derived from
+ALTER TABLE jobs ADD COLUMN options TEXT;
+ALTER TABLE jobs ADD COLUMN parent_job_id TEXT;
+
+-- Index on parent_job_id for listing derived jobs
+CREATE INDEX IF NOT EXISTS idx_jobs_parent_job_id ON jobs(parent_job_id);
@@ -0,0 +1,2 @@
+-- Path of the short looping preview generated after the main encode
+ALTER TABLE jobs ADD COLUMN preview_path TEXT;
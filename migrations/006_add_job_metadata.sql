@@ -0,0 +1,2 @@
+-- Media facts recorded during processing (JSON)
+ALTER TABLE jobs ADD COLUMN metadata TEXT;
@@ -0,0 +1,7 @@
+-- Canonical form of the submitted URL, used for deduplication and cache lookups
+ALTER TABLE jobs ADD COLUMN normalized_url TEXT;
+
+-- Existing jobs fall back to their raw URL
+UPDATE jobs SET normalized_url = url WHERE normalized_url IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_normalized_url ON jobs(normalized_url);
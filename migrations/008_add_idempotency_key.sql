@@ -0,0 +1,6 @@
+-- Client-supplied Idempotency-Key and the request it was first used with
+ALTER TABLE jobs ADD COLUMN idempotency_key TEXT;
+ALTER TABLE jobs ADD COLUMN request_fingerprint TEXT;
+
+-- NULLs don't collide, so only jobs submitted with a key are constrained
+CREATE UNIQUE INDEX IF NOT EXISTS idx_jobs_idempotency_key ON jobs(idempotency_key);
@@ -0,0 +1,2 @@
+-- Stable classification of why a job failed, alongside the raw error_message
+ALTER TABLE jobs ADD COLUMN failure_reason TEXT;
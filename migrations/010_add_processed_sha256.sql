@@ -0,0 +1,2 @@
+-- SHA-256 of the processed output, for downstream integrity checks
+ALTER TABLE jobs ADD COLUMN processed_sha256 TEXT;
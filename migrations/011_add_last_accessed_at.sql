@@ -0,0 +1,3 @@
+-- When a completed job's output was last served, so eviction can pick the least recently used
+ALTER TABLE jobs ADD COLUMN last_accessed_at DATETIME;
+CREATE INDEX IF NOT EXISTS idx_jobs_status_last_accessed ON jobs(status, last_accessed_at);
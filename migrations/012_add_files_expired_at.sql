@@ -0,0 +1,2 @@
+-- When retention removed a terminal job's files; the row itself is kept until record retention
+ALTER TABLE jobs ADD COLUMN files_expired_at DATETIME;
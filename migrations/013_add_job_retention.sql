@@ -0,0 +1,3 @@
+-- Per-job retention override in days, and when a completed job's files become eligible for removal
+ALTER TABLE jobs ADD COLUMN retain_days INTEGER;
+ALTER TABLE jobs ADD COLUMN expires_at DATETIME;
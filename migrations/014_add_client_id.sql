@@ -0,0 +1,6 @@
+-- Name of the API key that submitted the job; NULL for jobs submitted without one
+ALTER TABLE jobs ADD COLUMN client_id TEXT;
+
+-- Per-client quota checks count a client's active and recent jobs
+CREATE INDEX IF NOT EXISTS idx_jobs_client_status ON jobs(client_id, status);
+CREATE INDEX IF NOT EXISTS idx_jobs_client_created_at ON jobs(client_id, created_at);
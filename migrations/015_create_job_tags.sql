@@ -0,0 +1,9 @@
+-- Free-form labels on jobs, e.g. a campaign or project, for filtering and bulk actions
+CREATE TABLE IF NOT EXISTS job_tags (
+    job_id TEXT NOT NULL REFERENCES jobs(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (job_id, tag)
+);
+
+-- Filtering by tag looks jobs up from the tag side
+CREATE INDEX IF NOT EXISTS idx_job_tags_tag ON job_tags(tag, job_id);
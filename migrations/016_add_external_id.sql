@@ -0,0 +1,7 @@
+-- The client's own reference for the job, returned as given and filterable
+ALTER TABLE jobs ADD COLUMN external_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_external_id ON jobs(external_id);
+
+-- With an API key an external id names one job; NULLs don't collide, so jobs without a key are not constrained
+CREATE UNIQUE INDEX IF NOT EXISTS idx_jobs_client_external_id ON jobs(client_id, external_id);
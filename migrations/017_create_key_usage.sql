@@ -0,0 +1,12 @@
+-- Per API key daily counters for chargeback. Kept independently of job rows, so usage survives retention.
+-- Submissions count on the UTC day the job was created, everything else on the day it completed.
+CREATE TABLE IF NOT EXISTS key_usage (
+    client_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    jobs_submitted INTEGER NOT NULL DEFAULT 0,
+    jobs_completed INTEGER NOT NULL DEFAULT 0,
+    bytes_downloaded INTEGER NOT NULL DEFAULT 0,
+    bytes_output INTEGER NOT NULL DEFAULT 0,
+    processing_seconds INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (client_id, day)
+);
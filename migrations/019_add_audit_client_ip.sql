@@ -0,0 +1,2 @@
+-- The client address behind the request, resolved through trusted proxies
+ALTER TABLE audit_log ADD COLUMN client_ip TEXT;
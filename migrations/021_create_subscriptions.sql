@@ -0,0 +1,18 @@
+-- RSS/Atom feeds polled for new entries, each submitted as a job with the stored options
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id TEXT PRIMARY KEY,
+    feed_url TEXT NOT NULL UNIQUE,
+    options TEXT NOT NULL DEFAULT '{}',
+    priority TEXT,
+    tags TEXT NOT NULL DEFAULT '[]',
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    last_seen_entry TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    last_polled_at DATETIME,
+    last_error TEXT,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    next_poll_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscriptions_next_poll_at ON subscriptions(next_poll_at);
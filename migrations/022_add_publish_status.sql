@@ -0,0 +1,6 @@
+-- Outcome of uploading a completed output to the configured publisher, e.g. a Blossom server
+ALTER TABLE jobs ADD COLUMN publish_status TEXT;
+ALTER TABLE jobs ADD COLUMN published_url TEXT;
+ALTER TABLE jobs ADD COLUMN published_sha256 TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_publish_status ON jobs(publish_status) WHERE publish_status IS NOT NULL;
@@ -0,0 +1,9 @@
+-- The URLs a concat job joins, in order; single-URL jobs have none
+CREATE TABLE IF NOT EXISTS job_sources (
+    job_id TEXT NOT NULL REFERENCES jobs(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    downloaded_path TEXT,
+    PRIMARY KEY (job_id, position)
+);
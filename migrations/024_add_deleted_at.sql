@@ -0,0 +1,4 @@
+-- When the job was deleted; deleted jobs are hidden until restored, or removed by retention once the restore window passes
+ALTER TABLE jobs ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_deleted_at ON jobs(deleted_at);
@@ -0,0 +1,22 @@
+-- Indexes for the queries that slow down as the jobs table grows
+
+-- Listings only show jobs that aren't deleted; these serve their filters and order directly,
+-- including the (created_at, id) cursor, and let counts per status skip the table
+CREATE INDEX IF NOT EXISTS idx_jobs_live_created_at ON jobs(created_at, id) WHERE deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_jobs_live_status_created_at ON jobs(status, created_at, id) WHERE deleted_at IS NULL;
+
+-- Almost every job has a NULL deleted_at, which made the full index a poor choice the planner
+-- still picked for listings; only the purge of deleted jobs needs it
+DROP INDEX IF EXISTS idx_jobs_deleted_at;
+CREATE INDEX IF NOT EXISTS idx_jobs_deleted_at ON jobs(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Deduplication and the result cache look up a URL in given statuses
+DROP INDEX IF EXISTS idx_jobs_normalized_url;
+CREATE INDEX IF NOT EXISTS idx_jobs_normalized_url_status ON jobs(normalized_url, status);
+
+-- Record retention selects finished jobs by age
+DROP INDEX IF EXISTS idx_jobs_updated_at;
+CREATE INDEX IF NOT EXISTS idx_jobs_updated_at_status ON jobs(updated_at, status);
+
+-- File retention only ever looks at jobs whose files are still on disk, a small part of the table
+CREATE INDEX IF NOT EXISTS idx_jobs_files_kept ON jobs(status, updated_at) WHERE files_expired_at IS NULL;
@@ -0,0 +1,7 @@
+-- Counters and histogram summaries saved by APERIO_METRICS_SNAPSHOT_INTERVAL, restored at startup.
+-- A single row holding the registry as JSON; each save replaces it.
+CREATE TABLE IF NOT EXISTS metrics_snapshot (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    snapshot TEXT NOT NULL,
+    saved_at DATETIME NOT NULL
+);
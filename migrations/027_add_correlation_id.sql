@@ -0,0 +1,2 @@
+-- Correlation id of the request that submitted the job, so its processing logs can be matched to that request
+ALTER TABLE jobs ADD COLUMN correlation_id TEXT;
@@ -0,0 +1,9 @@
+-- The job whose kept original a re-processing job reads instead of downloading its own; retention keeps that original until every job reading it has finished
+ALTER TABLE jobs ADD COLUMN source_job_id TEXT;
+
+-- Re-processing jobs from before the column borrowed their parent's original exactly when their paths match
+UPDATE jobs SET source_job_id = parent_job_id
+WHERE parent_job_id IS NOT NULL
+  AND downloaded_path = (SELECT parent.downloaded_path FROM jobs AS parent WHERE parent.id = jobs.parent_job_id);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_source_job_id ON jobs(source_job_id);
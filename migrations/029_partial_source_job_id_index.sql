@@ -0,0 +1,5 @@
+-- Almost every job has a NULL source_job_id, so once ANALYZE has run the planner expects each
+-- lookup of the full index to match the whole table and scans it for every retention candidate
+-- instead; only the borrow check reads the column, and it never looks for NULL
+DROP INDEX IF EXISTS idx_jobs_source_job_id;
+CREATE INDEX IF NOT EXISTS idx_jobs_source_job_id ON jobs(source_job_id) WHERE source_job_id IS NOT NULL;
@@ -1,2 +1,3 @@
 pub mod routes;
 pub mod monitoring;
+pub mod openapi;
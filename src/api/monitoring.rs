@@ -1,11 +1,53 @@
 use crate::error::{AppError, AppResult};
-use crate::monitoring::HealthChecker;
-use crate::services::metrics;
+use crate::monitoring::{HealthChecker, HealthStatus};
+use crate::services::job_queue::{DrainStatus, QueueStats};
+use crate::services::pool_manager::PoolStats;
+use crate::services::resources::{ResourceMonitor, ResourceStats};
+use crate::services::retention::RetentionRun;
+use crate::services::{metrics, ConnectionPoolManager, JobQueue, JobRepository, RetentionService};
 use actix_web::{get, web, Responder, HttpResponse};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use tracing::warn;
 
 pub struct MonitoringState {
     pub health_checker: HealthChecker,
+    pub job_queue: Arc<JobQueue>,
+    pub pool_manager: Arc<ConnectionPoolManager>,
+    pub job_repository: JobRepository,
+    pub retention_service: RetentionService,
+    pub resource_monitor: Arc<ResourceMonitor>,
+}
+
+#[derive(Serialize)]
+struct DetailedHealth {
+    #[serde(flatten)]
+    health: HealthStatus,
+    drain: DrainStatus,
+    queue: QueueStats,
+    slots: SlotStats,
+    /// Jobs last updated within the past hour, per current status; left out if the count failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs_last_hour: Option<BTreeMap<String, i64>>,
+    retention: RetentionHealth,
+    /// The process's latest resource sample; null until the first is taken
+    resources: Option<ResourceStats>,
+}
+
+#[derive(Serialize)]
+struct SlotStats {
+    download: PoolStats,
+    processing: PoolStats,
+}
+
+#[derive(Serialize)]
+struct RetentionHealth {
+    enabled: bool,
+    /// Null until a cleanup cycle has finished since startup
+    last_run: Option<RetentionRun>,
+    /// When a cycle last finished without stopping, the same time as `aperio_retention_last_success_timestamp_seconds`
+    last_success_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 pub fn configure_monitoring_routes(cfg: &mut web::ServiceConfig) {
@@ -32,13 +74,40 @@ async fn health_check(data: web::Data<Arc<MonitoringState>>) -> AppResult<impl R
 #[get("/health/detailed")]
 async fn health_check_detailed(data: web::Data<Arc<MonitoringState>>) -> AppResult<impl Responder> {
     let health_status = data.health_checker.get_health_status().await;
-    Ok(web::Json(health_status))
+    let since = chrono::Utc::now() - chrono::Duration::hours(1);
+    let jobs_last_hour = match data.job_repository.count_recent_jobs_by_status(since).await {
+        Ok(counts) => Some(counts),
+        Err(e) => {
+            warn!("Failed to count recent jobs for the health report: {}", e);
+            None
+        }
+    };
+
+    Ok(web::Json(DetailedHealth {
+        health: health_status,
+        drain: data.job_queue.drain_status().await,
+        queue: data.job_queue.get_queue_stats().await,
+        slots: SlotStats {
+            download: data.pool_manager.get_download_stats(),
+            processing: data.pool_manager.get_processing_stats(),
+        },
+        jobs_last_hour,
+        retention: RetentionHealth {
+            enabled: data.retention_service.config().enabled,
+            last_run: data.retention_service.last_run(),
+            last_success_at: data.retention_service.last_success_at(),
+        },
+        resources: data.resource_monitor.latest(),
+    }))
 }
 
 #[get("/metrics")]
-async fn metrics_endpoint(_data: web::Data<Arc<MonitoringState>>) -> AppResult<impl Responder> {
+async fn metrics_endpoint(
+    _data: web::Data<Arc<MonitoringState>>,
+    filter: web::Query<metrics::MetricsFilter>,
+) -> AppResult<impl Responder> {
     let metrics_registry = metrics::get_metrics();
-    let metrics = metrics_registry.get_json_format().await;
+    let metrics = metrics_registry.get_json_format(&filter).await;
     Ok(web::Json(metrics))
 }
 
@@ -60,17 +129,14 @@ async fn metrics_history(_data: web::Data<Arc<MonitoringState>>) -> AppResult<im
 
 #[get("/health/ready")]
 async fn readiness_check(data: web::Data<Arc<MonitoringState>>) -> AppResult<impl Responder> {
-    let health_status = data.health_checker.get_health_status().await;
-    
-    // Ready if database is healthy (can serve requests)
-    if health_status.checks.database.status == "healthy" {
-        Ok(web::Json(serde_json::json!({
-            "status": "ready",
-            "timestamp": health_status.timestamp
-        })))
+    // Every condition is reported either way, so operators can see why an instance is out of rotation
+    let readiness = data.health_checker.get_readiness().await;
+    let mut response = if readiness.is_ready() {
+        HttpResponse::Ok()
     } else {
-        Err(AppError::Internal("Service not ready".to_string()))
-    }
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(response.json(readiness))
 }
 
 #[get("/health/live")]
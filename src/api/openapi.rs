@@ -0,0 +1,179 @@
+use crate::api::routes;
+use crate::error::ErrorResponse;
+use actix_web::{get, web, HttpResponse, Responder};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// The v1 API as an OpenAPI 3.1 document, generated from the handler and type annotations
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Aperio",
+        description = "Download videos from allowed sites, or upload them, and transcode them to MP4.\n\n\
+            Errors share one body, `ErrorResponse`, whose `error_type` says what went wrong.\n\n\
+            When `APERIO_AUTH_PASSWORD` is set every request needs HTTP Basic auth; admin endpoints use \
+            `APERIO_ADMIN_PASSWORD` instead when that is set."
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        routes::start_job,
+        routes::start_upload_job,
+        routes::start_concat_job,
+        routes::get_job_status,
+        routes::get_processed_video,
+        routes::get_processed_checksum,
+        routes::stream_processed_video,
+        routes::get_original_video,
+        routes::get_preview,
+        routes::get_bundle,
+        routes::get_frame,
+        routes::reprocess_job,
+        routes::get_job_details,
+        routes::get_job_logs,
+        routes::cancel_job,
+        routes::restore_job,
+        routes::cancel_tagged_jobs,
+        routes::purge_tagged_jobs,
+        routes::update_job_retention,
+        routes::list_jobs,
+        routes::export_jobs,
+        routes::get_job_stats,
+        routes::get_queue_stats,
+        routes::get_config,
+        routes::run_retention,
+        routes::sweep_orphans,
+        routes::run_db_maintenance,
+        routes::reset_metrics,
+        routes::get_storage_report,
+        routes::reload_config,
+        routes::start_drain,
+        routes::cancel_drain,
+        routes::get_key_usage,
+        routes::list_subscriptions,
+        routes::create_subscription,
+        routes::get_subscription,
+        routes::update_subscription,
+        routes::delete_subscription,
+        routes::list_audit_log,
+    ),
+    components(schemas(ErrorResponse, routes::BundleManifest)),
+    modifiers(&BasicAuth),
+    security((), ("basic_auth" = [])),
+    tags(
+        (name = "jobs", description = "Submit, inspect and manage jobs"),
+        (name = "media", description = "Download a job's outputs"),
+        (name = "server", description = "Queue state and server configuration"),
+        (name = "admin", description = "Maintenance; protected by the admin password when one is set"),
+    ),
+)]
+pub struct ApiDoc;
+
+struct BasicAuth;
+
+impl Modify for BasicAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("basic_auth", SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)));
+        components.add_security_scheme("admin_auth", SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)));
+    }
+}
+
+/// Registered outside the versioned scope so generators can find it without knowing the API version
+pub fn configure_openapi_routes(cfg: &mut web::ServiceConfig, swagger_ui: bool) {
+    if swagger_ui {
+        // Serves /openapi.json itself, alongside the UI
+        cfg.service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", ApiDoc::openapi()));
+    } else {
+        cfg.service(openapi_json);
+    }
+}
+
+#[get("/openapi.json")]
+async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::routes::{configure_routes, API_V1_PREFIX};
+    use actix_web::{http::Method, test as actix_test, App};
+    use regex::Regex;
+    use std::collections::BTreeSet;
+
+    /// Every (method, path) `configure_v1_routes` registers, read from the handlers' route
+    /// attributes since actix can't list its routes
+    fn registered_routes() -> BTreeSet<(String, String)> {
+        let source = include_str!("routes.rs");
+        let registrations = source.split("fn configure_v1_routes").nth(1).unwrap()
+            .split("\n}\n").next().unwrap();
+        let handlers: Vec<&str> = Regex::new(r"\.?service\((\w+)\)").unwrap()
+            .captures_iter(registrations)
+            .map(|captures| captures.get(1).unwrap().as_str())
+            .collect();
+        assert!(!handlers.is_empty());
+
+        let route = Regex::new(r#"#\[(get|post|put|patch|delete)\("([^"]+)"\)\]\s*(?:#\[[^\n]*\]\s*)*(?:pub\s+)?async fn (\w+)"#).unwrap();
+        let routes: Vec<(String, String, String)> = route.captures_iter(source)
+            .map(|captures| (captures[3].to_string(), captures[1].to_string(), captures[2].to_string()))
+            .collect();
+
+        handlers.iter().map(|handler| {
+            let (_, method, path) = routes.iter().find(|(name, _, _)| name == handler)
+                .unwrap_or_else(|| panic!("no route attribute found for registered handler {handler}"));
+            (method.clone(), path.clone())
+        }).collect()
+    }
+
+    fn documented_routes() -> BTreeSet<(String, String)> {
+        let mut documented = BTreeSet::new();
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            for (method, operation) in [
+                ("get", &item.get), ("post", &item.post), ("put", &item.put), ("patch", &item.patch), ("delete", &item.delete),
+            ] {
+                if operation.is_some() {
+                    documented.insert((method.to_string(), path.clone()));
+                }
+            }
+        }
+        documented
+    }
+
+    #[test]
+    fn spec_parses_and_documents_every_registered_route() {
+        let json = serde_json::to_string(&ApiDoc::openapi()).unwrap();
+        let parsed: utoipa::openapi::OpenApi = serde_json::from_str(&json).unwrap();
+        assert!(parsed.components.unwrap().security_schemes.contains_key("basic_auth"));
+
+        let registered = registered_routes();
+        let documented = documented_routes();
+        assert_eq!(
+            registered.difference(&documented).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "registered but missing from the spec"
+        );
+        assert_eq!(
+            documented.difference(&registered).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "in the spec but not registered"
+        );
+    }
+
+    #[actix_web::test]
+    async fn every_documented_route_is_served_under_the_versioned_prefix() {
+        let app = actix_test::init_service(App::new().configure(configure_routes)).await;
+        let placeholder = Regex::new(r"\{[^}]+\}").unwrap();
+
+        for (method, path) in documented_routes() {
+            let uri = format!("{API_V1_PREFIX}{}", placeholder.replace_all(&path, "00000000-0000-0000-0000-000000000000"));
+            let request = actix_test::TestRequest::default()
+                .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                .uri(&uri)
+                .to_request();
+            // Without app state the handlers fail, but a route that doesn't exist answers 404
+            let response = actix_test::call_service(&app, request).await;
+            assert_ne!(response.status(), actix_web::http::StatusCode::NOT_FOUND, "{method} {uri}");
+        }
+    }
+}
@@ -1,32 +1,177 @@
-use crate::error::{AppError, AppResult};
-use crate::models::job::{Job, JobStatus};
-use crate::services::process::ProcessService;
-use crate::services::{DownloadService, JobRepository, CleanupService, SecurityValidator, JobQueue, JobPriority};
-use crate::services::retry::{retry_with_backoff, RetryConfig, is_retryable_error};
+use crate::config::{Config, DownloaderBackend, ProcessorBackend, RetentionMode, FFMPEG_PRESETS};
+use crate::error::{AppError, AppResult, ErrorResponse};
+use crate::models::job::{FailureReason, Job, JobMetadata, JobSource, JobStatus, ProcessingOptions, PublishStatus, SourceStatus};
+use crate::services::process::{crop_rect, ProcessService};
+use crate::services::{AuditLog, ConnectionPoolManager, DownloadService, Downloader, Processor, StorageQuota, StorageService, JobRepository, CleanupService, SecurityValidator, JobQueue, JobPriority, UploadService, ResultCache, RetentionService, ConfigReloader, FailureAlerts, SubscriptionService, PublishService, Estimator, DbMaintenance, MetricsSnapshotStore};
+use crate::services::bundle::{stream_bundle, BundleEntry, BundleFile};
+use crate::services::export::ExportFormat;
+use crate::services::checksum::sha256_file;
+use crate::services::upload::UPLOAD_EXTENSIONS;
+use crate::services::quota::{job_output_bytes, StorageUsage};
+use crate::services::job_queue::{DrainStatus, QueueStats, StageOutcome};
+use crate::services::maintenance::MaintenanceReport;
+use crate::services::metrics::{get_metrics, MetricsReset};
+use crate::services::pool_manager::PoolStats;
+use crate::services::retention::{CleanupSummary, OrphanSweepReport};
+use crate::services::audit::{AuditAction, AuditActor, AuditEntry};
+use crate::services::subscriptions::{NewSubscription, Subscription, SubscriptionUpdate};
+use crate::services::hooks::PostHook;
+use crate::services::job_repository::{DailyUsage, JobFilter, JobLogEntry, JobLogLevel, UsageTotals};
+use crate::middleware::auth::ApiClient;
+use crate::middleware::ClientIp;
+use crate::services::config_reload::ConfigReloadReport;
+use crate::services::downloader::{check_download_size, DownloadedFile};
+use crate::services::security::{is_playlist_url, job_working_dir, normalize_url, redact_text, MAX_JOB_TAGS, MAX_TAG_LENGTH};
+use crate::services::error_mapping::{classify_error, ErrorSanitizer};
+use crate::services::retry::{retry_with_backoff, retry_with_backoff_if, RetryConfig, is_retryable_error};
 use crate::{counter_inc, gauge_set, histogram_record};
-use actix_web::{get, post, delete, web, Responder};
-use actix_web::http::header::{ContentDisposition, DispositionType};
+use actix_web::{get, post, patch, delete, web, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
+use actix_web::middleware::DefaultHeaders;
+use actix_web::http::header::{self, ContentDisposition, DispositionType};
+use actix_multipart::Multipart;
+use base64::Engine;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::path::Path;
+use tokio::sync::broadcast;
 use tracing::{info, warn, error, debug, instrument};
 
 pub struct AppState {
-    pub download_service: DownloadService,
-    pub process_service: ProcessService,
+    pub download_service: Arc<DownloadService>,
+    pub downloader: Box<dyn Downloader>,
+    pub working_dir: std::path::PathBuf,
+    pub storage_quota: Arc<StorageQuota>,
+    pub storage_service: Arc<StorageService>,
+    pub process_service: Arc<ProcessService>,
+    pub processor: Box<dyn Processor>,
+    pub upload_service: UploadService,
+    pub result_cache: ResultCache,
     pub cleanup_service: CleanupService,
+    pub retention_service: RetentionService,
     pub job_repository: JobRepository,
+    pub audit_log: AuditLog,
     pub security_validator: SecurityValidator,
     pub job_queue: Arc<JobQueue>,
+    pub pool_manager: Arc<ConnectionPoolManager>,
+    pub error_sanitizer: ErrorSanitizer,
+    pub config_reloader: Arc<ConfigReloader>,
+    pub api_urls: ApiUrls,
+    pub failure_alerts: Arc<FailureAlerts>,
+    pub subscriptions: Arc<SubscriptionService>,
+    pub publisher: Arc<PublishService>,
+    pub post_hook: Option<Arc<PostHook>>,
+    pub estimator: Arc<Estimator>,
+    pub db_maintenance: Arc<DbMaintenance>,
+    /// None unless APERIO_METRICS_SNAPSHOT_INTERVAL is set
+    pub metrics_snapshots: Option<Arc<MetricsSnapshotStore>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct DownloadRequest {
     pub url: String,
     pub priority: Option<String>,
+    pub keep_original: Option<bool>,
+    /// Days to keep the output after completion instead of the configured file retention
+    pub retain_days: Option<u32>,
+    pub options: Option<ProcessingOptions>,
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub expand_playlist: bool,
+    /// Labels for finding and bulk-managing the job later; at most 10, each up to 64 letters, digits, `-`, `_`, `.` or `:`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Your own reference for the job, up to 128 characters. With an API key it names one job:
+    /// resubmitting it returns that job instead of creating another.
+    pub external_id: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
+/// How the URLs of a multi-source job are combined
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiSourceMode {
+    /// One after another, in the order given
+    Concat,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ConcatRequest {
+    /// The sources to join, in order; from 2 to 20
+    pub urls: Vec<String>,
+    pub mode: MultiSourceMode,
+    pub priority: Option<String>,
+    /// Not available: the sources are only joined inside the encode, so there is no single original
+    pub keep_original: Option<bool>,
+    /// Days to keep the output after completion instead of the configured file retention
+    pub retain_days: Option<u32>,
+    pub options: Option<ProcessingOptions>,
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct RetentionRequest {
+    pub retain_days: u32,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ReprocessRequest {
+    pub options: ProcessingOptions,
+    pub priority: Option<String>,
+}
+
+/// Builds the URLs handed to clients, under APERIO_PUBLIC_URL when set
+#[derive(Clone, Debug)]
+pub struct ApiUrls {
+    base: String,
+}
+
+impl ApiUrls {
+    pub fn new(public_url: Option<&str>) -> Self {
+        Self { base: format!("{}{API_V1_PREFIX}", public_url.unwrap_or_default()) }
+    }
+
+    /// `path` relative to the API root, e.g. `/status/{job_id}`
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base)
+    }
+}
+
+/// Where to go next with a job. Links are only present while the action they lead to can succeed.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobLinks {
+    #[serde(rename = "self")]
+    pub self_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    /// DELETE this URL to cancel the job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel: Option<String>,
+}
+
+impl JobLinks {
+    fn new(job: &Job, output_available: bool, urls: &ApiUrls) -> Self {
+        let link = |condition: bool, path: String| condition.then(|| urls.url(&path));
+        Self {
+            self_: urls.url(&format!("/status/{}", job.id)),
+            video: link(output_available, format!("/video/{}", job.id)),
+            stream: link(output_available, format!("/stream/{}", job.id)),
+            thumbnail: link(output_available && job.preview_path.is_some(), format!("/preview/{}", job.id)),
+            cancel: link(!job.status.is_terminal(), format!("/jobs/{}", job.id)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
 pub struct JobResponse {
     pub id: String,
     pub status: JobStatus,
@@ -34,14 +179,61 @@ pub struct JobResponse {
     pub created_at: String,
     pub updated_at: String,
     pub error_message: Option<String>,
+    pub failure_reason: Option<FailureReason>,
     pub processing_time: Option<String>,
+    pub processed_sha256: Option<String>,
+    /// Whether `/video` can serve the output right now, so clients needn't try the download to find out
+    pub output_available: bool,
+    pub last_accessed_at: Option<String>,
+    pub retain_days: Option<u32>,
+    /// When retention may remove the output
+    pub expires_at: Option<String>,
+    pub keep_original: bool,
+    pub options: ProcessingOptions,
+    pub parent_job_id: Option<String>,
+    pub has_preview: bool,
+    pub metadata: JobMetadata,
+    /// Name of the API key that submitted the job
+    pub client_id: Option<String>,
+    pub external_id: Option<String>,
+    pub tags: Vec<String>,
+    /// Set once a completed output is handed to the configured publisher
+    pub publish_status: Option<PublishStatus>,
+    /// Where the publisher serves the output, e.g. a Blossom blob URL
+    pub published_url: Option<String>,
+    pub published_sha256: Option<String>,
+    /// For a concat job, each URL it joins and how far its download got
+    pub sources: Vec<JobSource>,
+    /// When a Downloading or Processing job is expected to finish, from recent jobs' stage times;
+    /// null for other statuses or until enough jobs have completed
+    pub estimated_completion_at: Option<String>,
+    /// When the job was deleted; only deleted jobs listed with `include_deleted` have one
+    pub deleted_at: Option<String>,
+    /// Correlation id of the request that submitted the job, as logged for that request and for
+    /// the job's processing
+    pub correlation_id: Option<String>,
+    pub links: JobLinks,
 }
 
-impl From<&Job> for JobResponse {
-    fn from(job: &Job) -> Self {
+#[derive(Serialize, Debug, ToSchema)]
+pub struct PlaylistResponse {
+    pub jobs: Vec<JobResponse>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobDetailResponse {
+    #[serde(flatten)]
+    pub job: JobResponse,
+    pub derived_jobs: Vec<JobResponse>,
+}
+
+impl JobResponse {
+    pub fn new(job: &Job, app_state: &AppState) -> Self {
+        let urls = &app_state.api_urls;
         let created_at = job.created_at.to_rfc3339();
         let updated_at = job.updated_at.to_rfc3339();
         let processing_time = job.get_processing_time().map(|d| format!("{d:?}"));
+        let output_available = job.status == JobStatus::Completed && job.processed_path.is_some();
 
         Self {
             id: job.id.clone(),
@@ -50,68 +242,272 @@ impl From<&Job> for JobResponse {
             created_at,
             updated_at,
             error_message: job.error_message.clone(),
+            failure_reason: job.failure_reason,
             processing_time,
+            processed_sha256: job.processed_sha256.clone(),
+            output_available,
+            last_accessed_at: job.last_accessed_at.map(|at| at.to_rfc3339()),
+            retain_days: job.retain_days,
+            expires_at: job.expires_at.map(|at| at.to_rfc3339()),
+            keep_original: job.keep_original,
+            options: job.options.clone(),
+            parent_job_id: job.parent_job_id.clone(),
+            has_preview: job.preview_path.is_some(),
+            metadata: job.metadata.clone(),
+            client_id: job.client_id.clone(),
+            external_id: job.external_id.clone(),
+            tags: job.tags.clone(),
+            publish_status: job.publish_status,
+            published_url: job.published_url.clone(),
+            published_sha256: job.published_sha256.clone(),
+            sources: job.sources.clone(),
+            estimated_completion_at: app_state.estimator.estimated_completion(job).map(|at| at.to_rfc3339()),
+            deleted_at: job.deleted_at.map(|at| at.to_rfc3339()),
+            correlation_id: job.correlation_id.clone(),
+            links: JobLinks::new(job, output_available, urls),
         }
     }
 }
 
+/// Prefix of the current API version. A later version gets its own scope, handlers and response
+/// types, leaving these handlers and `JobResponse` as the v1 contract.
+pub const API_V1_PREFIX: &str = "/api/v1";
+
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope(API_V1_PREFIX).configure(configure_v1_routes));
+
+    // The unversioned paths from before /api/v1, kept as deprecated aliases. New endpoints are
+    // only added under the versioned scope. The empty scope matches every path, so this must be
+    // registered after all other routes.
+    cfg.service(
+        web::scope("")
+            .wrap(DefaultHeaders::new()
+                .add(("Deprecation", "true"))
+                .add((header::LINK, format!("<{API_V1_PREFIX}>; rel=\"successor-version\""))))
+            .configure(configure_v1_routes),
+    );
+}
+
+fn configure_v1_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(start_job)
+        .service(start_upload_job)
+        .service(start_concat_job)
         .service(get_job_status)
         .service(get_processed_video)
+        .service(get_processed_checksum)
         .service(stream_processed_video)
+        .service(get_original_video)
+        .service(get_preview)
+        .service(get_bundle)
+        .service(get_frame)
+        .service(reprocess_job)
+        // Before get_job_details, whose /jobs/{job_id} would match it too
+        .service(get_job_stats)
+        .service(export_jobs)
+        .service(cancel_tagged_jobs)
+        .service(purge_tagged_jobs)
+        .service(get_job_details)
+        .service(get_job_logs)
         .service(cancel_job)
-        .service(list_jobs);
+        .service(restore_job)
+        .service(update_job_retention)
+        .service(list_jobs)
+        .service(get_queue_stats)
+        .service(get_config)
+        .service(run_retention)
+        .service(sweep_orphans)
+        .service(run_db_maintenance)
+        .service(reset_metrics)
+        .service(get_storage_report)
+        .service(reload_config)
+        .service(start_drain)
+        .service(cancel_drain)
+        .service(get_key_usage)
+        .service(list_subscriptions)
+        .service(create_subscription)
+        .service(get_subscription)
+        .service(update_subscription)
+        .service(delete_subscription)
+        .service(list_audit_log);
 }
 
+#[utoipa::path(
+    tag = "jobs",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays the original response for a repeated request with the same key")),
+    responses(
+        (status = 202, description = "Job created and queued; `Location` points at its status. With `expand_playlist`, a PlaylistResponse once any entry created a job",
+            body = JobResponse, headers(("Location" = String, description = "Where to poll the job's status"))),
+        (status = 201, description = "Job created and completed at once from a cached result of the same source and options",
+            body = JobResponse, headers(("Location" = String, description = "Where to poll the job's status"))),
+        (status = 200, description = "Nothing created: the existing active job for this URL, the job an idempotency key or the API key's external id already created, or a PlaylistResponse whose entries all had jobs", body = JobResponse),
+        (status = 400, description = "Invalid URL, domain not allowed or bad options", body = ErrorResponse),
+        (status = 413, description = "JSON body too large", body = ErrorResponse),
+        (status = 422, description = "Idempotency key reused with a different request", body = ErrorResponse),
+        (status = 503, description = "The server is draining and accepts no new jobs", body = ErrorResponse),
+        (status = 507, description = "Storage quota exhausted", body = ErrorResponse),
+    )
+)]
 #[post("/process")]
-#[instrument(skip(data), fields(url = %request.url))]
+#[instrument(skip(data, http_request, request), fields(url = %redact_text(&request.url)))]
 async fn start_job(
     data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
     request: web::Json<DownloadRequest>,
-) -> AppResult<impl Responder> {
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<HttpResponse> {
+    let client = client.map(web::ReqData::into_inner);
     let start_time = std::time::Instant::now();
     counter_inc!("aperio_job_requests_total");
-    info!("Starting new job for URL: {}", request.url);
+    info!("Starting new job for URL: {}", redact_text(&request.url));
+    check_accepting_jobs(&data).await?;
     
     // Enhanced input validation
     data.security_validator.validate_input(&request.url, "url", 2048)?;
+
+    let idempotency_key = match http_request.headers().get("Idempotency-Key") {
+        Some(value) => {
+            let key = value.to_str()
+                .map_err(|_| AppError::BadRequest("Idempotency-Key must be visible ASCII".to_string()))?;
+            data.security_validator.validate_input(key, "Idempotency-Key", 255)?;
+            Some(key.to_string())
+        }
+        None => None,
+    };
+    let request_fingerprint = serde_json::json!({
+        "url": request.url,
+        "priority": request.priority,
+        "keep_original": request.keep_original,
+        "retain_days": request.retain_days,
+        "options": request.options,
+        "force": request.force,
+        "tags": request.tags,
+        "external_id": request.external_id,
+    }).to_string();
+
+    // A retried submission returns the job its key already created
+    if let Some(key) = &idempotency_key {
+        if let Some(existing_job) = find_idempotent_job(&data, key, &request_fingerprint).await? {
+            return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
+        }
+    }
     
+    if let Some(days) = request.retain_days {
+        validate_retain_days(&data, days)?;
+    }
+    let tags = data.security_validator.validate_tags(&request.tags)?;
+    if let Some(external_id) = &request.external_id {
+        validate_external_id(&data, external_id)?;
+        if request.expand_playlist {
+            return Err(AppError::BadRequest(
+                "external_id names a single job and can't be combined with expand_playlist".to_string()
+            ));
+        }
+    }
+
+    // Like a duplicate URL, a resubmitted external id answers with the job it already names
+    if let Some(existing_job) = find_external_job(&data, client.as_ref().map(|client| client.name.as_str()), request.external_id.as_deref()).await? {
+        info!("External id matched job {}, returning it", existing_job.id);
+        return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
+    }
+    data.storage_quota.check_admission().await?;
+
     // Pre-validate URL before creating job
-    let _validated_url = data.security_validator.validate_url(&request.url)?;
+    let validated_url = data.security_validator.validate_url(&request.url)?;
+    let normalized_url = normalize_url(&validated_url);
+
+    let options = request.options.clone().unwrap_or_default();
+    data.security_validator.validate_processing_options(&options)?;
+    data.download_service.validate_auth_profile(options.auth_profile.as_deref())?;
+
+    // Playlists become one job per entry when asked for, and are rejected otherwise
+    if request.expand_playlist {
+        let (jobs, created) = expand_playlist(&data, &request, &options, &tags, client.as_ref(), correlation_id(&http_request)).await?;
+        let mut response = if created > 0 { HttpResponse::Accepted() } else { HttpResponse::Ok() };
+        return Ok(response.json(PlaylistResponse { jobs }));
+    }
+    if is_playlist_url(&validated_url) {
+        return Err(AppError::BadRequest(
+            "URL points to a playlist; submit a single video or set \"expand_playlist\": true".to_string()
+        ));
+    }
     
-    // Check for existing pending/active jobs with the same URL
-    match data.job_repository.find_active_job_by_url(&request.url).await? {
-        Some(existing_job) => {
+    // Check for existing pending/active jobs with the same URL and options
+    let existing = if request.force {
+        None
+    } else {
+        data.job_repository.find_active_job_by_url(&normalized_url).await?
+    };
+    match existing {
+        Some(existing_job) if existing_job.options == options => {
             info!("Found existing job {} for URL, returning existing job instead of creating duplicate", existing_job.id);
-            return Ok(web::Json(JobResponse::from(&existing_job)));
+            return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
         }
-        None => {
+        _ => {
             info!("No existing job found for URL, creating new job");
         }
     }
+    check_client_quota(&data, client.as_ref(), 1).await?;
     
-    let job = Job::new(request.url.clone());
+    let mut job = Job::new(request.url.clone());
+    job.client_id = client.map(|client| client.name);
+    job.correlation_id = correlation_id(&http_request);
+    job.normalized_url = normalized_url;
+    job.keep_original = request.keep_original.unwrap_or(false);
+    job.retain_days = request.retain_days;
+    job.options = options;
+    job.tags = tags;
+    job.external_id = request.external_id.clone();
+    if idempotency_key.is_some() {
+        job.idempotency_key = idempotency_key.clone();
+        job.request_fingerprint = Some(request_fingerprint.clone());
+    }
     let job_id = job.id.clone();
 
+    // Reuse a previous output unless the caller needs the original or asked for a fresh run
+    let cached = if request.force || job.keep_original {
+        None
+    } else {
+        data.result_cache.lookup(&job.normalized_url, &job.options).await?
+    };
+
     // Store the job in database
-    data.job_repository.create_job(&job).await?;
+    if let Err(e) = data.job_repository.create_job(&job).await {
+        // A concurrent request with the same key won the insert; answer as if we were the retry
+        if let Some(key) = &idempotency_key {
+            if let Some(existing_job) = find_idempotent_job(&data, key, &request_fingerprint).await? {
+                return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
+            }
+        }
+        if let Some(existing_job) = find_external_job(&data, job.client_id.as_deref(), job.external_id.as_deref()).await? {
+            return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
+        }
+        return Err(e);
+    }
     
-    info!("Created job {} for URL: {}", job_id, request.url);
+    info!("Created job {} for URL: {}", job_id, redact_text(&request.url));
+
+    if let Some(cached) = cached {
+        // Materializing completes the job on the spot
+        job.set_expiry(chrono::Utc::now(), data.retention_service.default_retain_days());
+        match data.result_cache.materialize(&mut job, &cached).await {
+            Ok(()) => {
+                counter_inc!("aperio_result_cache_hits_total");
+                // Nothing was downloaded or written, so only the completion counts
+                if let Err(e) = data.job_repository.record_completion_usage(&job, 0).await {
+                    warn!("Failed to record usage of job {}: {}", job_id, e);
+                }
+                data.publisher.publish_job(&mut job).await;
+                return Ok(job_created(HttpResponse::Created(), &job, &data));
+            }
+            Err(e) => warn!("Failed to serve job {} from cache, processing normally: {}", job_id, e),
+        }
+    }
 
     // Parse priority
-    let priority = match request.priority.as_deref() {
-        Some("high") => JobPriority::High,
-        Some("low") => JobPriority::Low,
-        _ => JobPriority::Normal,
-    };
+    let priority = parse_priority(request.priority.as_deref());
 
     // Add job to queue
-    if let Err(e) = data.job_queue.enqueue(job.clone(), priority).await {
-        error!("Failed to enqueue job {}: {}", job_id, e);
-        counter_inc!("aperio_job_errors_total", "error_type" => "queue_failed");
-        return Err(AppError::Internal(format!("Failed to queue job: {e}")));
-    }
+    enqueue_created_job(&data, &job, priority).await?;
     
     info!("Enqueued job {} for processing", job_id);
     
@@ -120,27 +516,610 @@ async fn start_job(
     histogram_record!("aperio_request_duration_ms", duration_ms, "endpoint" => "process");
     counter_inc!("aperio_jobs_created_total", "priority" => request.priority.as_deref().unwrap_or("normal"));
 
-    Ok(web::Json(JobResponse::from(&job)))
+    Ok(job_created(HttpResponse::Accepted(), &job, &data))
+}
+
+/// Queue a job that was just inserted. When the queue refuses it, e.g. because it is full, the
+/// row is removed again so the failed submission doesn't come back as a Pending job on restart.
+async fn enqueue_created_job(data: &AppState, job: &Job, priority: JobPriority) -> AppResult<()> {
+    let Err(e) = data.job_queue.enqueue(job.clone(), priority).await else {
+        return Ok(());
+    };
+
+    error!("Failed to enqueue job {}: {}", job.id, e);
+    counter_inc!("aperio_job_errors_total", "error_type" => "queue_failed");
+    if let Err(discard_error) = data.job_repository.discard_job(job).await {
+        warn!("Failed to remove job {} after the queue refused it: {}", job.id, discard_error);
+    }
+    Err(AppError::Internal(format!("Failed to queue job: {e}")))
+}
+
+/// Refuse new jobs with 503 while the server drains ahead of a deploy
+async fn check_accepting_jobs(data: &AppState) -> AppResult<()> {
+    if data.job_queue.is_draining().await {
+        counter_inc!("aperio_job_requests_refused_total", "reason" => "draining");
+        return Err(AppError::Draining(
+            "Server is draining for a restart and accepts no new jobs; submit to another instance".to_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Window `max_daily_jobs` counts submissions over
+const CLIENT_QUOTA_WINDOW_HOURS: i64 = 24;
+
+/// Refuse with 429 when creating `new_jobs` more jobs would take the client past its limits
+async fn check_client_quota(data: &AppState, client: Option<&ApiClient>, new_jobs: usize) -> AppResult<()> {
+    let Some(client) = client else { return Ok(()) };
+    if client.max_active_jobs.is_none() && client.max_daily_jobs.is_none() {
+        return Ok(());
+    }
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(CLIENT_QUOTA_WINDOW_HOURS);
+    let (active, recent) = data.job_repository.count_client_jobs(&client.name, since).await?;
+    let new_jobs = new_jobs as i64;
+    if let Some(max_active) = client.max_active_jobs {
+        if active + new_jobs > max_active as i64 {
+            counter_inc!("aperio_client_quota_rejections_total", "limit" => "active");
+            return Err(AppError::QuotaExceeded(format!(
+                "Client {} has {active} of {max_active} allowed active jobs; {new_jobs} more requested",
+                client.name
+            )));
+        }
+    }
+    if let Some(max_daily) = client.max_daily_jobs {
+        if recent + new_jobs > max_daily as i64 {
+            counter_inc!("aperio_client_quota_rejections_total", "limit" => "daily");
+            return Err(AppError::QuotaExceeded(format!(
+                "Client {} submitted {recent} of {max_daily} allowed jobs in the last {CLIENT_QUOTA_WINDOW_HOURS} hours; {new_jobs} more requested",
+                client.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Answer a request that created `job` with its body and a `Location` to poll it at. Callers
+/// pass 202 when the job was queued, or 201 when it completed on the spot.
+fn job_created(mut response: HttpResponseBuilder, job: &Job, app_state: &AppState) -> HttpResponse {
+    let body = JobResponse::new(job, app_state);
+    response
+        .insert_header((header::LOCATION, body.links.self_.clone()))
+        .json(body)
+}
+
+fn parse_priority(priority: Option<&str>) -> JobPriority {
+    match priority {
+        Some("high") => JobPriority::High,
+        Some("low") => JobPriority::Low,
+        _ => JobPriority::Normal,
+    }
+}
+
+/// Create one ordinary job per playlist entry, reusing active jobs for entries already in flight
+/// The playlist's jobs, and how many of them were newly created rather than already active
+async fn expand_playlist(
+    data: &AppState,
+    request: &DownloadRequest,
+    options: &ProcessingOptions,
+    tags: &[String],
+    client: Option<&ApiClient>,
+    correlation_id: Option<String>,
+) -> AppResult<(Vec<JobResponse>, usize)> {
+    let entries = data.download_service.list_playlist_entries(&request.url, options.auth_profile.as_deref()).await?;
+    // Counted as if every entry were new, so a playlist can't overshoot the quota
+    check_client_quota(data, client, entries.len()).await?;
+    let priority = parse_priority(request.priority.as_deref());
+    info!("Expanding playlist {} into {} jobs", redact_text(&request.url), entries.len());
+
+    let mut jobs = Vec::with_capacity(entries.len());
+    let mut created = 0;
+    for entry_url in entries {
+        let validated_url = data.security_validator.validate_url(&entry_url)?;
+        let normalized_url = normalize_url(&validated_url);
+
+        if !request.force {
+            if let Some(existing_job) = data.job_repository.find_active_job_by_url(&normalized_url).await? {
+                if &existing_job.options == options {
+                    jobs.push(JobResponse::new(&existing_job, data));
+                    continue;
+                }
+            }
+        }
+
+        let mut job = Job::new(entry_url);
+        job.client_id = client.map(|client| client.name.clone());
+        job.correlation_id = correlation_id.clone();
+        job.normalized_url = normalized_url;
+        job.keep_original = request.keep_original.unwrap_or(false);
+        job.retain_days = request.retain_days;
+        job.options = options.clone();
+        job.tags = tags.to_vec();
+        data.job_repository.create_job(&job).await?;
+
+        enqueue_created_job(data, &job, priority.clone()).await?;
+        counter_inc!("aperio_jobs_created_total", "priority" => request.priority.as_deref().unwrap_or("normal"));
+        jobs.push(JobResponse::new(&job, data));
+        created += 1;
+    }
+
+    Ok((jobs, created))
+}
+
+/// What became of one feed entry
+pub enum FeedEntryOutcome {
+    /// A job was created and queued; its id
+    Submitted(String),
+    /// A job for the URL already exists, in any status; its id
+    Duplicate(String),
+    /// The entry's URL was not accepted; why
+    Rejected(String),
+}
+
+/// Submit a subscription's feed entry as a job with the subscription's options and tags. Entries
+/// whose URL fails validation are rejected rather than failing the poll; errors are left for the
+/// poller to back off on.
+pub async fn submit_feed_entry(data: &AppState, subscription: &Subscription, entry_url: &str) -> AppResult<FeedEntryOutcome> {
+    let validated_url = match data.security_validator.validate_url(entry_url) {
+        Ok(url) if is_playlist_url(&url) => return Ok(FeedEntryOutcome::Rejected("URL points to a playlist".to_string())),
+        Ok(url) => url,
+        Err(e) => return Ok(FeedEntryOutcome::Rejected(e.to_string())),
+    };
+    let normalized_url = normalize_url(&validated_url);
+
+    if let Some(existing_job) = data.job_repository.find_latest_job_by_url(&normalized_url).await? {
+        return Ok(FeedEntryOutcome::Duplicate(existing_job.id));
+    }
+    check_accepting_jobs(data).await?;
+    data.storage_quota.check_admission().await?;
+
+    let mut job = Job::new(entry_url.to_string());
+    job.normalized_url = normalized_url;
+    job.options = subscription.options.clone();
+    job.tags = subscription.tags.clone();
+    job.tags.push(subscription.job_tag());
+    data.job_repository.create_job(&job).await?;
+
+    enqueue_created_job(data, &job, parse_priority(subscription.priority.as_deref())).await?;
+    counter_inc!("aperio_jobs_created_total", "source" => "subscription");
+    Ok(FeedEntryOutcome::Submitted(job.id))
+}
+
+/// Longest external id accepted, in bytes
+const MAX_EXTERNAL_ID_LENGTH: usize = 128;
+
+fn validate_external_id(data: &AppState, external_id: &str) -> AppResult<()> {
+    if external_id.is_empty() {
+        return Err(AppError::BadRequest("external_id must not be empty".to_string()));
+    }
+    data.security_validator.validate_input(external_id, "external_id", MAX_EXTERNAL_ID_LENGTH)
+}
+
+/// The job a client already submitted under `external_id`. Only jobs submitted with an API key
+/// are unique by external id, so there is never one without a client.
+async fn find_external_job(data: &AppState, client_id: Option<&str>, external_id: Option<&str>) -> AppResult<Option<Job>> {
+    match (client_id, external_id) {
+        (Some(client_id), Some(external_id)) => {
+            data.job_repository.find_client_job_by_external_id(client_id, external_id).await
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Look up the job created under an Idempotency-Key within the configured window
+async fn find_idempotent_job(data: &AppState, key: &str, request_fingerprint: &str) -> AppResult<Option<Job>> {
+    let Some(existing_job) = data.job_repository.find_job_by_idempotency_key(key).await? else {
+        return Ok(None);
+    };
+
+    // Outside the window the key is free again; release it so the new job can claim it
+    let window_start = chrono::Utc::now() - data.retention_service.idempotency_window();
+    if existing_job.created_at < window_start {
+        data.job_repository.expire_idempotency_keys(window_start).await?;
+        return Ok(None);
+    }
+
+    if existing_job.request_fingerprint.as_deref() != Some(request_fingerprint) {
+        return Err(AppError::Unprocessable(
+            "Idempotency-Key was already used with a different request".to_string()
+        ));
+    }
+
+    info!("Idempotency-Key matched job {}, returning it", existing_job.id);
+    Ok(Some(existing_job))
+}
+
+/// Most sources one concat job joins
+const MAX_CONCAT_SOURCES: usize = 20;
+
+/// The sources are only joined inside the encode, so what needs a single source file is refused:
+/// keeping an original, picking subtitle or audio tracks, and a size budget's analysis pass
+fn check_concat_options(options: &ProcessingOptions, keep_original: bool) -> AppResult<()> {
+    let unavailable = [
+        ("keep_original", keep_original),
+        ("options.subtitles", options.subtitles.is_some()),
+        ("options.audio_track", options.audio_track.is_some()),
+        ("options.max_output_size_mb", options.max_output_size_mb.is_some()),
+    ];
+    match unavailable.into_iter().find(|(_, requested)| *requested) {
+        Some((name, _)) => Err(AppError::BadRequest(format!("{name} is not available when joining sources"))),
+        None => Ok(()),
+    }
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    responses(
+        (status = 202, description = "Job created and queued; `Location` points at its status, whose `sources` show how far each download got",
+            body = JobResponse, headers(("Location" = String, description = "Where to poll the job's status"))),
+        (status = 200, description = "The active job joining the same URLs with the same options", body = JobResponse),
+        (status = 400, description = "Too few or too many URLs, an invalid or disallowed URL, or bad options", body = ErrorResponse),
+        (status = 503, description = "The server is draining and accepts no new jobs", body = ErrorResponse),
+        (status = 507, description = "Storage quota exhausted", body = ErrorResponse),
+    )
+)]
+#[post("/process/concat")]
+#[instrument(skip(data, http_request, request), fields(sources = request.urls.len()))]
+async fn start_concat_job(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    request: web::Json<ConcatRequest>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<HttpResponse> {
+    let client = client.map(web::ReqData::into_inner);
+    counter_inc!("aperio_job_requests_total");
+    info!("Starting new concat job for {} URLs", request.urls.len());
+    check_accepting_jobs(&data).await?;
+
+    if !(2..=MAX_CONCAT_SOURCES).contains(&request.urls.len()) {
+        return Err(AppError::BadRequest(format!(
+            "Concatenation needs 2 to {MAX_CONCAT_SOURCES} URLs, got {}", request.urls.len()
+        )));
+    }
+    if let Some(days) = request.retain_days {
+        validate_retain_days(&data, days)?;
+    }
+    let tags = data.security_validator.validate_tags(&request.tags)?;
+
+    // Every source must pass the checks a single-URL job would
+    let mut normalized_urls = Vec::with_capacity(request.urls.len());
+    for (index, url) in request.urls.iter().enumerate() {
+        let validated_url = data.security_validator.validate_input(url, "url", 2048)
+            .and_then(|()| data.security_validator.validate_url(url))
+            .map_err(|e| AppError::BadRequest(format!("URL {} ({}): {e}", index + 1, redact_text(url))))?;
+        if is_playlist_url(&validated_url) {
+            return Err(AppError::BadRequest(format!(
+                "URL {} ({}) points to a playlist; only single videos can be joined", index + 1, redact_text(url)
+            )));
+        }
+        normalized_urls.push(normalize_url(&validated_url));
+    }
+    data.storage_quota.check_admission().await?;
+
+    let options = request.options.clone().unwrap_or_default();
+    data.security_validator.validate_processing_options(&options)?;
+    data.download_service.validate_auth_profile(options.auth_profile.as_deref())?;
+    check_concat_options(&options, request.keep_original.unwrap_or(false))?;
+
+    // The same URLs in the same order deduplicate like a single URL does
+    let normalized_url = format!("concat:{}", normalized_urls.join(" "));
+    if !request.force {
+        if let Some(existing_job) = data.job_repository.find_active_job_by_url(&normalized_url).await? {
+            if existing_job.options == options {
+                info!("Found existing concat job {}, returning it instead of creating a duplicate", existing_job.id);
+                return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
+            }
+        }
+    }
+    check_client_quota(&data, client.as_ref(), 1).await?;
+
+    let mut job = match request.mode {
+        MultiSourceMode::Concat => Job::concat(request.urls.clone()),
+    };
+    job.client_id = client.map(|client| client.name);
+    job.correlation_id = correlation_id(&http_request);
+    job.normalized_url = normalized_url;
+    job.retain_days = request.retain_days;
+    job.options = options;
+    job.tags = tags;
+    data.job_repository.create_job(&job).await?;
+    info!("Created concat job {} joining {} URLs", job.id, job.sources.len());
+
+    enqueue_created_job(&data, &job, parse_priority(request.priority.as_deref())).await?;
+    counter_inc!("aperio_jobs_created_total", "priority" => request.priority.as_deref().unwrap_or("normal"));
+
+    Ok(job_created(HttpResponse::Accepted(), &job, &data))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    request_body(content_type = "multipart/form-data", description = "A `file` part holding the video, plus optional `priority`, `keep_original`, `retain_days`, `tags` (comma-separated), `external_id` and `options` (JSON) fields"),
+    responses(
+        (status = 202, description = "Job created from the uploaded file and queued", body = JobResponse,
+            headers(("Location" = String, description = "Where to poll the job's status"))),
+        (status = 200, description = "The API key already submitted a job with this external id; the upload is discarded", body = JobResponse),
+        (status = 400, description = "Missing file, unsupported extension or bad options", body = ErrorResponse),
+        (status = 413, description = "Upload exceeds APERIO_MAX_PAYLOAD", body = ErrorResponse),
+        (status = 503, description = "The server is draining and accepts no new jobs", body = ErrorResponse),
+        (status = 507, description = "Storage quota exhausted", body = ErrorResponse),
+    )
+)]
+#[post("/process/upload")]
+#[instrument(skip(data, http_request, payload))]
+async fn start_upload_job(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    mut payload: Multipart,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    let client = client.map(web::ReqData::into_inner);
+    counter_inc!("aperio_job_requests_total");
+    info!("Starting new job from uploaded file");
+    check_accepting_jobs(&data).await?;
+    let content_length = http_request.headers().get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    data.upload_service.check_content_length(content_length)?;
+    data.storage_quota.check_admission().await?;
+    check_client_quota(&data, client.as_ref(), 1).await?;
+
+    let mut job = Job::new(String::new());
+    job.client_id = client.map(|client| client.name);
+    job.correlation_id = correlation_id(&http_request);
+    let mut priority = JobPriority::Normal;
+    let mut stored_path = None;
+    // Once the file is stored, any bad field after it must not leave it behind
+    if let Err(e) = read_upload_form(&data, &mut payload, &mut job, &mut priority, &mut stored_path).await {
+        if let Some(path) = &stored_path {
+            data.upload_service.discard(path).await;
+        }
+        return Err(e);
+    }
+
+    let stored_path = stored_path
+        .ok_or_else(|| AppError::BadRequest("Multipart payload must contain a 'file' field".to_string()))?;
+
+    if let Some(existing_job) = find_external_job(&data, job.client_id.as_deref(), job.external_id.as_deref()).await? {
+        info!("External id matched job {}, discarding the upload", existing_job.id);
+        data.upload_service.discard(&stored_path).await;
+        return Ok(HttpResponse::Ok().json(JobResponse::new(&existing_job, &data)));
+    }
+
+    // Reject anything ffprobe can't read before it takes a queue slot
+    if let Err(e) = data.process_service.probe_duration(&stored_path).await {
+        data.upload_service.discard(&stored_path).await;
+        return Err(AppError::BadRequest(format!("Uploaded file rejected: {e}")));
+    }
+    if job.options.crop.is_some() {
+        let stream = data.process_service.probe_video_stream(&stored_path).await.ok();
+        if let Err(e) = check_crop_fits(&job.options, stream.as_ref().and_then(|stream| stream.width), stream.as_ref().and_then(|stream| stream.height)) {
+            data.upload_service.discard(&stored_path).await;
+            return Err(e);
+        }
+    }
+
+    // The upload replaces the download phase, so the job starts with its source in place
+    job.set_downloaded_path(stored_path.clone());
+    let job_id = job.id.clone();
+
+    if let Err(e) = data.job_repository.create_job(&job).await {
+        data.upload_service.discard(&stored_path).await;
+        return Err(e);
+    }
+
+    info!("Created upload job {} for {}", job_id, job.url);
+
+    if let Err(e) = enqueue_created_job(&data, &job, priority).await {
+        data.upload_service.discard(&stored_path).await;
+        return Err(e);
+    }
+
+    counter_inc!("aperio_jobs_created_total", "source" => "upload");
+
+    Ok(job_created(HttpResponse::Accepted(), &job, &data))
+}
+
+/// Read the upload form into `job`, storing its file under the job's id. `stored_path` is set as
+/// soon as the file is on disk, so the caller can discard it when a later field is rejected.
+async fn read_upload_form(
+    data: &AppState,
+    payload: &mut Multipart,
+    job: &mut Job,
+    priority: &mut JobPriority,
+    stored_path: &mut Option<std::path::PathBuf>,
+) -> AppResult<()> {
+    while let Some(field) = payload.next().await {
+        let mut field = field
+            .map_err(|e| AppError::BadRequest(format!("Invalid multipart payload: {e}")))?;
+        let field_name = field.name().unwrap_or_default().to_string();
+
+        match field_name.as_str() {
+            "file" if stored_path.is_none() => {
+                let raw_filename = field.content_disposition()
+                    .and_then(|cd| cd.get_filename())
+                    .unwrap_or("upload")
+                    .to_string();
+                let filename = data.upload_service.sanitize_filename(&raw_filename);
+                data.security_validator.validate_input(&filename, "filename", 255)?;
+
+                let path = data.upload_service.store_upload(&job.id, &filename, &mut field).await?;
+                job.url = format!("upload://{filename}");
+                *stored_path = Some(path);
+            }
+            "priority" | "keep_original" | "retain_days" => {
+                let mut value = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk
+                        .map_err(|e| AppError::BadRequest(format!("Invalid multipart payload: {e}")))?;
+                    value.extend_from_slice(&chunk);
+                    if value.len() > 16 {
+                        return Err(AppError::BadRequest(format!("{field_name} value too long")));
+                    }
+                }
+                let value = String::from_utf8_lossy(&value).trim().to_lowercase();
+
+                if field_name == "priority" {
+                    *priority = match value.as_str() {
+                        "high" => JobPriority::High,
+                        "low" => JobPriority::Low,
+                        _ => JobPriority::Normal,
+                    };
+                } else if field_name == "retain_days" {
+                    let days = value.parse::<u32>()
+                        .map_err(|_| AppError::BadRequest("retain_days must be a whole number of days".to_string()))?;
+                    validate_retain_days(data, days)?;
+                    job.retain_days = Some(days);
+                } else {
+                    job.keep_original = value == "true";
+                }
+            }
+            "tags" => {
+                let value = read_text_field(&mut field, &field_name, MAX_JOB_TAGS * (MAX_TAG_LENGTH + 1)).await?;
+                let tags: Vec<String> = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                job.tags = data.security_validator.validate_tags(&tags)?;
+            }
+            "external_id" => {
+                let value = read_text_field(&mut field, &field_name, MAX_EXTERNAL_ID_LENGTH).await?;
+                validate_external_id(data, &value)?;
+                job.external_id = Some(value);
+            }
+            _ => {
+                // Drain fields we don't understand so the stream can advance
+                while let Some(chunk) = field.next().await {
+                    chunk.map_err(|e| AppError::BadRequest(format!("Invalid multipart payload: {e}")))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reject a crop that can't fit a source whose frame size is already known. Otherwise it is
+/// checked once the source has been probed for processing.
+fn check_crop_fits(options: &ProcessingOptions, width: Option<u64>, height: Option<u64>) -> AppResult<()> {
+    match (&options.crop, width, height) {
+        (Some(crop), Some(width), Some(height)) => crop_rect(crop, width, height)
+            .map(|_| ())
+            .map_err(|reason| AppError::BadRequest(format!("Invalid crop: {reason}"))),
+        _ => Ok(()),
+    }
+}
+
+/// Read a small text form field, refusing values longer than `max_len` bytes
+async fn read_text_field(field: &mut actix_multipart::Field, field_name: &str, max_len: usize) -> AppResult<String> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk
+            .map_err(|e| AppError::BadRequest(format!("Invalid multipart payload: {e}")))?;
+        value.extend_from_slice(&chunk);
+        if value.len() > max_len {
+            return Err(AppError::BadRequest(format!("{field_name} value too long")));
+        }
+    }
+    Ok(String::from_utf8_lossy(&value).trim().to_string())
 }
 
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created"), StatusQuery),
+    responses(
+        (status = 200, description = "The job, once its status changed or `wait` ran out", body = JobResponse),
+        (status = 400, description = "Unknown last_status", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
 #[get("/status/{job_id}")]
 #[instrument(skip(data), fields(job_id = %job_id))]
 async fn get_job_status(
     data: web::Data<Arc<AppState>>,
     job_id: web::Path<String>,
+    query: web::Query<StatusQuery>,
 ) -> AppResult<impl Responder> {
     debug!("Getting status for job: {}", job_id);
     
     // Validate job_id input
     data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
-    
-    let job = data.job_repository.get_job(job_id.as_str()).await?
+    let last_status = query.last_status.as_deref()
+        .map(|name| JobStatus::from_name(name)
+            .ok_or_else(|| AppError::BadRequest(format!("Invalid last_status: {name}"))))
+        .transpose()?;
+
+    // Subscribed before reading so a change between the read and the wait isn't missed
+    let mut changes = data.job_repository.subscribe_status_changes();
+    let mut job = data.job_repository.get_job(job_id.as_str()).await?
         .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
 
+    let wait = query.wait.unwrap_or(0).min(MAX_STATUS_WAIT_SECS);
+    let baseline = last_status.unwrap_or_else(|| job.status.clone());
+    if wait > 0 && job.status == baseline && !job.status.is_terminal() {
+        debug!("Waiting up to {}s for job {} to leave {}", wait, job_id, baseline);
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(wait);
+        loop {
+            match tokio::time::timeout_at(deadline, changes.recv()).await {
+                Ok(Ok(change)) if change.job_id == *job_id && change.status != baseline => break,
+                Ok(Ok(_)) => continue,
+                // Missed some changes; this one may have been among them
+                Ok(Err(broadcast::error::RecvError::Lagged(_))) => {
+                    match data.job_repository.get_job(job_id.as_str()).await? {
+                        Some(current) if current.status == baseline => continue,
+                        _ => break,
+                    }
+                }
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => break,
+            }
+        }
+        job = data.job_repository.get_job(job_id.as_str()).await?
+            .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+    }
+
     debug!("Job {} status: {:?}", job_id, job.status);
-    Ok(web::Json(JobResponse::from(&job)))
+    Ok(web::Json(JobResponse::new(&job, &data)))
+}
+
+/// Per-job retention must be at least a day and no longer than APERIO_MAX_RETAIN_DAYS
+fn validate_retain_days(data: &AppState, days: u32) -> AppResult<()> {
+    let max_retain_days = data.retention_service.max_retain_days();
+    if days == 0 || days > max_retain_days {
+        return Err(AppError::BadRequest(format!(
+            "retain_days must be between 1 and {max_retain_days}"
+        )));
+    }
+    Ok(())
+}
+
+/// Outputs can only be served from completed jobs; expired ones get a distinct 410 so clients know to resubmit
+fn ensure_output_available(job: &Job) -> AppResult<()> {
+    match job.status {
+        JobStatus::Completed => Ok(()),
+        JobStatus::Expired => Err(AppError::Expired(
+            "Output expired and its files were removed by retention; resubmit the job to regenerate it".to_string()
+        )),
+        _ => Err(AppError::BadRequest("Job not completed yet".to_string())),
+    }
+}
+
+/// Note that a job's output was served, for LRU eviction; failures only cost eviction accuracy
+async fn record_access(data: &AppState, job_id: &str) {
+    if let Err(e) = data.job_repository.touch_last_accessed(job_id).await {
+        warn!("Failed to record access to job {}: {}", job_id, e);
+    }
 }
 
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "The processed video as an attachment; supports Range and conditional requests", content_type = "video/mp4"),
+        (status = 304, description = "Unchanged since the given ETag"),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
 #[get("/video/{job_id}")]
 #[instrument(skip(data, req), fields(job_id = %job_id))]
 async fn get_processed_video(
@@ -156,9 +1135,7 @@ async fn get_processed_video(
     let job = data.job_repository.get_job(job_id.as_str()).await?
         .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
 
-    if job.status != JobStatus::Completed {
-        return Err(AppError::BadRequest("Job not completed yet".to_string()));
-    }
+    ensure_output_available(&job)?;
 
     let processed_path = job.get_processed_path()
         .ok_or_else(|| AppError::NotFound("No processed file found".to_string()))?;
@@ -171,7 +1148,7 @@ async fn get_processed_video(
 
     // Get file metadata
     let file_metadata = tokio::fs::metadata(&processed_path).await
-        .map_err(|e| AppError::Internal(format!("Failed to get file metadata: {e}")))?;
+        .map_err(AppError::io("Failed to get file metadata"))?;
     
     let file_size = file_metadata.len();
     info!("Streaming video file for job {}, size: {} bytes", job_id, file_size);
@@ -181,19 +1158,92 @@ async fn get_processed_video(
     
     // Create streaming response using actix-files NamedFile with optimized settings
     let file = actix_files::NamedFile::open(&processed_path)
-        .map_err(|e| AppError::Internal(format!("Failed to open file for streaming: {e}")))?;
+        .map_err(AppError::io("Failed to open file for streaming"))?;
+    record_access(&data, &job.id).await;
+
+    // The content hash is a stronger validator than actix-files' inode/mtime ETag, so it replaces it when known
+    let etag = job.processed_sha256.as_ref().map(|sha256| format!("\"{sha256}\""));
+    if let Some(etag) = &etag {
+        let matches = req.headers().get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.split(',').any(|candidate| candidate.trim() == etag));
+        if matches {
+            return Ok(HttpResponse::NotModified().insert_header((header::ETAG, etag.as_str())).finish());
+        }
+    }
 
     // Enable range requests for better streaming support
-    Ok(file
-        .use_etag(true)
+    let mut response = file
+        .use_etag(etag.is_none())
         .use_last_modified(true)
         .set_content_disposition(ContentDisposition {
             disposition: DispositionType::Attachment,
             parameters: vec![actix_web::http::header::DispositionParam::Filename(filename)],
         })
-        .into_response(&req))
+        .into_response(&req);
+
+    if let (Some(etag), Some(sha256)) = (etag, &job.processed_sha256) {
+        let headers = response.headers_mut();
+        if let Ok(value) = header::HeaderValue::from_str(&etag) {
+            headers.insert(header::ETAG, value);
+        }
+        if let Ok(value) = header::HeaderValue::from_str(sha256) {
+            headers.insert(header::HeaderName::from_static("x-content-sha256"), value);
+        }
+    }
+
+    Ok(response)
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ChecksumResponse {
+    pub job_id: String,
+    pub algorithm: &'static str,
+    pub checksum: String,
+}
+
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, body = ChecksumResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
+#[get("/video/{job_id}/checksum")]
+#[instrument(skip(data), fields(job_id = %job_id))]
+async fn get_processed_checksum(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+) -> AppResult<impl Responder> {
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    let job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    ensure_output_available(&job)?;
+
+    let checksum = job.processed_sha256
+        .ok_or_else(|| AppError::NotFound("No checksum recorded for this job".to_string()))?;
+
+    Ok(web::Json(ChecksumResponse {
+        job_id: job.id,
+        algorithm: "sha256",
+        checksum,
+    }))
 }
 
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "The processed video served inline; supports Range requests", content_type = "video/mp4"),
+        (status = 206, description = "Partial content for a Range request", content_type = "video/mp4"),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
 #[get("/stream/{job_id}")]
 #[instrument(skip(data, req), fields(job_id = %job_id))]
 async fn stream_processed_video(
@@ -209,9 +1259,7 @@ async fn stream_processed_video(
     let job = data.job_repository.get_job(job_id.as_str()).await?
         .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
 
-    if job.status != JobStatus::Completed {
-        return Err(AppError::BadRequest("Job not completed yet".to_string()));
-    }
+    ensure_output_available(&job)?;
 
     let processed_path = job.get_processed_path()
         .ok_or_else(|| AppError::NotFound("No processed file found".to_string()))?;
@@ -224,14 +1272,15 @@ async fn stream_processed_video(
 
     // Get file metadata
     let file_metadata = tokio::fs::metadata(&processed_path).await
-        .map_err(|e| AppError::Internal(format!("Failed to get file metadata: {e}")))?;
+        .map_err(AppError::io("Failed to get file metadata"))?;
     
     let file_size = file_metadata.len();
     info!("Streaming video inline for job {}, size: {} bytes", job_id, file_size);
 
     // Create streaming response for inline viewing (no Content-Disposition header)
     let file = actix_files::NamedFile::open(&processed_path)
-        .map_err(|e| AppError::Internal(format!("Failed to open file for streaming: {e}")))?;
+        .map_err(AppError::io("Failed to open file for streaming"))?;
+    record_access(&data, &job.id).await;
 
     // Enable range requests and proper content type for video streaming
     Ok(file
@@ -241,19 +1290,572 @@ async fn stream_processed_video(
         .into_response(&req))
 }
 
-#[delete("/jobs/{job_id}")]
-#[instrument(skip(data), fields(job_id = %job_id))]
-async fn cancel_job(
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "The original download, kept when the job set `keep_original`", content_type = "application/octet-stream"),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
+#[get("/original/{job_id}")]
+#[instrument(skip(data, req), fields(job_id = %job_id))]
+async fn get_original_video(
     data: web::Data<Arc<AppState>>,
     job_id: web::Path<String>,
+    req: actix_web::HttpRequest,
 ) -> AppResult<impl Responder> {
-    info!("Cancelling job: {}", job_id);
-    
+    debug!("Streaming original file for job: {}", job_id);
+
+    // Validate job_id input
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    let job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    if !job.keep_original {
+        return Err(AppError::NotFound("Original file was not kept for this job".to_string()));
+    }
+
+    ensure_output_available(&job)?;
+
+    let original_path = job.get_downloaded_path()
+        .ok_or_else(|| AppError::NotFound("No original file found".to_string()))?;
+
+    if !original_path.exists() {
+        error!("Original file not found at path: {:?}", original_path);
+        return Err(AppError::NotFound("Original file not found on disk".to_string()));
+    }
+
+    let content_type = detect_video_content_type(&original_path).await;
+    let extension = original_path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("bin");
+    let filename = format!("original_{job_id}.{extension}");
+
+    info!("Streaming original file for job {} as {}", job_id, content_type);
+
+    let file = actix_files::NamedFile::open(&original_path)
+        .map_err(AppError::io("Failed to open file for streaming"))?;
+
+    Ok(file
+        .use_etag(true)
+        .use_last_modified(true)
+        .set_content_type(content_type)
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![actix_web::http::header::DispositionParam::Filename(filename)],
+        })
+        .into_response(&req))
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BundleManifest {
+    pub job: JobResponse,
+    pub entries: Vec<BundleEntry>,
+}
+
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "Zip of the job's files with a `manifest.json` (see BundleManifest)", content_type = "application/zip"),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
+#[get("/bundle/{job_id}")]
+#[instrument(skip(data), fields(job_id = %job_id))]
+async fn get_bundle(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    let job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    ensure_output_available(&job)?;
+
+    let processed_path = job.get_processed_path()
+        .ok_or_else(|| AppError::NotFound("No processed file found".to_string()))?;
+    let mut files = vec![BundleFile::open(processed_path, "video", job.processed_sha256.clone()).await?];
+    if let Some(preview_path) = job.get_preview_path() {
+        files.push(BundleFile::open(preview_path, "preview", None).await?);
+    }
+    if job.keep_original && job.owns_downloaded_file() {
+        if let Some(downloaded_path) = job.get_downloaded_path() {
+            files.push(BundleFile::open(downloaded_path, "original", None).await?);
+        }
+    }
+
+    let manifest = BundleManifest {
+        job: JobResponse::new(&job, &data),
+        entries: files.iter().map(BundleEntry::from).collect(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize bundle manifest: {e}")))?;
+
+    record_access(&data, &job.id).await;
+    info!("Streaming bundle of {} files for job {}", files.len(), job.id);
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![actix_web::http::header::DispositionParam::Filename(format!("job_{}.zip", job.id))],
+        })
+        .streaming(stream_bundle(job.id.clone(), files, manifest)))
+}
+
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "Animated preview of the processed video", content_type = "image/gif"),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
+#[get("/preview/{job_id}")]
+#[instrument(skip(data, req), fields(job_id = %job_id))]
+async fn get_preview(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+    req: actix_web::HttpRequest,
+) -> AppResult<impl Responder> {
+    debug!("Serving preview for job: {}", job_id);
+
+    // Validate job_id input
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    let job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    ensure_output_available(&job)?;
+
+    let preview_path = job.get_preview_path()
+        .ok_or_else(|| AppError::NotFound("No preview available for this job".to_string()))?;
+
+    if !preview_path.exists() {
+        error!("Preview file not found at path: {:?}", preview_path);
+        return Err(AppError::NotFound("Preview file not found on disk".to_string()));
+    }
+
+    let file = actix_files::NamedFile::open(&preview_path)
+        .map_err(AppError::io("Failed to open preview file"))?;
+
+    Ok(file
+        .use_etag(true)
+        .use_last_modified(true)
+        .set_content_type(mime::IMAGE_GIF)
+        .into_response(&req))
+}
+
+/// Longest a status request may be held open waiting for a change
+const MAX_STATUS_WAIT_SECS: u64 = 60;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct StatusQuery {
+    /// Seconds to hold the request open until the status changes, at most 60; answers at once when absent
+    pub wait: Option<u64>,
+    /// Status the caller last saw, e.g. `Downloading`; defaults to the status when the request arrives
+    pub last_status: Option<String>,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct FrameQuery {
+    /// Offset into the video in seconds, less than its duration
+    pub t: f64,
+    /// Scale the frame to this width (16-3840), keeping the aspect ratio
+    pub width: Option<u32>,
+}
+
+#[utoipa::path(
+    tag = "media",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created"), FrameQuery),
+    responses(
+        (status = 200, description = "A still frame from the processed video", content_type = "image/jpeg"),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "Output expired or was evicted", body = ErrorResponse),
+    )
+)]
+#[get("/frame/{job_id}")]
+#[instrument(skip(data, req), fields(job_id = %job_id))]
+async fn get_frame(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+    query: web::Query<FrameQuery>,
+    req: actix_web::HttpRequest,
+) -> AppResult<impl Responder> {
+    debug!("Extracting frame for job {} at {}s", job_id, query.t);
+
     // Validate job_id input
     data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    if !query.t.is_finite() || query.t < 0.0 {
+        return Err(AppError::BadRequest("t must be a non-negative number of seconds".to_string()));
+    }
+    if let Some(width) = query.width {
+        if !(16..=3840).contains(&width) {
+            return Err(AppError::BadRequest("width must be between 16 and 3840".to_string()));
+        }
+    }
+
+    let job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    ensure_output_available(&job)?;
+
+    let processed_path = job.get_processed_path()
+        .ok_or_else(|| AppError::NotFound("No processed file found".to_string()))?;
+
+    if !processed_path.exists() {
+        error!("Processed file not found at path: {:?}", processed_path);
+        return Err(AppError::NotFound("Processed file not found on disk".to_string()));
+    }
+
+    // Jobs processed before durations were recorded are probed on demand
+    let duration = match job.metadata.duration_seconds {
+        Some(duration) => duration,
+        None => data.process_service.probe_duration(&processed_path).await?,
+    };
+
+    // Seeking to the very end decodes nothing, so the last usable offset is just before it
+    if query.t >= duration {
+        return Err(AppError::BadRequest(format!(
+            "t={} must be less than the video duration of {:.3} seconds", query.t, duration
+        )));
+    }
+
+    let frame_path = data.process_service
+        .extract_frame(&job, &processed_path, query.t, query.width)
+        .await?;
+
+    let file = actix_files::NamedFile::open(&frame_path)
+        .map_err(AppError::io("Failed to open frame file"))?;
+
+    Ok(file
+        .use_etag(true)
+        .use_last_modified(true)
+        .set_content_type(mime::IMAGE_JPEG)
+        .into_response(&req))
+}
+
+/// Detect a video container's content type from its magic bytes, falling back to the extension
+async fn detect_video_content_type(path: &Path) -> mime::Mime {
+    use tokio::io::AsyncReadExt;
+
+    let mut header = [0u8; 64];
+    let read = match tokio::fs::File::open(path).await {
+        Ok(mut file) => file.read(&mut header).await.unwrap_or(0),
+        Err(_) => 0,
+    };
+    let header = &header[..read];
+
+    let detected = if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        // ISO base media: QuickTime brand is "qt  ", everything else is served as MP4
+        if &header[8..12] == b"qt  " { Some("video/quicktime") } else { Some("video/mp4") }
+    } else if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // EBML header: the DocType distinguishes WebM from generic Matroska
+        if header.windows(4).any(|w| w == b"webm") { Some("video/webm") } else { Some("video/x-matroska") }
+    } else if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"AVI " {
+        Some("video/x-msvideo")
+    } else {
+        None
+    };
+
+    let content_type = detected.unwrap_or_else(|| {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()).as_deref() {
+            Some("mp4") | Some("m4v") => "video/mp4",
+            Some("mov") => "video/quicktime",
+            Some("webm") => "video/webm",
+            Some("mkv") => "video/x-matroska",
+            Some("avi") => "video/x-msvideo",
+            _ => "application/octet-stream",
+        }
+    });
+
+    content_type.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM)
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 202, description = "A new job processing the same source with the given options, queued", body = JobResponse,
+            headers(("Location" = String, description = "Where to poll the job's status"))),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 410, description = "The source files were removed", body = ErrorResponse),
+        (status = 503, description = "The server is draining and accepts no new jobs", body = ErrorResponse),
+    )
+)]
+#[post("/jobs/{job_id}/reprocess")]
+#[instrument(skip(data, http_request, request), fields(job_id = %job_id))]
+async fn reprocess_job(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    job_id: web::Path<String>,
+    request: web::Json<ReprocessRequest>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    let client = client.map(web::ReqData::into_inner);
+    info!("Re-processing job: {}", job_id);
+
+    // Validate job_id input
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+    data.security_validator.validate_processing_options(&request.options)?;
+    check_accepting_jobs(&data).await?;
+    data.storage_quota.check_admission().await?;
+    check_client_quota(&data, client.as_ref(), 1).await?;
+
+    let parent = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    if parent.status != JobStatus::Completed {
+        return Err(AppError::BadRequest("Only completed jobs can be re-processed".to_string()));
+    }
+    check_crop_fits(&request.options, parent.metadata.source_width, parent.metadata.source_height)?;
+
+    let mut child = Job::new(parent.url.clone());
+    child.normalized_url = parent.normalized_url.clone();
+    child.options = request.options.clone();
+    child.parent_job_id = Some(parent.id.clone());
+    child.client_id = client.map(|client| client.name);
+    child.correlation_id = correlation_id(&http_request);
+    child.tags = parent.tags.clone();
+
+    // Reuse the parent's kept original when possible, otherwise fall back to a fresh download
+    let kept_original = parent.get_downloaded_path()
+        .filter(|path| parent.keep_original && path.exists());
+
+    if let Some(original_path) = &kept_original {
+        info!("Job {} reuses original file of parent job {}", child.id, parent.id);
+        child.set_downloaded_path(original_path.clone());
+        child.source_job_id = Some(parent.id.clone());
+        data.cleanup_service.acquire_source(&original_path.to_string_lossy(), &child.id).await;
+    } else {
+        info!("Parent job {} has no kept original, job {} will download again", parent.id, child.id);
+    }
+
+    let release_source = || async {
+        if let Some(original_path) = &kept_original {
+            data.cleanup_service.release_source(&original_path.to_string_lossy(), &child.id).await;
+        }
+    };
+
+    if let Err(e) = data.job_repository.create_job(&child).await {
+        release_source().await;
+        return Err(e);
+    }
+
+    let priority = parse_priority(request.priority.as_deref());
+
+    if let Err(e) = enqueue_created_job(&data, &child, priority).await {
+        release_source().await;
+        return Err(e);
+    }
+
+    counter_inc!("aperio_jobs_created_total", "source" => "reprocess");
+
+    Ok(job_created(HttpResponse::Accepted(), &child, &data))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, body = JobDetailResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[get("/jobs/{job_id}")]
+#[instrument(skip(data), fields(job_id = %job_id))]
+async fn get_job_details(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+) -> AppResult<impl Responder> {
+    debug!("Getting details for job: {}", job_id);
+
+    // Validate job_id input
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    let job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    let derived_jobs = data.job_repository.list_child_jobs(job_id.as_str()).await?;
+
+    Ok(web::Json(JobDetailResponse {
+        job: JobResponse::new(&job, &data),
+        derived_jobs: derived_jobs.iter().map(|job| JobResponse::new(job, &data)).collect(),
+    }))
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobLogsResponse {
+    pub job_id: String,
+    /// Oldest first; only the newest 200 lines of a job are kept
+    pub entries: Vec<JobLogEntry>,
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "Status changes, retries, warnings and errors the job went through", body = JobLogsResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[get("/jobs/{job_id}/logs")]
+#[instrument(skip(data), fields(job_id = %job_id))]
+async fn get_job_logs(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+) -> AppResult<impl Responder> {
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+
+    data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+    let entries = data.job_repository.get_job_logs(job_id.as_str()).await?;
+
+    Ok(web::Json(JobLogsResponse { job_id: job_id.into_inner(), entries }))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, body = JobResponse),
+        (status = 400, description = "retain_days outside 1..=APERIO_MAX_RETAIN_DAYS", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[patch("/jobs/{job_id}/retention")]
+#[instrument(skip(data, request), fields(job_id = %job_id))]
+async fn update_job_retention(
+    data: web::Data<Arc<AppState>>,
+    job_id: web::Path<String>,
+    request: web::Json<RetentionRequest>,
+) -> AppResult<impl Responder> {
+    data.security_validator.validate_input(job_id.as_str(), "job_id", 100)?;
+    validate_retain_days(&data, request.retain_days)?;
+
+    let mut job = data.job_repository.get_job(job_id.as_str()).await?
+        .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
+
+    // Expiry counts from completion, so only a finished output has one to change
+    ensure_output_available(&job)?;
+
+    job.retain_days = Some(request.retain_days);
+    job.set_expiry(job.updated_at, data.retention_service.default_retain_days());
+    data.job_repository.update_retention(&job.id, request.retain_days, job.expires_at).await?;
+
+    info!("Job {} now retained for {} days (expires at {:?})", job.id, request.retain_days, job.expires_at);
+    Ok(web::Json(JobResponse::new(&job, &data)))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "An unfinished job is cancelled; a finished one is deleted and can be restored within APERIO_SOFT_DELETE_WINDOW_HOURS",
+         body = Object, example = json!({"message": "Job cancelled successfully", "job_id": "..."})),
+        (status = 400, description = "Job finished while it was being cancelled", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[delete("/jobs/{job_id}")]
+#[instrument(skip(data, http_request), fields(job_id = %job_id))]
+async fn cancel_job(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    job_id: web::Path<String>,
+) -> AppResult<impl Responder> {
+    // A finished job can't be cancelled, so deleting it hides it instead
+    let finished = data.job_repository.get_job(&job_id).await?
+        .is_some_and(|job| job.status.is_terminal());
+    if finished {
+        let result = soft_delete_job_by_id(&data, &job_id).await;
+        audit(&data, &http_request, AuditAction::DeleteJob, &job_id, &result, |_| None).await;
+        return result.map(web::Json);
+    }
+
+    let result = cancel_job_by_id(&data, &job_id).await;
+    audit(&data, &http_request, AuditAction::CancelJob, &job_id, &result, |_| None).await;
+    result.map(web::Json)
+}
+
+async fn soft_delete_job_by_id(data: &AppState, job_id: &str) -> AppResult<serde_json::Value> {
+    data.security_validator.validate_input(job_id, "job_id", 100)?;
+
+    // Files stay until retention removes the job, so a restore brings the output back too
+    if !data.job_repository.soft_delete_job(job_id).await? {
+        return Err(AppError::NotFound(format!("Job not found: {job_id}")));
+    }
+    let restorable_until = chrono::Utc::now() + data.retention_service.soft_delete_window();
+
+    info!("Deleted job {}, restorable until {}", job_id, restorable_until);
+    Ok(serde_json::json!({
+        "message": "Job deleted",
+        "job_id": job_id,
+        "restorable_until": restorable_until.to_rfc3339(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id returned when the job was created")),
+    responses(
+        (status = 200, description = "Job visible again, with its files if retention hasn't removed them", body = JobResponse),
+        (status = 400, description = "Job is not deleted", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "Deleted longer ago than APERIO_SOFT_DELETE_WINDOW_HOURS", body = ErrorResponse),
+    )
+)]
+#[post("/jobs/{job_id}/restore")]
+#[instrument(skip(data, http_request), fields(job_id = %job_id))]
+async fn restore_job(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    job_id: web::Path<String>,
+) -> AppResult<impl Responder> {
+    let result = restore_job_by_id(&data, &job_id).await;
+    audit(&data, &http_request, AuditAction::RestoreJob, &job_id, &result, |_| None).await;
+    result.map(web::Json)
+}
+
+async fn restore_job_by_id(data: &AppState, job_id: &str) -> AppResult<JobResponse> {
+    data.security_validator.validate_input(job_id, "job_id", 100)?;
+
+    let not_found = || AppError::NotFound(format!("Job not found: {job_id}"));
+    let job = data.job_repository.get_job_including_deleted(job_id).await?.ok_or_else(not_found)?;
+    let Some(deleted_at) = job.deleted_at else {
+        return Err(AppError::BadRequest(format!("Job {job_id} is not deleted")));
+    };
+
+    let deleted_after = chrono::Utc::now() - data.retention_service.soft_delete_window();
+    if !data.job_repository.restore_job(job_id, deleted_after).await? {
+        return Err(AppError::Conflict(format!(
+            "Job {job_id} was deleted at {} and can no longer be restored", deleted_at.to_rfc3339()
+        )));
+    }
+
+    let job = data.job_repository.get_job(job_id).await?.ok_or_else(not_found)?;
+    info!("Restored job {}", job_id);
+    Ok(JobResponse::new(&job, data))
+}
+
+async fn cancel_job_by_id(data: &AppState, job_id: &str) -> AppResult<serde_json::Value> {
+    info!("Cancelling job: {}", job_id);
+    
+    // Validate job_id input
+    data.security_validator.validate_input(job_id, "job_id", 100)?;
     
     // Get the job from database
-    let mut job = data.job_repository.get_job(job_id.as_str()).await?
+    let mut job = data.job_repository.get_job(job_id).await?
         .ok_or_else(|| AppError::NotFound(format!("Job not found: {job_id}")))?;
 
     // Check if job can be cancelled
@@ -267,209 +1869,1461 @@ async fn cancel_job(
         JobStatus::Failed => {
             return Err(AppError::BadRequest("Cannot cancel failed job".to_string()));
         }
+        JobStatus::Expired => {
+            return Err(AppError::BadRequest("Cannot cancel expired job".to_string()));
+        }
         _ => {} // Can cancel pending, downloading, or processing jobs
     }
 
-    // Try to cancel the job in the queue/active jobs
-    let cancelled = data.job_queue.cancel_job(job_id.as_str()).await
-        .map_err(|e| AppError::Internal(format!("Failed to cancel job: {e}")))?;
+    if cancel_active_job(data, &mut job).await? {
+        info!("Successfully cancelled job: {}", job_id);
+        Ok(serde_json::json!({
+            "message": "Job cancelled successfully",
+            "job_id": job_id
+        }))
+    } else {
+        warn!("Job {} not found in queue or active jobs, may have already completed", job_id);
+        Err(AppError::BadRequest("Job cannot be cancelled (may have already completed)".to_string()))
+    }
+}
+
+/// Pull a job that isn't finished out of the queue or its worker, mark it cancelled and remove
+/// its files. False when it was neither queued nor running, e.g. because it just finished.
+async fn cancel_active_job(data: &AppState, job: &mut Job) -> AppResult<bool> {
+    // Try to cancel the job in the queue/active jobs
+    let cancelled = data.job_queue.cancel_job(&job.id).await
+        .map_err(|e| AppError::Internal(format!("Failed to cancel job: {e}")))?;
+    if !cancelled {
+        return Ok(false);
+    }
+
+    // Update job status in database
+    // set_error marks the job Failed, so the status comes after it
+    job.set_error("Job cancelled by user".to_string());
+    job.update_status(JobStatus::Cancelled);
+
+    match data.job_repository.update_job(job).await {
+        Ok(()) => {}
+        // It finished before the cancellation landed, so its files are left alone
+        Err(AppError::Conflict(reason)) => {
+            info!("Not cancelling job {}: {}", job.id, reason);
+            return Ok(false);
+        }
+        Err(e) => warn!("Failed to update cancelled job status in database: {}", e),
+    }
+
+    // Aborted jobs never reach their own release, so drop any hold on a parent's original
+    if !job.owns_downloaded_file() {
+        if let Some(source) = job.get_downloaded_path() {
+            data.cleanup_service.release_source(&source.to_string_lossy(), &job.id).await;
+        }
+    }
+
+    // Clean up any temporary files
+    if let Err(e) = data.cleanup_service.cleanup_job_files(&job.id).await {
+        warn!("Failed to cleanup files for cancelled job {}: {}", job.id, e);
+    }
+
+    Ok(true)
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct TagQuery {
+    /// Act on the jobs carrying this tag. Non-admin API keys only reach their own jobs.
+    pub tag: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct TagBulkResponse {
+    pub tag: String,
+    /// Jobs cancelled or purged
+    pub jobs: Vec<String>,
+    /// Tagged jobs left alone: ones that finished before they could be cancelled, or that
+    /// haven't finished and so can't be purged
+    pub skipped: Vec<String>,
+}
+
+impl TagBulkResponse {
+    fn audit_detail(&self) -> Option<String> {
+        Some(format!("{} jobs, {} skipped", self.jobs.len(), self.skipped.len()))
+    }
+}
+
+/// The jobs carrying `tag` that the caller may act on
+async fn tagged_jobs(data: &AppState, tag: &str, client: Option<&ApiClient>) -> AppResult<Vec<Job>> {
+    data.security_validator.validate_tag(tag)?;
+    // Deleted jobs are finished, so cancelling skips them, and purging removes them for good
+    let filter = JobFilter {
+        client_id: visible_client(client, None),
+        tag: Some(tag.to_string()),
+        include_deleted: true,
+        ..JobFilter::default()
+    };
+    data.job_repository.list_matching_jobs(&filter).await
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(TagQuery),
+    responses(
+        (status = 200, description = "Every unfinished job with the tag cancelled", body = TagBulkResponse),
+        (status = 400, description = "Invalid tag", body = ErrorResponse),
+    )
+)]
+#[post("/jobs/cancel")]
+#[instrument(skip(data, http_request, client))]
+async fn cancel_tagged_jobs(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    query: web::Query<TagQuery>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    let result: AppResult<TagBulkResponse> = async {
+        let jobs = tagged_jobs(&data, &query.tag, client.as_deref()).await?;
+
+        let mut cancelled = Vec::new();
+        let mut skipped = Vec::new();
+        for mut job in jobs.into_iter().filter(|job| !job.status.is_terminal()) {
+            if cancel_active_job(&data, &mut job).await? {
+                cancelled.push(job.id);
+            } else {
+                skipped.push(job.id);
+            }
+        }
+
+        info!("Cancelled {} jobs tagged {} ({} skipped)", cancelled.len(), query.tag, skipped.len());
+        Ok(TagBulkResponse { tag: query.tag.clone(), jobs: cancelled, skipped })
+    }.await;
+
+    audit(&data, &http_request, AuditAction::CancelTaggedJobs, &query.tag, &result, TagBulkResponse::audit_detail).await;
+    result.map(web::Json)
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(TagQuery),
+    responses(
+        (status = 200, description = "Every finished job with the tag deleted, records and files", body = TagBulkResponse),
+        (status = 400, description = "Invalid tag", body = ErrorResponse),
+    )
+)]
+#[post("/jobs/purge")]
+#[instrument(skip(data, http_request, client))]
+async fn purge_tagged_jobs(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    query: web::Query<TagQuery>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    let result: AppResult<TagBulkResponse> = async {
+        let jobs = tagged_jobs(&data, &query.tag, client.as_deref()).await?;
+        let (finished, running): (Vec<Job>, Vec<Job>) = jobs.into_iter().partition(|job| job.status.is_terminal());
+
+        let finished: Vec<String> = finished.into_iter().map(|job| job.id).collect();
+        let purged = data.job_repository.purge_jobs(&finished).await?;
+        for job_id in &purged {
+            if let Err(e) = data.cleanup_service.cleanup_job_files(job_id).await {
+                warn!("Failed to cleanup files for purged job {}: {}", job_id, e);
+            }
+        }
+
+        let skipped = running.into_iter().map(|job| job.id).collect();
+
+        info!("Purged {} jobs tagged {}", purged.len(), query.tag);
+        Ok(TagBulkResponse { tag: query.tag.clone(), jobs: purged, skipped })
+    }.await;
+
+    audit(&data, &http_request, AuditAction::PurgeTaggedJobs, &query.tag, &result, TagBulkResponse::audit_detail).await;
+    result.map(web::Json)
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct JobListQuery {
+    /// Zero-based page number
+    pub page: Option<u32>,
+    /// Jobs per page, 20 by default and at most 100
+    pub page_size: Option<u32>,
+    /// `next_cursor` of the previous page. Switches to cursor pagination, which stays fast on
+    /// deep pages and doesn't skip or repeat jobs when new ones arrive; can't be combined with
+    /// `page`.
+    pub cursor: Option<String>,
+    /// Jobs per cursor page, 20 by default and at most 100. Without `cursor` this starts cursor
+    /// pagination at the newest job.
+    pub limit: Option<u32>,
+}
+
+/// Which jobs `/jobs` and `/jobs/export` return
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct JobFilterQuery {
+    /// Only jobs in these comma-separated statuses: pending, downloading, processing, completed,
+    /// failed, cancelled or expired
+    pub status: Option<String>,
+    /// Only jobs created at or after this time
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only jobs created before this time
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only jobs submitted with this API key name. Non-admin keys only ever see their own jobs.
+    pub client: Option<String>,
+    /// Only jobs carrying this tag
+    pub tag: Option<String>,
+    /// Only jobs submitted with this external id
+    pub external_id: Option<String>,
+    /// Also list deleted jobs that can still be restored, with their `deleted_at`. Admin only;
+    /// ignored for other API keys.
+    pub include_deleted: Option<bool>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobListResponse {
+    pub jobs: Vec<JobResponse>,
+    /// Page numbers, for `page`/`page_size` requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PaginationInfo>,
+    /// Pass as `cursor` to get the next, older jobs; null on the last page. Only set for
+    /// `cursor`/`limit` requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Option<String>>,
+}
+
+/// Position of the last job on a cursor page: base64 of its `created_at` and id
+struct JobCursor {
+    created_at: chrono::DateTime<chrono::Utc>,
+    id: String,
+}
+
+impl JobCursor {
+    fn encode(&self) -> String {
+        let key = format!("{}|{}", self.created_at.to_rfc3339(), self.id);
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key)
+    }
+
+    fn decode(cursor: &str) -> AppResult<Self> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+        let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let key = String::from_utf8(key).map_err(|_| invalid())?;
+        let (created_at, id) = key.split_once('|').ok_or_else(invalid)?;
+        let created_at = chrono::DateTime::parse_from_rfc3339(created_at).map_err(|_| invalid())?;
+        if id.is_empty() {
+            return Err(invalid());
+        }
+        Ok(JobCursor { created_at: created_at.with_timezone(&chrono::Utc), id: id.to_string() })
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct PaginationInfo {
+    pub current_page: u32,
+    pub page_size: u32,
+    pub total_pages: u32,
+    pub total_jobs: usize,
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(JobListQuery, JobFilterQuery),
+    responses(
+        (status = 200, body = JobListResponse),
+        (status = 400, description = "Unknown status filter, invalid time, invalid tag or external id, or invalid cursor", body = ErrorResponse),
+    )
+)]
+#[get("/jobs")]
+#[instrument(skip(data))]
+async fn list_jobs(
+    data: web::Data<Arc<AppState>>,
+    query: web::Query<JobListQuery>,
+    filter_query: web::Query<JobFilterQuery>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    debug!("Listing jobs with query: {:?} {:?}", query, filter_query);
+    
+    let filter = job_filter(&data, &filter_query, client.as_deref())?;
+    if query.cursor.is_some() || query.limit.is_some() {
+        if query.page.is_some() || query.page_size.is_some() {
+            return Err(AppError::BadRequest("Use either cursor and limit or page and page_size, not both".to_string()));
+        }
+        let cursor = query.cursor.as_deref().map(JobCursor::decode).transpose()?;
+        let limit = query.limit.unwrap_or(20).clamp(1, 100);
+        let before = cursor.map(|cursor| (cursor.created_at, cursor.id));
+
+        // One extra job tells whether there is another page
+        let mut jobs = data.job_repository.list_jobs_before(&filter, before.as_ref(), limit + 1).await?;
+        let more = jobs.len() > limit as usize;
+        jobs.truncate(limit as usize);
+        let next_cursor = jobs.last()
+            .filter(|_| more)
+            .map(|last| JobCursor { created_at: last.created_at, id: last.id.clone() }.encode());
+
+        debug!("Returning {} jobs, more: {}", jobs.len(), more);
+        return Ok(web::Json(JobListResponse {
+            jobs: jobs.iter().map(|job| JobResponse::new(job, &data)).collect(),
+            pagination: None,
+            next_cursor: Some(next_cursor),
+        }));
+    }
+
+    // Parse and validate parameters
+    let page = query.page.unwrap_or(0);
+    let page_size = query.page_size.unwrap_or(20).min(100); // Max 100 items per page
+
+    // Get paginated jobs
+    let (jobs, total_pages) = data.job_repository
+        .list_jobs_paginated(page, page_size, &filter)
+        .await?;
+    
+    let job_responses: Vec<JobResponse> = jobs.iter().map(|job| JobResponse::new(job, &data)).collect();
+    
+    let response = JobListResponse {
+        jobs: job_responses,
+        pagination: Some(PaginationInfo {
+            current_page: page,
+            page_size,
+            total_pages,
+            total_jobs: jobs.len(),
+        }),
+        next_cursor: None,
+    };
+    
+    debug!("Returning {} jobs on page {} of {}", jobs.len(), page, total_pages);
+    Ok(web::Json(response))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct ExportQuery {
+    /// `csv` (the default) or `ndjson`
+    pub format: Option<ExportFormat>,
+}
+
+/// Jobs fetched per query while exporting
+const EXPORT_BATCH_SIZE: u32 = 500;
+
+#[utoipa::path(
+    tag = "jobs",
+    params(ExportQuery, JobFilterQuery),
+    responses(
+        (status = 200, description = "Every matching job, oldest first: CSV with a header row, or with format=ndjson one JobResponse per line (application/x-ndjson)",
+         content_type = "text/csv"),
+        (status = 400, description = "Unknown format or status filter, invalid time, or invalid tag or external id", body = ErrorResponse),
+    )
+)]
+#[get("/jobs/export")]
+#[instrument(skip(data))]
+async fn export_jobs(
+    data: web::Data<Arc<AppState>>,
+    query: web::Query<ExportQuery>,
+    filter_query: web::Query<JobFilterQuery>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    let format = query.format.unwrap_or_default();
+    let filter = job_filter(&data, &filter_query, client.as_deref())?;
+    let filename = format!("jobs_{}.{}", chrono::Utc::now().format("%Y%m%dT%H%M%SZ"), format.extension());
+
+    info!("Exporting jobs as {}", filename);
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![actix_web::http::header::DispositionParam::Filename(filename)],
+        })
+        .streaming(stream_job_export(data.get_ref().clone(), filter, format)))
+}
+
+/// The export body, a batch of jobs at a time, so memory use doesn't grow with the number of jobs
+fn stream_job_export(app_state: Arc<AppState>, filter: JobFilter, format: ExportFormat) -> impl Stream<Item = AppResult<web::Bytes>> {
+    let header = stream::iter(format.header().map(|header| Ok(web::Bytes::from(header))));
+    let rows = stream::try_unfold(Some((app_state, filter, None)), move |state| async move {
+        let Some((app_state, filter, after)) = state else {
+            return Ok(None);
+        };
+        let jobs = app_state.job_repository.list_jobs_after(&filter, after.as_ref(), EXPORT_BATCH_SIZE).await?;
+
+        let mut chunk = String::new();
+        for job in &jobs {
+            chunk.push_str(&format.line(&JobResponse::new(job, &app_state))?);
+        }
+
+        // A short batch is the last one
+        let next = match jobs.last() {
+            Some(last) if jobs.len() == EXPORT_BATCH_SIZE as usize => Some((last.created_at, last.id.clone())),
+            _ => None,
+        };
+        let state = next.map(|after| (app_state, filter, Some(after)));
+        Ok(Some((web::Bytes::from(chunk), state)))
+    });
+
+    // The status line has gone out by now, so a failure can only cut the export short
+    header.chain(rows).inspect_err(|e| warn!("Job export ended early: {}", e))
+}
+
+/// Validate the filter parameters shared by the job listings
+fn job_filter(data: &AppState, query: &JobFilterQuery, client: Option<&ApiClient>) -> AppResult<JobFilter> {
+    let statuses = match &query.status {
+        Some(statuses) => statuses.split(',')
+            .map(|status| match status.trim().to_lowercase().as_str() {
+                "pending" => Ok(JobStatus::Pending),
+                "downloading" => Ok(JobStatus::Downloading),
+                "processing" => Ok(JobStatus::Processing),
+                "completed" => Ok(JobStatus::Completed),
+                "failed" => Ok(JobStatus::Failed),
+                "cancelled" => Ok(JobStatus::Cancelled),
+                "expired" => Ok(JobStatus::Expired),
+                _ => Err(AppError::BadRequest(format!("Invalid status filter: {status}"))),
+            })
+            .collect::<AppResult<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+
+    if let Some(tag) = &query.tag {
+        data.security_validator.validate_tag(tag)?;
+    }
+    if let Some(external_id) = &query.external_id {
+        validate_external_id(data, external_id)?;
+    }
+
+    Ok(JobFilter {
+        statuses,
+        client_id: visible_client(client, query.client.clone()),
+        tag: query.tag.clone(),
+        external_id: query.external_id.clone(),
+        include_deleted: query.include_deleted.unwrap_or(false) && client.is_none_or(|client| client.admin),
+        created_after: query.created_after,
+        created_before: query.created_before,
+    })
+}
+
+/// The client whose jobs a caller sees: its own for non-admin keys, otherwise whichever it asked for
+fn visible_client(client: Option<&ApiClient>, requested: Option<String>) -> Option<String> {
+    match client {
+        Some(client) if !client.admin => Some(client.name.clone()),
+        _ => requested,
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ClientJobStats {
+    /// Null for jobs submitted without an API key
+    pub client_id: Option<String>,
+    pub by_status: BTreeMap<String, i64>,
+    /// Pending or running jobs, counted against `max_active_jobs`
+    pub active_jobs: i64,
+    /// Jobs submitted in the last 24 hours, counted against `max_daily_jobs`
+    pub submitted_last_24h: i64,
+    pub max_active_jobs: Option<u32>,
+    pub max_daily_jobs: Option<u32>,
+    /// All-time usage of the key; only shown to admin callers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageTotals>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobStatsResponse {
+    pub by_status: BTreeMap<String, i64>,
+    pub clients: Vec<ClientJobStats>,
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    responses((status = 200, description = "Job counts overall and per client, plus each key's usage for admin callers; non-admin keys see only their own counts", body = JobStatsResponse))
+)]
+#[get("/jobs/stats")]
+#[instrument(skip(data, client))]
+async fn get_job_stats(
+    data: web::Data<Arc<AppState>>,
+    client: Option<web::ReqData<ApiClient>>,
+) -> AppResult<impl Responder> {
+    let client_filter = visible_client(client.as_deref(), None);
+    let since = chrono::Utc::now() - chrono::Duration::hours(CLIENT_QUOTA_WINDOW_HOURS);
+    let counts = data.job_repository.get_client_job_counts(client_filter.as_deref(), since).await?;
+    let config = data.config_reloader.effective_config();
+
+    let new_stats = |client_id: Option<String>| {
+        let api_key = config.security.api_keys.iter()
+            .find(|api_key| Some(&api_key.name) == client_id.as_ref());
+        ClientJobStats {
+            client_id,
+            by_status: BTreeMap::new(),
+            active_jobs: 0,
+            submitted_last_24h: 0,
+            max_active_jobs: api_key.and_then(|api_key| api_key.max_active_jobs),
+            max_daily_jobs: api_key.and_then(|api_key| api_key.max_daily_jobs),
+            usage: None,
+        }
+    };
+
+    let mut by_status: BTreeMap<String, i64> = BTreeMap::new();
+    let mut clients: BTreeMap<Option<String>, ClientJobStats> = BTreeMap::new();
+    for count in counts {
+        *by_status.entry(count.status.clone()).or_default() += count.jobs;
+        let stats = clients.entry(count.client_id.clone()).or_insert_with(|| new_stats(count.client_id.clone()));
+        if JobStatus::from_name(&count.status).is_some_and(|status| !status.is_terminal()) {
+            stats.active_jobs += count.jobs;
+        }
+        stats.submitted_last_24h += count.recent;
+        stats.by_status.insert(count.status, count.jobs);
+    }
+
+    // Usage outlives the job rows retention deletes, so keys may appear here with no jobs left
+    if client.as_deref().is_none_or(|client| client.admin) {
+        for totals in data.job_repository.get_usage_totals(client_filter.as_deref()).await? {
+            let client_id = Some(totals.client_id);
+            clients.entry(client_id.clone()).or_insert_with(|| new_stats(client_id)).usage = Some(totals.usage);
+        }
+    }
+
+    Ok(web::Json(JobStatsResponse {
+        by_status,
+        clients: clients.into_values().collect(),
+    }))
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub queue: QueueStats,
+    pub storage: StorageUsage,
+    pub slots: SlotStats,
+}
+
+/// Download and processing slots taken right now; jobs beyond them wait for one
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SlotStats {
+    pub download: PoolStats,
+    pub processing: PoolStats,
+}
+
+#[utoipa::path(
+    tag = "server",
+    responses((status = 200, body = StatsResponse))
+)]
+#[get("/queue/stats")]
+async fn get_queue_stats(data: web::Data<Arc<AppState>>) -> AppResult<impl Responder> {
+    Ok(web::Json(StatsResponse {
+        queue: data.job_queue.get_queue_stats().await,
+        storage: data.storage_quota.usage(),
+        slots: SlotStats {
+            download: data.pool_manager.get_download_stats(),
+            processing: data.pool_manager.get_processing_stats(),
+        },
+    }))
+}
+
+/// What clients may submit, built field by field so settings added to `Config` later
+/// (secrets, paths, credentials) are never exposed without being listed here
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ConfigResponse {
+    pub version: &'static str,
+    pub allowed_domains: Vec<String>,
+    pub limits: ConfigLimits,
+    pub processing: ProcessingDefaults,
+    pub retention: RetentionSettings,
+    /// Optional features enabled on this server
+    pub capabilities: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ConfigLimits {
+    pub max_file_size_mb: u64,
+    pub max_url_length: usize,
+    pub max_queue_size: usize,
+    pub max_playlist_items: usize,
+    /// Longest live stream recorded; null when live streams are rejected
+    pub max_live_duration_secs: Option<u64>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ProcessingDefaults {
+    pub output_format: &'static str,
+    pub video_codec: String,
+    pub audio_codec: String,
+    pub preset: String,
+    pub crf: u32,
+    pub audio_bitrate: String,
+    pub max_fps: Option<u32>,
+    pub presets: Vec<&'static str>,
+    pub upload_extensions: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RetentionSettings {
+    pub enabled: bool,
+    pub file_retention_days: u32,
+    pub record_retention_days: u32,
+    pub max_retain_days: u32,
+}
+
+impl ConfigResponse {
+    fn from_config(config: &Config) -> Self {
+        let capabilities = [
+            ("uploads", true),
+            ("playlists", config.download.max_playlist_items > 0),
+            ("live_streams", config.download.allow_live_streams),
+            ("direct_http_downloads", matches!(config.download.backend, DownloaderBackend::Auto)),
+            ("auth_profiles", !config.download.auth_profiles.is_empty()),
+            ("transcoding", matches!(config.processing.backend, ProcessorBackend::Ffmpeg)),
+            ("watermark", config.processing.watermark.is_some()),
+            ("result_cache", config.processing.result_cache_enabled),
+            ("retention", config.retention.enabled),
+            ("lru_eviction", config.retention.mode == RetentionMode::Lru),
+            ("storage_quota", config.storage.max_storage_bytes.is_some()),
+        ];
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            allowed_domains: config.download.allowed_domains.clone(),
+            limits: ConfigLimits {
+                max_file_size_mb: config.security.max_file_size_mb,
+                max_url_length: config.security.max_url_length,
+                max_queue_size: config.queue.max_queue_size,
+                max_playlist_items: config.download.max_playlist_items,
+                max_live_duration_secs: config.download.allow_live_streams
+                    .then_some(config.download.max_live_duration.as_secs()),
+            },
+            processing: ProcessingDefaults {
+                output_format: "mp4",
+                video_codec: config.processing.video_codec.clone(),
+                audio_codec: config.processing.audio_codec.clone(),
+                preset: config.processing.preset.clone(),
+                crf: config.processing.crf,
+                audio_bitrate: config.processing.audio_bitrate.clone(),
+                max_fps: config.processing.max_fps,
+                presets: FFMPEG_PRESETS.to_vec(),
+                upload_extensions: UPLOAD_EXTENSIONS.to_vec(),
+            },
+            retention: RetentionSettings {
+                enabled: config.retention.enabled,
+                file_retention_days: config.retention.file_retention_days,
+                record_retention_days: config.retention.record_retention_days,
+                max_retain_days: config.retention.max_retain_days,
+            },
+            capabilities: capabilities.into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "server",
+    responses((status = 200, body = ConfigResponse))
+)]
+#[get("/config")]
+async fn get_config(data: web::Data<Arc<AppState>>) -> AppResult<impl Responder> {
+    Ok(web::Json(ConfigResponse::from_config(&data.config_reloader.effective_config())))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct DryRunQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct StorageReportQuery {
+    /// How many of the largest jobs to list
+    pub top: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Default, ToSchema)]
+pub struct StatusUsage {
+    pub jobs: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobUsage {
+    pub job_id: String,
+    /// `Unknown` for files whose job no longer has a row
+    pub status: String,
+    pub bytes: u64,
+    pub age_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct StorageReport {
+    /// When the sizes were scanned; they lag by up to APERIO_STORAGE_RECONCILE_INTERVAL
+    pub scanned_at: Option<String>,
+    pub working_dir_bytes: u64,
+    pub storage_dir_bytes: u64,
+    pub by_status: BTreeMap<String, StatusUsage>,
+    pub largest_jobs: Vec<JobUsage>,
+    pub quota: StorageUsage,
+    pub eviction_high_water_bytes: Option<u64>,
+    pub eviction_target_bytes: Option<u64>,
+}
+
+const DEFAULT_STORAGE_REPORT_TOP: usize = 10;
+const MAX_STORAGE_REPORT_TOP: usize = 100;
+
+#[utoipa::path(
+    tag = "admin",
+    params(StorageReportQuery),
+    security((), ("admin_auth" = [])),
+    responses((status = 200, body = StorageReport))
+)]
+#[get("/admin/storage")]
+#[instrument(skip(data))]
+async fn get_storage_report(
+    data: web::Data<Arc<AppState>>,
+    query: web::Query<StorageReportQuery>,
+) -> AppResult<impl Responder> {
+    let top = query.top.unwrap_or(DEFAULT_STORAGE_REPORT_TOP).min(MAX_STORAGE_REPORT_TOP);
+    let snapshot = data.storage_quota.snapshot();
+    let dir_bytes = |dir: &Path| snapshot.dir_bytes.iter()
+        .find(|(path, _)| path == dir)
+        .map(|(_, bytes)| *bytes)
+        .unwrap_or_default();
+
+    let job_ids: Vec<String> = snapshot.job_bytes.keys().cloned().collect();
+    let summaries: HashMap<String, _> = data.job_repository.get_status_summaries(&job_ids).await?
+        .into_iter()
+        .map(|summary| (summary.id.clone(), summary))
+        .collect();
+
+    let now = chrono::Utc::now();
+    let mut by_status: BTreeMap<String, StatusUsage> = BTreeMap::new();
+    let mut jobs: Vec<JobUsage> = snapshot.job_bytes.iter()
+        .map(|(job_id, bytes)| {
+            let summary = summaries.get(job_id);
+            let status = summary.map(|summary| summary.status.clone()).unwrap_or_else(|| "Unknown".to_string());
+            let usage = by_status.entry(status.clone()).or_default();
+            usage.jobs += 1;
+            usage.bytes += bytes;
+            JobUsage {
+                job_id: job_id.clone(),
+                status,
+                bytes: *bytes,
+                age_seconds: summary.map(|summary| (now - summary.updated_at).num_seconds()),
+            }
+        })
+        .collect();
+    jobs.sort_unstable_by_key(|job| std::cmp::Reverse(job.bytes));
+    jobs.truncate(top);
+
+    let eviction = data.retention_service.eviction_policy();
+    Ok(web::Json(StorageReport {
+        scanned_at: snapshot.scanned_at.map(|at| at.to_rfc3339()),
+        working_dir_bytes: dir_bytes(&data.working_dir),
+        storage_dir_bytes: dir_bytes(&data.storage_service.root()),
+        by_status,
+        largest_jobs: jobs,
+        quota: data.storage_quota.usage(),
+        eviction_high_water_bytes: eviction.map(|policy| policy.high_water_bytes),
+        eviction_target_bytes: eviction.map(|policy| policy.target_bytes),
+    }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    params(DryRunQuery),
+    security((), ("admin_auth" = [])),
+    responses((status = 200, body = CleanupSummary))
+)]
+#[post("/admin/retention/run")]
+#[instrument(skip(data, http_request))]
+async fn run_retention(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    query: web::Query<DryRunQuery>,
+) -> AppResult<impl Responder> {
+    info!("Retention cycle requested (dry run: {})", query.dry_run);
+    let result = data.retention_service.run_cleanup_now(query.dry_run).await;
+    // A dry run changes nothing, so only real runs are audited
+    if !query.dry_run {
+        audit(&data, &http_request, AuditAction::RunRetention, "retention", &result, |summary| Some(format!(
+            "{} records deleted, files of {} jobs expired, {} bytes freed",
+            summary.records_deleted, summary.files_expired_jobs, summary.bytes_freed
+        ))).await;
+    }
+    result.map(web::Json)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    params(DryRunQuery),
+    security((), ("admin_auth" = [])),
+    responses((status = 200, body = OrphanSweepReport))
+)]
+#[post("/admin/cleanup/orphans")]
+#[instrument(skip(data, http_request))]
+async fn sweep_orphans(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    query: web::Query<DryRunQuery>,
+) -> AppResult<impl Responder> {
+    info!("Orphan sweep requested (dry run: {})", query.dry_run);
+    let result = data.retention_service.sweep_orphans_now(query.dry_run).await;
+    if !query.dry_run {
+        audit(&data, &http_request, AuditAction::SweepOrphans, "orphans", &result, |report| Some(format!(
+            "{} files removed, {} bytes freed", report.files.len(), report.bytes
+        ))).await;
+    }
+    result.map(web::Json)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses(
+        (status = 200, body = MaintenanceReport),
+        (status = 409, description = "A maintenance run is already in progress", body = ErrorResponse),
+    )
+)]
+#[post("/admin/db/maintenance")]
+#[instrument(skip(data, http_request))]
+async fn run_db_maintenance(data: web::Data<Arc<AppState>>, http_request: HttpRequest) -> AppResult<impl Responder> {
+    info!("Database maintenance requested");
+    let result = data.db_maintenance.run_now().await;
+    audit(&data, &http_request, AuditAction::RunDbMaintenance, "database", &result, |report| Some(match &report.skipped {
+        Some(reason) => format!("skipped: {reason}"),
+        None => format!("{} bytes reclaimed in {} ms", report.bytes_reclaimed, report.duration_ms),
+    })).await;
+    result.map(web::Json)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses((status = 200, description = "Counters and histograms cleared; gauges keep their values", body = MetricsReset))
+)]
+#[post("/admin/metrics/reset")]
+#[instrument(skip(data, http_request))]
+async fn reset_metrics(data: web::Data<Arc<AppState>>, http_request: HttpRequest) -> AppResult<impl Responder> {
+    let reset = get_metrics().reset().await;
+    info!("Metrics reset: {} counter and {} histogram series cleared", reset.counters_cleared, reset.histograms_cleared);
+    // Otherwise a restart before the next scheduled save would bring the old totals back
+    let result = match &data.metrics_snapshots {
+        Some(store) => store.save().await.map(|()| reset),
+        None => Ok(reset),
+    };
+    audit(&data, &http_request, AuditAction::ResetMetrics, "metrics", &result, |reset| Some(format!(
+        "{} counter and {} histogram series cleared", reset.counters_cleared, reset.histograms_cleared
+    ))).await;
+    result.map(web::Json)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses(
+        (status = 200, body = ConfigReloadReport),
+        (status = 422, description = "The new config is invalid; the running config is kept", body = ErrorResponse),
+    )
+)]
+#[post("/admin/config/reload")]
+#[instrument(skip(data, http_request))]
+async fn reload_config(data: web::Data<Arc<AppState>>, http_request: HttpRequest) -> AppResult<impl Responder> {
+    info!("Configuration reload requested");
+    let result = data.config_reloader.reload();
+    audit(&data, &http_request, AuditAction::ReloadConfig, "config", &result, |report| {
+        let applied: Vec<&str> = report.changes.iter()
+            .filter(|change| change.applied)
+            .map(|change| change.key.as_str())
+            .collect();
+        Some(format!("applied: [{}]; restart required: [{}]", applied.join(", "), report.restart_required.join(", ")))
+    }).await;
+    result.map(web::Json)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses((status = 200, description = "Draining: new jobs get 503 and /health/ready reports not ready, while queued and running jobs finish", body = DrainStatus))
+)]
+#[post("/admin/drain")]
+#[instrument(skip(data, http_request))]
+async fn start_drain(data: web::Data<Arc<AppState>>, http_request: HttpRequest) -> AppResult<impl Responder> {
+    let status = data.job_queue.start_draining().await;
+    let detail = format!("{} queued, {} active", status.queued_jobs, status.active_jobs);
+    data.audit_log.record(&audit_actor(&http_request), AuditAction::StartDrain, "server", Ok(Some(detail))).await;
+    Ok(web::Json(status))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses((status = 200, description = "No longer draining; new jobs are accepted again", body = DrainStatus))
+)]
+#[post("/admin/drain/cancel")]
+#[instrument(skip(data, http_request))]
+async fn cancel_drain(data: web::Data<Arc<AppState>>, http_request: HttpRequest) -> AppResult<impl Responder> {
+    let status = data.job_queue.stop_draining().await;
+    let detail = format!("{} queued, {} active", status.queued_jobs, status.active_jobs);
+    data.audit_log.record(&audit_actor(&http_request), AuditAction::CancelDrain, "server", Ok(Some(detail))).await;
+    Ok(web::Json(status))
+}
+
+/// Longest period one usage request may cover
+const MAX_USAGE_DAYS: i64 = 366;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct UsageQuery {
+    /// First day included (UTC, `YYYY-MM-DD`); defaults to 29 days before `to`
+    pub from: Option<chrono::NaiveDate>,
+    /// Last day included (UTC, `YYYY-MM-DD`); defaults to today
+    pub to: Option<chrono::NaiveDate>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct KeyUsageResponse {
+    pub client_id: String,
+    pub from: chrono::NaiveDate,
+    pub to: chrono::NaiveDate,
+    /// Sum of `days`
+    pub total: UsageTotals,
+    /// Days with any usage, oldest first
+    pub days: Vec<DailyUsage>,
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    params(("id" = String, Path, description = "API key name"), UsageQuery),
+    responses(
+        (status = 200, description = "Daily usage of the key, for chargeback", body = KeyUsageResponse),
+        (status = 400, description = "Invalid key name or date range", body = ErrorResponse),
+    )
+)]
+#[get("/admin/keys/{id}/usage")]
+#[instrument(skip(data))]
+async fn get_key_usage(
+    data: web::Data<Arc<AppState>>,
+    id: web::Path<String>,
+    query: web::Query<UsageQuery>,
+) -> AppResult<impl Responder> {
+    data.security_validator.validate_input(id.as_str(), "key name", 100)?;
+
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(29));
+    if from > to {
+        return Err(AppError::BadRequest("from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= MAX_USAGE_DAYS {
+        return Err(AppError::BadRequest(format!("A usage request covers at most {MAX_USAGE_DAYS} days")));
+    }
+
+    let days = data.job_repository.get_key_usage(id.as_str(), from, to).await?;
+    let total = days.iter().fold(UsageTotals::default(), |mut total, day| {
+        total.jobs_submitted += day.usage.jobs_submitted;
+        total.jobs_completed += day.usage.jobs_completed;
+        total.bytes_downloaded += day.usage.bytes_downloaded;
+        total.bytes_output += day.usage.bytes_output;
+        total.processing_seconds += day.usage.processing_seconds;
+        total
+    });
+
+    Ok(web::Json(KeyUsageResponse { client_id: id.into_inner(), from, to, total, days }))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct SubscriptionRequest {
+    /// HTTPS RSS or Atom feed on an allowed domain
+    pub feed_url: String,
+    pub options: Option<ProcessingOptions>,
+    /// `high`, `normal` or `low`
+    pub priority: Option<String>,
+    /// Added to every job besides `subscription:{id}`; at most 9
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub enabled: Option<bool>,
+}
 
-    if cancelled {
-        // Update job status in database
-        job.update_status(JobStatus::Cancelled);
-        job.set_error("Job cancelled by user".to_string());
-        
-        if let Err(e) = data.job_repository.update_job(&job).await {
-            warn!("Failed to update cancelled job status in database: {}", e);
+/// Check the options, priority and tags a subscription submits its jobs with
+fn validate_subscription_fields(
+    data: &AppState,
+    options: Option<&ProcessingOptions>,
+    priority: Option<&str>,
+    tags: Option<&[String]>,
+) -> AppResult<Option<Vec<String>>> {
+    if let Some(options) = options {
+        data.security_validator.validate_processing_options(options)?;
+        data.download_service.validate_auth_profile(options.auth_profile.as_deref())?;
+    }
+    if let Some(priority) = priority {
+        if !matches!(priority, "high" | "normal" | "low") {
+            return Err(AppError::BadRequest(format!("priority must be high, normal or low, got {priority}")));
         }
+    }
+    let Some(tags) = tags else { return Ok(None) };
+    // One tag is reserved for subscription:{id}
+    if tags.len() >= MAX_JOB_TAGS {
+        return Err(AppError::BadRequest(format!("A subscription takes at most {} tags", MAX_JOB_TAGS - 1)));
+    }
+    data.security_validator.validate_tags(tags).map(Some)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses((status = 200, description = "Every subscription, oldest first", body = Vec<Subscription>))
+)]
+#[get("/admin/subscriptions")]
+#[instrument(skip(data))]
+async fn list_subscriptions(data: web::Data<Arc<AppState>>) -> AppResult<impl Responder> {
+    Ok(web::Json(data.subscriptions.list().await?))
+}
 
-        // Clean up any temporary files
-        if let Err(e) = data.cleanup_service.cleanup_job_files(job_id.as_str()).await {
-            warn!("Failed to cleanup files for cancelled job {}: {}", job_id, e);
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    responses(
+        (status = 201, description = "Subscribed; the first poll records where the feed stands without submitting its existing entries", body = Subscription),
+        (status = 400, description = "Feed URL not allowed, or bad options, priority or tags", body = ErrorResponse),
+        (status = 409, description = "The feed already has a subscription", body = ErrorResponse),
+    )
+)]
+#[post("/admin/subscriptions")]
+#[instrument(skip(data, http_request, request), fields(feed_url = %redact_text(&request.feed_url)))]
+async fn create_subscription(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    request: web::Json<SubscriptionRequest>,
+) -> AppResult<impl Responder> {
+    let result = async {
+        let feed_url = data.security_validator.validate_url(&request.feed_url)?;
+        let tags = validate_subscription_fields(&data, request.options.as_ref(), request.priority.as_deref(), Some(&request.tags))?;
+        data.subscriptions.create(NewSubscription {
+            feed_url: feed_url.to_string(),
+            options: request.options.clone().unwrap_or_default(),
+            priority: request.priority.clone(),
+            tags: tags.unwrap_or_default(),
+            enabled: request.enabled.unwrap_or(true),
+        }).await
+    }.await;
+    let target = result.as_ref().map(|subscription| subscription.id.clone()).unwrap_or_else(|_| redact_text(&request.feed_url));
+    audit(&data, &http_request, AuditAction::CreateSubscription, &target, &result, |subscription| Some(redact_text(&subscription.feed_url))).await;
+    Ok(HttpResponse::Created().json(result?))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    params(("id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "The subscription, with its poll status", body = Subscription),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[get("/admin/subscriptions/{id}")]
+#[instrument(skip(data))]
+async fn get_subscription(data: web::Data<Arc<AppState>>, id: web::Path<String>) -> AppResult<impl Responder> {
+    let subscription = data.subscriptions.get(&id).await?
+        .ok_or_else(|| AppError::NotFound(format!("Subscription not found: {id}")))?;
+    Ok(web::Json(subscription))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    params(("id" = String, Path, description = "Subscription id")),
+    request_body = SubscriptionUpdate,
+    responses(
+        (status = 200, body = Subscription),
+        (status = 400, description = "Bad options, priority or tags", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[patch("/admin/subscriptions/{id}")]
+#[instrument(skip(data, http_request, request))]
+async fn update_subscription(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    id: web::Path<String>,
+    request: web::Json<SubscriptionUpdate>,
+) -> AppResult<impl Responder> {
+    let result = async {
+        let mut update = request.into_inner();
+        if let Some(tags) = validate_subscription_fields(&data, update.options.as_ref(), update.priority.as_deref(), update.tags.as_deref())? {
+            update.tags = Some(tags);
         }
+        data.subscriptions.update(&id, update).await?
+            .ok_or_else(|| AppError::NotFound(format!("Subscription not found: {id}")))
+    }.await;
+    audit(&data, &http_request, AuditAction::UpdateSubscription, &id, &result, |_| None).await;
+    result.map(web::Json)
+}
 
-        info!("Successfully cancelled job: {}", job_id);
-        Ok(web::Json(serde_json::json!({
-            "message": "Job cancelled successfully",
-            "job_id": job_id.as_str()
-        })))
-    } else {
-        warn!("Job {} not found in queue or active jobs, may have already completed", job_id);
-        Err(AppError::BadRequest("Job cannot be cancelled (may have already completed)".to_string()))
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    params(("id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 204, description = "Unsubscribed; jobs it already submitted are kept"),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+#[delete("/admin/subscriptions/{id}")]
+#[instrument(skip(data, http_request))]
+async fn delete_subscription(
+    data: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+    id: web::Path<String>,
+) -> AppResult<impl Responder> {
+    let result = match data.subscriptions.delete(&id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AppError::NotFound(format!("Subscription not found: {id}"))),
+        Err(e) => Err(e),
+    };
+    audit(&data, &http_request, AuditAction::DeleteSubscription, &id, &result, |_| None).await;
+    result.map(|()| HttpResponse::NoContent().finish())
+}
+
+/// The id the request tracking middleware gave a request and logs it under
+fn correlation_id(request: &HttpRequest) -> Option<String> {
+    request.extensions().get::<String>().cloned()
+}
+
+/// The key name behind a request, or `anonymous`, with its correlation id and client address
+fn audit_actor(request: &HttpRequest) -> AuditActor {
+    let extensions = request.extensions();
+    AuditActor {
+        actor: extensions.get::<ApiClient>()
+            .map(|client| client.name.clone())
+            .unwrap_or_else(|| "anonymous".to_string()),
+        correlation_id: correlation_id(request),
+        client_ip: extensions.get::<ClientIp>().map(|ClientIp(ip)| ip.to_string()),
     }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct JobListQuery {
+/// Record an action's outcome in the audit log; `detail` describes what a successful action did
+async fn audit<T>(
+    data: &AppState,
+    request: &HttpRequest,
+    action: AuditAction,
+    target: &str,
+    result: &AppResult<T>,
+    detail: impl FnOnce(&T) -> Option<String>,
+) {
+    let outcome = match result {
+        Ok(value) => Ok(detail(value)),
+        Err(e) => Err(e.to_string()),
+    };
+    data.audit_log.record(&audit_actor(request), action, target, outcome).await;
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct AuditQuery {
+    /// Only entries recorded at or after this time (RFC 3339)
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only entries for this action
+    pub action: Option<AuditAction>,
+    /// Zero-based page number
     pub page: Option<u32>,
+    /// Entries per page, 50 by default and at most 100
     pub page_size: Option<u32>,
-    pub status: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
-pub struct JobListResponse {
-    pub jobs: Vec<JobResponse>,
-    pub pagination: PaginationInfo,
+#[derive(Serialize, Debug, ToSchema)]
+pub struct AuditLogResponse {
+    /// Newest first
+    pub entries: Vec<AuditEntry>,
+    pub pagination: AuditPagination,
 }
 
-#[derive(Serialize, Debug)]
-pub struct PaginationInfo {
+#[derive(Serialize, Debug, ToSchema)]
+pub struct AuditPagination {
     pub current_page: u32,
     pub page_size: u32,
     pub total_pages: u32,
-    pub total_jobs: usize,
+    pub total_entries: i64,
 }
 
-#[get("/jobs")]
+#[utoipa::path(
+    tag = "admin",
+    security((), ("admin_auth" = [])),
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Who cancelled, purged, drained or reconfigured what, and when", body = AuditLogResponse),
+        (status = 400, description = "Invalid since time or unknown action", body = ErrorResponse),
+    )
+)]
+#[get("/admin/audit")]
 #[instrument(skip(data))]
-async fn list_jobs(
+async fn list_audit_log(
     data: web::Data<Arc<AppState>>,
-    query: web::Query<JobListQuery>,
+    query: web::Query<AuditQuery>,
 ) -> AppResult<impl Responder> {
-    debug!("Listing jobs with query: {:?}", query);
-    
-    // Parse and validate parameters
     let page = query.page.unwrap_or(0);
-    let page_size = query.page_size.unwrap_or(20).min(100); // Max 100 items per page
-    
-    // Parse status filter if provided
-    let status_filter = if let Some(status_str) = &query.status {
-        match status_str.to_lowercase().as_str() {
-            "pending" => Some(JobStatus::Pending),
-            "downloading" => Some(JobStatus::Downloading),
-            "processing" => Some(JobStatus::Processing),
-            "completed" => Some(JobStatus::Completed),
-            "failed" => Some(JobStatus::Failed),
-            "cancelled" => Some(JobStatus::Cancelled),
-            _ => return Err(AppError::BadRequest(format!("Invalid status filter: {status_str}"))),
-        }
-    } else {
-        None
-    };
-    
-    // Get paginated jobs
-    let (jobs, total_pages) = data.job_repository
-        .list_jobs_paginated(page, page_size, status_filter)
-        .await?;
-    
-    let job_responses: Vec<JobResponse> = jobs.iter().map(JobResponse::from).collect();
-    
-    let response = JobListResponse {
-        jobs: job_responses,
-        pagination: PaginationInfo {
-            current_page: page,
-            page_size,
-            total_pages,
-            total_jobs: jobs.len(),
-        },
-    };
-    
-    debug!("Returning {} jobs on page {} of {}", jobs.len(), page, total_pages);
-    Ok(web::Json(response))
+    let page_size = query.page_size.unwrap_or(50).clamp(1, 100);
+
+    let (entries, total) = data.audit_log.list(query.since, query.action, page, page_size).await?;
+    let total_pages = (total as u64).div_ceil(page_size as u64) as u32;
+
+    Ok(web::Json(AuditLogResponse {
+        entries,
+        pagination: AuditPagination { current_page: page, page_size, total_pages, total_entries: total },
+    }))
 }
 
-#[instrument(skip(app_state), fields(job_id = %job_id))]
-pub async fn process_job(job_id: &str, app_state: Arc<AppState>) {
-    let job_start_time = std::time::Instant::now();
+/// First stage of a job's run: fetch its source, then hand it to the processing queue. Jobs
+/// whose source is already in place, like uploads and reprocessing, go straight through.
+#[instrument(skip(app_state), fields(job_id = %job_id, correlation_id = tracing::field::Empty))]
+pub async fn download_job(job_id: &str, app_state: Arc<AppState>) -> StageOutcome {
     counter_inc!("aperio_jobs_processing_total");
     gauge_set!("aperio_jobs_active", 1.0);
     info!("Starting processing for job: {}", job_id);
-    
-    let cleanup_on_exit = {
-        let job_id = job_id.to_string();
-        let app_state = app_state.clone();
-        move || async move {
-            if let Err(e) = app_state.cleanup_service.cleanup_job_files(&job_id).await {
-                warn!("Failed to cleanup files for job {}: {}", job_id, e);
+
+    let Some(mut job) = claim_job(job_id, &app_state).await else {
+        gauge_set!("aperio_jobs_active", 0.0);
+        return StageOutcome::Done;
+    };
+
+    // Re-processed jobs borrow their parent's original, so hold it until the processing stage is done
+    let borrowed_source = borrowed_source(&job);
+    if let Some(source) = &borrowed_source {
+        app_state.cleanup_service.acquire_source(source, job_id).await;
+    }
+
+    // Jobs created from an upload already have their source file in place, as does a concat job
+    // whose sources were all fetched by an earlier run
+    let existing_source = match job.concat_inputs() {
+        Some(inputs) => inputs.into_iter().next(),
+        None => job.get_downloaded_path().filter(|path| path.exists()),
+    };
+
+    // Don't spend a download slot on a domain that is still rate-limiting us
+    if existing_source.is_none() {
+        for domain in pending_domains(&job) {
+            if let Some(until) = app_state.job_queue.domain_cooldown(&domain).await {
+                info!("Domain {} is cooling down, deferring job {} until {}", domain, job_id, until);
+                job_log(&app_state, job_id, JobLogLevel::Info, format!("{domain} is cooling down after rate limiting; deferred until {until}")).await;
+                gauge_set!("aperio_jobs_active", 0.0);
+                release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+                return defer_job(job, &app_state, until).await;
             }
         }
-    };
+    }
 
-    // Get the job from database with retry
-    let mut job = match retry_with_backoff(
-        || app_state.job_repository.get_job(job_id),
-        &RetryConfig::default(),
-        "database_get_job"
-    ).await {
-        Ok(Some(job)) => job,
-        Ok(None) => {
-            error!("Job not found: {}", job_id);
-            counter_inc!("aperio_job_errors_total", "error_type" => "job_not_found");
-            gauge_set!("aperio_jobs_active", 0.0);
-            return;
+    let download_result = match existing_source {
+        Some(path) => {
+            info!("Source file already present for job {}, skipping download phase", job_id);
+            job_log(&app_state, job_id, JobLogLevel::Info, "Source file already present, skipping download").await;
+            Ok(path)
         }
-        Err(e) => {
-            error!("Failed to get job {} after retries: {}", job_id, e);
-            counter_inc!("aperio_job_errors_total", "error_type" => "database_error");
-            gauge_set!("aperio_jobs_active", 0.0);
-            return;
+        None if job.is_concat() => {
+            info!("Starting download phase for the {} sources of job: {}", job.sources.len(), job_id);
+            download_sources(&mut job, &app_state).await
+        }
+        None => {
+            // Download phase with retry and cleanup
+            info!("Starting download phase for job: {}", job_id);
+            download_with_retry(&mut job, &app_state).await
         }
     };
 
-    let start_time = std::time::Instant::now();
-
-    // Download phase with retry and cleanup
-    info!("Starting download phase for job: {}", job_id);
-    
-    // Update status to Downloading and save to database
-    job.update_status(JobStatus::Downloading);
-    if let Err(e) = update_job_with_retry(&job, &app_state).await {
-        warn!("Failed to update job status to Downloading: {}", e);
-    }
-    
-    let downloaded_path = match download_with_retry(&mut job, &app_state).await {
+    match download_result {
         Ok(path) => {
             info!("Download completed for job {}: {:?}", job_id, path);
-            path
+        }
+        Err(e @ AppError::Conflict(_)) => {
+            return abandon_run(&app_state, job_id, &borrowed_source, &e).await;
+        }
+        Err(e) if classify_error(&e) == FailureReason::RateLimited
+            && job.metadata.rate_limit_deferrals < app_state.download_service.max_rate_limit_deferrals() => {
+            // Back off from the whole domain and retry this job later instead of failing it
+            let delay = app_state.download_service.rate_limit_delay(&e.to_string());
+            let until = chrono::Utc::now() + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::minutes(5));
+            let source_domain = url_domain(job.current_source().map_or(&job.url, |source| &source.url));
+            let domain = source_domain.as_deref().unwrap_or("unknown");
+            warn!("Job {} was rate limited by {}, retrying after {:?}", job_id, domain, delay);
+            job_log(&app_state, job_id, JobLogLevel::Warn, format!("Rate limited by {domain}; retrying after {}s: {e}", delay.as_secs())).await;
+            counter_inc!("aperio_rate_limited_total", "domain" => domain);
+            app_state.job_queue.cool_down_domain(domain, until).await;
+
+            job.metadata.rate_limit_deferrals += 1;
+            gauge_set!("aperio_jobs_active", 0.0);
+            release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+            cleanup_job_files(&app_state, job_id).await;
+            return defer_job(job, &app_state, until).await;
+        }
+        Err(e @ AppError::NoCapacity { .. }) => {
+            gauge_set!("aperio_jobs_active", 0.0);
+            release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+            return requeue_without_slot(job, &app_state, &e).await;
         }
         Err(e) => {
-            error!("Download failed for job {}: {}", job_id, e);
-            job.set_error(e.to_string());
-            let _ = update_job_with_retry(&job, &app_state).await;
+            error!("Download failed for job {}: {}", job_id, app_state.error_sanitizer.summarize(&e.to_string()));
+            let reason = record_failure(&mut job, &e, &app_state).await;
+            // Name the source that broke, so the request can be fixed
+            if let (Some(number), Some(source)) = (job.metadata.current_source, job.current_source()) {
+                let source = format!("Source {number} of {} ({})", job.sources.len(), redact_text(&source.url));
+                job.error_message = job.error_message.take().map(|message| format!("{source} failed: {message}"));
+            }
+            save_failure(&job, reason, &app_state).await;
             counter_inc!("aperio_jobs_failed_total", "phase" => "download");
             gauge_set!("aperio_jobs_active", 0.0);
-            cleanup_on_exit().await;
-            return;
+            release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+            cleanup_job_files(&app_state, job_id).await;
+            return StageOutcome::Done;
         }
+    }
+
+    // Pending with its source in place until a processing slot frees up; a restart picks it up
+    // again without downloading twice
+    job.update_status(JobStatus::Pending);
+    if let Err(e) = save_progress(&job, &app_state).await {
+        return abandon_run(&app_state, job_id, &borrowed_source, &e).await;
+    }
+    StageOutcome::ReadyForProcessing(job)
+}
+
+/// Second stage of a job's run: encode its downloaded source and store the outputs
+#[instrument(skip(app_state), fields(job_id = %job_id, correlation_id = tracing::field::Empty))]
+pub async fn process_job(job_id: &str, app_state: Arc<AppState>) -> StageOutcome {
+    let Some(mut job) = claim_job(job_id, &app_state).await else {
+        gauge_set!("aperio_jobs_active", 0.0);
+        return StageOutcome::Done;
+    };
+
+    let start_time = std::time::Instant::now();
+    let borrowed_source = borrowed_source(&job);
+    // A concat job is processed from its sources, which the encode joins
+    let concat_inputs = job.concat_inputs();
+    let source_path = match &concat_inputs {
+        Some(inputs) => inputs.first().cloned(),
+        None if job.is_concat() => None,
+        None => job.get_downloaded_path(),
+    };
+    let Some(downloaded_path) = source_path else {
+        let e = AppError::Internal("job reached processing without a source file".to_string());
+        error!("Processing failed for job {}: {}", job_id, e);
+        let reason = record_failure(&mut job, &e, &app_state).await;
+        save_failure(&job, reason, &app_state).await;
+        counter_inc!("aperio_jobs_failed_total", "phase" => "processing");
+        gauge_set!("aperio_jobs_active", 0.0);
+        cleanup_job_files(&app_state, job_id).await;
+        return StageOutcome::Done;
     };
 
+    // Encode time follows the source's length, which estimates use once it is known
+    if job.metadata.source_duration_seconds.is_none() {
+        let mut duration = Some(0.0);
+        for input in concat_inputs.unwrap_or_else(|| vec![downloaded_path.clone()]) {
+            let probed = app_state.process_service.probe_duration(&input).await.ok();
+            duration = duration.zip(probed).map(|(total, probed)| total + probed);
+        }
+        job.metadata.source_duration_seconds = duration;
+    }
+
     // Processing phase with retry and cleanup
     info!("Starting processing phase for job: {}", job_id);
-    
-    // Update status to Processing and save to database
-    job.update_status(JobStatus::Processing);
-    if let Err(e) = update_job_with_retry(&job, &app_state).await {
-        warn!("Failed to update job status to Processing: {}", e);
-    }
-    
-    let _processed_path = match process_with_retry(&mut job, &downloaded_path, &app_state).await {
+    let processed_path = match process_with_retry(&mut job, &downloaded_path, &app_state).await {
         Ok(path) => {
             info!("Processing completed for job {}: {:?}", job_id, path);
+            if let Some(threads) = job.metadata.ffmpeg_threads {
+                let nice = job.metadata.ffmpeg_nice.map(|nice| format!(" at nice {nice}")).unwrap_or_default();
+                job_log(&app_state, job_id, JobLogLevel::Info, format!("Encoded with -threads {threads}{nice}")).await;
+            }
             path
         }
+        Err(e @ AppError::Conflict(_)) => {
+            return abandon_run(&app_state, job_id, &borrowed_source, &e).await;
+        }
+        // The download is kept, so the requeued job goes straight to processing
+        Err(e @ AppError::NoCapacity { .. }) => {
+            gauge_set!("aperio_jobs_active", 0.0);
+            release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+            return requeue_without_slot(job, &app_state, &e).await;
+        }
         Err(e) => {
-            error!("Processing failed for job {}: {}", job_id, e);
-            job.set_error(e.to_string());
-            let _ = update_job_with_retry(&job, &app_state).await;
+            error!("Processing failed for job {}: {}", job_id, app_state.error_sanitizer.summarize(&e.to_string()));
+            let reason = record_failure(&mut job, &e, &app_state).await;
+            save_failure(&job, reason, &app_state).await;
             counter_inc!("aperio_jobs_failed_total", "phase" => "processing");
             gauge_set!("aperio_jobs_active", 0.0);
-            cleanup_on_exit().await;
-            return;
+            release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+            cleanup_job_files(&app_state, job_id).await;
+            return StageOutcome::Done;
         }
     };
 
+    // Hash the output alongside the remaining post-processing rather than after it
+    let checksum_task = {
+        let processed_path = processed_path.clone();
+        tokio::spawn(async move { sha256_file(&processed_path).await })
+    };
+
+    // Record the output duration so frame requests can be bounds-checked without re-probing
+    match app_state.process_service.probe_duration(&processed_path).await {
+        Ok(duration) => job.metadata.duration_seconds = Some(duration),
+        Err(e) => {
+            warn!("Failed to probe duration for job {}: {}", job_id, e);
+            job_log(&app_state, job_id, JobLogLevel::Warn, format!("Failed to probe output duration: {e}")).await;
+        }
+    }
+
+    // Preview generation is best-effort and never fails the job
+    if app_state.process_service.wants_preview(&job) {
+        match app_state.process_service.generate_preview(&job, &processed_path).await {
+            Ok(preview_path) => job.set_preview_path(preview_path),
+            Err(e) => {
+                warn!("Failed to generate preview for job {}: {}", job_id, e);
+                job_log(&app_state, job_id, JobLogLevel::Warn, format!("Failed to generate preview: {e}")).await;
+            }
+        }
+    }
+
+    // A missing checksum never fails the job
+    match checksum_task.await {
+        Ok(Ok(sha256)) => job.processed_sha256 = Some(sha256),
+        Ok(Err(e)) => {
+            warn!("Failed to hash output of job {}: {}", job_id, e);
+            job_log(&app_state, job_id, JobLogLevel::Warn, format!("Failed to hash output: {e}")).await;
+        }
+        Err(e) => warn!("Checksum task for job {} panicked: {}", job_id, e),
+    }
+
+    // Outputs leave the working dir, so everything still in it afterwards is temporary
+    if let Err(e) = store_outputs(&mut job, &app_state).await {
+        error!("Failed to store outputs of job {}: {}", job_id, app_state.error_sanitizer.summarize(&e.to_string()));
+        let reason = record_failure(&mut job, &e, &app_state).await;
+        save_failure(&job, reason, &app_state).await;
+        counter_inc!("aperio_jobs_failed_total", "phase" => "storage");
+        gauge_set!("aperio_jobs_active", 0.0);
+        release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+        cleanup_job_files(&app_state, job_id).await;
+        return StageOutcome::Done;
+    }
+
+    // A strict hook decides whether the job completes, so it runs before completion is recorded
+    if let Some(hook) = app_state.post_hook.as_ref().filter(|hook| hook.is_strict()) {
+        if let Err(rejection) = hook.run(&job, JobStatus::Completed).await {
+            let reason = record_failure(&mut job, &AppError::Processing(rejection), &app_state).await;
+            save_failure(&job, reason, &app_state).await;
+            counter_inc!("aperio_jobs_failed_total", "phase" => "post_hook");
+            gauge_set!("aperio_jobs_active", 0.0);
+            release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+            cleanup_job_files(&app_state, job_id).await;
+            return StageOutcome::Done;
+        }
+    }
+
     // Mark as completed and cleanup temporary files
     job.update_status(JobStatus::Completed);
     job.set_processing_time(start_time.elapsed());
+    job.set_expiry(job.updated_at, app_state.retention_service.default_retain_days());
 
-    if let Err(e) = update_job_with_retry(&job, &app_state).await {
-        error!("Failed to update job completion status: {}", e);
-    } else {
-        info!("Job {} completed successfully in {:?}", job_id, start_time.elapsed());
+    match update_job_with_retry(&job, &app_state).await {
+        Ok(()) => info!("Job {} completed successfully in {:?}", job_id, start_time.elapsed()),
+        // Cancelled while its outputs were being stored, which then go with the rest of its files
+        Err(e @ AppError::Conflict(_)) => return abandon_run(&app_state, job_id, &borrowed_source, &e).await,
+        Err(e) => error!("Failed to update job completion status: {}", e),
+    }
+    app_state.estimator.record(&job);
+    app_state.publisher.publish_job(&mut job).await;
+    if let Some(hook) = app_state.post_hook.as_ref().filter(|hook| !hook.is_strict()) {
+        hook.spawn(&job, JobStatus::Completed);
+    }
+    let output_bytes = job_output_bytes(&job).await;
+    job_log(&app_state, job_id, JobLogLevel::Info, format!(
+        "Stored {} bytes of output after {:.1}s", output_bytes, start_time.elapsed().as_secs_f64()
+    )).await;
+    app_state.storage_quota.add(output_bytes).await;
+    if let Err(e) = app_state.job_repository.record_completion_usage(&job, output_bytes).await {
+        warn!("Failed to record usage of job {}: {}", job_id, e);
     }
 
-    // Record completion metrics
-    let total_duration_ms = job_start_time.elapsed().as_millis() as f64;
+    // Record completion metrics; the job's duration runs from submission, including its time queued
+    let total_duration_ms = (chrono::Utc::now() - job.created_at).num_milliseconds() as f64;
     counter_inc!("aperio_jobs_completed_total");
     histogram_record!("aperio_job_duration_ms", total_duration_ms);
     if let Some(processing_time) = job.get_processing_time() {
@@ -477,44 +3331,318 @@ pub async fn process_job(job_id: &str, app_state: Arc<AppState>) {
     }
     gauge_set!("aperio_jobs_active", 0.0);
 
-    // Clean up temporary download files (keep processed files)
-    if let Some(downloaded_path) = job.get_downloaded_path() {
-        if let Err(e) = app_state.cleanup_service.cleanup_file(&downloaded_path).await {
-            warn!("Failed to cleanup downloaded file: {}", e);
+    // Everything the job still has in the working dir is temporary, including an original it didn't keep
+    release_borrowed_source(&app_state, job_id, &borrowed_source).await;
+    match app_state.cleanup_service.cleanup_working_files(job_id).await {
+        Ok(outcome) => job_log(&app_state, job_id, JobLogLevel::Info, format!(
+            "Removed {} temporary working files ({} bytes)", outcome.files_removed, outcome.bytes_freed
+        )).await,
+        Err(e) => {
+            warn!("Failed to cleanup working files for job {}: {}", job_id, e);
+            job_log(&app_state, job_id, JobLogLevel::Warn, format!("Failed to remove temporary working files: {e}")).await;
+        }
+    }
+    StageOutcome::Done
+}
+
+fn url_domain(url: &str) -> Option<String> {
+    url::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string))
+}
+
+/// Domains the job still has to download from: its URL's, or those of a concat job's sources
+/// that haven't been fetched yet
+fn pending_domains(job: &Job) -> Vec<String> {
+    if !job.is_concat() {
+        return url_domain(&job.url).into_iter().collect();
+    }
+    let mut domains: Vec<String> = job.sources.iter()
+        .filter(|source| source.downloaded_file().is_none())
+        .filter_map(|source| url_domain(&source.url))
+        .collect();
+    domains.sort_unstable();
+    domains.dedup();
+    domains
+}
+
+/// Claim a job for one of its stages and fetch it, adding its correlation id to the stage's span.
+/// None when it already started elsewhere or finished, e.g. because it was cancelled, or when
+/// the database can't be reached.
+async fn claim_job(job_id: &str, app_state: &AppState) -> Option<Job> {
+    match app_state.job_repository.try_claim_queued_job(job_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            warn!("Job {} is already running or finished, not starting it again", job_id);
+            return None;
+        }
+        Err(e) => {
+            error!("Failed to claim job {}: {}", job_id, e);
+            counter_inc!("aperio_job_errors_total", "error_type" => "database_error");
+            return None;
+        }
+    }
+
+    match retry_with_backoff(
+        || app_state.job_repository.get_job(job_id),
+        &RetryConfig::default(),
+        "database_get_job"
+    ).await {
+        Ok(Some(job)) => {
+            if let Some(correlation_id) = &job.correlation_id {
+                tracing::Span::current().record("correlation_id", correlation_id.as_str());
+            }
+            Some(job)
+        }
+        Ok(None) => {
+            error!("Job not found: {}", job_id);
+            counter_inc!("aperio_job_errors_total", "error_type" => "job_not_found");
+            None
+        }
+        Err(e) => {
+            error!("Failed to get job {} after retries: {}", job_id, e);
+            counter_inc!("aperio_job_errors_total", "error_type" => "database_error");
+            None
+        }
+    }
+}
+
+/// The parent's original a re-processed job reads from, which must outlive the job
+fn borrowed_source(job: &Job) -> Option<String> {
+    job.get_downloaded_path()
+        .filter(|path| !job.owns_downloaded_file() && path.exists())
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+async fn release_borrowed_source(app_state: &AppState, job_id: &str, borrowed_source: &Option<String>) {
+    if let Some(source) = borrowed_source {
+        app_state.cleanup_service.release_source(source, job_id).await;
+    }
+}
+
+/// Remove all of a job that didn't finish, logging the outcome to the job
+async fn cleanup_job_files(app_state: &AppState, job_id: &str) {
+    match app_state.cleanup_service.cleanup_job_files(job_id).await {
+        Ok(outcome) => job_log(app_state, job_id, JobLogLevel::Info, format!(
+            "Removed {} files ({} bytes)", outcome.files_removed, outcome.bytes_freed
+        )).await,
+        Err(e) => {
+            warn!("Failed to cleanup files for job {}: {}", job_id, e);
+            job_log(app_state, job_id, JobLogLevel::Warn, format!("Failed to remove the job's files: {e}")).await;
+        }
+    }
+}
+
+/// Move a finished job's outputs from the working dir into its storage directory
+async fn store_outputs(job: &mut Job, app_state: &AppState) -> AppResult<()> {
+    if let Some(processed_path) = job.get_processed_path() {
+        let stored_path = app_state.storage_service.store(job, &processed_path).await?;
+        job.set_processed_path(stored_path);
+    }
+    if let Some(preview_path) = job.get_preview_path() {
+        let stored_path = app_state.storage_service.store(job, &preview_path).await?;
+        job.set_preview_path(stored_path);
+    }
+    // A kept original is served by /original and re-used by reprocessing, so it is an output too
+    if job.keep_original && job.owns_downloaded_file() {
+        if let Some(downloaded_path) = job.get_downloaded_path() {
+            let stored_path = app_state.storage_service.store(job, &downloaded_path).await?;
+            job.set_downloaded_path(stored_path);
         }
     }
+    Ok(())
+}
+
+/// Return a job to Pending, to be requeued once `run_after` has passed
+async fn defer_job(mut job: Job, app_state: &Arc<AppState>, run_after: chrono::DateTime<chrono::Utc>) -> StageOutcome {
+    job.update_status(JobStatus::Pending);
+    job.metadata.deferred_until = Some(run_after);
+    match update_job_with_retry(&job, app_state).await {
+        Ok(()) => {}
+        Err(e @ AppError::Conflict(_)) => return abandon_run(app_state, &job.id, &None, &e).await,
+        Err(e) => warn!("Failed to record deferral of job {}: {}", job.id, e),
+    }
+    StageOutcome::Deferred(job, run_after)
+}
+
+/// Put a job that waited too long for a download or processing slot back at the end of the queue
+async fn requeue_without_slot(job: Job, app_state: &Arc<AppState>, error: &AppError) -> StageOutcome {
+    warn!("Requeueing job {}: {}", job.id, error);
+    job_log(app_state, &job.id, JobLogLevel::Warn, format!("{error}; requeued")).await;
+    counter_inc!("aperio_jobs_requeued_total", "reason" => "no_capacity");
+    defer_job(job, app_state, chrono::Utc::now()).await
+}
+
+/// Add a line to the log served by `GET /jobs/{job_id}/logs`, next to the tracing output.
+/// Status changes are logged by the repository; this is for everything in between.
+async fn job_log(app_state: &AppState, job_id: &str, level: JobLogLevel, message: impl AsRef<str>) {
+    let message = app_state.error_sanitizer.sanitize(message.as_ref());
+    if let Err(e) = app_state.job_repository.append_job_log(job_id, level, &message).await {
+        warn!("Failed to write log line for job {}: {}", job_id, e);
+    }
+}
+
+/// Mark a job failed with a classified reason and the tail of the error, keeping the whole
+/// output in the job log when the summary had to cut it. `save_failure` stores it.
+async fn record_failure(job: &mut Job, error: &AppError, app_state: &AppState) -> FailureReason {
+    let reason = classify_error(error);
+    let message = error.to_string();
+    let summary = app_state.error_sanitizer.summarize(&message);
+    let full = app_state.error_sanitizer.sanitize(&message);
+    if summary != full {
+        job_log(app_state, &job.id, JobLogLevel::Error, format!("Full error output:\n{full}")).await;
+    }
+    job.set_error(summary);
+    job.failure_reason = Some(reason);
+    counter_inc!("aperio_job_failures_total", "reason" => reason.as_str());
+    reason
+}
+
+/// Save a job `record_failure` marked failed, then send the failure alert and the post-hook.
+/// A failure that wasn't saved, e.g. on a job cancelled underneath the run, sends neither.
+async fn save_failure(job: &Job, reason: FailureReason, app_state: &Arc<AppState>) {
+    if let Err(e) = update_job_with_retry(job, app_state).await {
+        warn!("Failed to save failure of job {}: {}", job.id, e);
+        return;
+    }
+    app_state.failure_alerts.job_failed(job, reason.as_str(), app_state.api_urls.url(&format!("/status/{}", job.id)));
+    if let Some(hook) = &app_state.post_hook {
+        hook.spawn(job, JobStatus::Failed);
+    }
 }
 
 async fn download_with_retry(job: &mut Job, app_state: &Arc<AppState>) -> AppResult<std::path::PathBuf> {
+    // Live sources and disallowed media hosts are caught up front so they don't hold a download slot
+    let dest_dir = job_working_dir(&app_state.working_dir, &job.id);
+    app_state.downloader.probe(job, &dest_dir).await?;
+
+    // Only report Downloading once a slot is actually held
+    info!("Waiting for download slot for job {}", job.id);
+    let _permit = app_state.pool_manager.acquire_download_permit().await?;
+    job.start_stage(JobStatus::Downloading);
+    save_progress(job, app_state).await?;
+
+    let downloaded = fetch_with_retry(job, app_state).await?;
+    job.metadata.download_seconds = job.stage_elapsed_seconds();
+    info!("Downloaded {} bytes for job {}", downloaded.size_bytes, job.id);
+    job_log(app_state, &job.id, JobLogLevel::Info, format!("Downloaded {} bytes", downloaded.size_bytes)).await;
+    job.set_downloaded_path(downloaded.path.clone());
+    job.metadata.downloaded_bytes = Some(downloaded.size_bytes);
+    save_progress(job, app_state).await?;
+    Ok(downloaded.path)
+}
+
+/// Fetch `job.url` into the job's working dir, retrying once. The caller holds a download permit.
+async fn fetch_with_retry(job: &Job, app_state: &Arc<AppState>) -> AppResult<DownloadedFile> {
     let retry_config = RetryConfig {
         max_attempts: 2, // Reduce retry attempts
         base_delay: std::time::Duration::from_secs(1),
         max_delay: std::time::Duration::from_secs(10),
         backoff_multiplier: 2.0,
+        max_elapsed: None,
     };
 
-    let download_result = retry_with_backoff(
+    let mut attempt = 0;
+    // A rate-limited or unavailable source fails the same way again; only transient failures are retried
+    retry_with_backoff_if(
         || {
+            attempt += 1;
+            let attempt = attempt;
             let app_state = app_state.clone();
-            let mut job_clone = job.clone();
+            let job_clone = job.clone();
             async move {
-                app_state.download_service.download(&mut job_clone).await
+                let result = async {
+                    let dest_dir = job_working_dir(&app_state.working_dir, &job_clone.id);
+                    let downloaded = app_state.downloader.fetch(&job_clone, &dest_dir).await?;
+                    check_download_size(&downloaded, app_state.download_service.min_download_bytes()).await?;
+
+                    // A file with no playable duration would only fail later inside ffmpeg
+                    if let Ok(duration) = app_state.process_service.probe_duration(&downloaded.path).await {
+                        if duration <= 0.0 {
+                            let _ = tokio::fs::remove_file(&downloaded.path).await;
+                            return Err(AppError::Download("incomplete download: file has no media duration".to_string()));
+                        }
+                    }
+                    Ok(downloaded)
+                }.await;
+
+                if let Err(e) = &result {
+                    job_log(&app_state, &job_clone.id, JobLogLevel::Warn, format!("Download attempt {attempt} failed: {e}")).await;
+                }
+                result
             }
         },
         &retry_config,
-        "video_download"
-    ).await;
+        "video_download",
+        is_retryable_error
+    ).await
+}
 
-    match download_result {
-        Ok(path) => {
-            job.set_downloaded_path(path.clone());
-            let _ = update_job_with_retry(job, app_state).await;
-            Ok(path)
+/// Fetch a concat job's sources one after another under a single download slot. The processing
+/// stage joins them as part of its encode. Sources an earlier run already fetched are not
+/// fetched again.
+async fn download_sources(job: &mut Job, app_state: &Arc<AppState>) -> AppResult<std::path::PathBuf> {
+    info!("Waiting for download slot for job {}", job.id);
+    let download_permit = app_state.pool_manager.acquire_download_permit().await?;
+    job.start_stage(JobStatus::Downloading);
+    save_progress(job, app_state).await?;
+
+    let count = job.sources.len();
+    for position in 0..count {
+        if job.sources[position].downloaded_file().is_some() {
+            continue;
         }
-        Err(e) if is_retryable_error(&e) => {
-            Err(AppError::Download(format!("Download failed after retries: {e}")))
+        let number = position + 1;
+        let url = job.sources[position].url.clone();
+        job.metadata.current_source = Some(number as u32);
+        job.sources[position].status = SourceStatus::Downloading;
+        record_source(job, position, app_state).await;
+        save_progress(job, app_state).await?;
+        job_log(app_state, &job.id, JobLogLevel::Info, format!("Fetching source {number} of {count}: {}", redact_text(&url))).await;
+
+        let mut source_job = job.clone();
+        source_job.url = url;
+        let result = async {
+            let dest_dir = job_working_dir(&app_state.working_dir, &source_job.id);
+            app_state.downloader.probe(&mut source_job, &dest_dir).await?;
+            let downloaded = fetch_with_retry(&source_job, app_state).await?;
+            // The downloader always writes the job's original; each source needs its own name
+            let extension = downloaded.path.extension().and_then(|extension| extension.to_str()).unwrap_or("mp4");
+            let path = downloaded.path.with_file_name(format!("{}_source{number}.{extension}", job.id));
+            tokio::fs::rename(&downloaded.path, &path).await
+                .map_err(AppError::io("Failed to move downloaded source"))?;
+            Ok::<_, AppError>((path, downloaded.size_bytes))
+        }.await;
+
+        match result {
+            Ok((path, size_bytes)) => {
+                job_log(app_state, &job.id, JobLogLevel::Info, format!("Downloaded {size_bytes} bytes of source {number} of {count}")).await;
+                job.sources[position].status = SourceStatus::Downloaded;
+                job.sources[position].downloaded_path = Some(path.to_string_lossy().to_string());
+                record_source(job, position, app_state).await;
+            }
+            Err(e) => {
+                job.sources[position].status = SourceStatus::Failed;
+                record_source(job, position, app_state).await;
+                return Err(e);
+            }
         }
-        Err(e) => Err(e),
+    }
+    drop(download_permit);
+    job.metadata.download_seconds = job.stage_elapsed_seconds();
+
+    let inputs: Vec<std::path::PathBuf> = job.sources.iter().filter_map(|source| source.downloaded_file()).collect();
+    let mut downloaded_bytes = 0;
+    for input in &inputs {
+        downloaded_bytes += tokio::fs::metadata(input).await.map(|metadata| metadata.len()).unwrap_or(0);
+    }
+    job.metadata.current_source = None;
+    job.metadata.downloaded_bytes = Some(downloaded_bytes);
+    save_progress(job, app_state).await?;
+    inputs.into_iter().next().ok_or_else(|| AppError::Internal("concat job has no downloaded sources".to_string()))
+}
+
+async fn record_source(job: &Job, position: usize, app_state: &AppState) {
+    if let Err(e) = app_state.job_repository.update_job_source(&job.id, position, &job.sources[position]).await {
+        warn!("Failed to record source {} of job {}: {}", position + 1, job.id, e);
     }
 }
 
@@ -528,30 +3656,39 @@ async fn process_with_retry(
         base_delay: std::time::Duration::from_secs(1),
         max_delay: std::time::Duration::from_secs(5),
         backoff_multiplier: 1.0,
+        max_elapsed: None,
     };
 
-    let process_result = retry_with_backoff(
+    // Only report Processing once a slot is actually held
+    info!("Waiting for processing slot for job {}", job.id);
+    let _permit = app_state.pool_manager.acquire_processing_permit().await?;
+    job.start_stage(JobStatus::Processing);
+    save_progress(job, app_state).await?;
+
+    let process_result = retry_with_backoff_if(
         || {
             let app_state = app_state.clone();
             let mut job_clone = job.clone();
             let input_path = input_path.to_path_buf();
             async move {
-                app_state.process_service.process(&mut job_clone, &input_path).await
+                let path = app_state.processor.process(&mut job_clone, &input_path).await?;
+                // Carry back what the encode recorded about the media
+                Ok((path, job_clone.metadata))
             }
         },
         &retry_config,
-        "video_processing"
+        "video_processing",
+        is_retryable_error
     ).await;
 
     match process_result {
-        Ok(path) => {
+        Ok((path, metadata)) => {
+            job.metadata = metadata;
+            job.metadata.processing_seconds = job.stage_elapsed_seconds();
             job.set_processed_path(path.clone());
-            let _ = update_job_with_retry(job, app_state).await;
+            save_progress(job, app_state).await?;
             Ok(path)
         }
-        Err(e) if is_retryable_error(&e) => {
-            Err(AppError::Processing(format!("Processing failed after retries: {e}")))
-        }
         Err(e) => Err(e),
     }
 }
@@ -562,11 +3699,40 @@ async fn update_job_with_retry(job: &Job, app_state: &Arc<AppState>) -> AppResul
         base_delay: std::time::Duration::from_millis(50),
         max_delay: std::time::Duration::from_secs(2),
         backoff_multiplier: 2.0,
+        max_elapsed: None,
     };
 
+    // A refused transition is refused again, so it isn't retried
     retry_with_backoff(
-        || app_state.job_repository.update_job(job),
+        || async {
+            match app_state.job_repository.update_job(job).await {
+                Err(e @ AppError::Conflict(_)) => Ok(Err(e)),
+                result => result.map(Ok),
+            }
+        },
         &retry_config,
         "database_update"
-    ).await
+    ).await?
+}
+
+/// Save a running job's progress. Only a refused update is returned, which means the job was
+/// finished underneath the run, e.g. cancelled; any other failure is logged and the run goes on.
+async fn save_progress(job: &Job, app_state: &Arc<AppState>) -> AppResult<()> {
+    match update_job_with_retry(job, app_state).await {
+        Err(e @ AppError::Conflict(_)) => Err(e),
+        Err(e) => {
+            warn!("Failed to save progress of job {} in status {}: {}", job.id, job.status, e);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Stop a run whose job was finished underneath it, e.g. cancelled, removing what the run left behind
+async fn abandon_run(app_state: &AppState, job_id: &str, borrowed_source: &Option<String>, reason: &AppError) -> StageOutcome {
+    info!("Stopping the run of job {}: {}", job_id, reason);
+    gauge_set!("aperio_jobs_active", 0.0);
+    release_borrowed_source(app_state, job_id, borrowed_source).await;
+    cleanup_job_files(app_state, job_id).await;
+    StageOutcome::Done
 }
@@ -1,4 +1,11 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use ipnet::IpNet;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use tracing::{info, warn};
+use crate::services::job_queue::WORKER_HEARTBEAT_INTERVAL;
 
 #[derive(Clone)]
 pub struct Config {
@@ -10,20 +17,190 @@ pub struct Config {
     pub security: SecurityConfig,
     pub queue: QueueConfig,
     pub retention: RetentionConfig,
+    pub notify: NotifyConfig,
+    pub publish: PublishConfig,
+    pub hooks: HookConfig,
+}
+
+#[derive(Clone)]
+pub struct HookConfig {
+    /// Absolute path of an executable run after each job finishes; unset runs nothing
+    pub post_hook_command: Option<PathBuf>,
+    pub post_hook_timeout: Duration,
+    /// Fail a completed job when the hook exits non-zero instead of only logging it
+    pub post_hook_strict: bool,
+}
+
+#[derive(Clone)]
+pub struct PublishConfig {
+    /// Blossom server completed outputs are uploaded to; unset turns publishing off
+    pub blossom_url: Option<String>,
+    /// Hex Nostr secret key that signs a fresh upload authorization for each blob
+    pub nostr_secret_key: Option<String>,
+    /// Base64 authorization event sent as is, for when the key stays outside Aperio
+    pub auth_token: Option<String>,
+    pub max_attempts: u32,
+}
+
+#[derive(Clone)]
+pub struct NotifyConfig {
+    /// Where failure notifications are POSTed; unset turns them off
+    pub webhook_url: Option<String>,
+    /// JSON body of a notification, see `WebhookNotifier`
+    pub webhook_template: String,
+    /// Key deliveries are signed with, see `sign_delivery`; unset sends them unsigned
+    pub webhook_secret: Option<String>,
+    pub max_per_hour: u32,
+    /// Share of finished jobs failing within the window that sends an alert; unset disables it
+    pub failure_rate_threshold: Option<f64>,
+    pub failure_rate_window: Duration,
 }
 
 #[derive(Clone)]
 pub struct QueueConfig {
-    pub max_concurrent_jobs: usize,
+    /// Cap on jobs downloading and processing at once; the per-stage limits apply either way
+    pub max_concurrent_jobs: Option<usize>,
+    pub max_queue_size: usize,
+    /// Slots in each stage kept free for High priority jobs
+    pub high_priority_reserved_slots: usize,
+    /// Share of each stage's slots Low priority jobs may hold, in percent
+    pub low_priority_max_percent: u8,
+    /// Lines of a failed command's output kept in a job's `error_message`; the full output goes to its log
+    pub error_message_max_lines: usize,
+    /// Bytes kept in a job's `error_message`
+    pub error_message_max_bytes: usize,
+    /// How long a job waits for a download or processing slot before it is requeued
+    pub slot_wait_timeout: Duration,
+    /// Longest a job may wait in the queue before health reports the queue as stalled
+    pub max_pending_age: Duration,
+    /// Longest the worker may go without finishing a pass before health reports it as stalled
+    pub worker_stall_timeout: Duration,
+    /// Whether a stalled queue also fails the readiness probe
+    pub stall_fails_readiness: bool,
+    /// Conditions that take the instance out of rotation when they fail; the others are only reported
+    pub readiness_checks: Vec<ReadinessCheck>,
+    /// Share of APERIO_MAX_QUEUE_SIZE, in percent, at which the queue counts as full for readiness
+    pub readiness_queue_percent: u64,
+}
+
+/// A condition `/health/ready` reports on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadinessCheck {
+    Database,
+    Drain,
+    Worker,
+    QueueCapacity,
+    Disk,
+    QueueStall,
+}
+
+impl ReadinessCheck {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadinessCheck::Database => "database",
+            ReadinessCheck::Drain => "drain",
+            ReadinessCheck::Worker => "worker",
+            ReadinessCheck::QueueCapacity => "queue_capacity",
+            ReadinessCheck::Disk => "disk",
+            ReadinessCheck::QueueStall => "queue_stall",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            ReadinessCheck::Database,
+            ReadinessCheck::Drain,
+            ReadinessCheck::Worker,
+            ReadinessCheck::QueueCapacity,
+            ReadinessCheck::Disk,
+            ReadinessCheck::QueueStall,
+        ].into_iter().find(|check| check.as_str() == name)
+    }
 }
 
 #[derive(Clone)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// APERIO_LISTEN as given; takes the place of host and port, see `listener`
+    pub listen: Option<String>,
+    /// Whether APERIO_HOST or APERIO_PORT was set, which APERIO_LISTEN may not be combined with
+    pub host_or_port_set: bool,
+    /// Permission bits of a unix socket listener
+    pub socket_mode: u32,
     pub client_timeout: Duration,
     pub keep_alive: Duration,
+    /// HTTP worker threads; defaults to the number of CPUs, as actix does
+    pub http_workers: usize,
+    /// Connections waiting to be accepted before new ones are refused
+    pub backlog: u32,
+    /// How long shutdown waits for in-flight requests before dropping them
+    pub shutdown_timeout: Duration,
+    /// Cap on an upload request's body, multipart overhead included
     pub max_payload_size: usize,
+    /// Cap on JSON request bodies
+    pub max_json_payload: usize,
+    /// Requests served at once before more are shed with 503, for GET/HEAD/OPTIONS and everything else
+    pub max_inflight_reads: usize,
+    pub max_inflight_writes: usize,
+    /// Resident memory above which the process health check is degraded; None disables the limit
+    pub max_rss_bytes: Option<u64>,
+    /// Origins allowed by CORS; None keeps the restrictive default
+    pub cors_origins: Option<Vec<String>>,
+    /// Peers whose X-Forwarded-For and Forwarded headers are believed when finding the client address
+    pub trusted_proxies: Vec<IpNet>,
+    /// Serve Swagger UI for the OpenAPI document under /docs
+    pub swagger_ui: bool,
+    /// Scheme and host clients reach the API at, e.g. behind a reverse proxy; links are relative without it
+    pub public_url: Option<String>,
+    /// PEM certificate chain and private key; HTTPS is served when both are set
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+/// Where the server accepts connections
+#[derive(Clone, Debug, PartialEq)]
+pub enum Listener {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp { host, port } if host.contains(':') => write!(f, "tcp://[{host}]:{port}"),
+            Listener::Tcp { host, port } => write!(f, "tcp://{host}:{port}"),
+            Listener::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The listener from APERIO_LISTEN (`tcp://host:port` or `unix:///path`), else from APERIO_HOST and APERIO_PORT
+    pub fn listener(&self) -> Result<Listener, String> {
+        let Some(listen) = &self.listen else {
+            return Ok(Listener::Tcp { host: self.host.clone(), port: self.port });
+        };
+
+        if let Some(path) = listen.strip_prefix("unix://") {
+            if !path.starts_with('/') {
+                return Err(format!("APERIO_LISTEN `{listen}` must name an absolute socket path, e.g. unix:///run/aperio.sock"));
+            }
+            return Ok(Listener::Unix(PathBuf::from(path)));
+        }
+        if let Some(address) = listen.strip_prefix("tcp://") {
+            // rsplit so bracketed IPv6 hosts keep their colons
+            let parsed = address.rsplit_once(':').and_then(|(host, port)| {
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                Some((host, port.parse::<u16>().ok()?)).filter(|_| !host.is_empty())
+            });
+            return match parsed {
+                Some((host, port)) => Ok(Listener::Tcp { host: host.to_string(), port }),
+                None => Err(format!("APERIO_LISTEN `{listen}` must be tcp://host:port")),
+            };
+        }
+        Err(format!("APERIO_LISTEN `{listen}` must start with tcp:// or unix://"))
+    }
 }
 
 #[derive(Clone)]
@@ -32,26 +209,161 @@ pub struct DownloadConfig {
     pub download_command: String,
     pub allowed_domains: Vec<String>,
     pub max_concurrent_downloads: usize,
+    pub max_playlist_items: usize,
+    pub allow_live_streams: bool,
+    pub max_live_duration: Duration,
+    pub rate_limit_cooldown: Duration,
+    pub max_rate_limit_deferrals: u32,
+    pub cookies_file: Option<PathBuf>,
+    pub auth_profiles: HashMap<String, PathBuf>,
+    pub concurrent_fragments: u32,
+    pub extra_args: Vec<String>,
+    pub backend: DownloaderBackend,
+    pub min_download_size_kb: u64,
+    pub http_download_command: String,
+    /// Nice level yt-dlp and curl run at
+    pub download_nice: Option<u32>,
+    pub media_hosts: Vec<String>,
+    pub subscription_poll_interval: Duration,
+}
+
+/// Which processor turns job sources into outputs
+#[derive(Clone, Debug)]
+pub enum ProcessorBackend {
+    Ffmpeg,
+    /// Copies the source unchanged, for environments without ffmpeg
+    Passthrough,
+}
+
+impl ProcessorBackend {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "passthrough" => ProcessorBackend::Passthrough,
+            _ => ProcessorBackend::Ffmpeg,
+        }
+    }
+}
+
+/// How retention frees disk space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetentionMode {
+    /// Jobs and their files are deleted once older than the retention period
+    Age,
+    /// Additionally evict the least recently served outputs when usage passes the high-water mark
+    Lru,
+}
+
+impl RetentionMode {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "lru" => RetentionMode::Lru,
+            _ => RetentionMode::Age,
+        }
+    }
+}
+
+/// Which downloader fetches job sources
+#[derive(Clone, Debug)]
+pub enum DownloaderBackend {
+    /// Plain .mp4/.mov links are fetched over HTTP, everything else through yt-dlp
+    Auto,
+    YtDlp,
+    /// Copies a local fixture file instead of touching the network
+    Mock(PathBuf),
+}
+
+impl DownloaderBackend {
+    fn from_env_value(value: &str, mock_fixture: Option<String>) -> Self {
+        match value.to_lowercase().as_str() {
+            "yt-dlp" | "ytdlp" => DownloaderBackend::YtDlp,
+            "mock" => DownloaderBackend::Mock(PathBuf::from(mock_fixture.unwrap_or_default())),
+            _ => DownloaderBackend::Auto,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct ProcessingConfig {
     pub processing_timeout: Duration,
     pub ffmpeg_command: String,
+    pub ffprobe_command: String,
     pub video_codec: String,
     pub audio_codec: String,
     pub preset: String,
     pub crf: u32,
     pub audio_bitrate: String,
     pub max_concurrent_processing: usize,
+    /// ffmpeg's `-threads` for encodes; 0 lets ffmpeg use every core
+    pub ffmpeg_threads: u32,
+    /// Most threads a job may ask for with its `threads` option
+    pub max_ffmpeg_threads: u32,
+    /// Nice level ffmpeg runs at, so encodes don't starve the API
+    pub ffmpeg_nice: Option<u32>,
+    pub preview_enabled: bool,
+    pub preview_duration_secs: u32,
+    pub normalize_audio: bool,
+    pub loudnorm_integrated: f64,
+    pub loudnorm_true_peak: f64,
+    pub loudnorm_lra: f64,
+    pub max_fps: Option<u32>,
+    pub strip_metadata: bool,
+    pub min_video_bitrate_kbps: u32,
+    pub remux_if_compatible: bool,
+    pub watermark: Option<WatermarkConfig>,
+    pub result_cache_enabled: bool,
+    pub backend: ProcessorBackend,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl WatermarkPosition {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "top-left" => WatermarkPosition::TopLeft,
+            "top-right" => WatermarkPosition::TopRight,
+            "bottom-left" => WatermarkPosition::BottomLeft,
+            _ => WatermarkPosition::BottomRight,
+        }
+    }
+}
+
+/// Logo overlaid on processed videos; absent when `APERIO_WATERMARK_PATH` is unset
+#[derive(Clone, Debug)]
+pub struct WatermarkConfig {
+    pub path: PathBuf,
+    pub position: WatermarkPosition,
+    /// Distance in output pixels from the chosen corner
+    pub margin: u32,
+    pub opacity: f64,
 }
 
 #[derive(Clone)]
 pub struct StorageConfig {
-    #[allow(dead_code)]
     pub storage_type: StorageType,
-    #[allow(dead_code)]
+    /// Where completed outputs are moved, in `{job_id}/` directories
     pub local_path: Option<String>,
+    /// Temporary files of jobs in flight, in `{job_id}/` directories
+    pub working_dir: PathBuf,
+    pub database_url: String,
+    /// None sizes the pool from the CPU count
+    pub db_max_connections: Option<u32>,
+    /// Cap on bytes held in the working and storage dirs; None disables admission control
+    pub max_storage_bytes: Option<u64>,
+    /// Space kept free under the cap for jobs already admitted
+    pub storage_headroom_bytes: u64,
+    pub storage_reconcile_interval: Duration,
+    /// Between WAL checkpoint, ANALYZE and incremental vacuum runs; None disables them
+    pub db_maintenance_interval: Option<Duration>,
+    /// Between saves of the metrics counters and histograms to the database; None disables saving and restoring
+    pub metrics_snapshot_interval: Option<Duration>,
+    /// Free space in the working dir below which the disk check turns critical
+    pub disk_critical_free_bytes: u64,
 }
 
 #[derive(Clone)]
@@ -66,39 +378,209 @@ pub struct SecurityConfig {
     #[allow(dead_code)]
     pub blocked_ips: Vec<String>,
     pub auth_password: Option<String>,
+    /// Password for `/admin/*` routes; they fall back to `auth_password` when unset
+    pub admin_password: Option<String>,
+    /// Keys accepted as `Authorization: Bearer <key>`, each identifying a client
+    pub api_keys: Vec<ApiKey>,
+    /// Extra regex whose matches are masked in logged URLs and stored error messages
+    pub redact_pattern: Option<String>,
+}
+
+/// A named API key. Its name is stamped on the jobs it creates as their `client_id`.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    /// May use `/admin/*` routes and see every client's jobs
+    pub admin: bool,
+    /// Jobs the client may have pending or running at once
+    pub max_active_jobs: Option<u32>,
+    /// Jobs the client may submit in any 24 hours
+    pub max_daily_jobs: Option<u32>,
 }
 
 #[derive(Clone)]
 pub struct RetentionConfig {
     pub enabled: bool,
-    pub retention_days: u32,
+    /// Days a terminal job keeps its files before they are deleted and the job is flagged expired
+    pub file_retention_days: u32,
+    /// Days a terminal job's record is kept; never shorter than `file_retention_days`
+    pub record_retention_days: u32,
+    /// Upper bound on the `retain_days` a job may request
+    pub max_retain_days: u32,
     pub cleanup_interval_hours: u64,
+    pub idempotency_window_hours: u64,
+    /// Hours a stray working file must sit untouched before the orphan sweep may remove it
+    pub orphan_grace_hours: u64,
+    /// Hours a deleted job can be restored before retention removes it for good
+    pub soft_delete_window_hours: u64,
+    /// Days audit log entries are kept
+    pub audit_retention_days: u32,
+    pub mode: RetentionMode,
+    /// Percent of the storage quota above which eviction starts
+    pub eviction_high_water_percent: u64,
+    /// Percent of the storage quota eviction frees space down to
+    pub eviction_low_water_percent: u64,
+    pub eviction_interval: Duration,
 }
 
+/// Presets accepted by ffmpeg's x264/x265 encoders
+/// Notification body when APERIO_NOTIFY_WEBHOOK_TEMPLATE is unset; Slack and Matrix hookshot both read `text`
+pub const DEFAULT_WEBHOOK_TEMPLATE: &str = r#"{"text": "{message}"}"#;
+
+pub const FFMPEG_PRESETS: [&str; 10] = [
+    "ultrafast", "superfast", "veryfast", "faster", "fast",
+    "medium", "slow", "slower", "veryslow", "placebo",
+];
+
 impl Default for Config {
     fn default() -> Self {
+        Self::from_source(&ConfigSource::from_env())
+    }
+}
+
+impl Config {
+    /// Build the config from settings resolved by `source`, applying defaults for anything unset
+    pub fn from_source(source: &ConfigSource) -> Self {
         let parse_env_var = |key: &str, default: &str| -> String {
-            std::env::var(key).unwrap_or_else(|_| default.to_string())
+            source.get_or(key, default)
         };
         
         let parse_env_number = |key: &str, default: u64| -> u64 {
-            std::env::var(key)
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(default)
+            let value = source.get_or(key, &default.to_string());
+            value.trim().parse().unwrap_or_else(|_| {
+                source.warn_defaulted(key, &value, &default.to_string());
+                default
+            })
         };
         
+        let parse_env_float = |key: &str, default: f64| -> f64 {
+            let value = source.get_or(key, &default.to_string());
+            value.trim().parse().unwrap_or_else(|_| {
+                source.warn_defaulted(key, &value, &default.to_string());
+                default
+            })
+        };
+
+        let parse_env_bool = |key: &str, default: bool| -> bool {
+            let value = source.get_or(key, &default.to_string());
+            match value.trim().to_lowercase().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => {
+                    source.warn_defaulted(key, &value, &default.to_string());
+                    default
+                }
+            }
+        };
+
+        // Settings that are unset unless given; an unparseable value is reported and left unset
+        let parse_env_optional = |key: &str| -> Option<u32> {
+            let value = source.get(key)?;
+            value.trim().parse().map_err(|_| source.warn_defaulted(key, &value, "unset")).ok()
+        };
+
+        // `name=value` pairs, as in APERIO_AUTH_PROFILES
+        let parse_env_map = |key: &str| -> Vec<(String, String)> {
+            parse_env_var(key, "")
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .collect()
+        };
+
+        // A JSON array of arguments, so a value may contain spaces. Unlike other settings a bad value
+        // stops startup, since dropping it would quietly change how every download behaves.
+        let parse_env_args = |key: &str| -> Vec<String> {
+            let value = parse_env_var(key, "");
+            if value.trim().is_empty() {
+                return Vec::new();
+            }
+            serde_json::from_str(&value).unwrap_or_else(|_| {
+                source.errors.borrow_mut().push(format!(
+                    "{key}={value:?} is not a JSON array of strings, e.g. [\"--limit-rate\", \"5M\"]"
+                ));
+                Vec::new()
+            })
+        };
+
+        // Per-key job limits; an unparseable limit is reported and the key left unlimited
+        let parse_key_limits = |key: &str| -> HashMap<String, u32> {
+            parse_env_map(key).into_iter()
+                .filter_map(|(name, value)| match value.parse() {
+                    Ok(limit) => Some((name, limit)),
+                    Err(_) => {
+                        source.warn_defaulted(&format!("{key} ({name})"), &value, "unlimited");
+                        None
+                    }
+                })
+                .collect()
+        };
+
         let parse_env_duration = |key: &str, default_secs: u64| -> Duration {
             Duration::from_secs(parse_env_number(key, default_secs))
         };
 
+        // APERIO_RETENTION_DAYS predates the two stages and still sets both when they aren't given
+        let retention_days = parse_env_number("APERIO_RETENTION_DAYS", 30);
+        let record_retention_days = parse_env_number("APERIO_RECORD_RETENTION_DAYS", retention_days) as u32;
+        let file_retention_days = parse_env_number("APERIO_FILE_RETENTION_DAYS", record_retention_days as u64) as u32;
+
         Config {
             server: ServerConfig {
                 host: parse_env_var("APERIO_HOST", "0.0.0.0"),
                 port: parse_env_number("APERIO_PORT", 8080) as u16,
+                listen: source.get("APERIO_LISTEN")
+                    .map(|listen| listen.trim().to_string())
+                    .filter(|listen| !listen.is_empty()),
+                host_or_port_set: source.get("APERIO_HOST").is_some() || source.get("APERIO_PORT").is_some(),
+                socket_mode: {
+                    let value = parse_env_var("APERIO_SOCKET_MODE", "660");
+                    u32::from_str_radix(value.trim(), 8)
+                        .ok()
+                        .filter(|mode| *mode <= 0o777)
+                        .unwrap_or_else(|| {
+                            source.warn_defaulted("APERIO_SOCKET_MODE", &value, "660");
+                            0o660
+                        })
+                },
                 client_timeout: parse_env_duration("APERIO_CLIENT_TIMEOUT", 1800),
                 keep_alive: parse_env_duration("APERIO_KEEP_ALIVE", 1800),
+                http_workers: parse_env_number(
+                    "APERIO_HTTP_WORKERS",
+                    std::thread::available_parallelism().map_or(1, |cpus| cpus.get()) as u64,
+                ) as usize,
+                backlog: parse_env_number("APERIO_BACKLOG", 2048) as u32,
+                shutdown_timeout: parse_env_duration("APERIO_SHUTDOWN_TIMEOUT", 30),
                 max_payload_size: parse_env_number("APERIO_MAX_PAYLOAD", 100 * 1024 * 1024) as usize,
+                max_json_payload: parse_env_number("APERIO_MAX_JSON_PAYLOAD", 64 * 1024) as usize,
+                max_inflight_reads: parse_env_number("APERIO_MAX_INFLIGHT_READS", 256) as usize,
+                max_inflight_writes: parse_env_number("APERIO_MAX_INFLIGHT_WRITES", 64) as usize,
+                max_rss_bytes: Some(parse_env_number("APERIO_MAX_RSS_MB", 0) * 1024 * 1024).filter(|bytes| *bytes > 0),
+                cors_origins: source.get("APERIO_CORS_ORIGINS")
+                    .map(|origins| origins.split(',').map(|s| s.trim().to_string()).collect()),
+                // CIDRs or single addresses; an entry that doesn't parse is reported and left out
+                trusted_proxies: parse_env_var("APERIO_TRUSTED_PROXIES", "")
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|entry| !entry.is_empty())
+                    .filter_map(|entry| {
+                        entry.parse::<IpNet>()
+                            .or_else(|_| entry.parse::<std::net::IpAddr>().map(IpNet::from))
+                            .map_err(|_| source.warn(format!("APERIO_TRUSTED_PROXIES entry {entry:?} is not an address or CIDR; ignoring it")))
+                            .ok()
+                    })
+                    .collect(),
+                swagger_ui: parse_env_bool("APERIO_SWAGGER_UI", false),
+                public_url: source.get("APERIO_PUBLIC_URL")
+                    .map(|url| url.trim().trim_end_matches('/').to_string())
+                    .filter(|url| !url.is_empty()),
+                tls_cert: source.get("APERIO_TLS_CERT")
+                    .filter(|path| !path.trim().is_empty())
+                    .map(PathBuf::from),
+                tls_key: source.get("APERIO_TLS_KEY")
+                    .filter(|path| !path.trim().is_empty())
+                    .map(PathBuf::from),
             },
             download: DownloadConfig {
                 download_timeout: parse_env_duration("APERIO_DOWNLOAD_TIMEOUT", 900),
@@ -109,20 +591,90 @@ impl Default for Config {
                     .filter(|s| !s.is_empty())
                     .collect(),
                 max_concurrent_downloads: parse_env_number("APERIO_MAX_CONCURRENT_DOWNLOADS", 2) as usize,
+                max_playlist_items: parse_env_number("APERIO_MAX_PLAYLIST_ITEMS", 50) as usize,
+                allow_live_streams: parse_env_bool("APERIO_ALLOW_LIVE_STREAMS", false),
+                max_live_duration: parse_env_duration("APERIO_MAX_LIVE_DURATION", 600),
+                rate_limit_cooldown: parse_env_duration("APERIO_RATE_LIMIT_COOLDOWN", 300),
+                max_rate_limit_deferrals: parse_env_number("APERIO_MAX_RATE_LIMIT_DEFERRALS", 5) as u32,
+                cookies_file: source.get("APERIO_COOKIES_FILE")
+                    .filter(|path| !path.trim().is_empty())
+                    .map(PathBuf::from),
+                // Comma-separated name=path pairs, e.g. "members=/secrets/members.txt"
+                auth_profiles: parse_env_var("APERIO_AUTH_PROFILES", "")
+                    .split(',')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(name, path)| (name.trim().to_string(), PathBuf::from(path.trim())))
+                    .filter(|(name, path)| !name.is_empty() && !path.as_os_str().is_empty())
+                    .collect(),
+                concurrent_fragments: parse_env_number("APERIO_DOWNLOAD_CONCURRENT_FRAGMENTS", 1).clamp(1, 32) as u32,
+                // Checked against an allowlist at startup; see DownloadCommandBuilder
+                extra_args: parse_env_args("APERIO_YTDLP_EXTRA_ARGS"),
+                backend: DownloaderBackend::from_env_value(
+                    &parse_env_var("APERIO_DOWNLOADER", "auto"),
+                    source.get("APERIO_MOCK_DOWNLOAD_FIXTURE"),
+                ),
+                http_download_command: parse_env_var("APERIO_HTTP_DOWNLOAD_COMMAND", "curl"),
+                download_nice: parse_env_optional("APERIO_DOWNLOAD_NICE"),
+                min_download_size_kb: parse_env_number("APERIO_MIN_DOWNLOAD_SIZE_KB", 256),
+                // CDN hosts the allowed sites serve media from; not accepted as submitted URLs
+                media_hosts: parse_env_var("APERIO_MEDIA_HOSTS", "googlevideo.com,ytimg.com,cdninstagram.com,fbcdn.net")
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                subscription_poll_interval: parse_env_duration("APERIO_SUBSCRIPTION_POLL_INTERVAL", 900),
             },
             processing: ProcessingConfig {
                 processing_timeout: parse_env_duration("APERIO_PROCESSING_TIMEOUT", 900),
                 ffmpeg_command: parse_env_var("APERIO_FFMPEG_COMMAND", "ffmpeg"),
+                ffprobe_command: parse_env_var("APERIO_FFPROBE_COMMAND", "ffprobe"),
                 video_codec: parse_env_var("APERIO_VIDEO_CODEC", "libx264"),
                 audio_codec: parse_env_var("APERIO_VIDEO_AUDIO_CODEC", "aac"),
                 preset: parse_env_var("APERIO_PRESET", "medium"),
                 crf: parse_env_number("APERIO_CRF", 23) as u32,
                 audio_bitrate: parse_env_var("APERIO_AUDIO_BITRATE", "128k"),
                 max_concurrent_processing: parse_env_number("APERIO_MAX_CONCURRENT_PROCESSING", 1) as usize,
+                ffmpeg_threads: parse_env_number("APERIO_FFMPEG_THREADS", 0) as u32,
+                max_ffmpeg_threads: parse_env_number(
+                    "APERIO_MAX_FFMPEG_THREADS",
+                    std::thread::available_parallelism().map_or(1, |cores| cores.get() as u64),
+                ) as u32,
+                ffmpeg_nice: parse_env_optional("APERIO_FFMPEG_NICE"),
+                preview_enabled: parse_env_bool("APERIO_PREVIEW_ENABLED", false),
+                preview_duration_secs: parse_env_number("APERIO_PREVIEW_DURATION", 4).clamp(3, 5) as u32,
+                normalize_audio: parse_env_bool("APERIO_NORMALIZE_AUDIO", false),
+                loudnorm_integrated: parse_env_float("APERIO_LOUDNORM_I", -16.0),
+                loudnorm_true_peak: parse_env_float("APERIO_LOUDNORM_TP", -1.5),
+                loudnorm_lra: parse_env_float("APERIO_LOUDNORM_LRA", 11.0),
+                max_fps: parse_env_optional("APERIO_MAX_FPS"),
+                strip_metadata: parse_env_bool("APERIO_STRIP_METADATA", false),
+                min_video_bitrate_kbps: parse_env_number("APERIO_MIN_VIDEO_BITRATE", 150) as u32,
+                remux_if_compatible: parse_env_bool("APERIO_REMUX_IF_COMPATIBLE", true),
+                result_cache_enabled: parse_env_bool("APERIO_RESULT_CACHE_ENABLED", false),
+                backend: ProcessorBackend::from_env_value(&parse_env_var("APERIO_PROCESSOR", "ffmpeg")),
+                watermark: source.get("APERIO_WATERMARK_PATH")
+                    .filter(|path| !path.trim().is_empty())
+                    .map(|path| WatermarkConfig {
+                        path: PathBuf::from(path),
+                        position: WatermarkPosition::from_env_value(&parse_env_var("APERIO_WATERMARK_POSITION", "bottom-right")),
+                        margin: parse_env_number("APERIO_WATERMARK_MARGIN", 16) as u32,
+                        opacity: parse_env_float("APERIO_WATERMARK_OPACITY", 0.8).clamp(0.0, 1.0),
+                    }),
             },
             storage: StorageConfig {
                 storage_type: StorageType::Local,
                 local_path: Some(parse_env_var("APERIO_STORAGE_PATH", "/app/storage")),
+                working_dir: PathBuf::from(parse_env_var("APERIO_WORKING_DIR", "/app/working")),
+                database_url: parse_env_var("APERIO_DATABASE_URL", "sqlite:///app/storage/aperio.db"),
+                db_max_connections: parse_env_optional("APERIO_DB_MAX_CONNECTIONS"),
+                max_storage_bytes: Some(parse_env_number("APERIO_MAX_STORAGE_BYTES", 0)).filter(|bytes| *bytes > 0),
+                storage_headroom_bytes: parse_env_number("APERIO_STORAGE_HEADROOM_BYTES", 1024 * 1024 * 1024),
+                storage_reconcile_interval: parse_env_duration("APERIO_STORAGE_RECONCILE_INTERVAL", 900),
+                db_maintenance_interval: Some(parse_env_duration("APERIO_DB_MAINTENANCE_INTERVAL", 86400))
+                    .filter(|every| !every.is_zero()),
+                metrics_snapshot_interval: Some(parse_env_duration("APERIO_METRICS_SNAPSHOT_INTERVAL", 0))
+                    .filter(|every| !every.is_zero()),
+                disk_critical_free_bytes: parse_env_number("APERIO_DISK_CRITICAL_FREE_MB", 1024) * 1024 * 1024,
             },
             security: SecurityConfig {
                 max_file_size_mb: parse_env_number("APERIO_MAX_FILE_SIZE_MB", 500),
@@ -132,20 +684,1045 @@ impl Default for Config {
                     "localhost".to_string(),
                     "0.0.0.0".to_string(),
                 ],
-                auth_password: std::env::var("APERIO_AUTH_PASSWORD").ok(),
+                auth_password: source.get("APERIO_AUTH_PASSWORD"),
+                admin_password: source.get("APERIO_ADMIN_PASSWORD"),
+                api_keys: {
+                    let admin_keys = parse_env_var("APERIO_ADMIN_API_KEYS", "");
+                    let admin_keys: Vec<&str> = admin_keys.split(',').map(str::trim).collect();
+                    let max_active_jobs = parse_key_limits("APERIO_API_KEY_MAX_ACTIVE_JOBS");
+                    let max_daily_jobs = parse_key_limits("APERIO_API_KEY_MAX_DAILY_JOBS");
+                    let keys = parse_env_map("APERIO_API_KEYS");
+                    let named = |name: &str| keys.iter().any(|(key_name, _)| key_name == name);
+                    for (setting, name) in admin_keys.iter().filter(|name| !name.is_empty()).map(|name| ("APERIO_ADMIN_API_KEYS", *name))
+                        .chain(max_active_jobs.keys().map(|name| ("APERIO_API_KEY_MAX_ACTIVE_JOBS", name.as_str())))
+                        .chain(max_daily_jobs.keys().map(|name| ("APERIO_API_KEY_MAX_DAILY_JOBS", name.as_str())))
+                    {
+                        if !named(name) {
+                            source.warn(format!("{setting} names `{name}`, which is not in APERIO_API_KEYS; ignoring it"));
+                        }
+                    }
+                    keys.into_iter()
+                        .map(|(name, key)| ApiKey {
+                            admin: admin_keys.contains(&name.as_str()),
+                            max_active_jobs: max_active_jobs.get(&name).copied(),
+                            max_daily_jobs: max_daily_jobs.get(&name).copied(),
+                            name,
+                            key,
+                        })
+                        .collect()
+                },
+                redact_pattern: source.get("APERIO_REDACT_PATTERN"),
             },
             queue: QueueConfig {
-                max_concurrent_jobs: parse_env_number("APERIO_MAX_CONCURRENT_JOBS", 2) as usize,
+                max_concurrent_jobs: parse_env_optional("APERIO_MAX_CONCURRENT_JOBS").map(|max| max as usize),
+                max_queue_size: parse_env_number("APERIO_MAX_QUEUE_SIZE", 1000) as usize,
+                high_priority_reserved_slots: parse_env_number("APERIO_HIGH_PRIORITY_RESERVED_SLOTS", 0) as usize,
+                low_priority_max_percent: parse_env_number("APERIO_LOW_PRIORITY_MAX_PERCENT", 100).min(100) as u8,
+                error_message_max_lines: parse_env_number("APERIO_ERROR_MESSAGE_MAX_LINES", 20) as usize,
+                error_message_max_bytes: parse_env_number("APERIO_ERROR_MESSAGE_MAX_BYTES", 2000) as usize,
+                slot_wait_timeout: parse_env_duration("APERIO_SLOT_WAIT_TIMEOUT", 300),
+                max_pending_age: parse_env_duration("APERIO_MAX_PENDING_AGE", 3600),
+                worker_stall_timeout: parse_env_duration("APERIO_WORKER_STALL_TIMEOUT", 300),
+                stall_fails_readiness: parse_env_bool("APERIO_QUEUE_STALL_FAILS_READINESS", false),
+                // Unknown names are reported and skipped rather than gating on nothing
+                readiness_checks: parse_env_var("APERIO_READINESS_CHECKS", "database,drain,worker,queue_capacity,disk")
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .filter_map(|name| ReadinessCheck::from_name(name).or_else(|| {
+                        source.warn(format!("APERIO_READINESS_CHECKS names unknown check `{name}`; ignoring it"));
+                        None
+                    }))
+                    .collect(),
+                readiness_queue_percent: parse_env_number("APERIO_READINESS_QUEUE_PERCENT", 90).clamp(1, 100),
             },
             retention: RetentionConfig {
-                enabled: parse_env_var("APERIO_RETENTION_ENABLED", "true").to_lowercase() == "true",
-                retention_days: parse_env_number("APERIO_RETENTION_DAYS", 30) as u32,
+                enabled: parse_env_bool("APERIO_RETENTION_ENABLED", true),
+                file_retention_days: file_retention_days.min(record_retention_days),
+                record_retention_days,
+                max_retain_days: parse_env_number("APERIO_MAX_RETAIN_DAYS", 365).max(1) as u32,
                 cleanup_interval_hours: parse_env_number("APERIO_CLEANUP_INTERVAL_HOURS", 24),
+                idempotency_window_hours: parse_env_number("APERIO_IDEMPOTENCY_WINDOW_HOURS", 24),
+                orphan_grace_hours: parse_env_number("APERIO_ORPHAN_GRACE_HOURS", 24),
+                soft_delete_window_hours: parse_env_number("APERIO_SOFT_DELETE_WINDOW_HOURS", 72),
+                audit_retention_days: parse_env_number("APERIO_AUDIT_RETENTION_DAYS", 90).max(1) as u32,
+                mode: RetentionMode::from_env_value(&parse_env_var("APERIO_RETENTION_MODE", "age")),
+                eviction_high_water_percent: parse_env_number("APERIO_EVICTION_HIGH_WATER_PERCENT", 90).min(100),
+                eviction_low_water_percent: parse_env_number("APERIO_EVICTION_LOW_WATER_PERCENT", 75).min(100),
+                eviction_interval: parse_env_duration("APERIO_EVICTION_INTERVAL", 300),
+            },
+            notify: NotifyConfig {
+                webhook_url: source.get("APERIO_NOTIFY_WEBHOOK_URL").filter(|url| !url.trim().is_empty()),
+                webhook_template: parse_env_var("APERIO_NOTIFY_WEBHOOK_TEMPLATE", DEFAULT_WEBHOOK_TEMPLATE),
+                webhook_secret: source.get("APERIO_NOTIFY_WEBHOOK_SECRET").filter(|secret| !secret.is_empty()),
+                max_per_hour: parse_env_number("APERIO_NOTIFY_MAX_PER_HOUR", 10) as u32,
+                failure_rate_threshold: source.get("APERIO_NOTIFY_FAILURE_RATE").and_then(|value| {
+                    value.trim().parse().map_err(|_| source.warn_defaulted("APERIO_NOTIFY_FAILURE_RATE", &value, "unset")).ok()
+                }),
+                failure_rate_window: parse_env_duration("APERIO_NOTIFY_FAILURE_RATE_WINDOW", 3600),
+            },
+            publish: PublishConfig {
+                blossom_url: source.get("APERIO_PUBLISH_BLOSSOM_URL").filter(|url| !url.trim().is_empty()),
+                nostr_secret_key: source.get("APERIO_PUBLISH_NOSTR_KEY").filter(|key| !key.trim().is_empty()),
+                auth_token: source.get("APERIO_PUBLISH_AUTH_TOKEN").filter(|token| !token.trim().is_empty()),
+                max_attempts: parse_env_number("APERIO_PUBLISH_MAX_ATTEMPTS", 3) as u32,
+            },
+            hooks: HookConfig {
+                post_hook_command: source.get("APERIO_POST_HOOK_COMMAND")
+                    .filter(|path| !path.trim().is_empty())
+                    .map(PathBuf::from),
+                post_hook_timeout: parse_env_duration("APERIO_POST_HOOK_TIMEOUT", 60),
+                post_hook_strict: parse_env_bool("APERIO_POST_HOOK_STRICT", false),
             },
         }
     }
+
+    /// Check settings that would otherwise only fail once jobs run. Returns every problem
+    /// found rather than stopping at the first.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        if self.processing.crf > 51 {
+            problems.push(format!("APERIO_CRF must be between 0 and 51, got {}", self.processing.crf));
+        }
+        if !FFMPEG_PRESETS.contains(&self.processing.preset.as_str()) {
+            problems.push(format!(
+                "APERIO_PRESET `{}` is not a known ffmpeg preset (expected one of {})",
+                self.processing.preset,
+                FFMPEG_PRESETS.join(", ")
+            ));
+        }
+
+        for (key, timeout) in [
+            ("APERIO_CLIENT_TIMEOUT", self.server.client_timeout),
+            ("APERIO_DOWNLOAD_TIMEOUT", self.download.download_timeout),
+            ("APERIO_SLOT_WAIT_TIMEOUT", self.queue.slot_wait_timeout),
+            ("APERIO_MAX_PENDING_AGE", self.queue.max_pending_age),
+            ("APERIO_PROCESSING_TIMEOUT", self.processing.processing_timeout),
+            ("APERIO_SUBSCRIPTION_POLL_INTERVAL", self.download.subscription_poll_interval),
+            ("APERIO_POST_HOOK_TIMEOUT", self.hooks.post_hook_timeout),
+            ("APERIO_NOTIFY_FAILURE_RATE_WINDOW", self.notify.failure_rate_window),
+        ] {
+            if timeout.is_zero() {
+                problems.push(format!("{key} must be greater than 0 seconds"));
+            }
+        }
+
+        for (key, value) in [
+            ("APERIO_MAX_QUEUE_SIZE", self.queue.max_queue_size),
+            ("APERIO_NOTIFY_MAX_PER_HOUR", self.notify.max_per_hour as usize),
+            ("APERIO_PUBLISH_MAX_ATTEMPTS", self.publish.max_attempts as usize),
+            ("APERIO_ERROR_MESSAGE_MAX_LINES", self.queue.error_message_max_lines),
+            ("APERIO_ERROR_MESSAGE_MAX_BYTES", self.queue.error_message_max_bytes),
+            ("APERIO_MAX_PAYLOAD", self.server.max_payload_size),
+            ("APERIO_MAX_JSON_PAYLOAD", self.server.max_json_payload),
+            ("APERIO_MAX_INFLIGHT_READS", self.server.max_inflight_reads),
+            ("APERIO_MAX_INFLIGHT_WRITES", self.server.max_inflight_writes),
+            ("APERIO_HTTP_WORKERS", self.server.http_workers),
+            ("APERIO_BACKLOG", self.server.backlog as usize),
+            ("APERIO_MAX_CONCURRENT_DOWNLOADS", self.download.max_concurrent_downloads),
+            ("APERIO_MAX_CONCURRENT_PROCESSING", self.processing.max_concurrent_processing),
+            ("APERIO_MAX_FFMPEG_THREADS", self.processing.max_ffmpeg_threads as usize),
+        ] {
+            if value == 0 {
+                problems.push(format!("{key} must be at least 1"));
+            }
+        }
+
+        if self.processing.ffmpeg_threads > self.processing.max_ffmpeg_threads {
+            problems.push(format!(
+                "APERIO_FFMPEG_THREADS ({}) must not exceed APERIO_MAX_FFMPEG_THREADS ({})",
+                self.processing.ffmpeg_threads, self.processing.max_ffmpeg_threads
+            ));
+        }
+        // Only lowering priority is allowed without privileges
+        for (key, nice) in [
+            ("APERIO_FFMPEG_NICE", self.processing.ffmpeg_nice),
+            ("APERIO_DOWNLOAD_NICE", self.download.download_nice),
+        ] {
+            if let Some(nice) = nice.filter(|nice| *nice > 19) {
+                problems.push(format!("{key} must be between 0 and 19, got {nice}"));
+            }
+        }
+
+        if let Some(webhook_url) = &self.notify.webhook_url {
+            if !(webhook_url.starts_with("http://") || webhook_url.starts_with("https://")) {
+                problems.push("APERIO_NOTIFY_WEBHOOK_URL must start with http:// or https://".to_string());
+            }
+        }
+        if self.notify.webhook_secret.as_ref().is_some_and(|secret| secret.len() < 16) {
+            problems.push("APERIO_NOTIFY_WEBHOOK_SECRET must be at least 16 characters".to_string());
+        }
+        if let Some(threshold) = self.notify.failure_rate_threshold {
+            if !(threshold > 0.0 && threshold <= 1.0) {
+                problems.push(format!("APERIO_NOTIFY_FAILURE_RATE must be a fraction above 0 and at most 1, got {threshold}"));
+            }
+        }
+
+        if let Some(blossom_url) = &self.publish.blossom_url {
+            if !blossom_url.starts_with("https://") {
+                problems.push("APERIO_PUBLISH_BLOSSOM_URL must start with https://".to_string());
+            }
+            match (&self.publish.nostr_secret_key, &self.publish.auth_token) {
+                (None, None) => problems.push(
+                    "APERIO_PUBLISH_BLOSSOM_URL needs APERIO_PUBLISH_NOSTR_KEY or APERIO_PUBLISH_AUTH_TOKEN".to_string()
+                ),
+                (Some(_), Some(_)) => problems.push(
+                    "Set only one of APERIO_PUBLISH_NOSTR_KEY and APERIO_PUBLISH_AUTH_TOKEN".to_string()
+                ),
+                _ => {}
+            }
+        }
+        if let Some(key) = &self.publish.nostr_secret_key {
+            if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+                problems.push("APERIO_PUBLISH_NOSTR_KEY must be a 64-character hex secret key".to_string());
+            }
+        }
+
+        if let Some(command) = &self.hooks.post_hook_command {
+            if !command.is_absolute() {
+                problems.push(format!("APERIO_POST_HOOK_COMMAND `{}` must be an absolute path", command.display()));
+            }
+        }
+
+        if self.queue.max_concurrent_jobs == Some(0) {
+            problems.push("APERIO_MAX_CONCURRENT_JOBS must be at least 1".to_string());
+        }
+        // Normal and Low jobs need at least one slot in each stage, and under the cap on all
+        // jobs, that isn't reserved
+        let limits = [
+            ("APERIO_MAX_CONCURRENT_DOWNLOADS", Some(self.download.max_concurrent_downloads)),
+            ("APERIO_MAX_CONCURRENT_PROCESSING", Some(self.processing.max_concurrent_processing)),
+            ("APERIO_MAX_CONCURRENT_JOBS", self.queue.max_concurrent_jobs.filter(|max| *max > 0)),
+        ];
+        for (key, slots) in limits.into_iter().filter_map(|(key, slots)| slots.map(|slots| (key, slots))) {
+            if self.queue.high_priority_reserved_slots >= slots {
+                problems.push(format!(
+                    "APERIO_HIGH_PRIORITY_RESERVED_SLOTS ({}) must be less than {key} ({slots})",
+                    self.queue.high_priority_reserved_slots
+                ));
+            }
+        }
+        if self.queue.low_priority_max_percent == 0 {
+            problems.push("APERIO_LOW_PRIORITY_MAX_PERCENT must be between 1 and 100".to_string());
+        }
+        // An idle worker only reports in once per heartbeat interval
+        let min_stall_timeout = WORKER_HEARTBEAT_INTERVAL * 2;
+        if self.queue.worker_stall_timeout < min_stall_timeout {
+            problems.push(format!(
+                "APERIO_WORKER_STALL_TIMEOUT must be at least {} seconds, got {}",
+                min_stall_timeout.as_secs(),
+                self.queue.worker_stall_timeout.as_secs()
+            ));
+        }
+
+        if let Some(public_url) = &self.server.public_url {
+            if !(public_url.starts_with("http://") || public_url.starts_with("https://")) {
+                problems.push(format!("APERIO_PUBLIC_URL `{public_url}` must start with http:// or https://"));
+            }
+        }
+
+        match self.server.listener() {
+            Ok(Listener::Unix(_)) if self.server.tls_cert.is_some() => {
+                problems.push("APERIO_TLS_CERT cannot be used with a unix socket listener".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(e),
+        }
+        if self.server.listen.is_some() && self.server.host_or_port_set {
+            problems.push("APERIO_LISTEN cannot be combined with APERIO_HOST or APERIO_PORT; set only one".to_string());
+        }
+
+        match (&self.server.tls_cert, &self.server.tls_key) {
+            (Some(_), None) => problems.push("APERIO_TLS_CERT is set but APERIO_TLS_KEY is not".to_string()),
+            (None, Some(_)) => problems.push("APERIO_TLS_KEY is set but APERIO_TLS_CERT is not".to_string()),
+            _ => {}
+        }
+
+        if let Some(pattern) = &self.security.redact_pattern {
+            if let Err(e) = regex::Regex::new(pattern) {
+                problems.push(format!("APERIO_REDACT_PATTERN is not a valid regex: {e}"));
+            }
+        }
+
+        let mut key_names = std::collections::HashSet::new();
+        for api_key in &self.security.api_keys {
+            if api_key.name.is_empty() || !api_key.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                problems.push(format!("APERIO_API_KEYS name `{}` may only contain letters, digits, '-' and '_'", api_key.name));
+            }
+            if api_key.key.len() < 16 {
+                problems.push(format!("APERIO_API_KEYS key for `{}` must be at least 16 characters", api_key.name));
+            }
+            if !key_names.insert(api_key.name.as_str()) {
+                problems.push(format!("APERIO_API_KEYS names `{}` more than once", api_key.name));
+            }
+        }
+
+        if self.download.allowed_domains.is_empty() {
+            problems.push("APERIO_ALLOWED_DOMAINS is empty, so every submitted URL would be rejected".to_string());
+        }
+
+        if !matches!(self.download.backend, DownloaderBackend::Mock(_)) && !command_exists(&self.download.download_command) {
+            problems.push(format!(
+                "APERIO_DOWNLOAD_COMMAND `{}` was not found on PATH",
+                self.download.download_command
+            ));
+        }
+        if matches!(self.processing.backend, ProcessorBackend::Ffmpeg) && !command_exists(&self.processing.ffmpeg_command) {
+            problems.push(format!(
+                "APERIO_FFMPEG_COMMAND `{}` was not found on PATH; install ffmpeg or set APERIO_PROCESSOR=passthrough",
+                self.processing.ffmpeg_command
+            ));
+        }
+
+        if self.retention.enabled {
+            if self.retention.cleanup_interval_hours == 0 {
+                problems.push("APERIO_CLEANUP_INTERVAL_HOURS must be at least 1 while retention is enabled".to_string());
+            }
+            if self.retention.record_retention_days == 0 {
+                problems.push("APERIO_RECORD_RETENTION_DAYS must be at least 1 while retention is enabled".to_string());
+            }
+        }
+        if self.retention.mode == RetentionMode::Lru {
+            if self.retention.eviction_low_water_percent >= self.retention.eviction_high_water_percent {
+                problems.push(format!(
+                    "APERIO_EVICTION_LOW_WATER_PERCENT ({}) must be below APERIO_EVICTION_HIGH_WATER_PERCENT ({})",
+                    self.retention.eviction_low_water_percent, self.retention.eviction_high_water_percent
+                ));
+            }
+            if self.retention.eviction_interval.is_zero() {
+                problems.push("APERIO_EVICTION_INTERVAL must be greater than 0 seconds".to_string());
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Whether `command` names an executable, either as a path or by searching PATH
+fn command_exists(command: &str) -> bool {
+    if command.contains(std::path::MAIN_SEPARATOR) {
+        return Path::new(command).is_file();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+        .unwrap_or(false)
+}
+
+/// Type a config file value must have. File values are rendered into env var syntax so both
+/// go through the same parsing below.
+#[derive(Clone, Copy, Debug)]
+enum ValueKind {
+    Str,
+    Int,
+    Float,
+    Bool,
+    /// Array of strings, joined with commas
+    List,
+    /// Array of strings, rendered as a JSON array
+    Args,
+    /// Table of strings, rendered as `name=value` pairs
+    Map,
+}
+
+/// Every setting the config file may contain: the env var that overrides it, its `section.key`, and its type
+const SETTINGS: &[(&str, &str, ValueKind)] = &[
+    ("APERIO_HOST", "server.host", ValueKind::Str),
+    ("APERIO_PORT", "server.port", ValueKind::Int),
+    ("APERIO_LISTEN", "server.listen", ValueKind::Str),
+    ("APERIO_SOCKET_MODE", "server.socket_mode", ValueKind::Str),
+    ("APERIO_CLIENT_TIMEOUT", "server.client_timeout", ValueKind::Int),
+    ("APERIO_KEEP_ALIVE", "server.keep_alive", ValueKind::Int),
+    ("APERIO_HTTP_WORKERS", "server.http_workers", ValueKind::Int),
+    ("APERIO_BACKLOG", "server.backlog", ValueKind::Int),
+    ("APERIO_SHUTDOWN_TIMEOUT", "server.shutdown_timeout", ValueKind::Int),
+    ("APERIO_MAX_PAYLOAD", "server.max_payload_size", ValueKind::Int),
+    ("APERIO_MAX_JSON_PAYLOAD", "server.max_json_payload", ValueKind::Int),
+    ("APERIO_MAX_INFLIGHT_READS", "server.max_inflight_reads", ValueKind::Int),
+    ("APERIO_MAX_INFLIGHT_WRITES", "server.max_inflight_writes", ValueKind::Int),
+    ("APERIO_MAX_RSS_MB", "server.max_rss_bytes", ValueKind::Int),
+    ("APERIO_CORS_ORIGINS", "server.cors_origins", ValueKind::List),
+    ("APERIO_TRUSTED_PROXIES", "server.trusted_proxies", ValueKind::List),
+    ("APERIO_SWAGGER_UI", "server.swagger_ui", ValueKind::Bool),
+    ("APERIO_PUBLIC_URL", "server.public_url", ValueKind::Str),
+    ("APERIO_TLS_CERT", "server.tls_cert", ValueKind::Str),
+    ("APERIO_TLS_KEY", "server.tls_key", ValueKind::Str),
+    ("APERIO_DOWNLOAD_TIMEOUT", "download.download_timeout", ValueKind::Int),
+    ("APERIO_DOWNLOAD_COMMAND", "download.download_command", ValueKind::Str),
+    ("APERIO_ALLOWED_DOMAINS", "download.allowed_domains", ValueKind::List),
+    ("APERIO_MAX_CONCURRENT_DOWNLOADS", "download.max_concurrent_downloads", ValueKind::Int),
+    ("APERIO_MAX_PLAYLIST_ITEMS", "download.max_playlist_items", ValueKind::Int),
+    ("APERIO_ALLOW_LIVE_STREAMS", "download.allow_live_streams", ValueKind::Bool),
+    ("APERIO_MAX_LIVE_DURATION", "download.max_live_duration", ValueKind::Int),
+    ("APERIO_RATE_LIMIT_COOLDOWN", "download.rate_limit_cooldown", ValueKind::Int),
+    ("APERIO_MAX_RATE_LIMIT_DEFERRALS", "download.max_rate_limit_deferrals", ValueKind::Int),
+    ("APERIO_COOKIES_FILE", "download.cookies_file", ValueKind::Str),
+    ("APERIO_AUTH_PROFILES", "download.auth_profiles", ValueKind::Map),
+    ("APERIO_DOWNLOAD_CONCURRENT_FRAGMENTS", "download.concurrent_fragments", ValueKind::Int),
+    ("APERIO_YTDLP_EXTRA_ARGS", "download.extra_args", ValueKind::Args),
+    ("APERIO_DOWNLOADER", "download.backend", ValueKind::Str),
+    ("APERIO_MOCK_DOWNLOAD_FIXTURE", "download.mock_fixture", ValueKind::Str),
+    ("APERIO_HTTP_DOWNLOAD_COMMAND", "download.http_download_command", ValueKind::Str),
+    ("APERIO_DOWNLOAD_NICE", "download.download_nice", ValueKind::Int),
+    ("APERIO_MIN_DOWNLOAD_SIZE_KB", "download.min_download_size_kb", ValueKind::Int),
+    ("APERIO_MEDIA_HOSTS", "download.media_hosts", ValueKind::List),
+    ("APERIO_SUBSCRIPTION_POLL_INTERVAL", "download.subscription_poll_interval", ValueKind::Int),
+    ("APERIO_PROCESSING_TIMEOUT", "processing.processing_timeout", ValueKind::Int),
+    ("APERIO_FFMPEG_COMMAND", "processing.ffmpeg_command", ValueKind::Str),
+    ("APERIO_FFPROBE_COMMAND", "processing.ffprobe_command", ValueKind::Str),
+    ("APERIO_VIDEO_CODEC", "processing.video_codec", ValueKind::Str),
+    ("APERIO_VIDEO_AUDIO_CODEC", "processing.audio_codec", ValueKind::Str),
+    ("APERIO_PRESET", "processing.preset", ValueKind::Str),
+    ("APERIO_CRF", "processing.crf", ValueKind::Int),
+    ("APERIO_AUDIO_BITRATE", "processing.audio_bitrate", ValueKind::Str),
+    ("APERIO_MAX_CONCURRENT_PROCESSING", "processing.max_concurrent_processing", ValueKind::Int),
+    ("APERIO_FFMPEG_THREADS", "processing.ffmpeg_threads", ValueKind::Int),
+    ("APERIO_MAX_FFMPEG_THREADS", "processing.max_ffmpeg_threads", ValueKind::Int),
+    ("APERIO_FFMPEG_NICE", "processing.ffmpeg_nice", ValueKind::Int),
+    ("APERIO_PREVIEW_ENABLED", "processing.preview_enabled", ValueKind::Bool),
+    ("APERIO_PREVIEW_DURATION", "processing.preview_duration_secs", ValueKind::Int),
+    ("APERIO_NORMALIZE_AUDIO", "processing.normalize_audio", ValueKind::Bool),
+    ("APERIO_LOUDNORM_I", "processing.loudnorm_integrated", ValueKind::Float),
+    ("APERIO_LOUDNORM_TP", "processing.loudnorm_true_peak", ValueKind::Float),
+    ("APERIO_LOUDNORM_LRA", "processing.loudnorm_lra", ValueKind::Float),
+    ("APERIO_MAX_FPS", "processing.max_fps", ValueKind::Int),
+    ("APERIO_STRIP_METADATA", "processing.strip_metadata", ValueKind::Bool),
+    ("APERIO_MIN_VIDEO_BITRATE", "processing.min_video_bitrate_kbps", ValueKind::Int),
+    ("APERIO_REMUX_IF_COMPATIBLE", "processing.remux_if_compatible", ValueKind::Bool),
+    ("APERIO_RESULT_CACHE_ENABLED", "processing.result_cache_enabled", ValueKind::Bool),
+    ("APERIO_PROCESSOR", "processing.backend", ValueKind::Str),
+    ("APERIO_WATERMARK_PATH", "processing.watermark_path", ValueKind::Str),
+    ("APERIO_WATERMARK_POSITION", "processing.watermark_position", ValueKind::Str),
+    ("APERIO_WATERMARK_MARGIN", "processing.watermark_margin", ValueKind::Int),
+    ("APERIO_WATERMARK_OPACITY", "processing.watermark_opacity", ValueKind::Float),
+    ("APERIO_STORAGE_PATH", "storage.local_path", ValueKind::Str),
+    ("APERIO_WORKING_DIR", "storage.working_dir", ValueKind::Str),
+    ("APERIO_DATABASE_URL", "storage.database_url", ValueKind::Str),
+    ("APERIO_DB_MAX_CONNECTIONS", "storage.db_max_connections", ValueKind::Int),
+    ("APERIO_MAX_STORAGE_BYTES", "storage.max_storage_bytes", ValueKind::Int),
+    ("APERIO_STORAGE_HEADROOM_BYTES", "storage.storage_headroom_bytes", ValueKind::Int),
+    ("APERIO_STORAGE_RECONCILE_INTERVAL", "storage.storage_reconcile_interval", ValueKind::Int),
+    ("APERIO_DB_MAINTENANCE_INTERVAL", "storage.db_maintenance_interval", ValueKind::Int),
+    ("APERIO_METRICS_SNAPSHOT_INTERVAL", "storage.metrics_snapshot_interval", ValueKind::Int),
+    ("APERIO_DISK_CRITICAL_FREE_MB", "storage.disk_critical_free_mb", ValueKind::Int),
+    ("APERIO_MAX_FILE_SIZE_MB", "security.max_file_size_mb", ValueKind::Int),
+    ("APERIO_MAX_URL_LENGTH", "security.max_url_length", ValueKind::Int),
+    ("APERIO_AUTH_PASSWORD", "security.auth_password", ValueKind::Str),
+    ("APERIO_ADMIN_PASSWORD", "security.admin_password", ValueKind::Str),
+    ("APERIO_API_KEYS", "security.api_keys", ValueKind::Map),
+    ("APERIO_ADMIN_API_KEYS", "security.admin_api_keys", ValueKind::List),
+    ("APERIO_API_KEY_MAX_ACTIVE_JOBS", "security.api_key_max_active_jobs", ValueKind::Map),
+    ("APERIO_API_KEY_MAX_DAILY_JOBS", "security.api_key_max_daily_jobs", ValueKind::Map),
+    ("APERIO_REDACT_PATTERN", "security.redact_pattern", ValueKind::Str),
+    ("APERIO_MAX_CONCURRENT_JOBS", "queue.max_concurrent_jobs", ValueKind::Int),
+    ("APERIO_MAX_QUEUE_SIZE", "queue.max_queue_size", ValueKind::Int),
+    ("APERIO_HIGH_PRIORITY_RESERVED_SLOTS", "queue.high_priority_reserved_slots", ValueKind::Int),
+    ("APERIO_LOW_PRIORITY_MAX_PERCENT", "queue.low_priority_max_percent", ValueKind::Int),
+    ("APERIO_ERROR_MESSAGE_MAX_LINES", "queue.error_message_max_lines", ValueKind::Int),
+    ("APERIO_ERROR_MESSAGE_MAX_BYTES", "queue.error_message_max_bytes", ValueKind::Int),
+    ("APERIO_SLOT_WAIT_TIMEOUT", "queue.slot_wait_timeout", ValueKind::Int),
+    ("APERIO_MAX_PENDING_AGE", "queue.max_pending_age", ValueKind::Int),
+    ("APERIO_WORKER_STALL_TIMEOUT", "queue.worker_stall_timeout", ValueKind::Int),
+    ("APERIO_QUEUE_STALL_FAILS_READINESS", "queue.stall_fails_readiness", ValueKind::Bool),
+    ("APERIO_READINESS_CHECKS", "queue.readiness_checks", ValueKind::List),
+    ("APERIO_READINESS_QUEUE_PERCENT", "queue.readiness_queue_percent", ValueKind::Int),
+    ("APERIO_RETENTION_ENABLED", "retention.enabled", ValueKind::Bool),
+    ("APERIO_RETENTION_DAYS", "retention.retention_days", ValueKind::Int),
+    ("APERIO_FILE_RETENTION_DAYS", "retention.file_retention_days", ValueKind::Int),
+    ("APERIO_RECORD_RETENTION_DAYS", "retention.record_retention_days", ValueKind::Int),
+    ("APERIO_MAX_RETAIN_DAYS", "retention.max_retain_days", ValueKind::Int),
+    ("APERIO_CLEANUP_INTERVAL_HOURS", "retention.cleanup_interval_hours", ValueKind::Int),
+    ("APERIO_IDEMPOTENCY_WINDOW_HOURS", "retention.idempotency_window_hours", ValueKind::Int),
+    ("APERIO_ORPHAN_GRACE_HOURS", "retention.orphan_grace_hours", ValueKind::Int),
+    ("APERIO_SOFT_DELETE_WINDOW_HOURS", "retention.soft_delete_window_hours", ValueKind::Int),
+    ("APERIO_AUDIT_RETENTION_DAYS", "retention.audit_retention_days", ValueKind::Int),
+    ("APERIO_RETENTION_MODE", "retention.mode", ValueKind::Str),
+    ("APERIO_EVICTION_HIGH_WATER_PERCENT", "retention.eviction_high_water_percent", ValueKind::Int),
+    ("APERIO_EVICTION_LOW_WATER_PERCENT", "retention.eviction_low_water_percent", ValueKind::Int),
+    ("APERIO_EVICTION_INTERVAL", "retention.eviction_interval", ValueKind::Int),
+    ("APERIO_NOTIFY_WEBHOOK_URL", "notify.webhook_url", ValueKind::Str),
+    ("APERIO_NOTIFY_WEBHOOK_TEMPLATE", "notify.webhook_template", ValueKind::Str),
+    ("APERIO_NOTIFY_WEBHOOK_SECRET", "notify.webhook_secret", ValueKind::Str),
+    ("APERIO_NOTIFY_MAX_PER_HOUR", "notify.max_per_hour", ValueKind::Int),
+    ("APERIO_NOTIFY_FAILURE_RATE", "notify.failure_rate", ValueKind::Float),
+    ("APERIO_NOTIFY_FAILURE_RATE_WINDOW", "notify.failure_rate_window", ValueKind::Int),
+    ("APERIO_PUBLISH_BLOSSOM_URL", "publish.blossom_url", ValueKind::Str),
+    ("APERIO_PUBLISH_NOSTR_KEY", "publish.nostr_key", ValueKind::Str),
+    ("APERIO_PUBLISH_AUTH_TOKEN", "publish.auth_token", ValueKind::Str),
+    ("APERIO_PUBLISH_MAX_ATTEMPTS", "publish.max_attempts", ValueKind::Int),
+    ("APERIO_POST_HOOK_COMMAND", "hooks.post_hook_command", ValueKind::Str),
+    ("APERIO_POST_HOOK_TIMEOUT", "hooks.post_hook_timeout", ValueKind::Int),
+    ("APERIO_POST_HOOK_STRICT", "hooks.post_hook_strict", ValueKind::Bool),
+];
+
+/// Settings a config reload applies to the running server; changing any other setting
+/// requires a restart
+pub const RELOADABLE_SETTINGS: &[&str] = &[
+    "APERIO_ALLOWED_DOMAINS",
+    "APERIO_PROCESSING_TIMEOUT",
+    "APERIO_FFMPEG_COMMAND",
+    "APERIO_FFPROBE_COMMAND",
+    "APERIO_VIDEO_CODEC",
+    "APERIO_VIDEO_AUDIO_CODEC",
+    "APERIO_PRESET",
+    "APERIO_CRF",
+    "APERIO_AUDIO_BITRATE",
+    "APERIO_FFMPEG_THREADS",
+    "APERIO_MAX_FFMPEG_THREADS",
+    "APERIO_FFMPEG_NICE",
+    "APERIO_PREVIEW_ENABLED",
+    "APERIO_PREVIEW_DURATION",
+    "APERIO_NORMALIZE_AUDIO",
+    "APERIO_LOUDNORM_I",
+    "APERIO_LOUDNORM_TP",
+    "APERIO_LOUDNORM_LRA",
+    "APERIO_MAX_FPS",
+    "APERIO_STRIP_METADATA",
+    "APERIO_MIN_VIDEO_BITRATE",
+    "APERIO_REMUX_IF_COMPATIBLE",
+    "APERIO_WATERMARK_PATH",
+    "APERIO_WATERMARK_POSITION",
+    "APERIO_WATERMARK_MARGIN",
+    "APERIO_WATERMARK_OPACITY",
+    "APERIO_RETENTION_DAYS",
+    "APERIO_FILE_RETENTION_DAYS",
+    "APERIO_RECORD_RETENTION_DAYS",
+    "APERIO_MAX_RETAIN_DAYS",
+    "APERIO_IDEMPOTENCY_WINDOW_HOURS",
+    "APERIO_ORPHAN_GRACE_HOURS",
+    "APERIO_SOFT_DELETE_WINDOW_HOURS",
+    "APERIO_AUDIT_RETENTION_DAYS",
+];
+
+/// Settings that may instead be read from the file named by `{KEY}_FILE`, for Docker secrets.
+/// Their values are never printed.
+const SECRET_SETTINGS: [&str; 7] = [
+    "APERIO_AUTH_PASSWORD",
+    "APERIO_ADMIN_PASSWORD",
+    "APERIO_API_KEYS",
+    "APERIO_NOTIFY_WEBHOOK_URL",
+    "APERIO_NOTIFY_WEBHOOK_SECRET",
+    "APERIO_PUBLISH_NOSTR_KEY",
+    "APERIO_PUBLISH_AUTH_TOKEN",
+];
+
+/// Where a setting's effective value came from
+#[derive(Clone, Copy, Debug)]
+pub enum ValueSource {
+    Env,
+    SecretFile,
+    ConfigFile,
+    Default,
+}
+
+impl std::fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueSource::Env => write!(f, "env"),
+            ValueSource::SecretFile => write!(f, "secret file"),
+            ValueSource::ConfigFile => write!(f, "config file"),
+            ValueSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// Resolves settings from env vars, then `_FILE` secrets, then the config file, and records
+/// the value each one ended up with
+pub struct ConfigSource {
+    file_path: Option<PathBuf>,
+    file_values: HashMap<&'static str, String>,
+    resolved: RefCell<BTreeMap<String, (Option<String>, ValueSource)>>,
+    errors: RefCell<Vec<String>>,
+    /// Values that failed to parse and were replaced by their default
+    warnings: RefCell<Vec<String>>,
+}
+
+impl ConfigSource {
+    /// Env vars only, as before config files existed
+    pub fn from_env() -> Self {
+        Self {
+            file_path: None,
+            file_values: HashMap::new(),
+            resolved: RefCell::new(BTreeMap::new()),
+            errors: RefCell::new(Vec::new()),
+            warnings: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Read a TOML config file. Unknown keys are logged and skipped; values of the wrong type
+    /// are all reported together as an error naming each key.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config file {}: {e}", path.display()))?;
+        let table: toml::Table = contents.parse()
+            .map_err(|e| format!("failed to parse config file {}: {e}", path.display()))?;
+
+        let mut file_values = HashMap::new();
+        let mut errors = Vec::new();
+        for (section, values) in &table {
+            let Some(values) = values.as_table() else {
+                warn!("Ignoring unknown config file key `{}`", section);
+                continue;
+            };
+            for (key, value) in values {
+                let file_key = format!("{section}.{key}");
+                let Some((env_key, _, kind)) = SETTINGS.iter().find(|(_, setting, _)| *setting == file_key) else {
+                    warn!("Ignoring unknown config file key `{}`", file_key);
+                    continue;
+                };
+                match render_file_value(value, *kind) {
+                    Some(rendered) => {
+                        file_values.insert(*env_key, rendered);
+                    }
+                    None => errors.push(format!("`{file_key}` must be {}, found {}", kind_description(*kind), value.type_str())),
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(format!("invalid config file {}: {}", path.display(), errors.join("; ")));
+        }
+        Ok(Self {
+            file_path: Some(path.to_path_buf()),
+            file_values,
+            resolved: RefCell::new(BTreeMap::new()),
+            errors: RefCell::new(Vec::new()),
+            warnings: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// A setting's raw value, or None when nothing sets it
+    pub fn get(&self, key: &str) -> Option<String> {
+        let (value, source) = if let Ok(value) = std::env::var(key) {
+            (Some(value), ValueSource::Env)
+        } else if let Some(value) = self.read_secret_file(key) {
+            (Some(value), ValueSource::SecretFile)
+        } else if let Some(value) = self.file_values.get(key) {
+            (Some(value.clone()), ValueSource::ConfigFile)
+        } else {
+            (None, ValueSource::Default)
+        };
+        self.resolved.borrow_mut().insert(key.to_string(), (value.clone(), source));
+        value
+    }
+
+    /// A setting's raw value, falling back to `default`
+    pub fn get_or(&self, key: &str, default: &str) -> String {
+        self.get(key).unwrap_or_else(|| {
+            self.resolved.borrow_mut().insert(key.to_string(), (Some(default.to_string()), ValueSource::Default));
+            default.to_string()
+        })
+    }
+
+    /// Record that `key` held an unparseable value and `default` is used instead
+    fn warn_defaulted(&self, key: &str, value: &str, default: &str) {
+        self.warn(format!("{key}={value:?} could not be parsed; using {default}"));
+    }
+
+    fn warn(&self, message: String) {
+        self.warnings.borrow_mut().push(message);
+    }
+
+    fn read_secret_file(&self, key: &str) -> Option<String> {
+        if !SECRET_SETTINGS.contains(&key) {
+            return None;
+        }
+        let path = std::env::var(format!("{key}_FILE")).ok()?;
+        match std::fs::read_to_string(&path) {
+            // Secrets files usually end with a newline that isn't part of the secret
+            Ok(contents) => Some(contents.trim_end_matches(['\r', '\n']).to_string()),
+            Err(e) => {
+                self.errors.borrow_mut().push(format!("failed to read {key}_FILE {path}: {e}"));
+                None
+            }
+        }
+    }
+
+    /// One line per setting read while building the config, with secrets redacted
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![match &self.file_path {
+            Some(path) => format!("# config file: {}", path.display()),
+            None => "# config file: none".to_string(),
+        }];
+        for (key, (value, source)) in self.resolved.borrow().iter() {
+            lines.push(format!("{key}={} ({source})", display_value(key, value.as_deref())));
+        }
+        lines
+    }
+
+    /// The value of every setting read while building the config, with secrets redacted
+    pub fn values(&self) -> BTreeMap<String, String> {
+        self.resolved.borrow().iter()
+            .map(|(key, (value, _))| (key.clone(), display_value(key, value.as_deref())))
+            .collect()
+    }
+}
+
+fn display_value(key: &str, value: Option<&str>) -> String {
+    match value {
+        Some(_) if SECRET_SETTINGS.contains(&key) => "<redacted>".to_string(),
+        Some(value) => value.to_string(),
+        None => "<unset>".to_string(),
+    }
+}
+
+/// Render a file value in the syntax of the env var it stands in for; None if the type is wrong
+fn render_file_value(value: &toml::Value, kind: ValueKind) -> Option<String> {
+    let strings = |value: &toml::Value| -> Option<Vec<String>> {
+        value.as_array()?.iter().map(|item| item.as_str().map(str::to_string)).collect()
+    };
+    match kind {
+        ValueKind::Str => value.as_str().map(str::to_string),
+        ValueKind::Int => value.as_integer().filter(|n| *n >= 0).map(|n| n.to_string()),
+        ValueKind::Float => value.as_float().or_else(|| value.as_integer().map(|n| n as f64)).map(|n| n.to_string()),
+        ValueKind::Bool => value.as_bool().map(|b| b.to_string()),
+        ValueKind::List => strings(value).map(|items| items.join(",")),
+        ValueKind::Args => strings(value).and_then(|items| serde_json::to_string(&items).ok()),
+        ValueKind::Map => value.as_table()?
+            .iter()
+            .map(|(name, value)| {
+                let value = value.as_str().map(str::to_string).or_else(|| value.as_integer().map(|n| n.to_string()));
+                value.map(|value| format!("{name}={value}"))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|pairs| pairs.join(",")),
+    }
+}
+
+fn kind_description(kind: ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Str => "a string",
+        ValueKind::Int => "a non-negative integer",
+        ValueKind::Float => "a number",
+        ValueKind::Bool => "a boolean",
+        ValueKind::List | ValueKind::Args => "an array of strings",
+        ValueKind::Map => "a table of strings or integers",
+    }
+}
+
+/// Read the optional TOML config file named by `APERIO_CONFIG` (or `./aperio.toml` when present),
+/// then apply env var overrides on top. Returns the config with the source it was resolved from.
+pub fn load_config() -> Result<(Config, ConfigSource), String> {
+    let source = match std::env::var("APERIO_CONFIG") {
+        Ok(path) => ConfigSource::from_file(Path::new(&path))?,
+        Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => ConfigSource::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+        Err(_) => ConfigSource::from_env(),
+    };
+    if let Some(path) = &source.file_path {
+        info!("Loaded config file {}", path.display());
+    }
+
+    let config = Config::from_source(&source);
+    for warning in source.warnings.borrow().iter() {
+        warn!("{}", warning);
+    }
+    let errors = source.errors.borrow().clone();
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok((config, source))
 }
 
-pub fn load_config() -> Config {
-    Config::default()
-}
\ No newline at end of file
+const DEFAULT_CONFIG_FILE: &str = "aperio.toml";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_args_keep_values_with_spaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aperio.toml");
+        std::fs::write(&path, r#"
+[download]
+extra_args = ["--user-agent", "Mozilla/5.0 (X11; Linux x86_64)", "--add-header", "Accept-Language: en \"GB\""]
+"#).unwrap();
+
+        let source = ConfigSource::from_file(&path).unwrap();
+        let config = Config::from_source(&source);
+        assert_eq!(
+            config.download.extra_args,
+            ["--user-agent", "Mozilla/5.0 (X11; Linux x86_64)", "--add-header", "Accept-Language: en \"GB\""]
+        );
+        assert!(source.errors.borrow().is_empty());
+    }
+
+    #[test]
+    fn file_args_must_be_an_array() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aperio.toml");
+        std::fs::write(&path, "[download]\nextra_args = \"--limit-rate 5M\"\n").unwrap();
+
+        let error = ConfigSource::from_file(&path).err().unwrap();
+        assert!(error.contains("`download.extra_args` must be an array of strings"), "{error}");
+    }
+
+    /// A config that passes validation in any environment: `sh` stands in for yt-dlp and ffmpeg
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.download.backend = DownloaderBackend::YtDlp;
+        config.download.download_command = "sh".to_string();
+        config.download.allowed_domains = vec!["youtube.com".to_string()];
+        config.processing.backend = ProcessorBackend::Ffmpeg;
+        config.processing.ffmpeg_command = "sh".to_string();
+        config.server.listen = None;
+        config.server.host_or_port_set = false;
+        config.server.tls_cert = None;
+        config.server.tls_key = None;
+        config.server.public_url = None;
+        config.security.api_keys.clear();
+        config.security.redact_pattern = None;
+        config.notify.webhook_url = None;
+        config.notify.failure_rate_threshold = None;
+        config.notify.webhook_secret = None;
+        config.publish.blossom_url = None;
+        config.publish.nostr_secret_key = None;
+        config.publish.auth_token = None;
+        config.hooks.post_hook_command = None;
+        config.retention.mode = RetentionMode::Age;
+        config
+    }
+
+    /// The problems validation reports once `configure` has changed a valid config
+    fn problems(configure: impl FnOnce(&mut Config)) -> Vec<String> {
+        let mut config = valid();
+        configure(&mut config);
+        config.validate().err().unwrap_or_default()
+    }
+
+    #[track_caller]
+    fn assert_rejected(configure: impl FnOnce(&mut Config), expected: &str) {
+        let problems = problems(configure);
+        assert!(problems.iter().any(|problem| problem.contains(expected)), "expected `{expected}` in {problems:?}");
+    }
+
+    fn api_key(name: &str, key: &str) -> ApiKey {
+        ApiKey { name: name.to_string(), key: key.to_string(), admin: false, max_active_jobs: None, max_daily_jobs: None }
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        assert_eq!(problems(|_| {}), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let problems = problems(|config| {
+            config.processing.crf = 60;
+            config.processing.preset = "warp".to_string();
+            config.download.allowed_domains.clear();
+        });
+        assert_eq!(problems.len(), 3, "{problems:?}");
+    }
+
+    #[test]
+    fn crf_must_be_in_range() {
+        assert!(problems(|config| config.processing.crf = 51).is_empty());
+        assert_rejected(|config| config.processing.crf = 52, "APERIO_CRF must be between 0 and 51, got 52");
+    }
+
+    #[test]
+    fn preset_must_be_known() {
+        assert_rejected(|config| config.processing.preset = "warp".to_string(), "APERIO_PRESET `warp` is not a known ffmpeg preset");
+    }
+
+    #[test]
+    fn timeouts_must_be_non_zero() {
+        assert_rejected(|config| config.download.download_timeout = Duration::ZERO, "APERIO_DOWNLOAD_TIMEOUT must be greater than 0 seconds");
+        assert_rejected(|config| config.processing.processing_timeout = Duration::ZERO, "APERIO_PROCESSING_TIMEOUT must be greater than 0 seconds");
+        assert_rejected(|config| config.hooks.post_hook_timeout = Duration::ZERO, "APERIO_POST_HOOK_TIMEOUT must be greater than 0 seconds");
+    }
+
+    #[test]
+    fn counts_must_be_at_least_one() {
+        assert_rejected(|config| config.download.max_concurrent_downloads = 0, "APERIO_MAX_CONCURRENT_DOWNLOADS must be at least 1");
+        assert_rejected(|config| config.processing.max_concurrent_processing = 0, "APERIO_MAX_CONCURRENT_PROCESSING must be at least 1");
+        assert_rejected(|config| config.queue.max_queue_size = 0, "APERIO_MAX_QUEUE_SIZE must be at least 1");
+        assert_rejected(|config| config.server.max_json_payload = 0, "APERIO_MAX_JSON_PAYLOAD must be at least 1");
+        assert_rejected(|config| config.queue.max_concurrent_jobs = Some(0), "APERIO_MAX_CONCURRENT_JOBS must be at least 1");
+    }
+
+    #[test]
+    fn ffmpeg_threads_must_fit_under_the_cap() {
+        assert_rejected(|config| {
+            config.processing.max_ffmpeg_threads = 4;
+            config.processing.ffmpeg_threads = 8;
+        }, "APERIO_FFMPEG_THREADS (8) must not exceed APERIO_MAX_FFMPEG_THREADS (4)");
+    }
+
+    #[test]
+    fn nice_values_only_lower_priority() {
+        assert!(problems(|config| config.processing.ffmpeg_nice = Some(19)).is_empty());
+        assert_rejected(|config| config.processing.ffmpeg_nice = Some(20), "APERIO_FFMPEG_NICE must be between 0 and 19, got 20");
+        assert_rejected(|config| config.download.download_nice = Some(40), "APERIO_DOWNLOAD_NICE must be between 0 and 19, got 40");
+    }
+
+    #[test]
+    fn failure_notifications_need_a_web_url_and_a_fraction() {
+        assert_rejected(|config| config.notify.webhook_url = Some("ftp://alerts".to_string()), "APERIO_NOTIFY_WEBHOOK_URL must start with http:// or https://");
+        assert_rejected(|config| config.notify.failure_rate_threshold = Some(0.0), "APERIO_NOTIFY_FAILURE_RATE must be a fraction above 0 and at most 1");
+        assert_rejected(|config| config.notify.failure_rate_threshold = Some(1.5), "got 1.5");
+        assert!(problems(|config| config.notify.webhook_secret = Some("0123456789abcdef".to_string())).is_empty());
+        assert_rejected(|config| config.notify.webhook_secret = Some("short".to_string()), "APERIO_NOTIFY_WEBHOOK_SECRET must be at least 16 characters");
+    }
+
+    #[test]
+    fn publishing_needs_https_and_exactly_one_credential() {
+        let blossom = |config: &mut Config| config.publish.blossom_url = Some("https://blossom.example".to_string());
+        assert_rejected(|config| {
+            blossom(config);
+            config.publish.blossom_url = Some("http://blossom.example".to_string());
+            config.publish.auth_token = Some("token".to_string());
+        }, "APERIO_PUBLISH_BLOSSOM_URL must start with https://");
+        assert_rejected(blossom, "needs APERIO_PUBLISH_NOSTR_KEY or APERIO_PUBLISH_AUTH_TOKEN");
+        assert_rejected(|config| {
+            blossom(config);
+            config.publish.auth_token = Some("token".to_string());
+            config.publish.nostr_secret_key = Some("a".repeat(64));
+        }, "Set only one of APERIO_PUBLISH_NOSTR_KEY and APERIO_PUBLISH_AUTH_TOKEN");
+        assert_rejected(|config| config.publish.nostr_secret_key = Some("xyz".to_string()), "APERIO_PUBLISH_NOSTR_KEY must be a 64-character hex secret key");
+    }
+
+    #[test]
+    fn post_hook_must_be_an_absolute_path() {
+        assert!(problems(|config| config.hooks.post_hook_command = Some(PathBuf::from("/usr/local/bin/hook"))).is_empty());
+        assert_rejected(|config| config.hooks.post_hook_command = Some(PathBuf::from("hook.sh")), "APERIO_POST_HOOK_COMMAND `hook.sh` must be an absolute path");
+    }
+
+    #[test]
+    fn priority_settings_leave_room_for_normal_jobs() {
+        assert_rejected(|config| {
+            config.download.max_concurrent_downloads = 2;
+            config.queue.high_priority_reserved_slots = 2;
+        }, "APERIO_HIGH_PRIORITY_RESERVED_SLOTS (2) must be less than APERIO_MAX_CONCURRENT_DOWNLOADS (2)");
+        assert_rejected(|config| {
+            config.queue.max_concurrent_jobs = Some(1);
+            config.queue.high_priority_reserved_slots = 1;
+        }, "APERIO_HIGH_PRIORITY_RESERVED_SLOTS (1) must be less than APERIO_MAX_CONCURRENT_JOBS (1)");
+        assert_rejected(|config| config.queue.low_priority_max_percent = 0, "APERIO_LOW_PRIORITY_MAX_PERCENT must be between 1 and 100");
+    }
+
+    #[test]
+    fn stall_timeout_covers_two_heartbeats() {
+        assert_rejected(
+            |config| config.queue.worker_stall_timeout = WORKER_HEARTBEAT_INTERVAL,
+            &format!("APERIO_WORKER_STALL_TIMEOUT must be at least {} seconds", (WORKER_HEARTBEAT_INTERVAL * 2).as_secs()),
+        );
+    }
+
+    #[test]
+    fn public_url_must_be_a_web_url() {
+        assert_rejected(|config| config.server.public_url = Some("aperio.example".to_string()), "APERIO_PUBLIC_URL `aperio.example` must start with http:// or https://");
+    }
+
+    #[test]
+    fn listener_must_be_well_formed_and_exclusive() {
+        assert_rejected(|config| config.server.listen = Some("0.0.0.0:8080".to_string()), "must start with tcp:// or unix://");
+        assert_rejected(|config| config.server.listen = Some("unix://run/aperio.sock".to_string()), "must name an absolute socket path");
+        assert_rejected(|config| {
+            config.server.listen = Some("tcp://0.0.0.0:8080".to_string());
+            config.server.host_or_port_set = true;
+        }, "APERIO_LISTEN cannot be combined with APERIO_HOST or APERIO_PORT");
+        assert_rejected(|config| {
+            config.server.listen = Some("unix:///run/aperio.sock".to_string());
+            config.server.tls_cert = Some(PathBuf::from("/tls/cert.pem"));
+            config.server.tls_key = Some(PathBuf::from("/tls/key.pem"));
+        }, "APERIO_TLS_CERT cannot be used with a unix socket listener");
+    }
+
+    #[test]
+    fn tls_needs_both_halves() {
+        assert_rejected(|config| config.server.tls_cert = Some(PathBuf::from("/tls/cert.pem")), "APERIO_TLS_CERT is set but APERIO_TLS_KEY is not");
+        assert_rejected(|config| config.server.tls_key = Some(PathBuf::from("/tls/key.pem")), "APERIO_TLS_KEY is set but APERIO_TLS_CERT is not");
+    }
+
+    #[test]
+    fn redact_pattern_must_compile() {
+        assert_rejected(|config| config.security.redact_pattern = Some("(unclosed".to_string()), "APERIO_REDACT_PATTERN is not a valid regex");
+    }
+
+    #[test]
+    fn api_keys_need_clean_unique_names_and_long_keys() {
+        assert!(problems(|config| config.security.api_keys = vec![api_key("ci-runner_1", "0123456789abcdef")]).is_empty());
+        assert_rejected(|config| config.security.api_keys = vec![api_key("ci runner", "0123456789abcdef")], "APERIO_API_KEYS name `ci runner` may only contain");
+        assert_rejected(|config| config.security.api_keys = vec![api_key("ci", "short")], "APERIO_API_KEYS key for `ci` must be at least 16 characters");
+        assert_rejected(|config| {
+            config.security.api_keys = vec![api_key("ci", "0123456789abcdef"), api_key("ci", "fedcba9876543210")];
+        }, "APERIO_API_KEYS names `ci` more than once");
+    }
+
+    #[test]
+    fn allowlist_must_not_be_empty() {
+        assert_rejected(|config| config.download.allowed_domains.clear(), "APERIO_ALLOWED_DOMAINS is empty");
+    }
+
+    #[test]
+    fn download_command_must_exist_unless_mocked() {
+        assert_rejected(
+            |config| config.download.download_command = "aperio-no-such-yt-dlp".to_string(),
+            "APERIO_DOWNLOAD_COMMAND `aperio-no-such-yt-dlp` was not found on PATH",
+        );
+        assert_rejected(|config| config.download.download_command = "/nonexistent/yt-dlp".to_string(), "`/nonexistent/yt-dlp` was not found");
+        assert!(problems(|config| {
+            config.download.download_command = "aperio-no-such-yt-dlp".to_string();
+            config.download.backend = DownloaderBackend::Mock(PathBuf::from("/fixtures/clip.mp4"));
+        }).is_empty());
+    }
+
+    #[test]
+    fn ffmpeg_must_exist_unless_passing_through() {
+        assert_rejected(
+            |config| config.processing.ffmpeg_command = "aperio-no-such-ffmpeg".to_string(),
+            "APERIO_FFMPEG_COMMAND `aperio-no-such-ffmpeg` was not found on PATH",
+        );
+        assert!(problems(|config| {
+            config.processing.ffmpeg_command = "aperio-no-such-ffmpeg".to_string();
+            config.processing.backend = ProcessorBackend::Passthrough;
+        }).is_empty());
+    }
+
+    #[test]
+    fn retention_intervals_must_be_set_while_enabled() {
+        assert_rejected(|config| {
+            config.retention.enabled = true;
+            config.retention.cleanup_interval_hours = 0;
+        }, "APERIO_CLEANUP_INTERVAL_HOURS must be at least 1 while retention is enabled");
+        assert_rejected(|config| {
+            config.retention.enabled = true;
+            config.retention.record_retention_days = 0;
+        }, "APERIO_RECORD_RETENTION_DAYS must be at least 1 while retention is enabled");
+        assert!(problems(|config| {
+            config.retention.enabled = false;
+            config.retention.cleanup_interval_hours = 0;
+        }).is_empty());
+    }
+
+    #[test]
+    fn lru_eviction_needs_a_band_and_an_interval() {
+        assert_rejected(|config| {
+            config.retention.mode = RetentionMode::Lru;
+            config.retention.eviction_low_water_percent = 90;
+            config.retention.eviction_high_water_percent = 90;
+        }, "APERIO_EVICTION_LOW_WATER_PERCENT (90) must be below APERIO_EVICTION_HIGH_WATER_PERCENT (90)");
+        assert_rejected(|config| {
+            config.retention.mode = RetentionMode::Lru;
+            config.retention.eviction_interval = Duration::ZERO;
+        }, "APERIO_EVICTION_INTERVAL must be greater than 0 seconds");
+    }
+
+    #[test]
+    fn unparseable_values_are_reported_as_defaulted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aperio.toml");
+        std::fs::write(&path, "[server]\nsocket_mode = \"rw-rw----\"\n").unwrap();
+
+        let source = ConfigSource::from_file(&path).unwrap();
+        let config = Config::from_source(&source);
+        assert_eq!(config.server.socket_mode, 0o660);
+        assert_eq!(*source.warnings.borrow(), [r#"APERIO_SOCKET_MODE="rw-rw----" could not be parsed; using 660"#]);
+    }
+}
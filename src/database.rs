@@ -3,7 +3,8 @@ use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
 use std::path::Path;
 use std::os::unix::fs::PermissionsExt;
 
-pub async fn create_database_pool(database_url: &str) -> AppResult<SqlitePool> {
+/// `max_connections` of None sizes the pool from the CPU count
+pub async fn create_database_pool(database_url: &str, max_connections: Option<u32>) -> AppResult<SqlitePool> {
     let db_path = database_url.trim_start_matches("sqlite://");
     tracing::info!("Database file path: {}", db_path);
     
@@ -11,11 +12,11 @@ pub async fn create_database_pool(database_url: &str) -> AppResult<SqlitePool> {
     if let Some(parent) = Path::new(db_path).parent() {
         tracing::info!("Creating database directory: {:?}", parent);
         tokio::fs::create_dir_all(parent).await
-            .map_err(|e| AppError::Internal(format!("Failed to create database directory: {e}")))?;
+            .map_err(AppError::io("Failed to create database directory"))?;
         
         // Check directory permissions
         let metadata = tokio::fs::metadata(parent).await
-            .map_err(|e| AppError::Internal(format!("Failed to read directory metadata: {e}")))?;
+            .map_err(AppError::io("Failed to read directory metadata"))?;
         tracing::info!("Directory permissions: {:o}", metadata.permissions().mode() & 0o777);
         
         // Try to create a test file
@@ -42,9 +43,8 @@ pub async fn create_database_pool(database_url: &str) -> AppResult<SqlitePool> {
     tracing::info!("Connecting with URL: {}", connection_url);
     
     // Configure connection pool based on environment
-    let max_connections = std::env::var("APERIO_DB_MAX_CONNECTIONS")
-        .ok()
-        .and_then(|s| s.parse().ok())
+    let max_connections = max_connections
+        .map(|n| n as usize)
         .unwrap_or_else(|| {
             // Default to 4x CPU cores, min 10, max 100
             let cpus = std::thread::available_parallelism()
@@ -59,7 +59,7 @@ pub async fn create_database_pool(database_url: &str) -> AppResult<SqlitePool> {
         .max_connections(max_connections as u32)
         .connect(&connection_url)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to create database pool: {e}")))?;
+        .map_err(AppError::database("Failed to create database pool"))?;
 
     Ok(pool)
 }
@@ -77,20 +77,51 @@ pub async fn run_migrations(pool: &SqlitePool) -> AppResult<()> {
     sqlx::query("PRAGMA journal_mode = WAL")
         .execute(pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to set journal mode: {e}")))?;
+        .map_err(AppError::database("Failed to set journal mode"))?;
     
     // Set synchronous mode for better performance
     sqlx::query("PRAGMA synchronous = NORMAL")
         .execute(pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to set synchronous mode: {e}")))?;
+        .map_err(AppError::database("Failed to set synchronous mode"))?;
     
     // Increase cache size for better performance
     sqlx::query("PRAGMA cache_size = 1000")
         .execute(pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to set cache size: {e}")))?;
+        .map_err(AppError::database("Failed to set cache size"))?;
+
+    enable_incremental_vacuum(pool).await?;
 
     tracing::info!("SQLite optimizations applied successfully");
     Ok(())
 }
+
+/// SQLite's incremental auto-vacuum mode, so database maintenance can return pages freed by
+/// retention deletes to the filesystem. An existing database only switches after a full VACUUM,
+/// which can't run inside a migration's transaction, so it is done here once.
+async fn enable_incremental_vacuum(pool: &SqlitePool) -> AppResult<()> {
+    const INCREMENTAL: i64 = 2;
+
+    let mut conn = pool.acquire().await
+        .map_err(AppError::database("Failed to acquire a connection"))?;
+    let mode: i64 = sqlx::query_scalar("PRAGMA auto_vacuum")
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::database("Failed to read auto_vacuum mode"))?;
+    if mode == INCREMENTAL {
+        return Ok(());
+    }
+
+    tracing::info!("Switching the database to incremental auto-vacuum; this rewrites the database file once");
+    // The mode is held by the connection until VACUUM applies it, so both run on this one
+    sqlx::query("PRAGMA auto_vacuum = INCREMENTAL")
+        .execute(&mut *conn)
+        .await
+        .map_err(AppError::database("Failed to set auto_vacuum mode"))?;
+    sqlx::query("VACUUM")
+        .execute(&mut *conn)
+        .await
+        .map_err(AppError::database("Failed to vacuum the database"))?;
+    Ok(())
+}
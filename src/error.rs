@@ -1,69 +1,201 @@
-use actix_web::{HttpResponse, ResponseError};
+use crate::models::job::FailureReason;
+use actix_web::{error::JsonPayloadError, http::StatusCode, HttpRequest, HttpResponse, ResponseError};
 use serde::Serialize;
+use utoipa::ToSchema;
 use std::fmt;
-use std::fmt::Debug;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, thiserror::Error)]
 pub enum AppError {
+    #[error("Bad Request error: {0}")]
     BadRequest(String),
+    /// A numeric option outside the range it accepts; its own `error_type` lets clients tell it apart
+    #[error("Bad Request error: {0}")]
+    OutOfRange(String),
+    #[error("Not Found error: {0}")]
     NotFound(String),
+    #[error("Unprocessable error: {0}")]
+    Unprocessable(String),
+    #[error("Internal error: {0}")]
     Internal(String),
-    #[allow(dead_code)]
+    #[error("Storage error: {0}")]
     Storage(String),
+    #[error("Download error: {0}")]
     Download(String),
+    #[error("Processing error: {0}")]
     Processing(String),
+    #[error("Timeout error: {0}")]
     Timeout(String),
+    #[error("Insufficient storage error: {0}")]
+    InsufficientStorage(String),
+    #[error("Expired error: {0}")]
+    Expired(String),
+    #[error("Conflict error: {0}")]
+    Conflict(String),
+    #[error("Payload too large error: {0}")]
+    PayloadTooLarge(String),
+    #[error("Overloaded error: {0}")]
+    Overloaded(String),
+    #[error("Quota exceeded error: {0}")]
+    QuotaExceeded(String),
+    #[error("Draining error: {0}")]
+    Draining(String),
+    /// No download or processing slot became free in time; the job can run later
+    #[error("Overloaded error: No {pool} slot became free within {}s", waited.as_secs())]
+    NoCapacity {
+        pool: &'static str,
+        waited: std::time::Duration,
+    },
+    /// A query or connection failed; the sqlx error is kept so retries can tell a busy database from a broken one
+    #[error("Internal error: {context}: {source}")]
+    Database {
+        context: String,
+        #[source]
+        source: sqlx::Error,
+    },
+    /// A filesystem operation failed
+    #[error("Internal error: {context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// yt-dlp, curl, ffmpeg or ffprobe could not be started or waited on
+    #[error("{phase} error: {program} command failed: {source}")]
+    Spawn {
+        phase: Phase,
+        program: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The download command ran and exited unsuccessfully
+    #[error("Download error: {stderr}")]
+    DownloadFailed {
+        exit_code: Option<i32>,
+        /// The command's stderr, with cookie paths and credentials removed
+        stderr: String,
+        reason: FailureReason,
+    },
+    /// ffmpeg or ffprobe ran and exited unsuccessfully
+    #[error("Processing error: {stderr}")]
+    ProcessingFailed {
+        exit_code: Option<i32>,
+        stderr: String,
+    },
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
-    error_type: String,
-    message: String,
+/// Which part of a job an external command belongs to, deciding how its failures are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Download,
+    Processing,
 }
 
-impl fmt::Display for AppError {
+impl fmt::Display for Phase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            AppError::Download(msg) => write!(f, "Download error: {msg}"),
-            AppError::Processing(msg) => write!(f, "Processing error: {msg}"),
-            AppError::Storage(msg) => write!(f, "Storage error: {msg}"),
-            AppError::Timeout(msg) => write!(f, "Timeout error: {msg}"),
-            AppError::Internal(msg) => write!(f, "Internal error: {msg}"),
-            AppError::BadRequest(msg) => write!(f, "Bad Request error: {msg}"),
-            AppError::NotFound(msg) => write!(f, "Not Found error: {msg}"),
+            Phase::Download => write!(f, "Download"),
+            Phase::Processing => write!(f, "Processing"),
         }
     }
 }
 
-impl ResponseError for AppError {
-    fn error_response(&self) -> HttpResponse {
-        let (error_type, message) = match self {
-            AppError::Download(msg) => ("download_error", msg),
-            AppError::Processing(msg) => ("processing_error", msg),
-            AppError::Storage(msg) => ("storage_error", msg),
-            AppError::Timeout(msg) => ("timeout_error", msg),
-            AppError::Internal(msg) => ("internal_error", msg),
-            AppError::BadRequest(msg) => ("bad_request", msg),
-            AppError::NotFound(msg) => ("not_found", msg),
-        };
+impl AppError {
+    /// For `map_err`: a database failure described by `context`, e.g. "Failed to fetch job"
+    pub fn database<C: Into<String>>(context: C) -> impl FnOnce(sqlx::Error) -> AppError {
+        move |source| AppError::Database { context: context.into(), source }
+    }
 
-        let error_response = ErrorResponse {
-            error: "request_failed".to_string(),
-            error_type: error_type.to_string(),
-            message: message.clone(),
-        };
+    /// For `map_err`: a filesystem failure described by `context`
+    pub fn io<C: Into<String>>(context: C) -> impl FnOnce(std::io::Error) -> AppError {
+        move |source| AppError::Io { context: context.into(), source }
+    }
+
+    /// `program` could not be started or waited on
+    pub fn spawn(phase: Phase, program: &str, source: std::io::Error) -> AppError {
+        AppError::Spawn { phase, program: program.to_string(), source }
+    }
 
+    /// The `error_type`, status and message sent to clients
+    fn response_parts(&self) -> (&'static str, StatusCode, String) {
         match self {
-            AppError::Download(_) => HttpResponse::BadRequest().json(error_response),
-            AppError::Processing(_) => HttpResponse::InternalServerError().json(error_response),
-            AppError::Storage(_) => HttpResponse::InternalServerError().json(error_response),
-            AppError::Timeout(_) => HttpResponse::RequestTimeout().json(error_response),
-            AppError::Internal(_) => HttpResponse::InternalServerError().json(error_response),
-            AppError::BadRequest(_) => HttpResponse::BadRequest().json(error_response),
-            AppError::NotFound(_) => HttpResponse::NotFound().json(error_response),
+            AppError::Download(msg) => ("download_error", StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::Processing(msg) => ("processing_error", StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
+            AppError::Storage(msg) => ("storage_error", StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
+            AppError::Timeout(msg) => ("timeout_error", StatusCode::REQUEST_TIMEOUT, msg.clone()),
+            AppError::Internal(msg) => ("internal_error", StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
+            AppError::BadRequest(msg) => ("bad_request", StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::OutOfRange(msg) => ("option_out_of_range", StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::NotFound(msg) => ("not_found", StatusCode::NOT_FOUND, msg.clone()),
+            AppError::Unprocessable(msg) => ("unprocessable_entity", StatusCode::UNPROCESSABLE_ENTITY, msg.clone()),
+            AppError::InsufficientStorage(msg) => ("insufficient_storage", StatusCode::INSUFFICIENT_STORAGE, msg.clone()),
+            AppError::Expired(msg) => ("output_expired", StatusCode::GONE, msg.clone()),
+            AppError::Conflict(msg) => ("conflict", StatusCode::CONFLICT, msg.clone()),
+            AppError::PayloadTooLarge(msg) => ("payload_too_large", StatusCode::PAYLOAD_TOO_LARGE, msg.clone()),
+            AppError::Overloaded(msg) => ("overloaded", StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
+            AppError::QuotaExceeded(msg) => ("quota_exceeded", StatusCode::TOO_MANY_REQUESTS, msg.clone()),
+            AppError::Draining(msg) => ("draining", StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
+            AppError::NoCapacity { pool, waited } => {
+                let message = format!("No {pool} slot became free within {}s", waited.as_secs());
+                ("overloaded", StatusCode::SERVICE_UNAVAILABLE, message)
+            }
+            AppError::Database { context, source } => {
+                ("internal_error", StatusCode::INTERNAL_SERVER_ERROR, format!("{context}: {source}"))
+            }
+            AppError::Io { context, source } => {
+                ("internal_error", StatusCode::INTERNAL_SERVER_ERROR, format!("{context}: {source}"))
+            }
+            AppError::Spawn { phase: Phase::Download, program, source } => {
+                ("download_error", StatusCode::BAD_REQUEST, format!("{program} command failed: {source}"))
+            }
+            AppError::Spawn { phase: Phase::Processing, program, source } => {
+                ("processing_error", StatusCode::INTERNAL_SERVER_ERROR, format!("{program} command failed: {source}"))
+            }
+            AppError::DownloadFailed { stderr, .. } => ("download_error", StatusCode::BAD_REQUEST, stderr.clone()),
+            AppError::ProcessingFailed { stderr, .. } => {
+                ("processing_error", StatusCode::INTERNAL_SERVER_ERROR, stderr.clone())
+            }
         }
     }
 }
 
-pub type AppResult<T> = Result<T, AppError>;
\ No newline at end of file
+/// Body of every error response. `error_type` is one of: `bad_request`, `option_out_of_range`, `download_error` (400),
+/// `not_found` (404), `timeout_error` (408), `conflict` (409), `output_expired` (410),
+/// `payload_too_large` (413), `unprocessable_entity` (422), `quota_exceeded` (429), `internal_error`, `processing_error`,
+/// `storage_error` (500), `overloaded`, `draining` (503), `insufficient_storage` (507)
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Always `request_failed`
+    error: String,
+    #[schema(example = "not_found")]
+    error_type: String,
+    message: String,
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        self.response_parts().1
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let (error_type, status, message) = self.response_parts();
+        HttpResponse::build(status).json(ErrorResponse {
+            error: "request_failed".to_string(),
+            error_type: error_type.to_string(),
+            message,
+        })
+    }
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+/// Answer malformed or oversized JSON bodies with an `ErrorResponse` instead of actix's plain text
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let app_error = match &err {
+        JsonPayloadError::Overflow { limit } | JsonPayloadError::OverflowKnownLength { limit, .. } => {
+            AppError::PayloadTooLarge(format!("JSON body exceeds the {limit} byte limit"))
+        }
+        JsonPayloadError::ContentType => AppError::BadRequest("Content-Type must be application/json".to_string()),
+        _ => AppError::BadRequest(format!("Invalid JSON body: {err}")),
+    };
+    app_error.into()
+}
\ No newline at end of file
@@ -7,19 +7,37 @@ mod database;
 mod middleware;
 mod monitoring;
 
-use crate::api::routes::{configure_routes, AppState};
+use crate::api::routes::{configure_routes, ApiUrls, AppState};
 use crate::api::monitoring::{configure_monitoring_routes, MonitoringState};
-use crate::config::load_config;
-use crate::services::{ProcessService, DownloadService, JobRepository, CleanupService, SecurityValidator, ConnectionPoolManager, JobQueue, RetentionService};
+use crate::api::openapi::configure_openapi_routes;
+use crate::config::{load_config, Listener};
+use crate::services::downloader::build_downloader;
+use crate::services::processor::build_processor;
+use crate::services::publish::build_publisher;
+use crate::services::hooks::PostHook;
+use crate::services::{AuditLog, ProcessService, DownloadService, JobRepository, CleanupService, SecurityValidator, ConnectionPoolManager, JobQueue, RetentionService, UploadService, ResultCache, StorageQuota, StorageService, ConfigReloader, FailureAlerts, SubscriptionService, PublishService, Estimator, DbMaintenance, MetricsSnapshotStore};
+use crate::services::job_queue::PriorityPolicy;
+use crate::services::notify::{Notifier, WebhookNotifier};
+use crate::services::resources::ResourceMonitor;
+use crate::services::retention::EvictionPolicy;
+use crate::services::error_mapping::ErrorSanitizer;
+use crate::services::estimator::HISTORY_SIZE;
+use crate::services::security::set_redact_pattern;
+use crate::services::tls::CertificateReloader;
 use crate::database::{create_database_pool, run_migrations};
-use crate::middleware::{SecurityHeaders, Cors, RequestTracking, AuthMiddleware};
+use crate::middleware::{SecurityHeaders, Cors, RequestTracking, AuthMiddleware, LoadShedding};
+use crate::error::json_error_handler;
 use crate::monitoring::HealthChecker;
 use actix_web::{web, App, HttpServer};
-use std::path::PathBuf;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 use tracing_actix_web::TracingLogger;
 
+/// Pending jobs younger than this are left alone when restoring the queue at startup
+const RESTORE_MIN_AGE_SECS: i64 = 5;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize structured logging
@@ -28,27 +46,47 @@ async fn main() -> std::io::Result<()> {
     info!("Starting Aperio Video Processing API v{}", env!("CARGO_PKG_VERSION"));
 
     // Load configuration
-    let config = load_config();
+    let (config, config_source) = match load_config() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(problems) = config.validate() {
+        for problem in &problems {
+            error!("Invalid configuration: {}", problem);
+        }
+        error!("Refusing to start: {} configuration problem(s)", problems.len());
+        std::process::exit(1);
+    }
+    if std::env::args().any(|arg| arg == "--check-config") {
+        for line in config_source.describe() {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    if let Some(pattern) = &config.security.redact_pattern {
+        // Checked by validate()
+        set_redact_pattern(regex::Regex::new(pattern).expect("APERIO_REDACT_PATTERN was validated"));
+    }
     let server_config = config.server.clone();
     
     // Create working directory
-    let working_dir_path = std::env::var("APERIO_WORKING_DIR").unwrap_or_else(|_| "/app/working".to_string());
-    let working_dir = PathBuf::from(&working_dir_path);
+    let working_dir = config.storage.working_dir.clone();
     tokio::fs::create_dir_all(&working_dir).await.expect("Failed to create working directory");
-    info!("Working directory initialized: {}", working_dir_path);
+    info!("Working directory initialized: {}", working_dir.display());
 
     // Create storage directory
-    let storage_dir_path = std::env::var("APERIO_STORAGE_PATH").unwrap_or_else(|_| "/app/storage".to_string());
-    let storage_dir = PathBuf::from(&storage_dir_path);
+    let storage_dir = PathBuf::from(config.storage.local_path.clone().unwrap_or_default());
     tokio::fs::create_dir_all(&storage_dir).await.expect("Failed to create storage directory");
-    info!("Storage directory initialized: {}", storage_dir_path);
+    info!("Storage directory initialized: {}", storage_dir.display());
 
     // Initialize database
-    let database_url = std::env::var("APERIO_DATABASE_URL")
-        .unwrap_or_else(|_| "sqlite:///app/storage/aperio.db".to_string());
+    let database_url = &config.storage.database_url;
 
     info!("Connecting to database: {}", database_url);
-    let pool = create_database_pool(&database_url)
+    let pool = create_database_pool(database_url, config.storage.db_max_connections)
         .await
         .expect("Failed to create database pool");
 
@@ -57,46 +95,198 @@ async fn main() -> std::io::Result<()> {
     run_migrations(&pool)
         .await
         .expect("Failed to run database migrations");
+    #[cfg(feature = "query-plan-checks")]
+    JobRepository::new(pool.clone()).check_query_plans()
+        .await
+        .expect("Query plan check failed");
+
+    // Restored before anything is counted, so the first increments land on the saved totals
+    let metrics_snapshots = config.storage.metrics_snapshot_interval.map(|every| {
+        let store = Arc::new(MetricsSnapshotStore::new(pool.clone()));
+        (store, every)
+    });
+    if let Some((store, every)) = &metrics_snapshots {
+        store.restore().await;
+        tokio::spawn(store.clone().start_scheduler(*every));
+    }
 
     // Initialize connection pool manager
     let pool_manager = Arc::new(ConnectionPoolManager::new(
         config.download.max_concurrent_downloads,
         config.processing.max_concurrent_processing,
+        config.queue.slot_wait_timeout,
     ));
 
     // Initialize services
     info!("Initializing services");
-    let download_service = DownloadService::new(config.download.clone(), working_dir.clone(), &config.security, pool_manager.clone());
-    let process_service = ProcessService::new(config.processing.clone(), working_dir.clone(), pool_manager.clone());
-    let cleanup_service = Arc::new(CleanupService::new(working_dir.clone()));
-    let job_repository = Arc::new(JobRepository::new(pool.clone()));
+    let storage_service = Arc::new(StorageService::new(config.storage.clone()).expect("Invalid storage configuration"));
+    let storage_quota = Arc::new(StorageQuota::new(
+        config.storage.max_storage_bytes,
+        config.storage.storage_headroom_bytes,
+        vec![working_dir.clone(), storage_service.root()],
+    ));
+    let used_bytes = storage_quota.reconcile().await;
+    match config.storage.max_storage_bytes {
+        Some(max_bytes) => info!("Storage quota: {} of {} bytes used", used_bytes, max_bytes),
+        None => info!("Storage quota disabled; {} bytes in working and storage directories", used_bytes),
+    }
+    tokio::spawn(storage_quota.clone().start_reconciler(config.storage.storage_reconcile_interval));
+
+    // Shared by every service that checks URLs, so a config reload updates the allowlist everywhere
     let security_validator = SecurityValidator::new(
         config.download.allowed_domains.clone(),
         config.security.max_file_size_mb as u32,
         config.security.max_url_length as u32,
     );
 
+    let download_service = Arc::new(DownloadService::new(
+        config.download.clone(),
+        working_dir.clone(),
+        security_validator.clone(),
+        storage_quota.clone(),
+    ));
+    let downloader = build_downloader(&config.download, security_validator.clone(), download_service.clone())
+        .expect("Invalid downloader configuration");
+    let process_service = Arc::new(ProcessService::new(config.processing.clone(), working_dir.clone(), pool_manager.clone()));
+    process_service.validate_watermark().expect("Invalid watermark configuration");
+    let processor = build_processor(&config.processing.backend, process_service.clone());
+    download_service.validate_cookies().expect("Invalid cookies configuration");
+    download_service.validate_extra_args().expect("Invalid APERIO_YTDLP_EXTRA_ARGS");
+    let upload_service = UploadService::new(working_dir.clone(), &config.security, config.server.max_payload_size);
+    let cleanup_service = Arc::new(CleanupService::new(working_dir.clone(), storage_service.root()));
+    cleanup_service.detect_legacy_files().await;
+    let job_repository = Arc::new(JobRepository::new(pool.clone()));
+    let audit_log = AuditLog::new(pool.clone());
+    let result_cache = ResultCache::new(config.processing.result_cache_enabled, storage_service.clone(), (*job_repository).clone());
+    // Constructed regardless of APERIO_RETENTION_ENABLED so the admin endpoints can use it
+    let eviction = EvictionPolicy::from_config(
+        &config.retention,
+        config.storage.max_storage_bytes,
+        config.storage.storage_headroom_bytes,
+    ).expect("Invalid retention configuration");
+    let retention_service = RetentionService::new(
+        job_repository.clone(),
+        audit_log.clone(),
+        cleanup_service.clone(),
+        storage_quota.clone(),
+        &config.retention,
+        eviction,
+    );
+
     // Initialize job queue (simplified - no TaskManager overhead)
-    let job_queue = Arc::new(JobQueue::new(config.queue.max_concurrent_jobs));
+    let job_queue = Arc::new(JobQueue::new(
+        config.download.max_concurrent_downloads,
+        config.processing.max_concurrent_processing,
+        config.queue.max_concurrent_jobs,
+        config.queue.max_queue_size,
+        PriorityPolicy {
+            high_reserved_slots: config.queue.high_priority_reserved_slots,
+            low_max_percent: config.queue.low_priority_max_percent,
+        },
+    ));
+
+    let db_maintenance = Arc::new(DbMaintenance::new(pool.clone(), database_url, job_queue.clone()));
+    if let Some(every) = config.storage.db_maintenance_interval {
+        tokio::spawn(db_maintenance.clone().start_scheduler(every));
+    }
 
     // Initialize monitoring
+    let resource_monitor = Arc::new(ResourceMonitor::new(pool_manager.clone(), config.server.max_rss_bytes));
+    tokio::spawn(resource_monitor.clone().start_sampler());
     let health_checker = HealthChecker::new(
         pool.clone(),
         working_dir.clone(),
+        download_service.cookie_files(),
+        job_queue.clone(),
+        &config.queue,
+        config.storage.disk_critical_free_bytes,
+        resource_monitor.clone(),
     );
 
+    let config_reloader = Arc::new(ConfigReloader::new(
+        config.clone(),
+        config_source.values(),
+        security_validator.clone(),
+        process_service.clone(),
+        retention_service.clone(),
+    ));
+    tokio::spawn(config_reloader.clone().start_sighup_listener());
+
+    // Failure notifications go out through the same curl as direct downloads
+    let notifier = config.notify.webhook_url.as_deref().map(|url| -> Box<dyn Notifier> {
+        Box::new(WebhookNotifier::new(
+            &config.download.http_download_command,
+            url,
+            &config.notify.webhook_template,
+            config.notify.webhook_secret.clone(),
+        ))
+    });
+    let failure_alerts = Arc::new(FailureAlerts::new(&config.notify, notifier));
+    if let Some(threshold) = config.notify.failure_rate_threshold {
+        tokio::spawn(failure_alerts.clone().watch_failure_rate(threshold, config.notify.failure_rate_window));
+    }
+
+    let subscriptions = Arc::new(SubscriptionService::new(
+        pool.clone(),
+        &config.download.http_download_command,
+        config.download.subscription_poll_interval,
+    ));
+
+    // Published outputs are uploaded with the same curl as direct downloads
+    let publisher = build_publisher(&config.publish, &config.download.http_download_command)
+        .expect("Invalid publish configuration");
+    let publisher = Arc::new(PublishService::new(publisher, (*job_repository).clone(), config.publish.max_attempts));
+
+    let post_hook = PostHook::new(&config.hooks, (*job_repository).clone())
+        .expect("Invalid post-completion hook")
+        .map(Arc::new);
+
+    // Completion estimates start from the stage times of recently completed jobs
+    let estimator = Arc::new(Estimator::default());
+    match job_repository.list_recent_completed_metadata(HISTORY_SIZE as u32).await {
+        Ok(history) => estimator.seed(&history),
+        Err(e) => warn!("Failed to load job history for completion estimates: {}", e),
+    }
+
     let app_state = Arc::new(AppState {
         download_service,
+        downloader,
+        working_dir: working_dir.clone(),
+        storage_quota: storage_quota.clone(),
+        storage_service,
         process_service,
+        processor,
+        upload_service,
+        result_cache,
         cleanup_service: (*cleanup_service).clone(),
+        retention_service: retention_service.clone(),
         job_repository: (*job_repository).clone(),
+        audit_log,
         security_validator,
         job_queue: job_queue.clone(),
+        pool_manager: pool_manager.clone(),
+        error_sanitizer: ErrorSanitizer::new(
+            &working_dir,
+            config.storage.local_path.as_deref().map(Path::new),
+            config.queue.error_message_max_lines,
+            config.queue.error_message_max_bytes,
+        ),
+        config_reloader: config_reloader.clone(),
+        api_urls: ApiUrls::new(config.server.public_url.as_deref()),
+        failure_alerts: failure_alerts.clone(),
+        subscriptions: subscriptions.clone(),
+        publisher: publisher.clone(),
+        post_hook,
+        estimator,
+        db_maintenance,
+        metrics_snapshots: metrics_snapshots.as_ref().map(|(store, _)| store.clone()),
     });
 
     // Restore pending jobs from database to queue on startup with race condition protection
     info!("Restoring pending jobs from database to queue");
-    match job_repository.get_pending_jobs().await {
+    // Rows this young may belong to a submission still on its way into another instance's queue
+    let restore_before = chrono::Utc::now() - chrono::Duration::seconds(RESTORE_MIN_AGE_SECS);
+    match job_repository.get_pending_jobs(restore_before).await {
         Ok(pending_jobs) => {
             info!("Found {} pending jobs to restore", pending_jobs.len());
             for job in pending_jobs {
@@ -129,58 +319,145 @@ async fn main() -> std::io::Result<()> {
 
     // Start job queue worker
     job_queue.start_worker(app_state.clone()).await;
+    tokio::spawn(subscriptions.start_poller(app_state.clone()));
+    publisher.resume_pending().await;
 
     // Start retention service if enabled
     if config.retention.enabled {
-        info!("Starting retention service with {} day retention", config.retention.retention_days);
-        let retention_service = RetentionService::new(
-            job_repository.clone(),
-            cleanup_service.clone(),
-            config.retention.retention_days,
-            config.retention.cleanup_interval_hours,
+        info!(
+            "Starting retention service: files kept {} days, records {} days",
+            config.retention.file_retention_days, config.retention.record_retention_days
         );
-        
         let retention_service_clone = retention_service.clone();
         tokio::spawn(async move {
             retention_service_clone.start_background_cleanup().await;
         });
+        let eviction_service = retention_service.clone();
+        tokio::spawn(async move {
+            eviction_service.start_background_eviction().await;
+        });
     } else {
         info!("Retention service disabled");
     }
 
     let monitoring_state = Arc::new(MonitoringState {
         health_checker,
+        job_queue: job_queue.clone(),
+        pool_manager: pool_manager.clone(),
+        job_repository: (*job_repository).clone(),
+        retention_service: retention_service.clone(),
+        resource_monitor,
     });
 
     // Configure CORS
-    let cors_config = std::env::var("APERIO_CORS_ORIGINS")
-        .map(|origins| Cors::new(origins.split(',').map(|s| s.trim().to_string()).collect()))
-        .unwrap_or_else(|_| Cors::restrictive());
+    let cors_config = server_config.cors_origins.clone()
+        .map(Cors::new)
+        .unwrap_or_else(Cors::restrictive);
+
+    // Built once so every worker shares the same in-flight budgets
+    let load_shedding = LoadShedding::new(server_config.max_inflight_reads, server_config.max_inflight_writes);
 
-    info!("Starting Aperio server on {}:{}", server_config.host, server_config.port);
+    // Validation guarantees the certificate and key are set together
+    let tls_config = match (&server_config.tls_cert, &server_config.tls_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let loaded = CertificateReloader::load(cert_path, key_path)
+                .and_then(|certificates| Ok((certificates.server_config()?, certificates)));
+            match loaded {
+                Ok((tls_config, certificates)) => {
+                    tokio::spawn(certificates.start_watcher());
+                    Some(tls_config)
+                }
+                Err(e) => {
+                    error!("Invalid TLS configuration: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+    let security_headers = SecurityHeaders::new(tls_config.is_some());
+    let request_tracking = RequestTracking::new(server_config.trusted_proxies.clone());
+
+    let listener = server_config.listener().expect("listener is checked by Config::validate");
+    info!(
+        "Starting Aperio server on {}{}",
+        listener, if tls_config.is_some() { " (TLS)" } else { "" }
+    );
+    info!(
+        "HTTP server: {} workers, backlog {}, shutdown timeout {}s",
+        server_config.http_workers, server_config.backlog, server_config.shutdown_timeout.as_secs()
+    );
     info!("Security: File size limit: {}MB, URL length limit: {} chars",
            config.security.max_file_size_mb, config.security.max_url_length);
 
     // Start HTTP server with monitoring and security middleware
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(RequestTracking) // Add request correlation IDs and performance tracking
+            .wrap(request_tracking.clone()) // Add request correlation IDs, client addresses and performance tracking
             .wrap(TracingLogger::default()) // Add request tracing
-            .wrap(SecurityHeaders) // Add security headers to all responses
+            .wrap(security_headers.clone()) // Add security headers to all responses
             .wrap(cors_config.clone()) // Add CORS support
             .wrap(AuthMiddleware::new(config.clone())) // Add authentication middleware
+            .wrap(load_shedding.clone()) // Shed requests over the in-flight caps before doing any work
             .app_data(web::Data::new(app_state.clone()))
             .app_data(web::Data::new(monitoring_state.clone()))
-            .app_data(web::PayloadConfig::new(server_config.max_payload_size))
-            .app_data(web::JsonConfig::default().limit(4096))
-            .configure(configure_routes)
+            // Only uploads may send large bodies; they check APERIO_MAX_PAYLOAD themselves
+            .app_data(web::JsonConfig::default()
+                .limit(server_config.max_json_payload)
+                .error_handler(json_error_handler))
+            // Monitoring and docs first: the deprecated unversioned API aliases catch every other path
             .configure(configure_monitoring_routes)
+            .configure(|cfg| configure_openapi_routes(cfg, server_config.swagger_ui))
+            .configure(configure_routes)
     })
         .client_request_timeout(server_config.client_timeout)
         .keep_alive(server_config.keep_alive)
-        .bind((server_config.host, server_config.port))?
-        .run()
-        .await
+        .workers(server_config.http_workers)
+        .backlog(server_config.backlog)
+        .shutdown_timeout(server_config.shutdown_timeout.as_secs());
+
+    let result = match (listener, tls_config) {
+        (Listener::Tcp { host, port }, Some(tls_config)) => server.bind_rustls_0_23((host, port), tls_config)?.run().await,
+        (Listener::Tcp { host, port }, None) => server.bind((host, port))?.run().await,
+        (Listener::Unix(path), _) => {
+            remove_stale_socket(&path)?;
+            let server = server.bind_uds(&path)?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(server_config.socket_mode))?;
+            server.run().await
+        }
+    };
+
+    // Keep what was counted since the last scheduled save
+    if let Some((store, _)) = &metrics_snapshots {
+        match store.save().await {
+            Ok(()) => info!("Saved metrics snapshot on shutdown"),
+            Err(e) => error!("{}", e),
+        }
+    }
+    result
+}
+
+/// Remove a socket file left behind by a previous run, refusing to touch anything else
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            format!("another process is listening on {}", path.display()),
+        ));
+    }
+    warn!("Removing stale socket {}", path.display());
+    std::fs::remove_file(path)
 }
 
 fn init_logging() {
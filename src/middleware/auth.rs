@@ -6,8 +6,32 @@ use actix_web::{
 use futures::future::{ok, Ready};
 use std::future::Future;
 use std::pin::Pin;
+use actix_web::HttpMessage;
 use base64::{engine::general_purpose, Engine as _};
-use crate::config::Config;
+use crate::api::routes::API_V1_PREFIX;
+use crate::config::{ApiKey, Config};
+
+/// The API key a request authenticated with; handlers take it as `Option<web::ReqData<ApiClient>>`
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    pub name: String,
+    pub admin: bool,
+    pub max_active_jobs: Option<u32>,
+    pub max_daily_jobs: Option<u32>,
+}
+
+impl From<&ApiKey> for ApiClient {
+    fn from(api_key: &ApiKey) -> Self {
+        Self {
+            name: api_key.name.clone(),
+            admin: api_key.admin,
+            max_active_jobs: api_key.max_active_jobs,
+            max_daily_jobs: api_key.max_daily_jobs,
+        }
+    }
+}
+
+type AuthFuture<B> = Pin<Box<dyn Future<Output = Result<ServiceResponse<EitherBody<B>>, Error>>>>;
 
 pub struct AuthMiddleware {
     config: Config,
@@ -52,45 +76,76 @@ where
 {
     type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+    type Future = AuthFuture<B>;
 
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        if let Some(password) = &self.config.security.auth_password {
-            if let Some(auth_header) = req.headers().get("Authorization") {
-                if let Ok(auth_str) = auth_header.to_str() {
-                    if let Some(basic_auth) = auth_str.strip_prefix("Basic ") {
-                        if let Ok(decoded) = general_purpose::STANDARD.decode(basic_auth) {
-                            if let Ok(decoded_str) = String::from_utf8(decoded) {
-                                if decoded_str == *password {
-                                    // Authentication successful, continue with the request
-                                    let fut = self.service.call(req);
-                                    return Box::pin(async move {
-                                        let res = fut.await?;
-                                        Ok(res.map_into_left_body())
-                                    });
-                                }
-                            }
-                        }
-                    }
+        let security = &self.config.security;
+        let path = req.path().strip_prefix(API_V1_PREFIX).unwrap_or(req.path());
+        let admin_route = path.starts_with("/admin/");
+
+        // An API key identifies the client; only keys marked admin open admin routes
+        if let Some(token) = bearer_token(&req) {
+            return match security.api_keys.iter().find(|api_key| api_key.key == token) {
+                Some(api_key) if !admin_route || api_key.admin => {
+                    req.extensions_mut().insert(ApiClient::from(api_key));
+                    self.forward(req)
                 }
-            }
-            
-            // Authentication failed, return unauthorized response
-            Box::pin(async move {
-                let response = HttpResponse::Unauthorized()
-                    .insert_header(("WWW-Authenticate", "Basic realm=\"Aperio API\""))
-                    .finish();
-                Ok(req.into_response(response).map_into_right_body())
-            })
+                _ => unauthorized(req),
+            };
+        }
+
+        // Admin routes get their own password when one is configured, whichever API path reached them
+        let required_password = if admin_route {
+            security.admin_password.as_ref().or(security.auth_password.as_ref())
         } else {
-            // No auth password configured, allow all requests
-            let fut = self.service.call(req);
-            Box::pin(async move {
-                let res = fut.await?;
-                Ok(res.map_into_left_body())
-            })
+            security.auth_password.as_ref()
+        };
+
+        match required_password {
+            Some(password) if basic_auth_password(&req).as_ref() == Some(password) => self.forward(req),
+            // Without a password, requests are open unless API keys are configured
+            None if security.api_keys.is_empty() => self.forward(req),
+            _ => unauthorized(req),
         }
     }
-}
\ No newline at end of file
+}
+
+impl<S, B> AuthMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    fn forward(&self, req: ServiceRequest) -> AuthFuture<B> {
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+fn unauthorized<B: 'static>(req: ServiceRequest) -> AuthFuture<B> {
+    Box::pin(async move {
+        let response = HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Basic realm=\"Aperio API\""))
+            .finish();
+        Ok(req.into_response(response).map_into_right_body())
+    })
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<&str> {
+    req.headers().get("Authorization")?
+        .to_str().ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// The password sent with HTTP Basic auth
+fn basic_auth_password(req: &ServiceRequest) -> Option<String> {
+    let auth_str = req.headers().get("Authorization")?.to_str().ok()?;
+    let decoded = general_purpose::STANDARD.decode(auth_str.strip_prefix("Basic ")?).ok()?;
+    String::from_utf8(decoded).ok()
+}
@@ -0,0 +1,122 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::{HeaderValue, RETRY_AFTER}, Method},
+    Error, ResponseError,
+};
+use futures::future::{ok, Ready};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tracing::warn;
+use crate::error::AppError;
+use crate::{counter_inc, gauge_set};
+
+/// Seconds a shed client is told to wait before trying again
+const RETRY_AFTER_SECS: u64 = 2;
+
+/// Caps requests in flight, with separate budgets for reads and writes. Requests over the cap
+/// are answered with 503 straight away rather than queued behind the ones being served.
+/// Health probes are never shed.
+#[derive(Clone)]
+pub struct LoadShedding {
+    reads: Arc<RequestBudget>,
+    writes: Arc<RequestBudget>,
+}
+
+struct RequestBudget {
+    class: &'static str,
+    limit: usize,
+    permits: Arc<Semaphore>,
+}
+
+impl LoadShedding {
+    pub fn new(max_inflight_reads: usize, max_inflight_writes: usize) -> Self {
+        let budget = |class, limit| Arc::new(RequestBudget {
+            class,
+            limit,
+            permits: Arc::new(Semaphore::new(limit)),
+        });
+        Self {
+            reads: budget("read", max_inflight_reads),
+            writes: budget("write", max_inflight_writes),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for LoadShedding
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = LoadSheddingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(LoadSheddingMiddleware {
+            service,
+            limits: self.clone(),
+        })
+    }
+}
+
+pub struct LoadSheddingMiddleware<S> {
+    service: S,
+    limits: LoadShedding,
+}
+
+impl<S, B> Service<ServiceRequest> for LoadSheddingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Probes must keep answering during overload, or the orchestrator restarts a busy instance
+        if req.path() == "/health" || req.path().starts_with("/health/") {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        let budget = match *req.method() {
+            Method::GET | Method::HEAD | Method::OPTIONS => self.limits.reads.clone(),
+            _ => self.limits.writes.clone(),
+        };
+
+        let Ok(permit) = budget.permits.clone().try_acquire_owned() else {
+            return Box::pin(async move {
+                warn!("Shedding {} {}: {} {} requests already in flight", req.method(), req.path(), budget.limit, budget.class);
+                counter_inc!("aperio_http_requests_shed_total", "class" => budget.class);
+
+                let mut response = AppError::Overloaded(
+                    "Server is at capacity, retry shortly".to_string()
+                ).error_response();
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+                Ok(req.into_response(response).map_into_right_body())
+            });
+        };
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let in_flight = |budget: &RequestBudget| (budget.limit - budget.permits.available_permits()) as f64;
+            gauge_set!("aperio_http_requests_in_flight", in_flight(&budget), "class" => budget.class);
+
+            let result = fut.await;
+
+            drop(permit);
+            gauge_set!("aperio_http_requests_in_flight", in_flight(&budget), "class" => budget.class);
+            Ok(result?.map_into_left_body())
+        })
+    }
+}
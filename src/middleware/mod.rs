@@ -1,8 +1,10 @@
 pub mod request_tracking;
 pub mod auth;
+pub mod load_shedding;
 
-pub use request_tracking::RequestTracking;
+pub use request_tracking::{ClientIp, RequestTracking};
 pub use auth::AuthMiddleware;
+pub use load_shedding::LoadShedding;
 
 use actix_web::{
     http::header::{HeaderValue, CONTENT_SECURITY_POLICY, X_FRAME_OPTIONS, X_CONTENT_TYPE_OPTIONS, X_XSS_PROTECTION, STRICT_TRANSPORT_SECURITY},
@@ -14,7 +16,17 @@ use std::future::Future;
 use std::pin::Pin;
 
 // Security Headers Middleware
-pub struct SecurityHeaders;
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    /// Send Strict-Transport-Security; only meaningful when the server itself speaks HTTPS
+    hsts: bool,
+}
+
+impl SecurityHeaders {
+    pub fn new(hsts: bool) -> Self {
+        Self { hsts }
+    }
+}
 
 impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
 where
@@ -29,12 +41,13 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(SecurityHeadersMiddleware { service })
+        ok(SecurityHeadersMiddleware { service, hsts: self.hsts })
     }
 }
 
 pub struct SecurityHeadersMiddleware<S> {
     service: S,
+    hsts: bool,
 }
 
 impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
@@ -51,6 +64,7 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let fut = self.service.call(req);
+        let hsts = self.hsts;
 
         Box::pin(async move {
             let mut res = fut.await?;
@@ -74,10 +88,12 @@ where
                 CONTENT_SECURITY_POLICY,
                 HeaderValue::from_static("default-src 'self'"),
             );
-            headers.insert(
-                STRICT_TRANSPORT_SECURITY,
-                HeaderValue::from_static("max-age=31536000; includeSubDomains"),
-            );
+            if hsts {
+                headers.insert(
+                    STRICT_TRANSPORT_SECURITY,
+                    HeaderValue::from_static("max-age=31536000; includeSubDomains"),
+                );
+            }
 
             Ok(res)
         })
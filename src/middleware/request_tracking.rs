@@ -1,14 +1,107 @@
+use crate::services::metrics::{get_metrics, SECONDS_BOUNDS};
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::{HeaderMap, FORWARDED}, Method},
     Error, HttpMessage,
 };
+use ipnet::IpNet;
+use std::collections::HashMap;
 use std::future::{ready, Ready, Future};
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Instant;
 use tracing::{info, warn};
 use uuid::Uuid;
 
-pub struct RequestTracking;
+/// The address a request came from, looking through trusted proxies. Absent for unix socket
+/// connections that carry no forwarding headers.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+#[derive(Clone)]
+pub struct RequestTracking {
+    trusted_proxies: Arc<Vec<IpNet>>,
+}
+
+impl RequestTracking {
+    pub fn new(trusted_proxies: Vec<IpNet>) -> Self {
+        Self { trusted_proxies: Arc::new(trusted_proxies) }
+    }
+}
+
+/// Resolve the client address: forwarding headers are only read when the peer is a trusted proxy,
+/// and then walked from the nearest hop outwards, stopping at the first address that isn't trusted
+/// so a client can't choose its own address by sending the headers itself. A unix socket peer
+/// counts as trusted once any proxy is configured, since only local processes can reach it.
+pub fn resolve_client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    match peer {
+        Some(peer) if !is_trusted(&peer) => return Some(peer),
+        None if trusted_proxies.is_empty() => return None,
+        _ => {}
+    }
+
+    let mut client = peer;
+    for hop in forwarded_chain(headers).into_iter().rev() {
+        // Unknown or obfuscated hops end the chain at the last address we could read
+        let Some(hop) = hop else { break };
+        client = Some(hop);
+        if !is_trusted(&hop) {
+            break;
+        }
+    }
+    client
+}
+
+/// Hops from X-Forwarded-For, or failing that the `for=` parameters of Forwarded, client first.
+/// A hop that isn't an IP address is None.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| headers.get_all(name).filter_map(|value| value.to_str().ok()).collect::<Vec<_>>().join(",");
+
+    let x_forwarded_for = values("x-forwarded-for");
+    if !x_forwarded_for.trim().is_empty() {
+        return x_forwarded_for.split(',').map(parse_hop).collect();
+    }
+
+    values(FORWARDED.as_str())
+        .split(',')
+        .filter_map(|element| {
+            element.split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .map(|(_, value)| parse_hop(value))
+        })
+        .collect()
+}
+
+/// An address as forwarding headers write it: bare, quoted, bracketed, or with a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    hop.parse::<IpAddr>().ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|address| address.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// Count a finished request in `aperio_http_requests_total` and time it in
+/// `aperio_http_request_duration_seconds`. Labels are kept to a bounded set: the route template
+/// rather than the path, standard methods, and the status class.
+async fn record_request(route: &str, method: &Method, status: u16, duration_seconds: f64) {
+    let method = match *method {
+        Method::GET | Method::HEAD | Method::POST | Method::PUT | Method::PATCH | Method::DELETE | Method::OPTIONS => method.as_str(),
+        _ => "OTHER",
+    };
+    let labels = HashMap::from([
+        ("route".to_string(), route.to_string()),
+        ("method".to_string(), method.to_string()),
+    ]);
+
+    let mut counter_labels = labels.clone();
+    counter_labels.insert("status".to_string(), format!("{}xx", status / 100));
+    let metrics = get_metrics();
+    metrics.increment_counter("aperio_http_requests_total", counter_labels).await;
+    metrics.record_histogram_with_bounds("aperio_http_request_duration_seconds", duration_seconds, labels, &SECONDS_BOUNDS).await;
+}
 
 impl<S, B> Transform<S, ServiceRequest> for RequestTracking
 where
@@ -23,12 +116,13 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(RequestTrackingMiddleware { service }))
+        ready(Ok(RequestTrackingMiddleware { service, trusted_proxies: self.trusted_proxies.clone() }))
     }
 }
 
 pub struct RequestTrackingMiddleware<S> {
     service: S,
+    trusted_proxies: Arc<Vec<IpNet>>,
 }
 
 impl<S, B> Service<ServiceRequest> for RequestTrackingMiddleware<S>
@@ -47,7 +141,10 @@ where
         let start_time = Instant::now();
         let correlation_id = Uuid::new_v4().to_string();
         let method = req.method().to_string();
+        let http_method = req.method().clone();
         let path = req.path().to_string();
+        // The resource pattern, e.g. /api/v1/jobs/{job_id}, so job ids don't each get a series
+        let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
         let user_agent = req
             .headers()
             .get("user-agent")
@@ -55,13 +152,24 @@ where
             .unwrap_or("unknown")
             .to_string();
 
-        // Add correlation ID to request extensions
+        let client_ip = resolve_client_ip(
+            req.peer_addr().map(|address| address.ip()),
+            req.headers(),
+            &self.trusted_proxies,
+        );
+        let client_ip_field = client_ip.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".to_string());
+
+        // Add correlation ID and client address to request extensions
         req.extensions_mut().insert(correlation_id.clone());
+        if let Some(client_ip) = client_ip {
+            req.extensions_mut().insert(ClientIp(client_ip));
+        }
 
         // Create a span for this request
         let span = tracing::info_span!(
             "http_request",
             correlation_id = %correlation_id,
+            client_ip = %client_ip_field,
             method = %method,
             path = %path,
             user_agent = %user_agent
@@ -74,6 +182,7 @@ where
 
             info!(
                 correlation_id = %correlation_id,
+                client_ip = %client_ip_field,
                 method = %method,
                 path = %path,
                 "Request started"
@@ -108,8 +217,7 @@ where
                         );
                     }
 
-                    // Store metrics for collection
-                    REQUEST_METRICS.record_request(duration_ms, status >= 400);
+                    record_request(&route, &http_method, status, duration.as_secs_f64()).await;
                 }
                 Err(error) => {
                     warn!(
@@ -121,7 +229,8 @@ where
                         "Request failed with error"
                     );
 
-                    REQUEST_METRICS.record_request(duration_ms, true);
+                    let status = error.as_response_error().status_code().as_u16();
+                    record_request(&route, &http_method, status, duration.as_secs_f64()).await;
                 }
             }
 
@@ -130,36 +239,87 @@ where
     }
 }
 
-// Simple metrics collector
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
 
-pub struct RequestMetrics {
-    total_requests: AtomicUsize,
-    error_requests: AtomicUsize,
-    total_duration_ms: AtomicU64,
-}
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
 
+    fn proxies(networks: &[&str]) -> Vec<IpNet> {
+        networks.iter().map(|network| network.parse().unwrap()).collect()
+    }
 
-impl RequestMetrics {
-    const fn new() -> Self {
-        Self {
-            total_requests: AtomicUsize::new(0),
-            error_requests: AtomicUsize::new(0),
-            total_duration_ms: AtomicU64::new(0),
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(HeaderName::from_bytes(name.as_bytes()).unwrap(), HeaderValue::from_str(value).unwrap());
         }
+        headers
     }
 
-    fn record_request(&self, duration_ms: f64, is_error: bool) {
-        self.total_requests.fetch_add(1, Ordering::Relaxed);
-        self.total_duration_ms.fetch_add(duration_ms as u64, Ordering::Relaxed);
+    #[test]
+    fn untrusted_peer_cannot_claim_an_address() {
+        let spoofed = headers(&[("x-forwarded-for", "1.2.3.4"), ("forwarded", "for=5.6.7.8")]);
+        assert_eq!(resolve_client_ip(Some(ip("203.0.113.9")), &spoofed, &proxies(&["10.0.0.0/8"])), Some(ip("203.0.113.9")));
+        // With no proxies configured nobody is trusted
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &spoofed, &[]), Some(ip("10.0.0.2")));
+    }
 
-        if is_error {
-            self.error_requests.fetch_add(1, Ordering::Relaxed);
-        }
+    #[test]
+    fn trusted_chain_is_walked_to_the_first_untrusted_hop() {
+        let trusted = proxies(&["10.0.0.0/8", "192.168.1.5/32"]);
+        // client, then a spoofed entry the client sent, then two of our proxies
+        let chain = headers(&[("x-forwarded-for", "9.9.9.9, 198.51.100.7, 10.1.2.3"), ("x-forwarded-for", "192.168.1.5")]);
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &chain, &trusted), Some(ip("198.51.100.7")));
 
+        // Every hop trusted: the furthest one is the best we know
+        let internal = headers(&[("x-forwarded-for", "10.9.9.9, 10.1.2.3")]);
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &internal, &trusted), Some(ip("10.9.9.9")));
+
+        // A trusted peer that forwards nothing is the client itself
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &HeaderMap::new(), &trusted), Some(ip("10.0.0.2")));
     }
 
-}
+    #[test]
+    fn forwarded_is_read_when_x_forwarded_for_is_absent() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let forwarded = headers(&[("forwarded", r#"for=198.51.100.7;proto=https, for="[2001:db8::1]:4711";by=10.0.0.1, for=10.1.2.3"#)]);
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &forwarded, &trusted), Some(ip("2001:db8::1")));
+
+        // X-Forwarded-For wins when both are present
+        let both = headers(&[("forwarded", "for=198.51.100.7"), ("x-forwarded-for", "203.0.113.1")]);
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &both, &trusted), Some(ip("203.0.113.1")));
+    }
+
+    #[test]
+    fn obfuscated_or_unknown_hops_end_the_chain() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        for hop in ["_hidden", "unknown", "\"_gazonk\""] {
+            let forwarded = headers(&[("forwarded", &format!("for=198.51.100.7, for={hop}, for=10.1.2.3"))]);
+            // Whatever sits behind an unreadable hop can't be vouched for
+            assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &forwarded, &trusted), Some(ip("10.1.2.3")), "{hop}");
+        }
+        let garbage = headers(&[("x-forwarded-for", "not-an-ip")]);
+        assert_eq!(resolve_client_ip(Some(ip("10.0.0.2")), &garbage, &trusted), Some(ip("10.0.0.2")));
+    }
 
-static REQUEST_METRICS: RequestMetrics = RequestMetrics::new();
+    #[test]
+    fn unix_socket_peer_is_trusted_only_once_proxies_are_configured() {
+        let forwarded = headers(&[("x-forwarded-for", "198.51.100.7, 10.1.2.3")]);
+        assert_eq!(resolve_client_ip(None, &forwarded, &[]), None);
+        assert_eq!(resolve_client_ip(None, &forwarded, &proxies(&["10.0.0.0/8"])), Some(ip("198.51.100.7")));
+        assert_eq!(resolve_client_ip(None, &HeaderMap::new(), &proxies(&["10.0.0.0/8"])), None);
+    }
 
+    #[test]
+    fn hops_may_carry_ports_quotes_and_brackets() {
+        assert_eq!(parse_hop(" 198.51.100.7 "), Some(ip("198.51.100.7")));
+        assert_eq!(parse_hop("198.51.100.7:8080"), Some(ip("198.51.100.7")));
+        assert_eq!(parse_hop("\"[2001:db8::1]:4711\""), Some(ip("2001:db8::1")));
+        assert_eq!(parse_hop("[2001:db8::1]"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_hop("unknown"), None);
+    }
+}
@@ -1,10 +1,11 @@
 use std::path::PathBuf;
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, sqlx::Type)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, sqlx::Type, ToSchema)]
 #[sqlx(type_name = "TEXT")]
 pub enum JobStatus {
     Pending,
@@ -14,6 +15,8 @@ pub enum JobStatus {
     Completed,
     Failed,
     Cancelled,
+    /// Completed, but the output was evicted to free space; resubmit to regenerate it
+    Expired,
 }
 
 impl std::fmt::Display for JobStatus {
@@ -26,21 +29,491 @@ impl std::fmt::Display for JobStatus {
             JobStatus::Completed => write!(f, "Completed"),
             JobStatus::Failed => write!(f, "Failed"),
             JobStatus::Cancelled => write!(f, "Cancelled"),
+            JobStatus::Expired => write!(f, "Expired"),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+impl JobStatus {
+    pub const ALL: [JobStatus; 8] = [
+        JobStatus::Pending,
+        JobStatus::Claimed,
+        JobStatus::Downloading,
+        JobStatus::Processing,
+        JobStatus::Completed,
+        JobStatus::Failed,
+        JobStatus::Cancelled,
+        JobStatus::Expired,
+    ];
+
+    /// Statuses a job doesn't leave on its own; only eviction still moves Completed to Expired
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled | JobStatus::Expired)
+    }
+
+    /// Whether a job in this status may move to `next`. A running job may be saved in its own
+    /// status to record progress; a finished one takes no further writes, apart from eviction.
+    pub fn can_transition_to(&self, next: &JobStatus) -> bool {
+        use JobStatus::*;
+        match (self, next) {
+            (Completed, Expired) => true,
+            (current, _) if current.is_terminal() => false,
+            (current, next) if current == next => true,
+            // Cached results complete a job without running it
+            (Pending, Claimed | Downloading | Completed | Failed | Cancelled) => true,
+            (Claimed, Pending | Downloading | Processing | Completed | Failed | Cancelled) => true,
+            // Downloaded jobs wait in Pending for a processing slot
+            (Downloading, Pending | Processing | Completed | Failed | Cancelled) => true,
+            // Processing goes back to Pending when no slot frees up in time
+            (Processing, Pending | Completed | Failed | Cancelled) => true,
+            _ => false,
+        }
+    }
+
+    /// The statuses a job may move to this one from
+    pub fn allowed_from(&self) -> Vec<JobStatus> {
+        JobStatus::ALL.into_iter().filter(|status| status.can_transition_to(self)).collect()
+    }
+
+    /// Parse the name a status is reported under, e.g. `Processing`
+    pub fn from_name(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleMode {
+    #[default]
+    None,
+    Embed,
+    Burn,
+}
+
+/// Why a job failed, stable enough for clients to branch on and for metrics to aggregate
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    Unavailable,
+    Private,
+    LoginRequired,
+    GeoBlocked,
+    Removed,
+    RateLimited,
+    UnsupportedUrl,
+    LiveStream,
+    Network,
+    IncompleteDownload,
+    DownloadFailed,
+    ProcessingFailed,
+    Timeout,
+    Internal,
+}
+
+impl FailureReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureReason::Unavailable => "unavailable",
+            FailureReason::Private => "private",
+            FailureReason::LoginRequired => "login_required",
+            FailureReason::GeoBlocked => "geo_blocked",
+            FailureReason::Removed => "removed",
+            FailureReason::RateLimited => "rate_limited",
+            FailureReason::UnsupportedUrl => "unsupported_url",
+            FailureReason::LiveStream => "live_stream",
+            FailureReason::Network => "network",
+            FailureReason::IncompleteDownload => "incomplete_download",
+            FailureReason::DownloadFailed => "download_failed",
+            FailureReason::ProcessingFailed => "processing_failed",
+            FailureReason::Timeout => "timeout",
+            FailureReason::Internal => "internal",
+        }
+    }
+
+    /// Transient failures worth another attempt; everything else fails the same way again
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, FailureReason::Network | FailureReason::IncompleteDownload | FailureReason::Timeout)
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+/// Where a completed job's output stands with the configured publisher
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishStatus {
+    /// Uploading, or waiting to retry
+    Pending,
+    Published,
+    /// Every attempt failed; the job's log says why
+    Failed,
+}
+
+impl PublishStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PublishStatus::Pending => "pending",
+            PublishStatus::Published => "published",
+            PublishStatus::Failed => "failed",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+/// How far one of a concat job's sources got
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceStatus {
+    #[default]
+    Pending,
+    Downloading,
+    Downloaded,
+    Failed,
+}
+
+impl SourceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceStatus::Pending => "pending",
+            SourceStatus::Downloading => "downloading",
+            SourceStatus::Downloaded => "downloaded",
+            SourceStatus::Failed => "failed",
+        }
+    }
+}
+
+/// One of the URLs a concat job downloads and joins, in order
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct JobSource {
+    pub url: String,
+    #[serde(default)]
+    pub status: SourceStatus,
+    /// Where the fetched file waits to be joined; kept so a restart doesn't fetch it again
+    #[serde(default, skip_serializing)]
+    pub downloaded_path: Option<String>,
+}
+
+impl JobSource {
+    pub fn new(url: String) -> Self {
+        Self { url, status: SourceStatus::Pending, downloaded_path: None }
+    }
+
+    /// The fetched file, if it is still there
+    pub fn downloaded_file(&self) -> Option<PathBuf> {
+        self.downloaded_path.as_ref().map(PathBuf::from).filter(|path| path.exists())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct SubtitleOptions {
+    #[serde(default)]
+    pub mode: SubtitleMode,
+    #[serde(default)]
+    pub languages: Vec<String>,
+}
+
+/// Aspect ratios a crop preset cuts the frame to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CropPreset {
+    /// 9:16, for vertical-video platforms
+    Vertical,
+    /// 1:1
+    Square,
+    /// 16:9
+    Landscape,
+}
+
+impl CropPreset {
+    /// Width and height of the aspect ratio
+    pub fn aspect_ratio(&self) -> (u64, u64) {
+        match self {
+            CropPreset::Vertical => (9, 16),
+            CropPreset::Square => (1, 1),
+            CropPreset::Landscape => (16, 9),
+        }
+    }
+}
+
+/// Cut the frame down before it is scaled: either the largest window of a preset's aspect ratio,
+/// or a rectangle in source pixels with `x`/`y` defaulting to 0
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct CropOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<CropPreset>,
+    /// Where a preset's window sits along the side it cuts, from 0.0 (left or top) to 1.0
+    /// (right or bottom); 0.5, the default, centers it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+/// Whether HDR sources are tone-mapped to SDR
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TonemapMode {
+    /// Tone-map when the source is detected as HDR (PQ or HLG transfer)
+    #[default]
+    Auto,
+    Off,
+    /// Tone-map even when the source isn't tagged as HDR
+    Force,
+}
+
+/// Which of a source's audio tracks to keep: a 0-based index among its audio streams,
+/// or a language code matched against the streams' language tags
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(untagged)]
+pub enum AudioTrack {
+    Index(u32),
+    Language(String),
+}
+
+impl std::fmt::Display for AudioTrack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioTrack::Index(index) => write!(f, "track {index}"),
+            AudioTrack::Language(language) => write!(f, "language {language}"),
+        }
+    }
+}
+
+/// An audio stream found in the source when it was probed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct AudioStreamInfo {
+    /// 0-based position among the source's audio streams, as accepted by `audio_track`
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u64>,
+}
+
+impl std::fmt::Display for AudioStreamInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.index, self.language.as_deref().unwrap_or("no language"))
+    }
+}
+
+/// Per-job overrides for the encoder settings in `ProcessingConfig`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ProcessingOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crf: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_bitrate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtitles: Option<SubtitleOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize_audio: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fps: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_metadata: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_title_and_chapters: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_size_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remux_if_compatible: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<CropOptions>,
+    /// Playback speed factor from 0.5 to 2.0; 2.0 plays twice as fast
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+    /// Keep only this audio track instead of the one ffmpeg picks by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_track: Option<AudioTrack>,
+    /// Drop the audio entirely
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mute: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tonemap: Option<TonemapMode>,
+    /// ffmpeg threads for this job's encode, capped at `APERIO_MAX_FFMPEG_THREADS`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+}
+
+/// Input loudness measured by the first loudnorm pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct LoudnessMeasurement {
+    pub integrated_lufs: f64,
+    pub true_peak_dbtp: f64,
+    pub loudness_range_lu: f64,
+    pub threshold_lufs: f64,
+    pub target_offset_lu: f64,
+}
+
+/// Facts recorded about a job's media while it is processed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct JobMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subtitle_languages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_loudness: Option<LoudnessMeasurement>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_fps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_width: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_height: Option<u64>,
+    /// Color tags of the source's video stream as reported by ffprobe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_color_transfer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_color_primaries: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_color_space: Option<String>,
+    /// Whether the source's transfer is PQ (smpte2084) or HLG (arib-std-b67)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hdr: Option<bool>,
+    /// Whether the output was tone-mapped from HDR to SDR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tonemapped: Option<bool>,
+    /// Audio streams in the source, so a reprocess can pick one with `audio_track`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audio_streams: Vec<AudioStreamInfo>,
+    /// Index of the audio stream kept in the output, when one was selected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_track: Option<u32>,
+    /// Frame size of the output as probed after encoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_width: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_height: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_fps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_video_bitrate_kbps: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_size_bytes: Option<u64>,
+    /// Size of the source as fetched by the downloader
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloaded_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_bitrate_kbps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_copy: Option<bool>,
+    /// `-threads` the encode ran with (0 lets ffmpeg decide) and the nice level it ran at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_threads: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_nice: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reencode_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_from: Option<String>,
+    /// yt-dlp's `live_status` for the source (e.g. `not_live`, `is_live`, `is_upcoming`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live_status: Option<String>,
+    /// Hosts yt-dlp resolved the media itself to, checked before downloading
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media_hosts: Vec<String>,
+    /// Times the job was put back on the queue because its source rate-limited us
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub rate_limit_deferrals: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deferred_until: Option<DateTime<Utc>>,
+    /// Length of the source in seconds, from the download probe or before encoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_duration_seconds: Option<f64>,
+    /// When the job entered its current Downloading or Processing stage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage_started_at: Option<DateTime<Utc>>,
+    /// Time spent in each stage once it finished, from holding its slot to its end
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_seconds: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processing_seconds: Option<f64>,
+    /// 1-based position of the concat source being fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_source: Option<u32>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
     pub id: String,
     pub url: String,
+    /// Canonical form of `url` used for deduplication; `url` itself is what gets downloaded
+    pub normalized_url: String,
     pub status: JobStatus,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub downloaded_path: Option<String>,
     pub processed_path: Option<String>,
+    /// Hex SHA-256 of the processed output, when hashing succeeded
+    pub processed_sha256: Option<String>,
     pub error_message: Option<String>,
+    pub failure_reason: Option<FailureReason>,
     pub processing_time_seconds: Option<i64>,
+    pub keep_original: bool,
+    pub options: ProcessingOptions,
+    pub parent_job_id: Option<String>,
+    /// The job whose kept original this job reads as its source; None when it has its own
+    pub source_job_id: Option<String>,
+    pub preview_path: Option<String>,
+    pub metadata: JobMetadata,
+    pub idempotency_key: Option<String>,
+    /// Canonical form of the request submitted with `idempotency_key`, to detect key reuse
+    pub request_fingerprint: Option<String>,
+    /// When `/video` or `/stream` last served the output, for LRU eviction
+    pub last_accessed_at: Option<DateTime<Utc>>,
+    /// Days to keep the output after completion, overriding the configured file retention
+    pub retain_days: Option<u32>,
+    /// When retention may remove the output; set at completion while retention is enabled
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Name of the API key that submitted the job
+    pub client_id: Option<String>,
+    /// The submitter's own reference for the job; unique per API key
+    pub external_id: Option<String>,
+    /// Labels given at submission, sorted and unique
+    pub tags: Vec<String>,
+    /// Unset when no publisher is configured or the job hasn't completed
+    pub publish_status: Option<PublishStatus>,
+    /// Where the publisher serves the output
+    pub published_url: Option<String>,
+    /// Hash the publisher reported for the output
+    pub published_sha256: Option<String>,
+    /// The URLs a concat job joins, in order; empty for a job with a single `url`
+    pub sources: Vec<JobSource>,
+    /// When the job was deleted; it stays restorable until retention removes it
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Correlation id of the request that submitted the job; None for jobs Aperio submitted itself
+    pub correlation_id: Option<String>,
 }
 
 impl Job {
@@ -48,21 +521,83 @@ impl Job {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4().to_string(),
+            normalized_url: url.clone(),
             url,
             status: JobStatus::Pending,
             created_at: now,
             updated_at: now,
             downloaded_path: None,
             processed_path: None,
+            processed_sha256: None,
             error_message: None,
+            failure_reason: None,
             processing_time_seconds: None,
+            keep_original: false,
+            options: ProcessingOptions::default(),
+            parent_job_id: None,
+            source_job_id: None,
+            preview_path: None,
+            metadata: JobMetadata::default(),
+            idempotency_key: None,
+            request_fingerprint: None,
+            last_accessed_at: None,
+            retain_days: None,
+            expires_at: None,
+            client_id: None,
+            external_id: None,
+            tags: Vec::new(),
+            publish_status: None,
+            published_url: None,
+            published_sha256: None,
+            sources: Vec::new(),
+            deleted_at: None,
+            correlation_id: None,
         }
     }
+
+    /// A job joining `urls` into one output. Its `url` is the first of them.
+    pub fn concat(urls: Vec<String>) -> Self {
+        let mut job = Self::new(urls.first().cloned().unwrap_or_default());
+        job.sources = urls.into_iter().map(JobSource::new).collect();
+        job
+    }
+
+    /// The concat source being fetched, or the one whose fetch failed
+    pub fn current_source(&self) -> Option<&JobSource> {
+        let number = self.metadata.current_source?;
+        self.sources.get((number as usize).checked_sub(1)?)
+    }
+
+    /// Whether the job joins several sources rather than downloading `url`
+    pub fn is_concat(&self) -> bool {
+        !self.sources.is_empty()
+    }
+
+    /// A concat job's fetched sources, in order, once every one of them is in place. The
+    /// processing stage joins them in the same encode that applies the job's options.
+    pub fn concat_inputs(&self) -> Option<Vec<PathBuf>> {
+        if !self.is_concat() {
+            return None;
+        }
+        self.sources.iter().map(JobSource::downloaded_file).collect()
+    }
     
     pub fn update_status(&mut self, status: JobStatus) {
         self.status = status;
         self.updated_at = Utc::now();
     }
+
+    /// Enter the Downloading or Processing stage, recording when it began
+    pub fn start_stage(&mut self, status: JobStatus) {
+        self.update_status(status);
+        self.metadata.stage_started_at = Some(self.updated_at);
+    }
+
+    /// Seconds since the current stage began
+    pub fn stage_elapsed_seconds(&self) -> Option<f64> {
+        self.metadata.stage_started_at
+            .map(|started_at| (Utc::now() - started_at).num_milliseconds().max(0) as f64 / 1000.0)
+    }
     
     pub fn set_error(&mut self, error: String) {
         self.status = JobStatus::Failed;
@@ -88,11 +623,23 @@ impl Job {
         self.updated_at = Utc::now();
     }
 
+    pub fn set_preview_path(&mut self, path: PathBuf) {
+        self.preview_path = Some(path.to_string_lossy().to_string());
+        self.updated_at = Utc::now();
+    }
+
     pub fn set_processing_time(&mut self, duration: Duration) {
         self.processing_time_seconds = Some(duration.as_secs() as i64);
         self.updated_at = Utc::now();
     }
     
+    /// Set `expires_at` from `retain_days`, or the configured file retention when there is no override.
+    /// `default_retain_days` is None when retention is disabled, in which case outputs never expire.
+    pub fn set_expiry(&mut self, completed_at: DateTime<Utc>, default_retain_days: Option<u32>) {
+        self.expires_at = default_retain_days
+            .map(|default_days| completed_at + chrono::Duration::days(self.retain_days.unwrap_or(default_days) as i64));
+    }
+
     // Helper methods for PathBuf conversion
     pub fn get_downloaded_path(&self) -> Option<PathBuf> {
         self.downloaded_path.as_ref().map(PathBuf::from)
@@ -102,7 +649,74 @@ impl Job {
         self.processed_path.as_ref().map(PathBuf::from)
     }
 
+    pub fn get_preview_path(&self) -> Option<PathBuf> {
+        self.preview_path.as_ref().map(PathBuf::from)
+    }
+
+    /// Whether the downloaded file belongs to this job rather than being borrowed from a parent job
+    pub fn owns_downloaded_file(&self) -> bool {
+        self.downloaded_path.is_some() && self.source_job_id.is_none()
+    }
+
+    /// Subtitle handling requested for this job
+    pub fn subtitle_mode(&self) -> SubtitleMode {
+        self.options.subtitles.as_ref().map(|s| s.mode).unwrap_or_default()
+    }
+
     pub fn get_processing_time(&self) -> Option<Duration> {
         self.processing_time_seconds.map(|s| Duration::from_secs(s as u64))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use JobStatus::*;
+
+    /// Every status and where it may go next, written out rather than derived so that a change
+    /// to `can_transition_to` has to be made here too
+    const TRANSITIONS: [(JobStatus, &[JobStatus]); 8] = [
+        (Pending, &[Pending, Claimed, Downloading, Completed, Failed, Cancelled]),
+        (Claimed, &[Pending, Claimed, Downloading, Processing, Completed, Failed, Cancelled]),
+        (Downloading, &[Pending, Downloading, Processing, Completed, Failed, Cancelled]),
+        (Processing, &[Pending, Processing, Completed, Failed, Cancelled]),
+        (Completed, &[Expired]),
+        (Failed, &[]),
+        (Cancelled, &[]),
+        (Expired, &[]),
+    ];
+
+    #[test]
+    fn transition_matrix() {
+        assert_eq!(TRANSITIONS.map(|(from, _)| from), JobStatus::ALL);
+        for (from, allowed) in &TRANSITIONS {
+            for to in &JobStatus::ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(to),
+                    "{from} -> {to} should be {}",
+                    if allowed.contains(to) { "allowed" } else { "rejected" }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn allowed_from_matches_the_matrix() {
+        for to in &JobStatus::ALL {
+            let expected: Vec<JobStatus> = TRANSITIONS.iter()
+                .filter(|(_, allowed)| allowed.contains(to))
+                .map(|(from, _)| from.clone())
+                .collect();
+            assert_eq!(to.allowed_from(), expected, "statuses that may move to {to}");
+        }
+    }
+
+    #[test]
+    fn terminal_statuses_accept_nothing_but_eviction() {
+        for from in JobStatus::ALL.iter().filter(|status| status.is_terminal()) {
+            let allowed: Vec<JobStatus> = JobStatus::ALL.into_iter().filter(|to| from.can_transition_to(to)).collect();
+            let expected: &[JobStatus] = if *from == Completed { &[Expired] } else { &[] };
+            assert_eq!(allowed, expected, "{from}");
+        }
+    }
+}
@@ -1,7 +1,12 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use chrono::Utc;
 use sqlx::SqlitePool;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
+use crate::config::{QueueConfig, ReadinessCheck};
+use crate::services::JobQueue;
+use crate::services::resources::ResourceMonitor;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthStatus {
@@ -17,6 +22,8 @@ pub struct HealthChecks {
     pub database: CheckResult,
     pub disk_space: CheckResult,
     pub dependencies: CheckResult,
+    pub queue: CheckResult,
+    pub process: CheckResult,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,22 +33,97 @@ pub struct CheckResult {
     pub response_time_ms: Option<u64>,
 }
 
+/// Whether the instance should get new submissions, with every condition that went into it
+#[derive(Debug, Serialize)]
+pub struct ReadinessStatus {
+    /// `ready`, or `not_ready` when a gating condition fails
+    pub status: String,
+    pub timestamp: u64,
+    pub conditions: ReadinessConditions,
+}
+
+impl ReadinessStatus {
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessConditions {
+    pub database: ReadinessCondition,
+    pub drain: ReadinessCondition,
+    pub worker: ReadinessCondition,
+    pub queue_capacity: ReadinessCondition,
+    pub disk: ReadinessCondition,
+    pub queue_stall: ReadinessCondition,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessCondition {
+    /// `pass` or `fail`
+    pub status: String,
+    /// Whether failing takes the instance out of rotation, per APERIO_READINESS_CHECKS
+    pub gating: bool,
+    pub message: String,
+}
+
+impl ReadinessCondition {
+    fn new(passed: bool, gating: bool, message: String) -> Self {
+        Self {
+            status: if passed { "pass" } else { "fail" }.to_string(),
+            gating,
+            message,
+        }
+    }
+
+    fn blocks_readiness(&self) -> bool {
+        self.gating && self.status == "fail"
+    }
+}
+
 
 pub struct HealthChecker {
     start_time: SystemTime,
     database_pool: SqlitePool,
     working_dir: PathBuf,
+    cookie_files: Vec<PathBuf>,
+    job_queue: Arc<JobQueue>,
+    max_pending_age: Duration,
+    worker_stall_timeout: Duration,
+    readiness_checks: Vec<ReadinessCheck>,
+    readiness_queue_percent: u64,
+    disk_critical_free_bytes: u64,
+    resource_monitor: Arc<ResourceMonitor>,
 }
 
 impl HealthChecker {
     pub fn new(
         database_pool: SqlitePool,
         working_dir: PathBuf,
+        cookie_files: Vec<PathBuf>,
+        job_queue: Arc<JobQueue>,
+        queue_config: &QueueConfig,
+        disk_critical_free_bytes: u64,
+        resource_monitor: Arc<ResourceMonitor>,
     ) -> Self {
+        // APERIO_QUEUE_STALL_FAILS_READINESS predates the list and still opts the stall check in
+        let mut readiness_checks = queue_config.readiness_checks.clone();
+        if queue_config.stall_fails_readiness && !readiness_checks.contains(&ReadinessCheck::QueueStall) {
+            readiness_checks.push(ReadinessCheck::QueueStall);
+        }
+
         Self {
             start_time: SystemTime::now(),
             database_pool,
             working_dir,
+            cookie_files,
+            job_queue,
+            max_pending_age: queue_config.max_pending_age,
+            worker_stall_timeout: queue_config.worker_stall_timeout,
+            readiness_checks,
+            readiness_queue_percent: queue_config.readiness_queue_percent,
+            disk_critical_free_bytes,
+            resource_monitor,
         }
     }
 
@@ -60,11 +142,15 @@ impl HealthChecker {
             database: self.check_database().await,
             disk_space: self.check_disk_space().await,
             dependencies: self.check_dependencies().await,
+            queue: self.check_queue().await,
+            process: self.check_process(),
         };
 
         let overall_status = if checks.database.status == "healthy"
             && checks.disk_space.status == "healthy"
-            && checks.dependencies.status == "healthy" {
+            && checks.dependencies.status == "healthy"
+            && checks.queue.status == "healthy"
+            && checks.process.status == "healthy" {
             "healthy"
         } else if checks.database.status == "critical" {
             "critical"
@@ -81,6 +167,89 @@ impl HealthChecker {
         }
     }
 
+    /// Readiness only looks at what decides whether new submissions can be taken on, so unlike
+    /// the health status it runs no external commands
+    pub async fn get_readiness(&self) -> ReadinessStatus {
+        let now = Utc::now();
+        let gating = |check| self.readiness_checks.contains(&check);
+
+        let database = self.check_database().await;
+        let database = ReadinessCondition::new(
+            database.status == "healthy",
+            gating(ReadinessCheck::Database),
+            database.message.unwrap_or_default(),
+        );
+
+        let drain = self.job_queue.drain_status().await;
+        let drain = ReadinessCondition::new(
+            !drain.draining,
+            gating(ReadinessCheck::Drain),
+            match drain.draining_since {
+                Some(since) => format!("Draining since {}", since.to_rfc3339()),
+                None => "Not draining".to_string(),
+            },
+        );
+
+        let worker_idle = self.worker_idle(now).await;
+        let worker = ReadinessCondition::new(
+            worker_idle.is_some_and(|idle| idle <= self.worker_stall_timeout),
+            gating(ReadinessCheck::Worker),
+            match worker_idle {
+                Some(idle) => format!(
+                    "Worker last finished a pass {}s ago (stalled after {}s)",
+                    idle.as_secs(), self.worker_stall_timeout.as_secs()
+                ),
+                None => "Worker has not started".to_string(),
+            },
+        );
+
+        let (queued, max_queued) = self.job_queue.queue_fill().await;
+        let high_water = (max_queued as u64 * self.readiness_queue_percent).div_ceil(100).max(1) as usize;
+        let queue_capacity = ReadinessCondition::new(
+            queued < high_water,
+            gating(ReadinessCheck::QueueCapacity),
+            format!("{queued} of {max_queued} queue slots taken (not ready from {high_water})"),
+        );
+
+        let disk = match fs2::available_space(&self.working_dir) {
+            Ok(free) => ReadinessCondition::new(
+                free >= self.disk_critical_free_bytes,
+                gating(ReadinessCheck::Disk),
+                format!("{free} bytes free in the working directory (critical below {})", self.disk_critical_free_bytes),
+            ),
+            Err(e) => ReadinessCondition::new(false, gating(ReadinessCheck::Disk), format!("Working directory inaccessible: {e}")),
+        };
+
+        let oldest_pending = self.oldest_pending(now).await;
+        let queue_stall = ReadinessCondition::new(
+            oldest_pending.is_none_or(|age| age <= self.max_pending_age),
+            gating(ReadinessCheck::QueueStall),
+            match oldest_pending {
+                Some(age) => format!(
+                    "Oldest queued job has waited {}s (stalled after {}s)",
+                    age.as_secs(), self.max_pending_age.as_secs()
+                ),
+                None => "No jobs queued".to_string(),
+            },
+        );
+
+        let conditions = ReadinessConditions { database, drain, worker, queue_capacity, disk, queue_stall };
+        let ready = ![
+            &conditions.database,
+            &conditions.drain,
+            &conditions.worker,
+            &conditions.queue_capacity,
+            &conditions.disk,
+            &conditions.queue_stall,
+        ].iter().any(|condition| condition.blocks_readiness());
+
+        ReadinessStatus {
+            status: if ready { "ready" } else { "not_ready" }.to_string(),
+            timestamp: now.timestamp() as u64,
+            conditions,
+        }
+    }
+
     async fn check_database(&self) -> CheckResult {
         let start = SystemTime::now();
 
@@ -103,15 +272,19 @@ impl HealthChecker {
 
     async fn check_disk_space(&self) -> CheckResult {
         // Check available disk space in working directory
-        match std::fs::metadata(&self.working_dir) {
-            Ok(_) => {
-                // Simple check that directory is accessible
-                CheckResult {
-                    status: "healthy".to_string(),
-                    message: Some("Working directory accessible".to_string()),
-                    response_time_ms: Some(0),
-                }
-            }
+        match fs2::available_space(&self.working_dir) {
+            Ok(free) if free < self.disk_critical_free_bytes => CheckResult {
+                status: "critical".to_string(),
+                message: Some(format!(
+                    "Working directory has {free} bytes free, below the critical {} bytes", self.disk_critical_free_bytes
+                )),
+                response_time_ms: None,
+            },
+            Ok(free) => CheckResult {
+                status: "healthy".to_string(),
+                message: Some(format!("Working directory has {free} bytes free")),
+                response_time_ms: Some(0),
+            },
             Err(e) => CheckResult {
                 status: "critical".to_string(),
                 message: Some(format!("Working directory inaccessible: {e}")),
@@ -132,14 +305,27 @@ impl HealthChecker {
             .output()
             .await;
 
+        // Paths stay out of the response; only whether the files are still readable is reported
+        let cookies_readable = self.cookie_files.iter().all(|path| std::fs::File::open(path).is_ok());
+        let cookies_note = match (self.cookie_files.is_empty(), cookies_readable) {
+            (true, _) => "no cookies file configured",
+            (false, true) => "cookies files readable",
+            (false, false) => "a configured cookies file is not readable",
+        };
+
         match (yt_dlp_check, ffmpeg_check) {
-            (Ok(yt_dlp), Ok(ffmpeg)) if yt_dlp.status.success() && ffmpeg.status.success() => {
+            (Ok(yt_dlp), Ok(ffmpeg)) if yt_dlp.status.success() && ffmpeg.status.success() && cookies_readable => {
                 CheckResult {
                     status: "healthy".to_string(),
-                    message: Some("All dependencies available".to_string()),
+                    message: Some(format!("All dependencies available; {cookies_note}")),
                     response_time_ms: Some(10),
                 }
             }
+            (Ok(yt_dlp), Ok(ffmpeg)) if yt_dlp.status.success() && ffmpeg.status.success() => CheckResult {
+                status: "degraded".to_string(),
+                message: Some(format!("Dependencies available, but {cookies_note}")),
+                response_time_ms: None,
+            },
             _ => CheckResult {
                 status: "degraded".to_string(),
                 message: Some("Some dependencies may be missing".to_string()),
@@ -147,5 +333,57 @@ impl HealthChecker {
             }
         }
     }
-}
 
+    async fn check_queue(&self) -> CheckResult {
+        // A worker that died or hangs leaves jobs queued forever while everything else looks fine
+        let now = Utc::now();
+        let worker_idle = self.worker_idle(now).await;
+        let oldest_pending = self.oldest_pending(now).await;
+
+        let worker_note = match worker_idle {
+            Some(idle) => format!("worker last finished a pass {}s ago", idle.as_secs()),
+            None => "worker has not started".to_string(),
+        };
+        let pending_note = match oldest_pending {
+            Some(age) => format!("oldest queued job has waited {}s", age.as_secs()),
+            None => "no jobs queued".to_string(),
+        };
+
+        let worker_stalled = worker_idle.is_none_or(|idle| idle > self.worker_stall_timeout);
+        let queue_stalled = oldest_pending.is_some_and(|age| age > self.max_pending_age);
+        CheckResult {
+            status: if worker_stalled || queue_stalled { "degraded" } else { "healthy" }.to_string(),
+            message: Some(format!("Queue {worker_note}; {pending_note}")),
+            response_time_ms: None,
+        }
+    }
+
+    /// Degraded while resident memory is over APERIO_MAX_RSS_MB, which catches leaks such as a
+    /// queue that keeps growing before the host runs out of memory
+    fn check_process(&self) -> CheckResult {
+        let rss_bytes = self.resource_monitor.latest().and_then(|stats| stats.rss_bytes);
+        let (status, message) = match (rss_bytes, self.resource_monitor.max_rss_bytes()) {
+            (Some(rss), Some(max)) if rss > max => ("degraded", format!("Resident memory is {rss} bytes, over the limit of {max} bytes")),
+            (Some(rss), Some(max)) => ("healthy", format!("Resident memory is {rss} bytes of the {max} bytes allowed")),
+            (Some(rss), None) => ("healthy", format!("Resident memory is {rss} bytes")),
+            (None, _) => ("healthy", "Resident memory not measured on this platform yet".to_string()),
+        };
+        CheckResult {
+            status: status.to_string(),
+            message: Some(message),
+            response_time_ms: None,
+        }
+    }
+
+    /// How long since the queue worker last finished a pass; None if it never started
+    async fn worker_idle(&self, now: chrono::DateTime<Utc>) -> Option<Duration> {
+        self.job_queue.worker_heartbeat().await
+            .map(|heartbeat| (now - heartbeat).to_std().unwrap_or_default())
+    }
+
+    /// How long the longest-waiting queued job has waited
+    async fn oldest_pending(&self, now: chrono::DateTime<Utc>) -> Option<Duration> {
+        self.job_queue.oldest_queued_at().await
+            .map(|queued_at| (now - queued_at).to_std().unwrap_or_default())
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::{AppError, AppResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Row, SqlitePool};
+use tracing::error;
+use utoipa::ToSchema;
+
+/// Administrative and destructive actions recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    CancelJob,
+    DeleteJob,
+    RestoreJob,
+    CancelTaggedJobs,
+    PurgeTaggedJobs,
+    RunRetention,
+    SweepOrphans,
+    RunDbMaintenance,
+    ResetMetrics,
+    StartDrain,
+    CancelDrain,
+    ReloadConfig,
+    CreateSubscription,
+    UpdateSubscription,
+    DeleteSubscription,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::CancelJob => "cancel_job",
+            AuditAction::DeleteJob => "delete_job",
+            AuditAction::RestoreJob => "restore_job",
+            AuditAction::CancelTaggedJobs => "cancel_tagged_jobs",
+            AuditAction::PurgeTaggedJobs => "purge_tagged_jobs",
+            AuditAction::RunRetention => "run_retention",
+            AuditAction::SweepOrphans => "sweep_orphans",
+            AuditAction::RunDbMaintenance => "run_db_maintenance",
+            AuditAction::ResetMetrics => "reset_metrics",
+            AuditAction::StartDrain => "start_drain",
+            AuditAction::CancelDrain => "cancel_drain",
+            AuditAction::ReloadConfig => "reload_config",
+            AuditAction::CreateSubscription => "create_subscription",
+            AuditAction::UpdateSubscription => "update_subscription",
+            AuditAction::DeleteSubscription => "delete_subscription",
+        }
+    }
+}
+
+/// One recorded action
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// API key name, or `anonymous` for requests authenticated by password or not at all
+    pub actor: String,
+    pub action: String,
+    /// The job id, tag or other resource acted on
+    pub target: String,
+    /// Ties the entry to the request's tracing logs
+    pub correlation_id: Option<String>,
+    /// Where the request came from, looking through trusted proxies
+    pub client_ip: Option<String>,
+    /// `success` or `failure`
+    pub outcome: String,
+    /// What the action did, e.g. how many jobs, or why it failed
+    pub detail: Option<String>,
+}
+
+/// Who performed an action, and with which request
+#[derive(Debug, Clone)]
+pub struct AuditActor {
+    pub actor: String,
+    pub correlation_id: Option<String>,
+    pub client_ip: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    pool: SqlitePool,
+}
+
+impl AuditLog {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Record an action. Best-effort: a failed insert is logged and never fails the action itself.
+    pub async fn record(
+        &self,
+        actor: &AuditActor,
+        action: AuditAction,
+        target: &str,
+        outcome: Result<Option<String>, String>,
+    ) {
+        let (outcome, detail) = match outcome {
+            Ok(detail) => ("success", detail),
+            Err(error) => ("failure", Some(error)),
+        };
+
+        let result = sqlx::query(
+            "INSERT INTO audit_log (created_at, actor, action, target, correlation_id, client_ip, outcome, detail)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(Utc::now())
+        .bind(&actor.actor)
+        .bind(action.as_str())
+        .bind(target)
+        .bind(&actor.correlation_id)
+        .bind(&actor.client_ip)
+        .bind(outcome)
+        .bind(detail)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = result {
+            error!(
+                action = action.as_str(), target, actor = %actor.actor,
+                "Failed to write audit log entry: {}", e
+            );
+        }
+    }
+
+    /// Entries newest first, optionally only those at or after `since` and of one action,
+    /// with the total matching so callers can page
+    pub async fn list(
+        &self,
+        since: Option<DateTime<Utc>>,
+        action: Option<AuditAction>,
+        page: u32,
+        page_size: u32,
+    ) -> AppResult<(Vec<AuditEntry>, i64)> {
+        let push_where = |query: &mut QueryBuilder<'_, sqlx::Sqlite>| {
+            let mut clause = " WHERE ";
+            if let Some(since) = since {
+                query.push(clause).push("created_at >= ").push_bind(since);
+                clause = " AND ";
+            }
+            if let Some(action) = action {
+                query.push(clause).push("action = ").push_bind(action.as_str());
+            }
+        };
+
+        let mut count_query = QueryBuilder::new("SELECT COUNT(*) AS total FROM audit_log");
+        push_where(&mut count_query);
+        let total: i64 = count_query.build()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to count audit log entries"))?
+            .get("total");
+
+        let mut query = QueryBuilder::new("SELECT * FROM audit_log");
+        push_where(&mut query);
+        query.push(" ORDER BY created_at DESC, id DESC LIMIT ")
+            .push_bind(page_size as i64)
+            .push(" OFFSET ")
+            .push_bind(page as i64 * page_size as i64);
+        let entries = query.build_query_as::<AuditEntry>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list audit log entries"))?;
+
+        Ok((entries, total))
+    }
+
+    /// Delete entries older than `retention_days`, returning how many went
+    pub async fn delete_older_than(&self, retention_days: u32) -> AppResult<u64> {
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let result = sqlx::query("DELETE FROM audit_log WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to delete old audit log entries"))?;
+
+        Ok(result.rows_affected())
+    }
+}
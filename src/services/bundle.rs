@@ -0,0 +1,93 @@
+use crate::error::{AppError, AppResult};
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::path::PathBuf;
+use tokio::io::DuplexStream;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+use tracing::{debug, warn};
+
+/// How much of the archive may be buffered ahead of a slow client
+const BUNDLE_BUFFER_BYTES: usize = 256 * 1024;
+
+/// A file to include in a job bundle, opened before streaming starts so retention can't remove it midway
+pub struct BundleFile {
+    pub name: String,
+    pub kind: &'static str,
+    pub file: tokio::fs::File,
+    pub size_bytes: u64,
+    pub sha256: Option<String>,
+}
+
+impl BundleFile {
+    pub async fn open(path: PathBuf, kind: &'static str, sha256: Option<String>) -> AppResult<Self> {
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| AppError::Internal(format!("Invalid bundle file path: {}", path.display())))?;
+        let file = tokio::fs::File::open(&path).await
+            .map_err(|e| AppError::NotFound(format!("{kind} file not found on disk: {e}")))?;
+        let size_bytes = file.metadata().await
+            .map_err(AppError::io("Failed to get file metadata"))?
+            .len();
+        Ok(Self { name, kind, file, size_bytes, sha256 })
+    }
+}
+
+/// One archive entry as described in `manifest.json`
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BundleEntry {
+    pub name: String,
+    pub kind: &'static str,
+    pub size_bytes: u64,
+    pub sha256: Option<String>,
+}
+
+impl From<&BundleFile> for BundleEntry {
+    fn from(file: &BundleFile) -> Self {
+        Self {
+            name: file.name.clone(),
+            kind: file.kind,
+            size_bytes: file.size_bytes,
+            sha256: file.sha256.clone(),
+        }
+    }
+}
+
+/// Stream a zip of `files` followed by `manifest.json`, assembled as it is read.
+/// Entries are stored uncompressed: media is already compressed and deflating it only burns CPU.
+pub fn stream_bundle(job_id: String, files: Vec<BundleFile>, manifest: Vec<u8>) -> ReaderStream<DuplexStream> {
+    let (writer, reader) = tokio::io::duplex(BUNDLE_BUFFER_BYTES);
+
+    tokio::spawn(async move {
+        match write_bundle(writer, files, manifest).await {
+            Ok(()) => debug!("Finished streaming bundle for job {}", job_id),
+            // The client sees a truncated archive, which every unzip tool rejects
+            Err(e) => warn!("Failed to stream bundle for job {}: {}", job_id, e),
+        }
+    });
+
+    ReaderStream::new(reader)
+}
+
+async fn write_bundle(writer: DuplexStream, files: Vec<BundleFile>, manifest: Vec<u8>) -> Result<(), String> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+
+    for bundle_file in files {
+        let entry = ZipEntryBuilder::new(bundle_file.name.clone().into(), Compression::Stored);
+        let mut entry_writer = zip.write_entry_stream(entry).await
+            .map_err(|e| format!("failed to start entry {}: {e}", bundle_file.name))?;
+        futures::io::copy(&mut bundle_file.file.compat(), &mut entry_writer).await
+            .map_err(|e| format!("failed to write entry {}: {e}", bundle_file.name))?;
+        entry_writer.close().await
+            .map_err(|e| format!("failed to finish entry {}: {e}", bundle_file.name))?;
+    }
+
+    let entry = ZipEntryBuilder::new("manifest.json".to_string().into(), Compression::Stored);
+    zip.write_entry_whole(entry, &manifest).await
+        .map_err(|e| format!("failed to write manifest: {e}"))?;
+    zip.close().await
+        .map_err(|e| format!("failed to finish archive: {e}"))?;
+    Ok(())
+}
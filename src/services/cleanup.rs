@@ -1,26 +1,49 @@
+use crate::counter_add;
 use crate::error::{AppError, AppResult};
-use std::path::PathBuf;
+use crate::services::security::job_working_dir;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::fs;
 use tracing::{info, warn};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+/// What removing a job's files recovered
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CleanupOutcome {
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+}
 
 #[derive(Clone)]
 pub struct CleanupService {
     working_dir: PathBuf,
+    // Where completed outputs are moved; a job's files live under both directories
+    storage_dir: PathBuf,
     // Track files currently being processed to prevent cleanup races
     active_files: Arc<Mutex<HashSet<String>>>,
+    // Track jobs that borrow another job's source file so it outlives the owner's cleanup
+    source_users: Arc<Mutex<HashMap<String, HashSet<String>>>>,
+    // Whether flat `{job_id}_*` files from before per-job directories may still exist
+    legacy_files_present: Arc<AtomicBool>,
 }
 
 impl CleanupService {
-    pub fn new(working_dir: PathBuf) -> Self {
+    pub fn new(working_dir: PathBuf, storage_dir: PathBuf) -> Self {
         Self { 
             working_dir,
+            storage_dir,
             active_files: Arc::new(Mutex::new(HashSet::new())),
+            source_users: Arc::new(Mutex::new(HashMap::new())),
+            legacy_files_present: Arc::new(AtomicBool::new(true)),
         }
     }
 
+    pub fn working_dir(&self) -> &std::path::Path {
+        &self.working_dir
+    }
+
     /// Mark a file as actively being processed
     pub async fn mark_file_active(&self, file_path: &str) -> AppResult<()> {
         let mut active_files = self.active_files.lock().await;
@@ -41,60 +64,75 @@ impl CleanupService {
         active_files.contains(file_path)
     }
 
+    /// Record that a job is using a source file it does not own
+    pub async fn acquire_source(&self, file_path: &str, job_id: &str) {
+        let mut source_users = self.source_users.lock().await;
+        source_users.entry(file_path.to_string()).or_default().insert(job_id.to_string());
+    }
+
+    /// Release a job's hold on a borrowed source file
+    pub async fn release_source(&self, file_path: &str, job_id: &str) {
+        let mut source_users = self.source_users.lock().await;
+        if let Some(users) = source_users.get_mut(file_path) {
+            users.remove(job_id);
+            if users.is_empty() {
+                source_users.remove(file_path);
+            }
+        }
+    }
+
+    /// Jobs currently borrowing a source file
+    async fn source_users(&self, file_path: &str) -> Vec<String> {
+        let source_users = self.source_users.lock().await;
+        source_users
+            .get(file_path)
+            .map(|users| users.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Clean up files associated with a job with race condition protection
-    pub async fn cleanup_job_files(&self, job_id: &str) -> AppResult<()> {
+    pub async fn cleanup_job_files(&self, job_id: &str) -> AppResult<CleanupOutcome> {
         let mut cleaned_files = Vec::new();
+        let mut bytes_freed = 0;
         let mut errors = Vec::new();
         let mut skipped_files = Vec::new();
 
-        // Clean up any files that start with the job ID
-        if let Ok(mut entries) = fs::read_dir(&self.working_dir).await {
-            while let Ok(Some(entry)) = entries.next_entry().await {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(filename) = path.file_name() {
-                        let filename_str = filename.to_string_lossy();
-                        if filename_str.starts_with(job_id) {
-                            let path_str = path.to_string_lossy().to_string();
-                            
-                            // Check if file is currently being processed
-                            if self.is_file_active(&path_str).await {
-                                skipped_files.push(path_str);
-                                warn!("Skipping cleanup of active file: {}", path.display());
-                                continue;
-                            }
-
-                            // Try to acquire a cleanup lock by temporarily marking the file
-                            self.mark_file_active(&path_str).await?;
-                            
-                            // Double-check the file still exists (prevent TOCTOU)
-                            if !path.exists() {
-                                self.unmark_file_active(&path_str).await?;
-                                continue;
-                            }
+        for job_dir in self.job_dirs(job_id) {
+            self.remove_job_dir(&job_dir, &mut cleaned_files, &mut bytes_freed, &mut skipped_files, &mut errors).await?;
+        }
 
-                            match fs::remove_file(&path).await {
-                                Ok(_) => {
-                                    cleaned_files.push(path.clone());
-                                    info!("Cleaned up file: {}", path.display());
-                                }
-                                Err(e) => {
-                                    errors.push(format!("Failed to remove {}: {}", path.display(), e));
-                                }
-                            }
-                            
-                            // Always unmark the file after cleanup attempt
-                            self.unmark_file_active(&path_str).await?;
-                        }
+        // Files written before per-job directories still live flat in the working dir
+        if self.legacy_files_present.load(Ordering::Relaxed) {
+            let mut remaining_legacy_files = 0;
+            if let Ok(mut entries) = fs::read_dir(&self.working_dir).await {
+                while let Ok(Some(entry)) = entries.next_entry().await {
+                    let path = entry.path();
+                    if !path.is_file() {
+                        continue;
+                    }
+                    let is_job_file = path.file_name()
+                        .map(|filename| filename.to_string_lossy().starts_with(job_id))
+                        .unwrap_or(false);
+                    if is_job_file {
+                        self.remove_job_file(&path, &mut cleaned_files, &mut bytes_freed, &mut skipped_files, &mut errors).await?;
+                    }
+                    if path.exists() {
+                        remaining_legacy_files += 1;
                     }
                 }
             }
+
+            if remaining_legacy_files == 0 {
+                info!("No legacy flat-named working files remain");
+                self.legacy_files_present.store(false, Ordering::Relaxed);
+            }
         }
 
         if !skipped_files.is_empty() {
             info!("Skipped {} active files during cleanup for job {}", skipped_files.len(), job_id);
         }
 
+        record_cleanup(cleaned_files.len(), bytes_freed, errors.len()).await;
         if !errors.is_empty() {
             return Err(AppError::Internal(format!(
                 "Cleanup completed with errors: {}",
@@ -102,27 +140,198 @@ impl CleanupService {
             )));
         }
 
-        info!("Successfully cleaned up {} files for job {}", cleaned_files.len(), job_id);
+        info!("Successfully cleaned up {} files ({} bytes) for job {}", cleaned_files.len(), bytes_freed, job_id);
+        Ok(CleanupOutcome {
+            files_removed: cleaned_files.len(),
+            bytes_freed,
+        })
+    }
+
+    /// Remove a job's temporary files from the working dir, leaving its stored outputs in place
+    pub async fn cleanup_working_files(&self, job_id: &str) -> AppResult<CleanupOutcome> {
+        let mut cleaned_files = Vec::new();
+        let mut bytes_freed = 0;
+        let mut errors = Vec::new();
+        let mut skipped_files = Vec::new();
+
+        let job_dir = job_working_dir(&self.working_dir, job_id);
+        self.remove_job_dir(&job_dir, &mut cleaned_files, &mut bytes_freed, &mut skipped_files, &mut errors).await?;
+
+        record_cleanup(cleaned_files.len(), bytes_freed, errors.len()).await;
+        if !errors.is_empty() {
+            return Err(AppError::Internal(format!(
+                "Cleanup completed with errors: {}",
+                errors.join(", ")
+            )));
+        }
+
+        info!("Cleaned up {} working files ({} bytes) for job {}", cleaned_files.len(), bytes_freed, job_id);
+        Ok(CleanupOutcome {
+            files_removed: cleaned_files.len(),
+            bytes_freed,
+        })
+    }
+
+    /// A job's directories: temporary files in the working dir, and stored outputs
+    fn job_dirs(&self, job_id: &str) -> [PathBuf; 2] {
+        [job_working_dir(&self.working_dir, job_id), job_working_dir(&self.storage_dir, job_id)]
+    }
+
+    /// Remove a job directory whole, or file by file when some of its files must outlive the cleanup
+    async fn remove_job_dir(
+        &self,
+        job_dir: &Path,
+        cleaned_files: &mut Vec<PathBuf>,
+        bytes_freed: &mut u64,
+        skipped_files: &mut Vec<String>,
+        errors: &mut Vec<String>,
+    ) -> AppResult<()> {
+        if !fs::try_exists(job_dir).await.unwrap_or(false) {
+            return Ok(());
+        }
+
+        let mut job_files = Vec::new();
+        if let Ok(mut entries) = fs::read_dir(job_dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                job_files.push(entry.path());
+            }
+        }
+
+        let mut busy = false;
+        for path in &job_files {
+            if self.is_file_busy(path).await {
+                busy = true;
+                break;
+            }
+        }
+
+        if busy {
+            // Some files must outlive this cleanup, so remove the rest one by one
+            for path in job_files.iter().filter(|path| path.is_file()) {
+                self.remove_job_file(path, cleaned_files, bytes_freed, skipped_files, errors).await?;
+            }
+        } else {
+            let mut dir_bytes = 0;
+            for path in &job_files {
+                dir_bytes += fs::metadata(path).await.map(|metadata| metadata.len()).unwrap_or(0);
+            }
+            match fs::remove_dir_all(job_dir).await {
+                Ok(_) => {
+                    info!("Cleaned up job directory: {}", job_dir.display());
+                    cleaned_files.extend(job_files);
+                    *bytes_freed += dir_bytes;
+                }
+                Err(e) => errors.push(format!("Failed to remove {}: {}", job_dir.display(), e)),
+            }
+        }
         Ok(())
     }
 
-    /// Clean up a specific file path
-    pub async fn cleanup_file(&self, file_path: &PathBuf) -> AppResult<()> {
-        if file_path.exists() {
-            match fs::remove_file(file_path).await {
+    /// Check the working dir once for files from the flat `{job_id}_*` layout
+    pub async fn detect_legacy_files(&self) {
+        let mut found = false;
+        if let Ok(mut entries) = fs::read_dir(&self.working_dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if entry.path().is_file() {
+                    found = true;
+                    break;
+                }
+            }
+        }
+
+        if found {
+            info!("Legacy flat-named working files found; cleanup will also scan the working directory");
+        }
+        self.legacy_files_present.store(found, Ordering::Relaxed);
+    }
+
+    /// What `cleanup_job_files` would remove for a job right now, without removing anything
+    pub async fn measure_job_files(&self, job_id: &str) -> CleanupOutcome {
+        let mut outcome = CleanupOutcome::default();
+        for job_dir in self.job_dirs(job_id) {
+            let Ok(mut entries) = fs::read_dir(job_dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if self.is_file_busy(&path).await {
+                    continue;
+                }
+                if let Ok(metadata) = entry.metadata().await {
+                    outcome.files_removed += 1;
+                    outcome.bytes_freed += metadata.len();
+                }
+            }
+        }
+        outcome
+    }
+
+    /// Whether any file in a job's directories is being processed or borrowed by another job
+    pub async fn has_busy_files(&self, job_id: &str) -> bool {
+        for job_dir in self.job_dirs(job_id) {
+            let Ok(mut entries) = fs::read_dir(&job_dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if self.is_file_busy(&entry.path()).await {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether a file is being processed or borrowed by another job
+    pub async fn is_file_busy(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+        self.is_file_active(&path_str).await || !self.source_users(&path_str).await.is_empty()
+    }
+
+    /// Remove a single job file unless it is active or borrowed
+    async fn remove_job_file(
+        &self,
+        path: &Path,
+        cleaned_files: &mut Vec<PathBuf>,
+        bytes_freed: &mut u64,
+        skipped_files: &mut Vec<String>,
+        errors: &mut Vec<String>,
+    ) -> AppResult<()> {
+        let path_str = path.to_string_lossy().to_string();
+
+        // Check if file is currently being processed
+        if self.is_file_active(&path_str).await {
+            skipped_files.push(path_str);
+            warn!("Skipping cleanup of active file: {}", path.display());
+            return Ok(());
+        }
+
+        // Defer removal while derived jobs still need this file
+        let users = self.source_users(&path_str).await;
+        if !users.is_empty() {
+            info!("Deferring cleanup of {}: in use by jobs {}", path.display(), users.join(","));
+            skipped_files.push(path_str);
+            return Ok(());
+        }
+
+        // Try to acquire a cleanup lock by temporarily marking the file
+        self.mark_file_active(&path_str).await?;
+
+        // Double-check the file still exists (prevent TOCTOU)
+        if let Ok(metadata) = fs::metadata(path).await {
+            match fs::remove_file(path).await {
                 Ok(_) => {
-                    info!("Cleaned up file: {}", file_path.display());
-                    Ok(())
+                    cleaned_files.push(path.to_path_buf());
+                    *bytes_freed += metadata.len();
+                    info!("Cleaned up file: {}", path.display());
+                }
+                Err(e) => {
+                    errors.push(format!("Failed to remove {}: {}", path.display(), e));
                 }
-                Err(e) => Err(AppError::Internal(format!(
-                    "Failed to remove file {}: {}",
-                    file_path.display(),
-                    e
-                )))
             }
-        } else {
-            Ok(()) // File doesn't exist, nothing to clean
         }
+
+        // Always unmark the file after cleanup attempt
+        self.unmark_file_active(&path_str).await
     }
 
     /// Clean up old temporary files (older than specified hours)
@@ -171,4 +380,11 @@ impl CleanupService {
         info!("Cleaned up {} old files", cleaned_count);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Count what a job cleanup removed, including the files removed before any error
+async fn record_cleanup(files_removed: usize, bytes_freed: u64, errors: usize) {
+    counter_add!("aperio_cleanup_files_removed_total", files_removed as u64);
+    counter_add!("aperio_cleanup_bytes_freed_total", bytes_freed);
+    counter_add!("aperio_cleanup_errors_total", errors as u64);
+}
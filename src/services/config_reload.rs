@@ -0,0 +1,143 @@
+use crate::config::{load_config, Config, ProcessingConfig, RELOADABLE_SETTINGS};
+use crate::error::{AppError, AppResult};
+use crate::services::{ProcessService, RetentionService, SecurityValidator};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+
+/// A setting whose value differs between the running config and the reloaded one
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SettingChange {
+    pub key: String,
+    /// Secrets are shown as `<redacted>`, settings nothing sets as `<unset>`
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// False when the setting is only read at startup and the change waits for a restart
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConfigReloadReport {
+    pub changes: Vec<SettingChange>,
+    /// Changed settings that take effect only after a restart
+    pub restart_required: Vec<String>,
+    /// Every setting a reload can change without a restart
+    pub reloadable_settings: Vec<&'static str>,
+}
+
+/// Re-reads the config file and env vars and swaps the reloadable parts into the running services
+pub struct ConfigReloader {
+    /// The config loaded at startup; its settings that need a restart stay in effect
+    startup: Config,
+    /// Effective values as of the last successful load, secrets redacted
+    values: Mutex<BTreeMap<String, String>>,
+    security_validator: SecurityValidator,
+    process_service: Arc<ProcessService>,
+    retention_service: RetentionService,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        startup: Config,
+        values: BTreeMap<String, String>,
+        security_validator: SecurityValidator,
+        process_service: Arc<ProcessService>,
+        retention_service: RetentionService,
+    ) -> Self {
+        Self {
+            startup,
+            values: Mutex::new(values),
+            security_validator,
+            process_service,
+            retention_service,
+        }
+    }
+
+    /// Load and validate the config, then apply it. Nothing is swapped unless the whole config is valid.
+    pub fn reload(&self) -> AppResult<ConfigReloadReport> {
+        let (config, source) = load_config()
+            .map_err(|e| AppError::Unprocessable(format!("Invalid configuration: {e}")))?;
+        config.validate()
+            .map_err(|problems| AppError::Unprocessable(format!("Invalid configuration: {}", problems.join("; "))))?;
+
+        // Held while swapping so concurrent reloads apply and diff one at a time
+        let mut values = self.values.lock().unwrap();
+
+        // First, since the watermark check is the only part that can still fail. The processor
+        // backend, its concurrency and the result cache are wired up at startup and stay as they are.
+        self.process_service.set_config(ProcessingConfig {
+            backend: self.startup.processing.backend.clone(),
+            max_concurrent_processing: self.startup.processing.max_concurrent_processing,
+            result_cache_enabled: self.startup.processing.result_cache_enabled,
+            ..config.processing.clone()
+        })?;
+        self.security_validator.set_allowed_domains(config.download.allowed_domains.clone());
+        self.retention_service.set_config(&config.retention);
+
+        let new_values = source.values();
+        let changes: Vec<SettingChange> = values.keys()
+            .chain(new_values.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|key| values.get(*key) != new_values.get(*key))
+            .map(|key| SettingChange {
+                key: key.clone(),
+                old: values.get(key).cloned(),
+                new: new_values.get(key).cloned(),
+                applied: RELOADABLE_SETTINGS.contains(&key.as_str()),
+            })
+            .collect();
+        let restart_required: Vec<String> = changes.iter()
+            .filter(|change| !change.applied)
+            .map(|change| change.key.clone())
+            .collect();
+
+        // Settings needing a restart keep their running value, so later diffs still report them
+        for change in changes.iter().filter(|change| change.applied) {
+            match &change.new {
+                Some(value) => values.insert(change.key.clone(), value.clone()),
+                None => values.remove(&change.key),
+            };
+        }
+
+        info!("Configuration reloaded: {} settings changed", changes.len());
+        if !restart_required.is_empty() {
+            warn!("Changed settings that require a restart: {}", restart_required.join(", "));
+        }
+        Ok(ConfigReloadReport {
+            changes,
+            restart_required,
+            reloadable_settings: RELOADABLE_SETTINGS.to_vec(),
+        })
+    }
+
+    /// The config the server is running with: the startup config plus whatever reloads applied
+    pub fn effective_config(&self) -> Config {
+        let mut config = self.startup.clone();
+        config.download.allowed_domains = self.security_validator.allowed_domains();
+        config.processing = (*self.process_service.config()).clone();
+        config.retention = self.retention_service.config();
+        config
+    }
+
+    /// Reload whenever the process receives SIGHUP
+    pub async fn start_sighup_listener(self: Arc<Self>) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                error!("Failed to install SIGHUP handler: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = self.reload() {
+                error!("Configuration reload failed, keeping the running config: {}", e);
+            }
+        }
+    }
+}
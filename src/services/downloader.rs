@@ -0,0 +1,449 @@
+use crate::config::{DownloadConfig, DownloaderBackend};
+use crate::error::{AppError, AppResult, Phase};
+use crate::models::job::Job;
+use crate::services::{DownloadService, SecurityValidator};
+use crate::services::error_mapping::download_failure;
+use crate::services::nice::lower_priority;
+use crate::services::security::redact_text;
+use futures::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::time::timeout;
+use tracing::info;
+
+/// Extensions fetched directly over HTTP instead of through yt-dlp
+const DIRECT_MEDIA_EXTENSIONS: [&str; 2] = ["mp4", "mov"];
+
+/// A source file fetched for a job
+#[derive(Debug, Clone)]
+pub struct DownloadedFile {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+/// Fetches a job's source into `dest_dir` as `{job_id}_original.{ext}`
+pub trait Downloader: Send + Sync {
+    /// Check the source before a download slot is taken, failing the job if it can't be
+    /// validated. Anything `fetch` needs from the probe is left in `dest_dir`.
+    fn probe<'a>(&'a self, job: &'a mut Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<()>>;
+
+    fn fetch<'a>(&'a self, job: &'a Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<DownloadedFile>>;
+}
+
+/// Pick the downloader configured by APERIO_DOWNLOADER
+pub fn build_downloader(
+    config: &DownloadConfig,
+    security_validator: SecurityValidator,
+    yt_dlp: Arc<DownloadService>,
+) -> Result<Box<dyn Downloader>, String> {
+    match &config.backend {
+        DownloaderBackend::YtDlp => Ok(Box::new(YtDlpDownloader { service: yt_dlp })),
+        DownloaderBackend::Auto => Ok(Box::new(AutoDownloader {
+            yt_dlp: YtDlpDownloader { service: yt_dlp },
+            http: HttpDownloader::new(config, security_validator),
+        })),
+        DownloaderBackend::Mock(fixture) => {
+            if !fixture.is_file() {
+                return Err("APERIO_DOWNLOADER=mock requires APERIO_MOCK_DOWNLOAD_FIXTURE to name a file".to_string());
+            }
+            Ok(Box::new(MockDownloader { fixture: fixture.clone() }))
+        }
+    }
+}
+
+/// Extension of a URL that points straight at a media file we can fetch without yt-dlp
+fn direct_media_extension(url: &url::Url) -> Option<String> {
+    let extension = Path::new(url.path()).extension()?.to_str()?.to_lowercase();
+    DIRECT_MEDIA_EXTENSIONS.contains(&extension.as_str()).then_some(extension)
+}
+
+async fn downloaded_file(path: PathBuf) -> AppResult<DownloadedFile> {
+    let size_bytes = tokio::fs::metadata(&path).await
+        .map_err(|e| AppError::Download(format!("Downloaded file is not readable: {e}")))?
+        .len();
+    Ok(DownloadedFile { path, size_bytes })
+}
+
+/// Reject a download too small to be real media, removing it so the next attempt starts clean.
+/// Extractors occasionally exit successfully after writing an empty or truncated file.
+pub async fn check_download_size(file: &DownloadedFile, min_bytes: u64) -> AppResult<()> {
+    if file.size_bytes >= min_bytes {
+        return Ok(());
+    }
+
+    let _ = tokio::fs::remove_file(&file.path).await;
+    Err(AppError::Download(format!(
+        "incomplete download: file is {} bytes, expected at least {min_bytes}",
+        file.size_bytes
+    )))
+}
+
+/// The yt-dlp backed `DownloadService`
+pub struct YtDlpDownloader {
+    service: Arc<DownloadService>,
+}
+
+impl Downloader for YtDlpDownloader {
+    fn probe<'a>(&'a self, job: &'a mut Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(self.service.probe_source(job, dest_dir))
+    }
+
+    fn fetch<'a>(&'a self, job: &'a Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<DownloadedFile>> {
+        Box::pin(async move {
+            let path = self.service.download(job, dest_dir).await?;
+            downloaded_file(path).await
+        })
+    }
+}
+
+/// Plain media links over HTTP, everything else through yt-dlp
+pub struct AutoDownloader {
+    yt_dlp: YtDlpDownloader,
+    http: HttpDownloader,
+}
+
+impl AutoDownloader {
+    fn is_direct(job: &Job) -> bool {
+        url::Url::parse(&job.url).ok()
+            .and_then(|url| direct_media_extension(&url))
+            .is_some()
+    }
+}
+
+impl Downloader for AutoDownloader {
+    fn probe<'a>(&'a self, job: &'a mut Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<()>> {
+        if Self::is_direct(job) {
+            self.http.probe(job, dest_dir)
+        } else {
+            self.yt_dlp.probe(job, dest_dir)
+        }
+    }
+
+    fn fetch<'a>(&'a self, job: &'a Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<DownloadedFile>> {
+        if Self::is_direct(job) {
+            self.http.fetch(job, dest_dir)
+        } else {
+            self.yt_dlp.fetch(job, dest_dir)
+        }
+    }
+}
+
+/// Streams a direct media URL to disk, enforcing the size limit while the bytes arrive
+pub struct HttpDownloader {
+    command: String,
+    download_timeout: Duration,
+    nice: Option<u32>,
+    security_validator: SecurityValidator,
+}
+
+impl HttpDownloader {
+    pub fn new(config: &DownloadConfig, security_validator: SecurityValidator) -> Self {
+        Self {
+            command: config.http_download_command.clone(),
+            download_timeout: config.download_timeout,
+            nice: config.download_nice,
+            security_validator,
+        }
+    }
+
+    /// The direct media link a job names, or why it isn't one
+    fn direct_url(&self, job: &Job) -> AppResult<(url::Url, String)> {
+        let validated_url = self.security_validator.validate_url(&job.url)?;
+        let extension = direct_media_extension(&validated_url)
+            .ok_or_else(|| AppError::BadRequest("URL is not a direct link to a media file".to_string()))?;
+        Ok((validated_url, extension))
+    }
+
+    async fn stream_to_file(&self, url: &url::Url, path: &Path) -> AppResult<u64> {
+        let max_size = self.security_validator.get_max_file_size();
+        let pin = self.security_validator.pin_host(url).await?;
+
+        // Redirects would leave the allowlisted domain, so curl is told to refuse them, and it
+        // connects to the addresses just checked instead of looking the host up again
+        let mut command = Command::new(&self.command);
+        lower_priority(&mut command, self.nice);
+        let mut child = command
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--location")
+            .arg("--max-redirs")
+            .arg("0")
+            .arg("--proto")
+            .arg("=http,https")
+            .arg("--resolve")
+            .arg(pin.curl_resolve())
+            .arg(url.as_str())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| AppError::spawn(Phase::Download, "curl", e))?;
+
+        let mut stdout = child.stdout.take()
+            .ok_or_else(|| AppError::Internal("Download command has no stdout".to_string()))?;
+        let mut file = tokio::fs::File::create(path).await
+            .map_err(AppError::io("Failed to create download file"))?;
+
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut written: u64 = 0;
+        loop {
+            let read = stdout.read(&mut buffer).await
+                .map_err(|e| AppError::Download(format!("Failed to read download stream: {e}")))?;
+            if read == 0 {
+                break;
+            }
+            written += read as u64;
+            if written > max_size {
+                let _ = child.kill().await;
+                return Err(AppError::Download(format!(
+                    "Downloaded file exceeds maximum size limit of {max_size} bytes"
+                )));
+            }
+            file.write_all(&buffer[..read]).await
+                .map_err(AppError::io("Failed to write download file"))?;
+        }
+        file.flush().await
+            .map_err(AppError::io("Failed to write download file"))?;
+
+        let status = child.wait().await
+            .map_err(|e| AppError::spawn(Phase::Download, "curl", e))?;
+        if !status.success() {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            return Err(download_failure(status, redact_text(&stderr)));
+        }
+
+        Ok(written)
+    }
+}
+
+impl Downloader for HttpDownloader {
+    fn probe<'a>(&'a self, job: &'a mut Job, _dest_dir: &'a Path) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let (validated_url, _) = self.direct_url(job)?;
+            let pin = self.security_validator.pin_host(&validated_url).await?;
+            job.metadata.media_hosts = vec![pin.host];
+            Ok(())
+        })
+    }
+
+    fn fetch<'a>(&'a self, job: &'a Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<DownloadedFile>> {
+        Box::pin(async move {
+            let (validated_url, extension) = self.direct_url(job)?;
+            self.security_validator.validate_input(&job.id, "job_id", 100)?;
+
+            tokio::fs::create_dir_all(dest_dir).await
+                .map_err(AppError::io("Failed to create job directory"))?;
+            let path = dest_dir.join(format!("{}_original.{extension}", job.id));
+
+            info!("Fetching {} directly over HTTP for job {}", extension, job.id);
+            let result = timeout(self.download_timeout, self.stream_to_file(&validated_url, &path)).await;
+            match result {
+                Ok(Ok(size_bytes)) => Ok(DownloadedFile { path, size_bytes }),
+                Ok(Err(e)) => {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    Err(e)
+                }
+                Err(_) => {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    Err(AppError::Timeout(format!(
+                        "Download timed out after {} seconds",
+                        self.download_timeout.as_secs()
+                    )))
+                }
+            }
+        })
+    }
+}
+
+/// Copies a fixture file in place of a download, for running the pipeline without network access
+pub struct MockDownloader {
+    fixture: PathBuf,
+}
+
+impl Downloader for MockDownloader {
+    /// The fixture is the source, so there is nothing to validate
+    fn probe<'a>(&'a self, _job: &'a mut Job, _dest_dir: &'a Path) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn fetch<'a>(&'a self, job: &'a Job, dest_dir: &'a Path) -> BoxFuture<'a, AppResult<DownloadedFile>> {
+        Box::pin(async move {
+            let extension = self.fixture.extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or("mp4");
+            tokio::fs::create_dir_all(dest_dir).await
+                .map_err(AppError::io("Failed to create job directory"))?;
+
+            let path = dest_dir.join(format!("{}_original.{extension}", job.id));
+            tokio::fs::copy(&self.fixture, &path).await
+                .map_err(|e| AppError::Download(format!("Failed to copy download fixture: {e}")))?;
+            downloaded_file(path).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn job() -> Job {
+        Job::new("https://youtube.com/watch?v=abcdefghijk".to_string())
+    }
+
+    fn mock_config(fixture: &Path) -> DownloadConfig {
+        let mut config = Config::default().download;
+        config.backend = DownloaderBackend::Mock(fixture.to_path_buf());
+        config
+    }
+
+    fn downloader(config: &DownloadConfig) -> Result<Box<dyn Downloader>, String> {
+        let security_validator = SecurityValidator::new(Vec::new(), 500, 2048);
+        let quota = Arc::new(crate::services::StorageQuota::new(None, 0, Vec::new()));
+        let yt_dlp = Arc::new(DownloadService::new(config.clone(), std::env::temp_dir(), security_validator.clone(), quota));
+        build_downloader(config, security_validator, yt_dlp)
+    }
+
+    #[tokio::test]
+    async fn mock_downloader_copies_the_fixture_as_the_jobs_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("clip.mov");
+        std::fs::write(&fixture, b"not really a movie").unwrap();
+        let job = job();
+        let dest_dir = dir.path().join(&job.id);
+
+        let downloaded = downloader(&mock_config(&fixture)).unwrap().fetch(&job, &dest_dir).await.unwrap();
+
+        assert_eq!(downloaded.path, dest_dir.join(format!("{}_original.mov", job.id)));
+        assert_eq!(downloaded.size_bytes, 18);
+        assert_eq!(std::fs::read(&downloaded.path).unwrap(), b"not really a movie");
+        // The fixture is copied, so every job gets its own
+        assert!(fixture.exists());
+    }
+
+    #[tokio::test]
+    async fn mock_downloader_fails_like_a_download_when_the_fixture_is_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("clip.mp4");
+        std::fs::write(&fixture, b"x").unwrap();
+        let downloader = downloader(&mock_config(&fixture)).unwrap();
+        std::fs::remove_file(&fixture).unwrap();
+
+        let error = downloader.fetch(&job(), &dir.path().join("job")).await.unwrap_err();
+        assert!(matches!(error, AppError::Download(_)), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn empty_download_is_a_retryable_failure_that_leaves_nothing_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("empty.mp4");
+        std::fs::write(&fixture, b"").unwrap();
+        let job = job();
+        let dest_dir = dir.path().join(&job.id);
+
+        let downloaded = downloader(&mock_config(&fixture)).unwrap().fetch(&job, &dest_dir).await.unwrap();
+        assert_eq!(downloaded.size_bytes, 0);
+        let error = check_download_size(&downloaded, 300 * 1024).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Download error: incomplete download: file is 0 bytes, expected at least 307200");
+        assert_eq!(crate::services::error_mapping::classify_error(&error), crate::models::job::FailureReason::IncompleteDownload);
+        assert!(crate::services::retry::is_retryable_error(&error));
+        // The next attempt must not pick up the stale file
+        assert!(!downloaded.path.exists());
+        assert_eq!(std::fs::read_dir(&dest_dir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn download_at_the_minimum_size_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("small.m4a");
+        std::fs::write(&fixture, [0u8; 1024]).unwrap();
+        let job = job();
+
+        let downloaded = downloader(&mock_config(&fixture)).unwrap().fetch(&job, &dir.path().join(&job.id)).await.unwrap();
+        assert!(check_download_size(&downloaded, 1024).await.is_ok());
+        assert!(check_download_size(&downloaded, 1025).await.is_err());
+        assert!(!downloaded.path.exists());
+    }
+
+    #[test]
+    fn mock_backend_needs_a_fixture_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = downloader(&mock_config(&dir.path().join("missing.mp4"))).err().unwrap();
+        assert!(error.contains("APERIO_MOCK_DOWNLOAD_FIXTURE"), "{error}");
+        let error = downloader(&mock_config(dir.path())).err().unwrap();
+        assert!(error.contains("APERIO_MOCK_DOWNLOAD_FIXTURE"), "{error}");
+    }
+
+    #[test]
+    fn only_plain_media_links_skip_yt_dlp() {
+        let extension = |url: &str| direct_media_extension(&url::Url::parse(url).unwrap());
+        assert_eq!(extension("https://cdn.example.com/media/clip.MP4?sig=abc").as_deref(), Some("mp4"));
+        assert_eq!(extension("https://cdn.example.com/clip.mov").as_deref(), Some("mov"));
+        assert_eq!(extension("https://cdn.example.com/clip.webm"), None);
+        assert_eq!(extension("https://youtube.com/watch?v=abcdefghijk"), None);
+        assert_eq!(extension("https://cdn.example.com/mp4"), None);
+    }
+
+    /// An HTTP downloader whose "curl" prints the arguments it was given as the download
+    fn echoing_http_downloader(dir: &Path, allowed_domain: &str) -> HttpDownloader {
+        let script = dir.join("curl");
+        std::fs::write(&script, "#!/bin/sh\nprintf '%s\\n' \"$@\"\n").unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut config = Config::default().download;
+        config.http_download_command = script.to_string_lossy().into_owned();
+        config.download_nice = None;
+        HttpDownloader::new(&config, SecurityValidator::new(vec![allowed_domain.to_string()], 500, 2048))
+    }
+
+    #[tokio::test]
+    async fn direct_download_connects_only_to_the_checked_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let http = echoing_http_downloader(dir.path(), "93.184.216.34");
+        let mut job = Job::new("https://93.184.216.34/clip.mp4".to_string());
+
+        http.probe(&mut job, dir.path()).await.unwrap();
+        assert_eq!(job.metadata.media_hosts, ["93.184.216.34"]);
+
+        let downloaded = http.fetch(&job, &dir.path().join(&job.id)).await.unwrap();
+        let argv = std::fs::read_to_string(&downloaded.path).unwrap();
+        let argv: Vec<&str> = argv.lines().collect();
+        assert!(argv.windows(2).any(|pair| pair == ["--resolve", "93.184.216.34:443:93.184.216.34"]), "{argv:?}");
+        assert!(argv.windows(2).any(|pair| pair == ["--max-redirs", "0"]), "{argv:?}");
+    }
+
+    #[tokio::test]
+    async fn direct_link_to_a_host_that_does_not_resolve_fails_the_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        let http = echoing_http_downloader(dir.path(), "media.invalid");
+        let mut job = Job::new("https://media.invalid/clip.mp4".to_string());
+
+        let error = http.probe(&mut job, dir.path()).await.unwrap_err();
+        assert!(error.to_string().contains("Could not resolve host 'media.invalid'"), "{error}");
+        // Nothing is fetched from a host that couldn't be checked
+        let error = http.fetch(&job, &dir.path().join(&job.id)).await.unwrap_err();
+        assert!(error.to_string().contains("Could not resolve host"), "{error}");
+        assert!(!dir.path().join(&job.id).join(format!("{}_original.mp4", job.id)).exists());
+    }
+
+    #[tokio::test]
+    async fn mock_downloader_has_nothing_to_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("clip.mp4");
+        std::fs::write(&fixture, b"x").unwrap();
+        let mut job = job();
+
+        downloader(&mock_config(&fixture)).unwrap().probe(&mut job, dir.path()).await.unwrap();
+        assert!(job.metadata.media_hosts.is_empty());
+    }
+}
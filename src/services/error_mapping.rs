@@ -0,0 +1,356 @@
+use crate::error::{AppError, Phase};
+use crate::models::job::FailureReason;
+use crate::services::security::redact_text;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Longest job log line; the full output of a failed command is kept there, up to this
+const MAX_LOG_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Terminal colour and cursor sequences, which yt-dlp and ffmpeg emit when they think they have a tty
+static ANSI_ESCAPES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-_]")
+        .expect("ANSI escape pattern is valid")
+});
+
+/// yt-dlp (and curl, for direct links) stderr fragments, checked in order so the most specific reason wins
+const DOWNLOAD_PATTERNS: [(FailureReason, &[&str]); 10] = [
+    (FailureReason::IncompleteDownload, &["incomplete download"]),
+    (FailureReason::LiveStream, &["live streams are not supported", "live stream has not started"]),
+    (FailureReason::RateLimited, &["http error 429", "returned error: 429", "too many requests", "rate-limit", "rate limit"]),
+    (FailureReason::Private, &["private video", "this video is private"]),
+    (FailureReason::LoginRequired, &[
+        "sign in to confirm your age", "age-restricted", "login required", "login_required",
+        "sign in to confirm", "members-only", "requires authentication", "use --cookies",
+    ]),
+    (FailureReason::GeoBlocked, &[
+        "not available in your country", "made this video available in your country", "geo restriction", "geo-restricted", "blocked it in your country",
+    ]),
+    (FailureReason::Removed, &[
+        "has been removed", "account associated with this video has been terminated",
+        "copyright claim", "violating youtube's", "no longer available",
+    ]),
+    (FailureReason::Unavailable, &[
+        "video unavailable", "is not available", "does not exist", "http error 404", "returned error: 404",
+    ]),
+    (FailureReason::UnsupportedUrl, &["unsupported url", "no video formats found", "is not a valid url"]),
+    (FailureReason::Network, &[
+        "timed out", "timeout", "connection", "network", "temporary failure", "reset by peer",
+        "refused", "could not resolve host", "http error 502", "http error 503", "http error 504",
+    ]),
+];
+
+/// Map a yt-dlp error message to a stable failure reason
+pub fn classify_download_error(message: &str) -> FailureReason {
+    let message = message.to_lowercase();
+    DOWNLOAD_PATTERNS
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|pattern| message.contains(pattern)))
+        .map(|(reason, _)| *reason)
+        .unwrap_or(FailureReason::DownloadFailed)
+}
+
+/// The error for a download command that exited unsuccessfully, classified by its stderr
+pub fn download_failure(status: ExitStatus, stderr: String) -> AppError {
+    AppError::DownloadFailed { exit_code: status.code(), reason: classify_download_error(&stderr), stderr }
+}
+
+/// The error for an ffmpeg or ffprobe run that exited unsuccessfully
+pub fn processing_failure(output: &Output) -> AppError {
+    AppError::ProcessingFailed {
+        exit_code: output.status.code(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }
+}
+
+/// Map any job error to a stable failure reason
+pub fn classify_error(error: &AppError) -> FailureReason {
+    match error {
+        AppError::DownloadFailed { reason, .. } => *reason,
+        AppError::Download(msg) => classify_download_error(msg),
+        AppError::Spawn { phase: Phase::Download, .. } => FailureReason::DownloadFailed,
+        AppError::Timeout(_) => FailureReason::Timeout,
+        AppError::Processing(_) | AppError::ProcessingFailed { .. } => FailureReason::ProcessingFailed,
+        AppError::Spawn { phase: Phase::Processing, .. } => FailureReason::ProcessingFailed,
+        _ => FailureReason::Internal,
+    }
+}
+
+/// Cleans command output before it is stored on a job or in its log: escape codes, progress
+/// overwrites, credentials and the server's own directories are removed
+#[derive(Clone)]
+pub struct ErrorSanitizer {
+    /// Absolute directories replaced by a placeholder, longest first so nested ones win
+    internal_dirs: Vec<(PathBuf, &'static str)>,
+    max_lines: usize,
+    max_bytes: usize,
+}
+
+impl ErrorSanitizer {
+    pub fn new(working_dir: &Path, storage_dir: Option<&Path>, max_lines: usize, max_bytes: usize) -> Self {
+        let mut internal_dirs = vec![(working_dir.to_path_buf(), "<working dir>")];
+        if let Some(storage_dir) = storage_dir {
+            internal_dirs.push((storage_dir.to_path_buf(), "<storage>"));
+        }
+        internal_dirs.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.as_os_str().len()));
+        Self { internal_dirs, max_lines, max_bytes }
+    }
+
+    /// The message cleaned and capped for the job log, where the full output of a failure is kept
+    pub fn sanitize(&self, message: &str) -> String {
+        truncate_tail(&self.clean(message), MAX_LOG_MESSAGE_LEN)
+    }
+
+    /// The message cleaned and cut down to its last lines for `error_message`.
+    /// yt-dlp and ffmpeg report the cause at the end, after pages of progress and stream info.
+    pub fn summarize(&self, message: &str) -> String {
+        let cleaned = self.clean(message);
+        let lines: Vec<&str> = cleaned.lines().collect();
+        let summary = if lines.len() > self.max_lines {
+            format!(
+                "({} earlier lines omitted)\n{}",
+                lines.len() - self.max_lines,
+                lines[lines.len() - self.max_lines..].join("\n")
+            )
+        } else {
+            cleaned
+        };
+        truncate_tail(&summary, self.max_bytes)
+    }
+
+    fn clean(&self, message: &str) -> String {
+        let message = ANSI_ESCAPES.replace_all(message, "");
+        // Progress output rewrites its line with \r; only the last state of each line matters
+        let message = message.lines()
+            .map(|line| line.trim_end_matches('\r').rsplit('\r').next().unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let message = self.internal_dirs.iter().fold(message, |message, (dir, placeholder)| {
+            message.replace(&*dir.to_string_lossy(), placeholder)
+        });
+        redact_text(message.trim())
+    }
+}
+
+/// Marks a message whose start was cut off
+const TRUNCATION_MARKER: &str = "(truncated) ...";
+
+/// Keep the end of a message within `max_bytes`, marker included, where the cause is reported
+pub fn truncate_tail(message: &str, max_bytes: usize) -> String {
+    if message.len() <= max_bytes {
+        return message.to_string();
+    }
+
+    let mut start = message.len() - max_bytes.saturating_sub(TRUNCATION_MARKER.len());
+    while !message.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("{TRUNCATION_MARKER}{}", &message[start..])
+}
+
+/// Extract a retry hint such as "Retry-After: 120" or "try again in 5 minutes" from an error message
+pub fn parse_retry_after(message: &str) -> Option<Duration> {
+    let message = message.to_lowercase();
+
+    for marker in ["retry-after:", "retry after", "try again in", "try again after"] {
+        let Some(position) = message.find(marker) else {
+            continue;
+        };
+        let mut words = message[position + marker.len()..].split_whitespace();
+        let Some(amount) = words.next().and_then(|word| word.trim_matches(|c: char| !c.is_ascii_digit()).parse::<u64>().ok()) else {
+            continue;
+        };
+        let multiplier = match words.next().unwrap_or_default() {
+            unit if unit.starts_with("hour") => 3600,
+            unit if unit.starts_with("minute") || unit.starts_with("min") => 60,
+            _ => 1,
+        };
+        return Some(Duration::from_secs(amount * multiplier));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    const WORKING_DIR: &str = "/var/lib/aperio/work";
+    const STORAGE_DIR: &str = "/var/lib/aperio/work/storage";
+
+    fn sanitizer(max_lines: usize, max_bytes: usize) -> ErrorSanitizer {
+        ErrorSanitizer::new(Path::new(WORKING_DIR), Some(Path::new(STORAGE_DIR)), max_lines, max_bytes)
+    }
+
+    /// What ffmpeg prints for a long encode that fails at the end: stream info, thousands of
+    /// colourised progress updates rewritten with \r, internal paths and a signed URL
+    fn giant_stderr() -> String {
+        let mut stderr = String::new();
+        stderr.push_str("ffmpeg version 6.1 Copyright (c) 2000-2023 the FFmpeg developers\n");
+        stderr.push_str(&format!("Input #0, mov,mp4,m4a, from '{WORKING_DIR}/job-1/input.mp4':\n"));
+        stderr.push_str("  Stream #0:0: Video: h264 (High), yuv420p, 1920x1080, 30 fps\n");
+        for frame in 0..5000 {
+            stderr.push_str(&format!(
+                "\x1b[0;33mframe={frame:5} fps=30 q=28.0 size={}kB time=00:00:{:02}.00\x1b[0m\r",
+                frame * 4,
+                frame % 60
+            ));
+            if frame % 10 == 9 {
+                stderr.push('\n');
+            }
+        }
+        stderr.push_str("[hls @ 0x55d0] Opening 'https://cdn.example.com/seg-7.ts?token=s3cr3t&part=7' for reading\n");
+        stderr.push_str(&format!("[mp4 @ 0x55d1] Could not write header for output file '{STORAGE_DIR}/job-1/out.mp4'\n"));
+        stderr.push_str("\x1b[1;31mConversion failed!\x1b[0m\n");
+        stderr
+    }
+
+    #[test]
+    fn giant_stderr_is_summarized_to_its_last_lines() {
+        let summary = sanitizer(3, 4096).summarize(&giant_stderr());
+
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 4, "{summary}");
+        assert_eq!(lines[0], "(503 earlier lines omitted)");
+        assert!(lines[1].starts_with("[hls @ 0x55d0] Opening 'https://cdn.example.com/seg-7.ts?"), "{summary}");
+        assert_eq!(lines[2], "[mp4 @ 0x55d1] Could not write header for output file '<storage>/job-1/out.mp4'");
+        assert_eq!(lines[3], "Conversion failed!");
+    }
+
+    #[test]
+    fn giant_stderr_is_cleaned_of_escapes_progress_paths_and_secrets() {
+        let sanitized = sanitizer(10, 4096).sanitize(&giant_stderr());
+
+        assert!(!sanitized.contains('\x1b'), "ANSI escapes are stripped");
+        assert!(!sanitized.contains('\r'), "progress overwrites are collapsed");
+        assert!(!sanitized.contains("/var/lib/aperio"), "internal directories are replaced");
+        assert!(sanitized.contains("'<working dir>/job-1/input.mp4'"));
+        assert!(sanitized.contains("'<storage>/job-1/out.mp4'"), "the nested storage dir wins over the working dir");
+        assert!(!sanitized.contains("s3cr3t"), "URL credentials are redacted");
+        assert!(sanitized.contains("part=7"));
+        // Only the last state of each rewritten line is kept
+        assert!(sanitized.contains("frame=    9 fps=30"));
+        assert!(!sanitized.contains("frame=    8 fps=30"));
+        assert!(sanitized.ends_with("Conversion failed!"));
+    }
+
+    #[test]
+    fn giant_stderr_fits_the_byte_limits() {
+        let summary = sanitizer(100_000, 1024).summarize(&giant_stderr());
+        assert!(summary.len() <= 1024, "summary is {} bytes", summary.len());
+        assert!(summary.starts_with("(truncated) ..."));
+        assert!(summary.ends_with("Conversion failed!"));
+
+        let stderr = giant_stderr().repeat(20);
+        assert!(stderr.len() > MAX_LOG_MESSAGE_LEN);
+        let sanitized = sanitizer(10, 1024).sanitize(&stderr);
+        assert!(sanitized.len() <= MAX_LOG_MESSAGE_LEN, "log line is {} bytes", sanitized.len());
+        assert!(sanitized.starts_with("(truncated) ..."));
+        assert!(sanitized.ends_with("Conversion failed!"));
+    }
+
+    #[test]
+    fn truncate_tail_keeps_the_end_within_the_limit() {
+        assert_eq!(truncate_tail("short", 64), "short");
+        assert_eq!(truncate_tail("0123456789abcdefghij0123456789", 20), "(truncated) ...56789");
+
+        // A multi-byte character straddling the cut is dropped whole
+        let message = format!("{}é{}", "a".repeat(40), "b".repeat(4));
+        let truncated = truncate_tail(&message, TRUNCATION_MARKER.len() + 5);
+        assert_eq!(truncated, "(truncated) ...bbbb");
+
+        for max_bytes in 0..=message.len() {
+            assert!(truncate_tail(&message, max_bytes).len() <= max_bytes.max(TRUNCATION_MARKER.len()));
+        }
+    }
+
+    fn failed_download(stderr: &str) -> AppError {
+        download_failure(ExitStatus::from_raw(1 << 8), stderr.to_string())
+    }
+
+    fn spawn_error(phase: Phase) -> AppError {
+        AppError::spawn(phase, "tool", std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+
+    /// An error each reason is reported for; the match fails to compile when a reason is added
+    fn error_for(reason: FailureReason) -> AppError {
+        match reason {
+            FailureReason::Unavailable => failed_download("ERROR: [youtube] abc: Video unavailable"),
+            FailureReason::Private => failed_download("ERROR: [youtube] abc: Private video. Sign in if you've been granted access"),
+            FailureReason::LoginRequired => failed_download("ERROR: [youtube] abc: Sign in to confirm your age. This video may be inappropriate"),
+            FailureReason::GeoBlocked => failed_download("ERROR: [youtube] abc: The uploader has not made this video available in your country"),
+            FailureReason::Removed => failed_download("ERROR: [youtube] abc: This video has been removed by the uploader"),
+            FailureReason::RateLimited => failed_download("ERROR: unable to download webpage: HTTP Error 429: Too Many Requests"),
+            FailureReason::UnsupportedUrl => failed_download("ERROR: Unsupported URL: https://example.com/page"),
+            FailureReason::LiveStream => failed_download("ERROR: [youtube] abc: This live stream has not started yet"),
+            FailureReason::Network => failed_download("ERROR: Unable to download: <urlopen error [Errno 111] Connection refused>"),
+            FailureReason::IncompleteDownload => failed_download("ERROR: Did not get any data blocks; incomplete download"),
+            FailureReason::DownloadFailed => failed_download("ERROR: Postprocessing: something unexpected"),
+            FailureReason::ProcessingFailed => AppError::ProcessingFailed {
+                exit_code: Some(1),
+                stderr: "Conversion failed!".to_string(),
+            },
+            FailureReason::Timeout => AppError::Timeout("Download timed out after 600 seconds".to_string()),
+            FailureReason::Internal => AppError::Internal("Failed to update job".to_string()),
+        }
+    }
+
+    const ALL_REASONS: [FailureReason; 14] = [
+        FailureReason::Unavailable,
+        FailureReason::Private,
+        FailureReason::LoginRequired,
+        FailureReason::GeoBlocked,
+        FailureReason::Removed,
+        FailureReason::RateLimited,
+        FailureReason::UnsupportedUrl,
+        FailureReason::LiveStream,
+        FailureReason::Network,
+        FailureReason::IncompleteDownload,
+        FailureReason::DownloadFailed,
+        FailureReason::ProcessingFailed,
+        FailureReason::Timeout,
+        FailureReason::Internal,
+    ];
+
+    #[test]
+    fn every_failure_reason_is_classified_from_its_error() {
+        for reason in ALL_REASONS {
+            assert_eq!(classify_error(&error_for(reason)), reason, "{reason:?}");
+        }
+    }
+
+    #[test]
+    fn download_failures_keep_their_exit_code_and_stderr() {
+        let AppError::DownloadFailed { exit_code, stderr, reason } = failed_download("ERROR: Private video") else {
+            panic!("download_failure builds a DownloadFailed");
+        };
+        assert_eq!(exit_code, Some(1));
+        assert_eq!(stderr, "ERROR: Private video");
+        assert_eq!(reason, FailureReason::Private);
+    }
+
+    #[test]
+    fn untyped_errors_are_classified_by_phase_and_message() {
+        assert_eq!(classify_error(&AppError::Download("Could not resolve host 'media.invalid'".to_string())), FailureReason::Network);
+        assert_eq!(classify_error(&AppError::Download("source was not validated before download".to_string())), FailureReason::DownloadFailed);
+        assert_eq!(classify_error(&spawn_error(Phase::Download)), FailureReason::DownloadFailed);
+        assert_eq!(classify_error(&spawn_error(Phase::Processing)), FailureReason::ProcessingFailed);
+        assert_eq!(classify_error(&AppError::Processing("output has no audio stream".to_string())), FailureReason::ProcessingFailed);
+        assert_eq!(classify_error(&AppError::Storage("disk full".to_string())), FailureReason::Internal);
+        assert_eq!(classify_error(&AppError::NoCapacity { pool: "download", waited: Duration::from_secs(30) }), FailureReason::Internal);
+    }
+
+    #[test]
+    fn the_most_specific_download_reason_wins() {
+        // A rate limit also mentions the connection; the rate limit is what the client can act on
+        assert_eq!(classify_download_error("HTTP Error 429: Too Many Requests (connection closed)"), FailureReason::RateLimited);
+        // Private videos ask for a sign-in too
+        assert_eq!(classify_download_error("Private video. Sign in to confirm you have access"), FailureReason::Private);
+        assert_eq!(classify_download_error("INCOMPLETE DOWNLOAD after connection reset by peer"), FailureReason::IncompleteDownload);
+        assert_eq!(classify_download_error(""), FailureReason::DownloadFailed);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::models::job::{Job, JobMetadata, JobStatus};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+/// Finished stages kept per stage; older ones fall off so estimates follow the current load
+pub const HISTORY_SIZE: usize = 100;
+/// Fewest samples an estimate is based on; with less history there is no estimate
+const MIN_SAMPLES: usize = 5;
+
+/// The stages a job's time is estimated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Download,
+    Processing,
+}
+
+/// How long one stage of a finished job took
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    seconds: f64,
+    source_duration: Option<f64>,
+}
+
+/// Estimates when active jobs will finish from how long recent jobs' stages took.
+/// Time scales with the source's length where both are known, so stages are compared per
+/// second of source; otherwise the typical stage time is used.
+#[derive(Default)]
+pub struct Estimator {
+    downloads: RwLock<VecDeque<Sample>>,
+    processing: RwLock<VecDeque<Sample>>,
+}
+
+impl Estimator {
+    /// Start from finished jobs' recorded stage times, newest first
+    pub fn seed(&self, history: &[JobMetadata]) {
+        for metadata in history.iter().rev() {
+            self.record_metadata(metadata);
+        }
+    }
+
+    /// Add a completed job's stage times to the history
+    pub fn record(&self, job: &Job) {
+        self.record_metadata(&job.metadata);
+    }
+
+    fn record_metadata(&self, metadata: &JobMetadata) {
+        let source_duration = metadata.source_duration_seconds;
+        if let Some(seconds) = metadata.download_seconds {
+            self.push(Stage::Download, Sample { seconds, source_duration });
+        }
+        if let Some(seconds) = metadata.processing_seconds {
+            self.push(Stage::Processing, Sample { seconds, source_duration });
+        }
+    }
+
+    fn history(&self, stage: Stage) -> &RwLock<VecDeque<Sample>> {
+        match stage {
+            Stage::Download => &self.downloads,
+            Stage::Processing => &self.processing,
+        }
+    }
+
+    fn push(&self, stage: Stage, sample: Sample) {
+        if !sample.seconds.is_finite() || sample.seconds < 0.0 {
+            return;
+        }
+        let mut history = self.history(stage).write().unwrap();
+        if history.len() == HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(sample);
+    }
+
+    /// Expected seconds for a stage, scaled to `source_duration` when enough history has lengths
+    pub fn stage_seconds(&self, stage: Stage, source_duration: Option<f64>) -> Option<f64> {
+        let history = self.history(stage).read().unwrap();
+
+        if let Some(duration) = source_duration.filter(|duration| *duration > 0.0) {
+            let rates: Vec<f64> = history.iter()
+                .filter_map(|sample| sample.source_duration
+                    .filter(|length| *length > 0.0)
+                    .map(|length| sample.seconds / length))
+                .collect();
+            if rates.len() >= MIN_SAMPLES {
+                return median(rates).map(|rate| rate * duration);
+            }
+        }
+
+        if history.len() < MIN_SAMPLES {
+            return None;
+        }
+        median(history.iter().map(|sample| sample.seconds).collect())
+    }
+
+    /// When a Downloading or Processing job should finish; `None` for jobs in any other status
+    /// or without enough history. A stage running past its estimate counts as about to end.
+    pub fn estimated_completion(&self, job: &Job) -> Option<DateTime<Utc>> {
+        let elapsed = job.stage_elapsed_seconds()?;
+        let source_duration = job.metadata.source_duration_seconds;
+        let processing = self.stage_seconds(Stage::Processing, source_duration)?;
+
+        let remaining = match job.status {
+            JobStatus::Downloading => {
+                let download = self.stage_seconds(Stage::Download, source_duration)?;
+                (download - elapsed).max(0.0) + processing
+            }
+            JobStatus::Processing => (processing - elapsed).max(0.0),
+            _ => return None,
+        };
+
+        Some(Utc::now() + chrono::Duration::milliseconds((remaining * 1000.0) as i64))
+    }
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(download: f64, processing: f64, source_duration: Option<f64>) -> JobMetadata {
+        JobMetadata {
+            download_seconds: Some(download),
+            processing_seconds: Some(processing),
+            source_duration_seconds: source_duration,
+            ..Default::default()
+        }
+    }
+
+    /// A job in `status` whose stage started `elapsed` seconds ago
+    fn active(status: JobStatus, elapsed: f64, source_duration: Option<f64>) -> Job {
+        let mut job = Job::new("https://youtube.com/watch?v=abcdefghijk".to_string());
+        job.status = status;
+        job.metadata.stage_started_at = Some(Utc::now() - chrono::Duration::milliseconds((elapsed * 1000.0) as i64));
+        job.metadata.source_duration_seconds = source_duration;
+        job
+    }
+
+    /// Seconds from now until the job's estimated completion
+    fn remaining(estimator: &Estimator, job: &Job) -> Option<f64> {
+        estimator.estimated_completion(job)
+            .map(|at| (at - Utc::now()).num_milliseconds() as f64 / 1000.0)
+    }
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("an estimate");
+        assert!((actual - expected).abs() < 0.5, "{actual} is not about {expected}");
+    }
+
+    #[test]
+    fn too_little_history_gives_no_estimate() {
+        let estimator = Estimator::default();
+        estimator.seed(&vec![finished(10.0, 20.0, None); MIN_SAMPLES - 1]);
+
+        assert_eq!(estimator.stage_seconds(Stage::Download, None), None);
+        assert_eq!(estimator.stage_seconds(Stage::Processing, Some(60.0)), None);
+        assert_eq!(estimator.estimated_completion(&active(JobStatus::Processing, 0.0, None)), None);
+
+        estimator.seed(&[finished(10.0, 20.0, None)]);
+        assert_eq!(estimator.stage_seconds(Stage::Download, None), Some(10.0));
+    }
+
+    #[test]
+    fn stage_time_is_the_median_of_recent_jobs() {
+        let estimator = Estimator::default();
+        // One stuck outlier doesn't drag the estimate
+        for (download, processing) in [(5.0, 40.0), (7.0, 60.0), (6.0, 50.0), (600.0, 3000.0), (8.0, 55.0), (4.0, 45.0)] {
+            estimator.seed(&[finished(download, processing, None)]);
+        }
+        assert_eq!(estimator.stage_seconds(Stage::Download, None), Some(6.5));
+        assert_eq!(estimator.stage_seconds(Stage::Processing, None), Some(52.5));
+    }
+
+    #[test]
+    fn stage_time_scales_with_the_source_length() {
+        let estimator = Estimator::default();
+        // Encodes run at half real time, whatever the length
+        for length in [60.0, 120.0, 300.0, 600.0, 1200.0] {
+            estimator.seed(&[finished(length / 100.0, length / 2.0, Some(length))]);
+        }
+
+        assert_eq!(estimator.stage_seconds(Stage::Processing, Some(1800.0)), Some(900.0));
+        assert_eq!(estimator.stage_seconds(Stage::Download, Some(1800.0)), Some(18.0));
+        // Without a length of its own the job gets the typical time
+        assert_eq!(estimator.stage_seconds(Stage::Processing, None), Some(150.0));
+        assert_eq!(estimator.stage_seconds(Stage::Processing, Some(0.0)), Some(150.0));
+    }
+
+    #[test]
+    fn too_few_lengths_fall_back_to_the_typical_time() {
+        let estimator = Estimator::default();
+        estimator.seed(&[finished(10.0, 100.0, Some(50.0))]);
+        estimator.seed(&vec![finished(10.0, 30.0, None); 5]);
+
+        assert_eq!(estimator.stage_seconds(Stage::Processing, Some(500.0)), Some(30.0));
+    }
+
+    #[test]
+    fn estimate_covers_what_is_left_of_the_current_stage() {
+        let estimator = Estimator::default();
+        estimator.seed(&vec![finished(20.0, 60.0, None); 5]);
+
+        // The rest of the download, then a whole encode
+        assert_near(remaining(&estimator, &active(JobStatus::Downloading, 5.0, None)), 15.0 + 60.0);
+        assert_near(remaining(&estimator, &active(JobStatus::Processing, 45.0, None)), 15.0);
+        // Running past the estimate counts as about to finish
+        assert_near(remaining(&estimator, &active(JobStatus::Downloading, 90.0, None)), 60.0);
+        assert_near(remaining(&estimator, &active(JobStatus::Processing, 90.0, None)), 0.0);
+    }
+
+    #[test]
+    fn estimate_uses_the_jobs_own_source_length() {
+        let estimator = Estimator::default();
+        for length in [100.0, 200.0, 300.0, 400.0, 500.0] {
+            estimator.seed(&[finished(length / 10.0, length, Some(length))]);
+        }
+
+        assert_near(remaining(&estimator, &active(JobStatus::Processing, 0.0, Some(30.0))), 30.0);
+        assert_near(remaining(&estimator, &active(JobStatus::Processing, 0.0, Some(3000.0))), 3000.0);
+    }
+
+    #[test]
+    fn only_running_jobs_get_an_estimate() {
+        let estimator = Estimator::default();
+        estimator.seed(&vec![finished(20.0, 60.0, None); 5]);
+
+        for status in [JobStatus::Pending, JobStatus::Completed, JobStatus::Failed] {
+            assert_eq!(estimator.estimated_completion(&active(status, 0.0, None)), None);
+        }
+        let mut not_started = active(JobStatus::Processing, 0.0, None);
+        not_started.metadata.stage_started_at = None;
+        assert_eq!(estimator.estimated_completion(&not_started), None);
+    }
+
+    #[test]
+    fn history_keeps_the_latest_jobs_and_skips_bad_samples() {
+        let estimator = Estimator::default();
+        estimator.seed(&vec![finished(1000.0, 1000.0, None); HISTORY_SIZE]);
+        estimator.seed(&[finished(f64::NAN, -1.0, None)]);
+        assert_eq!(estimator.processing.read().unwrap().len(), HISTORY_SIZE);
+
+        for _ in 0..HISTORY_SIZE {
+            let mut job = Job::new("https://youtube.com/watch?v=abcdefghijk".to_string());
+            job.metadata = finished(10.0, 30.0, None);
+            estimator.record(&job);
+        }
+        assert_eq!(estimator.stage_seconds(Stage::Download, None), Some(10.0));
+        assert_eq!(estimator.stage_seconds(Stage::Processing, None), Some(30.0));
+    }
+
+    #[test]
+    fn seed_replays_history_oldest_first() {
+        let estimator = Estimator::default();
+        // Newest first, as listed from the database: the newest HISTORY_SIZE are kept
+        let mut history = vec![finished(10.0, 30.0, None); HISTORY_SIZE];
+        history.extend(vec![finished(1000.0, 1000.0, None); 10]);
+        estimator.seed(&history);
+
+        assert_eq!(estimator.stage_seconds(Stage::Processing, None), Some(30.0));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::api::routes::JobResponse;
+use crate::error::{AppError, AppResult};
+use serde::Deserialize;
+use std::borrow::Cow;
+use utoipa::ToSchema;
+
+/// Columns of a CSV export, in order
+const CSV_COLUMNS: [&str; 15] = [
+    "id",
+    "status",
+    "url",
+    "created_at",
+    "updated_at",
+    "client_id",
+    "external_id",
+    "tags",
+    "failure_reason",
+    "error_message",
+    "processing_time",
+    "output_available",
+    "processed_sha256",
+    "expires_at",
+    "parent_job_id",
+];
+
+/// How `/jobs/export` writes jobs
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One row per job with a header row, for spreadsheets
+    #[default]
+    Csv,
+    /// One `JobResponse` JSON object per line
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// What comes before the first job
+    pub fn header(&self) -> Option<String> {
+        match self {
+            ExportFormat::Csv => Some(csv_line(CSV_COLUMNS.iter().map(|column| Cow::Borrowed(*column)))),
+            ExportFormat::Ndjson => None,
+        }
+    }
+
+    /// One job, newline included
+    pub fn line(&self, job: &JobResponse) -> AppResult<String> {
+        match self {
+            ExportFormat::Csv => Ok(csv_line(csv_values(job).into_iter())),
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_string(job)
+                    .map_err(|e| AppError::Internal(format!("Failed to serialize job {}: {e}", job.id)))?;
+                line.push('\n');
+                Ok(line)
+            }
+        }
+    }
+}
+
+/// The job's value for each of CSV_COLUMNS; missing values are empty
+fn csv_values(job: &JobResponse) -> [Cow<'_, str>; 15] {
+    fn optional(value: &Option<String>) -> Cow<'_, str> {
+        Cow::Borrowed(value.as_deref().unwrap_or_default())
+    }
+    [
+        Cow::Borrowed(job.id.as_str()),
+        Cow::Owned(job.status.to_string()),
+        Cow::Borrowed(job.url.as_str()),
+        Cow::Borrowed(job.created_at.as_str()),
+        Cow::Borrowed(job.updated_at.as_str()),
+        optional(&job.client_id),
+        optional(&job.external_id),
+        Cow::Owned(job.tags.join(";")),
+        Cow::Borrowed(job.failure_reason.map(|reason| reason.as_str()).unwrap_or_default()),
+        optional(&job.error_message),
+        optional(&job.processing_time),
+        Cow::Borrowed(if job.output_available { "true" } else { "false" }),
+        optional(&job.processed_sha256),
+        optional(&job.expires_at),
+        optional(&job.parent_job_id),
+    ]
+}
+
+/// Join fields into one RFC 4180 record. Fields holding a separator, quote or line break are
+/// quoted with inner quotes doubled, so URLs and multi-line error messages survive intact.
+fn csv_line<'a>(fields: impl Iterator<Item = Cow<'a, str>>) -> String {
+    let mut line = String::new();
+    for (position, field) in fields.enumerate() {
+        if position > 0 {
+            line.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            line.push('"');
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(&field);
+        }
+    }
+    line.push_str("\r\n");
+    line
+}
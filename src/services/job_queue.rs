@@ -1,12 +1,23 @@
-use std::collections::{HashMap, BinaryHeap};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet, BinaryHeap};
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::Serialize;
+use utoipa::ToSchema;
 use tokio::sync::{Mutex, Notify};
 use tokio::task::JoinHandle;
-use tracing::{info, warn, debug};
+use tracing::{info, warn, debug, error};
 use crate::models::job::Job;
 use crate::api::routes::AppState;
+use crate::gauge_set;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Longest the worker sleeps without new work, so an idle worker still shows it is alive
+pub const WORKER_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, ToSchema)]
 pub enum JobPriority {
     Low = 1,
     Normal = 2,
@@ -18,12 +29,14 @@ pub struct QueuedJob {
     pub job: Job,
     pub priority: JobPriority,
     pub queued_at: chrono::DateTime<chrono::Utc>,
+    /// Round-robin turn among the clients queued at this priority; see `ClientRounds`
+    round: u64,
 }
 
 // Implement ordering for BinaryHeap (higher priority first)
 impl PartialEq for QueuedJob {
     fn eq(&self, other: &Self) -> bool {
-        self.priority == other.priority && self.queued_at == other.queued_at
+        self.priority == other.priority && self.round == other.round && self.queued_at == other.queued_at
     }
 }
 
@@ -37,48 +50,248 @@ impl PartialOrd for QueuedJob {
 
 impl Ord for QueuedJob {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // Higher priority comes first, then older jobs (FIFO within same priority)
+        // Higher priority comes first, then earlier rounds so clients take turns, then older jobs
         self.priority.cmp(&other.priority)
+            .then_with(|| other.round.cmp(&self.round))
             .then_with(|| other.queued_at.cmp(&self.queued_at))
     }
 }
 
-impl QueuedJob {
-    pub fn new(job: Job, priority: JobPriority) -> Self {
+/// Hands out round-robin turns so that, within a priority, clients' jobs interleave instead of
+/// running in submission order. A client's n-th queued job goes into its n-th round, and jobs run
+/// round by round. A client that joins late starts at the round being served rather than jumping
+/// ahead of clients that queued earlier. Jobs without a client share one turn.
+#[derive(Debug, Default)]
+struct ClientRounds {
+    /// Round the next job of each client at each priority goes into
+    next: HashMap<(JobPriority, Option<String>), u64>,
+    /// Round of the job most recently started at each priority
+    serving: HashMap<JobPriority, u64>,
+}
+
+impl ClientRounds {
+    fn assign(&mut self, priority: &JobPriority, client_id: Option<&String>) -> u64 {
+        let serving = self.serving.get(priority).copied().unwrap_or_default();
+        let next = self.next.entry((priority.clone(), client_id.cloned())).or_default();
+        let round = (*next).max(serving);
+        *next = round + 1;
+        round
+    }
+
+    fn started(&mut self, queued_job: &QueuedJob) {
+        let serving = self.serving.entry(queued_job.priority.clone()).or_default();
+        *serving = (*serving).max(queued_job.round);
+    }
+}
+
+/// The two halves of a job's run. Each stage has its own concurrency limit, so downloads keep
+/// going while encodes are busy and the other way round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Download,
+    Processing,
+}
+
+/// What is left to do for a job once its stage has finished running. The queue acts on it after
+/// taking the job off the active list, so a job never runs twice at once.
+pub enum StageOutcome {
+    /// Completed, failed or never started; nothing more to run
+    Done,
+    /// Downloaded and waiting for a processing slot
+    ReadyForProcessing(Job),
+    /// Back on the queue once the time has passed
+    Deferred(Job, DateTime<Utc>),
+}
+
+/// A job running in one of the stages
+struct ActiveJob {
+    stage: Stage,
+    priority: JobPriority,
+    handle: JoinHandle<()>,
+}
+
+/// How each stage's slots are shared between priorities. Both rules apply to the download and
+/// processing slots separately.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct PriorityPolicy {
+    /// Slots in each stage, and of the cap on jobs across both stages, that only High priority
+    /// jobs may take
+    pub high_reserved_slots: usize,
+    /// Share of each stage's slots that Low priority jobs may hold, in percent. Low jobs always
+    /// get at least one slot, so they are never starved outright.
+    pub low_max_percent: u8,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
         Self {
+            high_reserved_slots: 0,
+            low_max_percent: 100,
+        }
+    }
+}
+
+impl PriorityPolicy {
+    /// Slots of a stage with `limit` slots that Low priority jobs may hold
+    fn low_limit(&self, limit: usize) -> usize {
+        (limit * self.low_max_percent as usize / 100).max(1)
+    }
+
+    /// Whether a job of this priority may take one of a stage's `limit` slots, given what
+    /// already runs there
+    fn admits(&self, priority: &JobPriority, limit: usize, load: &StageLoad) -> bool {
+        if load.running >= limit {
+            return false;
+        }
+        match priority {
+            JobPriority::High => true,
+            JobPriority::Normal => load.running < limit.saturating_sub(self.high_reserved_slots),
+            JobPriority::Low => {
+                load.running < limit.saturating_sub(self.high_reserved_slots)
+                    && load.low < self.low_limit(limit)
+            }
+        }
+    }
+
+    /// Whether a job of this priority may start under the optional cap on jobs running across
+    /// both stages, given the `running` total. Only the High reservation applies to the cap; the
+    /// Low share is kept per stage.
+    fn admits_overall(&self, priority: &JobPriority, max_jobs: Option<usize>, running: usize) -> bool {
+        let Some(max_jobs) = max_jobs else {
+            return true;
+        };
+        match priority {
+            JobPriority::High => running < max_jobs,
+            JobPriority::Normal | JobPriority::Low => running < max_jobs.saturating_sub(self.high_reserved_slots),
+        }
+    }
+}
+
+/// Jobs running in one stage
+#[derive(Debug, Default)]
+struct StageLoad {
+    running: usize,
+    low: usize,
+}
+
+/// Jobs waiting for a worker slot, in the order they will start
+#[derive(Debug, Default)]
+struct ReadyQueue {
+    jobs: BinaryHeap<QueuedJob>,
+    // Ids of the queued jobs, so a job is never queued twice
+    ids: HashSet<String>,
+    rounds: ClientRounds,
+}
+
+impl ReadyQueue {
+    fn push(&mut self, job: Job, priority: JobPriority) {
+        self.ids.insert(job.id.clone());
+        let round = self.rounds.assign(&priority, job.client_id.as_ref());
+        self.jobs.push(QueuedJob {
             job,
             priority,
             queued_at: chrono::Utc::now(),
+            round,
+        });
+    }
+
+    /// Take the first job, in queue order, whose priority may start. Jobs passed over keep
+    /// their place.
+    fn pop(&mut self, may_start: impl Fn(&JobPriority) -> bool) -> Option<QueuedJob> {
+        let mut skipped = Vec::new();
+        let mut found = None;
+        while let Some(queued_job) = self.jobs.pop() {
+            if may_start(&queued_job.priority) {
+                found = Some(queued_job);
+                break;
+            }
+            skipped.push(queued_job);
+        }
+        // Same ordering keys as before, so the skipped jobs go back exactly where they were
+        self.jobs.extend(skipped);
+
+        let queued_job = found?;
+        self.ids.remove(&queued_job.job.id);
+        self.rounds.started(&queued_job);
+        Some(queued_job)
+    }
+
+    fn contains(&self, job_id: &str) -> bool {
+        self.ids.contains(job_id)
+    }
+
+    fn remove(&mut self, job_id: &str) -> bool {
+        if !self.ids.remove(job_id) {
+            return false;
         }
+        self.jobs.retain(|queued_job| queued_job.job.id != job_id);
+        true
+    }
+
+    fn clear(&mut self) -> usize {
+        self.ids.clear();
+        let remaining = self.jobs.len();
+        self.jobs.clear();
+        remaining
     }
 }
 
 pub struct JobQueue {
-    queue: Arc<Mutex<BinaryHeap<QueuedJob>>>,
+    queue: Arc<Mutex<ReadyQueue>>,
+    // Downloaded jobs waiting for a processing slot
+    processing_queue: Arc<Mutex<ReadyQueue>>,
     notify: Arc<Notify>,
-    active_jobs: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
-    max_concurrent_jobs: usize,
+    active_jobs: Arc<Mutex<HashMap<String, ActiveJob>>>,
+    // Jobs waiting for their run_after time before re-entering the queue
+    deferred_jobs: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    // Domains that rate-limited us, and when downloads from them may resume
+    domain_cooldowns: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+    max_concurrent_downloads: usize,
+    max_concurrent_processing: usize,
+    // Optional cap on jobs running in either stage
+    max_concurrent_jobs: Option<usize>,
     max_queue_size: usize,
+    priority_policy: PriorityPolicy,
     is_shutdown: Arc<Mutex<bool>>,
+    // When draining started; new submissions are refused while set, but queued work still runs
+    draining_since: Arc<Mutex<Option<DateTime<Utc>>>>,
+    // When the worker last finished a pass over the queues; None until it starts
+    worker_heartbeat: Arc<Mutex<Option<DateTime<Utc>>>>,
 }
 
 impl JobQueue {
-    pub fn new(max_concurrent_jobs: usize) -> Self {
-        let max_queue_size = std::env::var("APERIO_MAX_QUEUE_SIZE")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(1000); // Default to 1000 jobs max in queue
-            
-        info!("Initializing job queue with max {} concurrent jobs and max {} queued jobs", 
-              max_concurrent_jobs, max_queue_size);
+    pub fn new(
+        max_concurrent_downloads: usize,
+        max_concurrent_processing: usize,
+        max_concurrent_jobs: Option<usize>,
+        max_queue_size: usize,
+        priority_policy: PriorityPolicy,
+    ) -> Self {
+        info!("Initializing job queue with max {} concurrent downloads, {} concurrent encodes and max {} queued jobs", 
+              max_concurrent_downloads, max_concurrent_processing, max_queue_size);
+        if let Some(max_concurrent_jobs) = max_concurrent_jobs {
+            info!("At most {} jobs run across both stages", max_concurrent_jobs);
+        }
+        if priority_policy.high_reserved_slots > 0 || priority_policy.low_max_percent < 100 {
+            info!("Each stage keeps {} slots for High priority jobs and gives Low priority jobs at most {}% of its slots",
+                  priority_policy.high_reserved_slots, priority_policy.low_max_percent);
+        }
         
         Self {
-            queue: Arc::new(Mutex::new(BinaryHeap::new())),
+            queue: Arc::new(Mutex::new(ReadyQueue::default())),
+            processing_queue: Arc::new(Mutex::new(ReadyQueue::default())),
             notify: Arc::new(Notify::new()),
             active_jobs: Arc::new(Mutex::new(HashMap::new())),
+            deferred_jobs: Arc::new(Mutex::new(HashMap::new())),
+            domain_cooldowns: Arc::new(Mutex::new(HashMap::new())),
+            max_concurrent_downloads,
+            max_concurrent_processing,
             max_concurrent_jobs,
             max_queue_size,
+            priority_policy,
             is_shutdown: Arc::new(Mutex::new(false)),
+            draining_since: Arc::new(Mutex::new(None)),
+            worker_heartbeat: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -88,19 +301,27 @@ impl JobQueue {
             return Err("Job queue is shutting down".to_string());
         }
 
-        let queued_job = QueuedJob::new(job.clone(), priority.clone());
+        let job_id = job.id.clone();
+        if self.is_active(&job_id).await {
+            info!("Job {} is already running, not queueing it again", job_id);
+            return Ok(());
+        }
         let mut queue = self.queue.lock().await;
+        if queue.contains(&job_id) || self.processing_queue.lock().await.contains(&job_id) {
+            info!("Job {} is already queued, not queueing it again", job_id);
+            return Ok(());
+        }
         
         // Check queue size limit
-        if queue.len() >= self.max_queue_size {
+        if queue.jobs.len() >= self.max_queue_size {
             return Err(format!("Queue is full (max {} jobs), try again later", self.max_queue_size));
         }
         
         // BinaryHeap automatically orders by priority (O(log n) insertion)
-        queue.push(queued_job);
+        queue.push(job, priority.clone());
         
         info!("Enqueued job {} with priority {:?}, queue size: {}", 
-              job.id, priority, queue.len());
+              job_id, priority, queue.jobs.len());
         
         // Notify worker that new job is available
         self.notify.notify_one();
@@ -108,15 +329,92 @@ impl JobQueue {
         Ok(())
     }
 
+    /// Hand a downloaded job to the processing stage. It was admitted when it was submitted, so
+    /// the queue size limit doesn't apply.
+    pub async fn enqueue_processing(&self, job: Job, priority: JobPriority) -> Result<(), String> {
+        if *self.is_shutdown.lock().await {
+            return Err("Job queue is shutting down".to_string());
+        }
+
+        let job_id = job.id.clone();
+        if self.is_active(&job_id).await {
+            info!("Job {} is already running, not queueing it again", job_id);
+            return Ok(());
+        }
+        let mut processing_queue = self.processing_queue.lock().await;
+        if processing_queue.contains(&job_id) {
+            info!("Job {} is already waiting for processing", job_id);
+            return Ok(());
+        }
+        processing_queue.push(job, priority);
+        debug!("Job {} is waiting for processing, {} waiting", job_id, processing_queue.jobs.len());
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    async fn is_active(&self, job_id: &str) -> bool {
+        self.active_jobs.lock().await.contains_key(job_id)
+    }
+
+    /// Put a job back on the queue once `run_after` has passed
+    pub async fn enqueue_after(self: &Arc<Self>, job: Job, priority: JobPriority, run_after: DateTime<Utc>) {
+        let job_id = job.id.clone();
+        let delay = (run_after - Utc::now()).to_std().unwrap_or_default();
+        info!("Deferring job {} until {} ({:?})", job_id, run_after, delay);
+
+        let queue = self.clone();
+        let deferred_job_id = job_id.clone();
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            queue.deferred_jobs.lock().await.remove(&deferred_job_id);
+            if let Err(e) = queue.enqueue(job, priority).await {
+                warn!("Failed to requeue deferred job {}: {}", deferred_job_id, e);
+            }
+        });
+
+        self.deferred_jobs.lock().await.insert(job_id, handle);
+    }
+
+    /// Hold off downloads from a domain until `until`, extending any existing cooldown
+    pub async fn cool_down_domain(&self, domain: &str, until: DateTime<Utc>) {
+        let mut cooldowns = self.domain_cooldowns.lock().await;
+        let entry = cooldowns.entry(domain.to_string()).or_insert(until);
+        if *entry < until {
+            *entry = until;
+        }
+        warn!("Domain {} is rate limited; downloads paused until {}", domain, entry);
+    }
+
+    /// When downloads from a domain may resume, if it is currently cooling down
+    pub async fn domain_cooldown(&self, domain: &str) -> Option<DateTime<Utc>> {
+        let mut cooldowns = self.domain_cooldowns.lock().await;
+        match cooldowns.get(domain) {
+            Some(until) if *until > Utc::now() => Some(*until),
+            Some(_) => {
+                info!("Rate limit cooldown for domain {} has expired", domain);
+                cooldowns.remove(domain);
+                None
+            }
+            None => None,
+        }
+    }
+
     pub async fn start_worker(&self, app_state: Arc<AppState>) {
         let queue = self.queue.clone();
+        let processing_queue = self.processing_queue.clone();
         let notify = self.notify.clone();
         let active_jobs = self.active_jobs.clone();
-        let max_concurrent = self.max_concurrent_jobs;
+        let max_concurrent_downloads = self.max_concurrent_downloads;
+        let max_concurrent_processing = self.max_concurrent_processing;
+        let max_concurrent_jobs = self.max_concurrent_jobs;
+        let priority_policy = self.priority_policy;
         let is_shutdown = self.is_shutdown.clone();
+        let worker_heartbeat = self.worker_heartbeat.clone();
 
         tokio::spawn(async move {
             info!("Job queue worker started");
+            *worker_heartbeat.lock().await = Some(Utc::now());
             
             loop {
                 // Check if we should shutdown
@@ -128,109 +426,163 @@ impl JobQueue {
                     }
                 }
 
-                // Wait for notification only - no periodic polling
-                notify.notified().await;
+                // Wake on new work, or after the heartbeat interval when there is none
+                let _ = tokio::time::timeout(WORKER_HEARTBEAT_INTERVAL, notify.notified()).await;
 
-                // Clean up completed jobs
-                {
-                    let mut active = active_jobs.lock().await;
-                    let mut completed_handles = Vec::new();
+                run_pass(&worker_heartbeat, async {
+                    // Clean up completed jobs
+                    {
+                        let mut active = active_jobs.lock().await;
+                        let mut completed_handles = Vec::new();
                     
-                    // Collect finished handles first, then remove them
-                    let mut to_remove = Vec::new();
-                    for (job_id, handle) in active.iter() {
-                        if handle.is_finished() {
-                            debug!("Job {} completed, removing from active jobs", job_id);
-                            to_remove.push(job_id.clone());
+                        // Collect finished handles first, then remove them
+                        let mut to_remove = Vec::new();
+                        for (job_id, active_job) in active.iter() {
+                            if active_job.handle.is_finished() {
+                                debug!("Job {} completed, removing from active jobs", job_id);
+                                to_remove.push(job_id.clone());
+                            }
                         }
-                    }
                     
-                    // Remove finished jobs and collect their handles
-                    for job_id in to_remove {
-                        if let Some(handle) = active.remove(&job_id) {
-                            completed_handles.push(handle);
+                        // Remove finished jobs and collect their handles
+                        for job_id in to_remove {
+                            if let Some(active_job) = active.remove(&job_id) {
+                                completed_handles.push(active_job.handle);
+                            }
                         }
-                    }
                     
-                    // Clean up finished task handles to prevent resource leaks
-                    for handle in completed_handles {
-                        let _ = handle.await;
+                        // Clean up finished task handles to prevent resource leaks
+                        for handle in completed_handles {
+                            let _ = handle.await;
+                        }
                     }
-                }
 
-                // Process as many jobs as we can until we hit the limit or run out of jobs
-                loop {
-                    // Check if we can start new jobs
-                    let current_active = {
-                        let active = active_jobs.lock().await;
-                        active.len()
-                    };
+                    // Start as many jobs as the stage limits allow, until both queues run dry
+                    loop {
+                        let (downloading, processing) = {
+                            let active = active_jobs.lock().await;
+                            (stage_load(&active, Stage::Download), stage_load(&active, Stage::Processing))
+                        };
+                        let running = downloading.running + processing.running;
 
-                    if current_active >= max_concurrent {
-                        debug!("Max concurrent jobs reached ({}/{}), waiting for notification", current_active, max_concurrent);
-                        break;
-                    }
+                        if max_concurrent_jobs.is_some_and(|max| running >= max) {
+                            debug!("Max concurrent jobs reached ({}/{:?}), waiting for notification",
+                                   running, max_concurrent_jobs);
+                            break;
+                        }
+
+                        // Downloaded jobs go first: they are closer to done and their sources take up disk.
+                        // Within each queue, jobs whose priority has no slot left are passed over.
+                        let mut next_job = None;
+                        if processing.running < max_concurrent_processing {
+                            next_job = processing_queue.lock().await
+                                .pop(|priority| {
+                                    priority_policy.admits(priority, max_concurrent_processing, &processing)
+                                        && priority_policy.admits_overall(priority, max_concurrent_jobs, running)
+                                })
+                                .map(|queued_job| (Stage::Processing, queued_job));
+                        }
+                        if next_job.is_none() && downloading.running < max_concurrent_downloads {
+                            next_job = queue.lock().await
+                                .pop(|priority| {
+                                    priority_policy.admits(priority, max_concurrent_downloads, &downloading)
+                                        && priority_policy.admits_overall(priority, max_concurrent_jobs, running)
+                                })
+                                .map(|queued_job| (Stage::Download, queued_job));
+                        }
 
-                    // Get next job from queue (highest priority first)
-                    let next_job = {
-                        let mut queue = queue.lock().await;
-                        queue.pop()
-                    };
-
-                    if let Some(queued_job) = next_job {
-                        let job_id = queued_job.job.id.clone();
-                        let job_id_for_cleanup = job_id.clone();
-                        let app_state_clone = app_state.clone();
-                        let active_jobs_clone = active_jobs.clone();
-                        let notify_clone = notify.clone();
+                        if let Some((stage, queued_job)) = next_job {
+                            let job_id = queued_job.job.id.clone();
+                            let job_id_for_cleanup = job_id.clone();
+                            let priority = queued_job.priority.clone();
+                            let app_state_clone = app_state.clone();
+                            let active_jobs_clone = active_jobs.clone();
+                            let notify_clone = notify.clone();
                         
-                        info!("Starting job {} (priority: {:?}, queued for: {:?})", 
-                              job_id, 
-                              queued_job.priority,
-                              chrono::Utc::now().signed_duration_since(queued_job.queued_at));
+                            info!("Starting {:?} stage of job {} (priority: {:?}, queued for: {:?})", 
+                                  stage,
+                                  job_id, 
+                                  queued_job.priority,
+                                  chrono::Utc::now().signed_duration_since(queued_job.queued_at));
                         
-                        // Spawn job processing directly without TaskManager overhead
-                        let handle = tokio::spawn(async move {
-                            crate::api::routes::process_job(&job_id_for_cleanup, app_state_clone).await;
+                            // Spawn job processing directly without TaskManager overhead
+                            let handle = tokio::spawn(async move {
+                                let outcome = match stage {
+                                    Stage::Download => crate::api::routes::download_job(&job_id_for_cleanup, app_state_clone.clone()).await,
+                                    Stage::Processing => crate::api::routes::process_job(&job_id_for_cleanup, app_state_clone.clone()).await,
+                                };
                             
-                            // Remove from active jobs when done and notify worker
+                                // Remove from active jobs before queueing the job again, then notify worker
+                                {
+                                    let mut active = active_jobs_clone.lock().await;
+                                    active.remove(&job_id_for_cleanup);
+                                }
+                                match outcome {
+                                    StageOutcome::Done => {}
+                                    StageOutcome::ReadyForProcessing(job) => {
+                                        if let Err(e) = app_state_clone.job_queue.enqueue_processing(job, priority).await {
+                                            warn!("Failed to queue job {} for processing: {}", job_id_for_cleanup, e);
+                                        }
+                                    }
+                                    StageOutcome::Deferred(job, run_after) => {
+                                        app_state_clone.job_queue.enqueue_after(job, priority, run_after).await;
+                                    }
+                                }
+                                notify_clone.notify_one();
+                            });
+                        
+                            // Track the job
                             {
-                                let mut active = active_jobs_clone.lock().await;
-                                active.remove(&job_id_for_cleanup);
+                                let mut active = active_jobs.lock().await;
+                                active.insert(job_id, ActiveJob { stage, priority: queued_job.priority, handle });
                             }
-                            notify_clone.notify_one();
-                        });
-                        
-                        // Track the job
-                        {
-                            let mut active = active_jobs.lock().await;
-                            active.insert(job_id, handle);
+                        } else {
+                            // Nothing queued that a free slot could take
+                            debug!("No more jobs ready to start");
+                            break;
                         }
-                    } else {
-                        // No more jobs in queue
-                        debug!("No more jobs in queue");
-                        break;
                     }
-                }
+                }).await;
             }
         });
     }
 
-    #[allow(dead_code)]
     pub async fn get_queue_stats(&self) -> QueueStats {
         let queue = self.queue.lock().await;
-        let active_jobs = self.active_jobs.lock().await;
+        let processing_queue = self.processing_queue.lock().await;
+        let (downloading_jobs, processing_jobs) = stage_counts(&*self.active_jobs.lock().await);
+        let deferred_jobs = self.deferred_jobs.lock().await;
         
         let mut priority_counts = HashMap::new();
-        for queued_job in queue.iter() {
+        let mut client_counts: BTreeMap<Option<String>, usize> = BTreeMap::new();
+        for queued_job in queue.jobs.iter() {
             *priority_counts.entry(queued_job.priority.clone()).or_insert(0) += 1;
+            *client_counts.entry(queued_job.job.client_id.clone()).or_default() += 1;
         }
 
+        let now = Utc::now();
+        let domain_cooldowns = self.domain_cooldowns.lock().await
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(domain, until)| (domain.clone(), *until))
+            .collect();
+
         QueueStats {
-            queued_jobs: queue.len(),
-            active_jobs: active_jobs.len(),
+            queued_jobs: queue.jobs.len(),
+            awaiting_processing: processing_queue.jobs.len(),
+            active_jobs: downloading_jobs + processing_jobs,
+            downloading_jobs,
+            processing_jobs,
+            deferred_jobs: deferred_jobs.len(),
+            max_concurrent_downloads: self.max_concurrent_downloads,
+            max_concurrent_processing: self.max_concurrent_processing,
             max_concurrent_jobs: self.max_concurrent_jobs,
+            priority_policy: self.priority_policy,
             priority_breakdown: priority_counts,
+            client_breakdown: client_counts.into_iter()
+                .map(|(client_id, queued_jobs)| ClientQueueCount { client_id, queued_jobs })
+                .collect(),
+            domain_cooldowns,
         }
     }
 
@@ -241,45 +593,98 @@ impl JobQueue {
         // Step 1: Try to cancel active job
         {
             let mut active = self.active_jobs.lock().await;
-            if let Some(handle) = active.remove(job_id) {
-                handle.abort();
-                info!("Cancelled active job: {}", job_id);
+            if let Some(active_job) = active.remove(job_id) {
+                active_job.handle.abort();
+                info!("Cancelled job {} in its {:?} stage", job_id, active_job.stage);
                 cancelled = true;
             }
         }
 
-        // Step 2: Try to remove from queue
+        // Step 2: Drop a pending deferred requeue
         {
-            let mut queue = self.queue.lock().await;
-            let mut temp_jobs = Vec::new();
-            let mut found_in_queue = false;
-            
-            // Drain the queue to find and remove the target job
-            while let Some(queued_job) = queue.pop() {
-                if queued_job.job.id == job_id && !found_in_queue {
-                    found_in_queue = true;
-                    info!("Cancelled queued job: {}", job_id);
-                    cancelled = true;
-                } else {
-                    temp_jobs.push(queued_job);
-                }
+            let mut deferred = self.deferred_jobs.lock().await;
+            if let Some(handle) = deferred.remove(job_id) {
+                handle.abort();
+                info!("Cancelled deferred job: {}", job_id);
+                cancelled = true;
             }
-            
-            // Rebuild the queue with remaining jobs
-            for job in temp_jobs {
-                queue.push(job);
+        }
+
+        // Step 3: Try to remove from either queue
+        for queue in [&self.queue, &self.processing_queue] {
+            if queue.lock().await.remove(job_id) {
+                info!("Cancelled queued job: {}", job_id);
+                cancelled = true;
             }
         }
 
         Ok(cancelled)
     }
 
+    /// Stop accepting new jobs ahead of a deploy; queued and running jobs carry on. Draining
+    /// again keeps the original start time.
+    pub async fn start_draining(&self) -> DrainStatus {
+        {
+            let mut draining_since = self.draining_since.lock().await;
+            if draining_since.is_none() {
+                info!("Draining: new jobs are refused until the drain is cancelled");
+                *draining_since = Some(Utc::now());
+            }
+        }
+        gauge_set!("aperio_draining", 1.0);
+        self.drain_status().await
+    }
+
+    pub async fn stop_draining(&self) -> DrainStatus {
+        if self.draining_since.lock().await.take().is_some() {
+            info!("Drain cancelled, accepting new jobs again");
+        }
+        gauge_set!("aperio_draining", 0.0);
+        self.drain_status().await
+    }
+
+    pub async fn is_draining(&self) -> bool {
+        self.draining_since.lock().await.is_some()
+    }
+
+    pub async fn drain_status(&self) -> DrainStatus {
+        let draining_since = *self.draining_since.lock().await;
+        let (queued_jobs, active_jobs) = self.get_queue_info().await;
+        DrainStatus {
+            draining: draining_since.is_some(),
+            draining_since,
+            queued_jobs,
+            active_jobs,
+        }
+    }
+
+    /// When the worker last finished a pass over the queues, or None if it never started
+    pub async fn worker_heartbeat(&self) -> Option<DateTime<Utc>> {
+        *self.worker_heartbeat.lock().await
+    }
+
+    /// When the longest-waiting job in either queue was queued. Deferred jobs are not waiting
+    /// on the worker, so they don't count.
+    pub async fn oldest_queued_at(&self) -> Option<DateTime<Utc>> {
+        let queue = self.queue.lock().await;
+        let processing_queue = self.processing_queue.lock().await;
+        queue.jobs.iter()
+            .chain(processing_queue.jobs.iter())
+            .map(|queued_job| queued_job.queued_at)
+            .min()
+    }
+
+    /// Jobs waiting to start downloading, and the most the queue takes before refusing new ones
+    pub async fn queue_fill(&self) -> (usize, usize) {
+        (self.queue.lock().await.jobs.len(), self.max_queue_size)
+    }
+
     /// Get queue statistics safely
-    #[allow(dead_code)]
     pub async fn get_queue_info(&self) -> (usize, usize) {
         let queue = self.queue.lock().await;
+        let processing_queue = self.processing_queue.lock().await;
         let active = self.active_jobs.lock().await;
-        (queue.len(), active.len())
+        (queue.jobs.len() + processing_queue.jobs.len(), active.len())
     }
 
     #[allow(dead_code)]
@@ -295,17 +700,15 @@ impl JobQueue {
         // Cancel all active jobs
         {
             let mut active = self.active_jobs.lock().await;
-            for (job_id, handle) in active.drain() {
+            for (job_id, active_job) in active.drain() {
                 warn!("Aborting job {} due to shutdown", job_id);
-                handle.abort();
+                active_job.handle.abort();
             }
         }
 
-        // Clear queue
-        {
-            let mut queue = self.queue.lock().await;
-            let remaining = queue.len();
-            queue.clear();
+        // Clear queues
+        for queue in [&self.queue, &self.processing_queue] {
+            let remaining = queue.lock().await.clear();
             if remaining > 0 {
                 warn!("Cancelled {} queued jobs due to shutdown", remaining);
             }
@@ -313,11 +716,466 @@ impl JobQueue {
     }
 }
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
+/// Run one pass of the worker. A panic in it is logged and the next pass runs as usual, rather
+/// than leaving the queue without a worker. Only a finished pass moves the heartbeat, so a
+/// worker that keeps panicking still shows up as stalled.
+async fn run_pass(worker_heartbeat: &Mutex<Option<DateTime<Utc>>>, pass: impl std::future::Future<Output = ()>) {
+    match AssertUnwindSafe(pass).catch_unwind().await {
+        Ok(()) => *worker_heartbeat.lock().await = Some(Utc::now()),
+        Err(panic) => error!("Job queue worker pass panicked: {}", panic_message(&*panic)),
+    }
+}
+
+/// Jobs running in the download and processing stages
+fn stage_counts(active_jobs: &HashMap<String, ActiveJob>) -> (usize, usize) {
+    let downloading = active_jobs.values().filter(|active_job| active_job.stage == Stage::Download).count();
+    (downloading, active_jobs.len() - downloading)
+}
+
+fn stage_load(active_jobs: &HashMap<String, ActiveJob>, stage: Stage) -> StageLoad {
+    active_jobs.values()
+        .filter(|active_job| active_job.stage == stage)
+        .fold(StageLoad::default(), |mut load, active_job| {
+            load.running += 1;
+            if active_job.priority == JobPriority::Low {
+                load.low += 1;
+            }
+            load
+        })
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct QueueStats {
+    /// Jobs waiting to be downloaded
     pub queued_jobs: usize,
+    /// Downloaded jobs waiting for a processing slot
+    pub awaiting_processing: usize,
     pub active_jobs: usize,
-    pub max_concurrent_jobs: usize,
+    pub downloading_jobs: usize,
+    pub processing_jobs: usize,
+    pub deferred_jobs: usize,
+    pub max_concurrent_downloads: usize,
+    pub max_concurrent_processing: usize,
+    /// Cap on jobs running across both stages; null when only the per-stage limits apply
+    pub max_concurrent_jobs: Option<usize>,
+    pub priority_policy: PriorityPolicy,
     pub priority_breakdown: HashMap<JobPriority, usize>,
-}
\ No newline at end of file
+    /// Queued jobs per client, which take turns within each priority
+    pub client_breakdown: Vec<ClientQueueCount>,
+    pub domain_cooldowns: HashMap<String, DateTime<Utc>>,
+}
+
+/// Whether the server refuses new jobs, and the work it still has to finish
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DrainStatus {
+    pub draining: bool,
+    pub draining_since: Option<DateTime<Utc>>,
+    pub queued_jobs: usize,
+    pub active_jobs: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ClientQueueCount {
+    /// Null for jobs submitted without an API key
+    pub client_id: Option<String>,
+    pub queued_jobs: usize,
+}
+
+/// The message a panic was raised with, when it was a string
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic.downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job::JobStatus;
+    use crate::services::job_repository::JobRepository;
+
+    fn job(name: &str) -> Job {
+        let mut job = Job::new(format!("https://youtube.com/watch?v={name}"));
+        job.id = name.to_string();
+        job
+    }
+
+    fn load(running: usize, low: usize) -> StageLoad {
+        StageLoad { running, low }
+    }
+
+    /// Start queued jobs into a stage of `limit` slots until the policy admits no more, the way
+    /// the worker does, and return the ids in start order
+    fn saturate(queue: &mut ReadyQueue, policy: PriorityPolicy, limit: usize, load: &mut StageLoad) -> Vec<String> {
+        let mut started = Vec::new();
+        while let Some(queued_job) = queue.pop(|priority| policy.admits(priority, limit, load)) {
+            load.running += 1;
+            if queued_job.priority == JobPriority::Low {
+                load.low += 1;
+            }
+            started.push(queued_job.job.id);
+        }
+        started
+    }
+
+    #[test]
+    fn default_policy_fills_every_slot_in_priority_order() {
+        let policy = PriorityPolicy::default();
+        for priority in [JobPriority::Low, JobPriority::Normal, JobPriority::High] {
+            assert!(policy.admits(&priority, 2, &load(1, 1)), "{priority:?}");
+            assert!(!policy.admits(&priority, 2, &load(2, 0)), "{priority:?}");
+        }
+
+        let mut queue = ReadyQueue::default();
+        queue.push(job("low"), JobPriority::Low);
+        queue.push(job("normal-1"), JobPriority::Normal);
+        queue.push(job("high"), JobPriority::High);
+        queue.push(job("normal-2"), JobPriority::Normal);
+        let mut load = load(0, 0);
+        assert_eq!(saturate(&mut queue, policy, 3, &mut load), ["high", "normal-1", "normal-2"]);
+        assert!(queue.contains("low"));
+    }
+
+    #[test]
+    fn reserved_slots_stay_free_for_high_priority() {
+        let policy = PriorityPolicy { high_reserved_slots: 1, ..PriorityPolicy::default() };
+
+        let mut queue = ReadyQueue::default();
+        for name in ["normal-1", "normal-2", "normal-3"] {
+            queue.push(job(name), JobPriority::Normal);
+        }
+        queue.push(job("low"), JobPriority::Low);
+        let mut load = load(0, 0);
+        // Normal jobs saturate everything but the reserved slot
+        assert_eq!(saturate(&mut queue, policy, 3, &mut load), ["normal-1", "normal-2"]);
+
+        // A High job arriving behind them still gets the reserved slot
+        queue.push(job("high"), JobPriority::High);
+        assert_eq!(saturate(&mut queue, policy, 3, &mut load), ["high"]);
+        assert_eq!(load.running, 3);
+        assert!(queue.contains("normal-3") && queue.contains("low"));
+    }
+
+    #[test]
+    fn reserving_every_slot_admits_only_high_priority() {
+        let policy = PriorityPolicy { high_reserved_slots: 5, ..PriorityPolicy::default() };
+        assert!(!policy.admits(&JobPriority::Normal, 2, &load(0, 0)));
+        assert!(!policy.admits(&JobPriority::Low, 2, &load(0, 0)));
+        assert!(policy.admits(&JobPriority::High, 2, &load(1, 0)));
+        assert!(!policy.admits(&JobPriority::High, 2, &load(2, 0)));
+    }
+
+    #[test]
+    fn low_priority_is_capped_to_its_share() {
+        let policy = PriorityPolicy { low_max_percent: 50, ..PriorityPolicy::default() };
+
+        let mut queue = ReadyQueue::default();
+        for name in ["low-1", "low-2", "low-3", "low-4"] {
+            queue.push(job(name), JobPriority::Low);
+        }
+        let mut load = load(0, 0);
+        assert_eq!(saturate(&mut queue, policy, 4, &mut load), ["low-1", "low-2"]);
+
+        // The rest of the slots are still open to other priorities
+        queue.push(job("normal"), JobPriority::Normal);
+        assert_eq!(saturate(&mut queue, policy, 4, &mut load), ["normal"]);
+        assert_eq!(load.running, 3);
+    }
+
+    #[test]
+    fn low_priority_always_keeps_one_slot() {
+        let policy = PriorityPolicy { low_max_percent: 0, ..PriorityPolicy::default() };
+        assert!(policy.admits(&JobPriority::Low, 4, &load(0, 0)));
+        assert!(!policy.admits(&JobPriority::Low, 4, &load(1, 1)));
+
+        // A 50% share of one slot rounds up to that slot
+        let policy = PriorityPolicy { low_max_percent: 50, ..PriorityPolicy::default() };
+        assert!(policy.admits(&JobPriority::Low, 1, &load(0, 0)));
+    }
+
+    #[test]
+    fn reservation_and_cap_combine() {
+        let policy = PriorityPolicy { high_reserved_slots: 1, low_max_percent: 25 };
+
+        let mut queue = ReadyQueue::default();
+        for name in ["low-1", "low-2", "normal-1", "normal-2", "normal-3", "normal-4"] {
+            let priority = if name.starts_with("low") { JobPriority::Low } else { JobPriority::Normal };
+            queue.push(job(name), priority);
+        }
+        let mut load = load(0, 0);
+        // 4 slots: one reserved for High, Low capped at one of them
+        assert_eq!(saturate(&mut queue, policy, 4, &mut load), ["normal-1", "normal-2", "normal-3"]);
+
+        let mut queue = ReadyQueue::default();
+        for name in ["low-1", "low-2"] {
+            queue.push(job(name), JobPriority::Low);
+        }
+        let mut load = StageLoad::default();
+        assert_eq!(saturate(&mut queue, policy, 4, &mut load), ["low-1"]);
+    }
+
+    #[test]
+    fn reserved_slots_stay_free_under_the_cap_on_all_jobs() {
+        let policy = PriorityPolicy { high_reserved_slots: 1, ..PriorityPolicy::default() };
+        let max_jobs = Some(3);
+        // One Normal job is processing, so downloads share the other two of the three slots
+        let processing = 1;
+
+        let mut queue = ReadyQueue::default();
+        for name in ["normal-1", "normal-2", "normal-3"] {
+            queue.push(job(name), JobPriority::Normal);
+        }
+        let mut downloading = load(0, 0);
+        let mut started = Vec::new();
+        let mut start = |queue: &mut ReadyQueue, downloading: &mut StageLoad| {
+            while let Some(queued_job) = queue.pop(|priority| {
+                policy.admits(priority, 3, downloading)
+                    && policy.admits_overall(priority, max_jobs, processing + downloading.running)
+            }) {
+                downloading.running += 1;
+                started.push(queued_job.job.id);
+            }
+        };
+        // The download stage has a free unreserved slot, but the cap on all jobs does not
+        start(&mut queue, &mut downloading);
+
+        // A High job arriving behind them still gets the reserved slot
+        queue.push(job("high"), JobPriority::High);
+        start(&mut queue, &mut downloading);
+        assert_eq!(started, ["normal-1", "high"]);
+        assert_eq!(processing + downloading.running, 3);
+        assert!(queue.contains("normal-2") && queue.contains("normal-3"));
+
+        assert!(policy.admits_overall(&JobPriority::Low, None, 100));
+    }
+
+    #[test]
+    fn skipped_jobs_keep_their_place() {
+        let policy = PriorityPolicy { high_reserved_slots: 1, ..PriorityPolicy::default() };
+
+        let mut queue = ReadyQueue::default();
+        for name in ["normal-1", "normal-2", "normal-3"] {
+            queue.push(job(name), JobPriority::Normal);
+        }
+        queue.push(job("high"), JobPriority::High);
+        // Only the reserved slot is left: the High job is taken from behind the Normal ones
+        let popped = queue.pop(|priority| policy.admits(priority, 2, &load(1, 0))).unwrap();
+        assert_eq!(popped.job.id, "high");
+
+        // Nothing is lost and the Normal jobs still run first in, first out
+        let mut load = load(0, 0);
+        assert_eq!(saturate(&mut queue, PriorityPolicy::default(), 3, &mut load), ["normal-1", "normal-2", "normal-3"]);
+        assert!(queue.jobs.is_empty() && queue.ids.is_empty());
+    }
+
+    fn queue(max_queue_size: usize) -> JobQueue {
+        JobQueue::new(1, 1, None, max_queue_size, PriorityPolicy::default())
+    }
+
+    async fn repository(dir: &std::path::Path) -> JobRepository {
+        let pool = crate::database::create_database_pool(&format!("sqlite://{}", dir.join("aperio.db").display()), Some(2))
+            .await
+            .unwrap();
+        crate::database::run_migrations(&pool).await.unwrap();
+        JobRepository::new(pool)
+    }
+
+    #[tokio::test]
+    async fn full_queue_refuses_the_job_and_keeps_what_it_has() {
+        let queue = queue(2);
+        queue.enqueue(job("first"), JobPriority::Normal).await.unwrap();
+        queue.enqueue(job("second"), JobPriority::Normal).await.unwrap();
+
+        let error = queue.enqueue(job("third"), JobPriority::High).await.unwrap_err();
+        assert_eq!(error, "Queue is full (max 2 jobs), try again later");
+        assert_eq!(queue.queue_fill().await, (2, 2));
+        assert!(!queue.queue.lock().await.contains("third"));
+
+        // A processing handoff was admitted with its submission and isn't refused
+        queue.enqueue_processing(job("downloaded"), JobPriority::Normal).await.unwrap();
+        assert_eq!(queue.get_queue_info().await, (3, 0));
+    }
+
+    #[tokio::test]
+    async fn refused_submission_leaves_no_pending_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository(dir.path()).await;
+        let queue = queue(1);
+
+        // What start_job does: insert, queue, and discard the row when the queue refuses it
+        let mut outcomes = Vec::new();
+        for name in ["queued", "refused"] {
+            let job = job(name);
+            repository.create_job(&job).await.unwrap();
+            let outcome = queue.enqueue(job.clone(), JobPriority::Normal).await;
+            if outcome.is_err() {
+                repository.discard_job(&job).await.unwrap();
+            }
+            outcomes.push(outcome.is_ok());
+        }
+        assert_eq!(outcomes, [true, false]);
+
+        let pending: Vec<String> = repository.list_jobs_by_status(JobStatus::Pending).await.unwrap()
+            .into_iter()
+            .map(|job| job.id)
+            .collect();
+        assert_eq!(pending, ["queued"]);
+        assert!(repository.get_job_including_deleted("refused").await.unwrap().is_none());
+        assert!(repository.get_job_logs("refused").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shut_down_queue_refuses_jobs() {
+        let queue = queue(10);
+        queue.shutdown().await;
+        assert_eq!(queue.enqueue(job("late"), JobPriority::Normal).await.unwrap_err(), "Job queue is shutting down");
+        assert_eq!(queue.queue_fill().await.0, 0);
+    }
+
+    #[tokio::test]
+    async fn enqueueing_a_job_twice_queues_it_once() {
+        let queue = queue(10);
+        queue.enqueue(job("twice"), JobPriority::Normal).await.unwrap();
+        queue.enqueue(job("twice"), JobPriority::High).await.unwrap();
+        assert_eq!(queue.queue_fill().await.0, 1);
+
+        let mut ready = queue.queue.lock().await;
+        let popped = ready.pop(|_| true).unwrap();
+        assert_eq!((popped.job.id.as_str(), popped.priority), ("twice", JobPriority::Normal));
+        assert!(ready.pop(|_| true).is_none(), "the job would run a second time");
+    }
+
+    #[tokio::test]
+    async fn job_already_running_or_handed_to_processing_is_not_queued_again() {
+        let queue = queue(10);
+
+        queue.enqueue_processing(job("downloaded"), JobPriority::Normal).await.unwrap();
+        queue.enqueue_processing(job("downloaded"), JobPriority::Normal).await.unwrap();
+        queue.enqueue(job("downloaded"), JobPriority::Normal).await.unwrap();
+        assert_eq!(queue.get_queue_info().await, (1, 0));
+
+        queue.active_jobs.lock().await.insert("running".to_string(), ActiveJob {
+            stage: Stage::Download,
+            priority: JobPriority::Normal,
+            handle: tokio::spawn(async {}),
+        });
+        queue.enqueue(job("running"), JobPriority::Normal).await.unwrap();
+        queue.enqueue_processing(job("running"), JobPriority::Normal).await.unwrap();
+        assert_eq!(queue.get_queue_info().await, (1, 1));
+    }
+
+    #[tokio::test]
+    async fn a_started_job_cannot_be_claimed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository(dir.path()).await;
+        repository.create_job(&job("claimed")).await.unwrap();
+
+        assert!(repository.try_claim_queued_job("claimed").await.unwrap());
+        assert!(repository.update_job_status("claimed", JobStatus::Downloading, Some(JobStatus::Claimed)).await.unwrap());
+        // A stray second run finds the job already under way and leaves it alone
+        assert!(!repository.try_claim_queued_job("claimed").await.unwrap());
+        assert!(!repository.try_claim_pending_job("claimed").await.unwrap());
+        let job = repository.get_job("claimed").await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Downloading);
+    }
+
+    /// Health as reported for `queue`, with a worker stalling after 60s and jobs after 120s
+    async fn health_checker(dir: &std::path::Path, queue: Arc<JobQueue>) -> crate::monitoring::HealthChecker {
+        let pool = crate::database::create_database_pool(&format!("sqlite://{}", dir.join("aperio.db").display()), Some(2))
+            .await
+            .unwrap();
+        let mut queue_config = crate::config::Config::default().queue;
+        queue_config.worker_stall_timeout = Duration::from_secs(60);
+        queue_config.max_pending_age = Duration::from_secs(120);
+        queue_config.readiness_checks = vec![crate::config::ReadinessCheck::Worker];
+        let pool_manager = Arc::new(crate::services::ConnectionPoolManager::new(1, 1, Duration::from_secs(1)));
+        crate::monitoring::HealthChecker::new(
+            pool,
+            dir.to_path_buf(),
+            Vec::new(),
+            queue,
+            &queue_config,
+            0,
+            Arc::new(crate::services::resources::ResourceMonitor::new(pool_manager, None)),
+        )
+    }
+
+    async fn queue_health(health: &crate::monitoring::HealthChecker) -> (String, String) {
+        let check = health.get_health_status().await.checks.queue;
+        (check.status, check.message.unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn killed_worker_degrades_health() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = Arc::new(queue(10));
+        let health = health_checker(dir.path(), queue.clone()).await;
+
+        let (status, message) = queue_health(&health).await;
+        assert_eq!(status, "degraded");
+        assert!(message.contains("worker has not started"), "{message}");
+
+        run_pass(&queue.worker_heartbeat, async {}).await;
+        assert_eq!(queue_health(&health).await.0, "healthy");
+        assert!(health.get_readiness().await.is_ready());
+
+        // The worker died a while ago: its last pass is older than the stall timeout
+        *queue.worker_heartbeat.lock().await = Some(Utc::now() - chrono::Duration::seconds(61));
+        let (status, message) = queue_health(&health).await;
+        assert_eq!(status, "degraded");
+        assert!(message.contains("worker last finished a pass 6"), "{message}");
+        let readiness = health.get_readiness().await;
+        assert!(!readiness.is_ready());
+        assert_eq!(readiness.conditions.worker.status, "fail");
+    }
+
+    #[tokio::test]
+    async fn worker_survives_a_panicking_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = Arc::new(queue(10));
+        let health = health_checker(dir.path(), queue.clone()).await;
+        let stale = Utc::now() - chrono::Duration::seconds(61);
+        *queue.worker_heartbeat.lock().await = Some(stale);
+
+        // A panicking pass is caught, and doesn't count as the worker being alive
+        run_pass(&queue.worker_heartbeat, async { panic!("corrupt job") }).await;
+        assert_eq!(queue.worker_heartbeat().await, Some(stale));
+        assert_eq!(queue_health(&health).await.0, "degraded");
+
+        // The next pass runs and health recovers
+        run_pass(&queue.worker_heartbeat, async {}).await;
+        assert!(queue.worker_heartbeat().await > Some(stale));
+        assert_eq!(queue_health(&health).await.0, "healthy");
+    }
+
+    #[tokio::test]
+    async fn job_waiting_too_long_degrades_health() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = Arc::new(queue(10));
+        let health = health_checker(dir.path(), queue.clone()).await;
+        run_pass(&queue.worker_heartbeat, async {}).await;
+
+        queue.enqueue(job("fresh"), JobPriority::Normal).await.unwrap();
+        assert_eq!(queue_health(&health).await.0, "healthy");
+
+        queue.enqueue(job("stuck"), JobPriority::Low).await.unwrap();
+        let mut ready = queue.queue.lock().await;
+        let jobs = std::mem::take(&mut ready.jobs);
+        ready.jobs = jobs.into_iter().map(|mut queued_job| {
+            if queued_job.job.id == "stuck" {
+                queued_job.queued_at = Utc::now() - chrono::Duration::seconds(121);
+            }
+            queued_job
+        }).collect();
+        drop(ready);
+
+        let (status, message) = queue_health(&health).await;
+        assert_eq!(status, "degraded");
+        assert!(message.contains("oldest queued job has waited 12"), "{message}");
+        // Only the worker condition gates readiness here, and the worker is fine
+        let readiness = health.get_readiness().await;
+        assert_eq!(readiness.conditions.queue_stall.status, "fail");
+        assert!(readiness.is_ready());
+    }
+}
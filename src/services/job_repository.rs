@@ -1,22 +1,440 @@
 use crate::error::{AppError, AppResult};
-use crate::models::job::{Job, JobStatus};
-use sqlx::{SqlitePool, Row};
+use crate::models::job::{FailureReason, Job, JobMetadata, JobSource, JobStatus, ProcessingOptions, PublishStatus};
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool, Row};
+use sqlx::sqlite::SqliteRow;
+use std::collections::{BTreeMap, HashSet};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Jobs whose kept original no unfinished job reads as its source. Every stage that removes a
+/// job's files includes it, so a parent's original stays until its last re-processing job is done
+/// and the parent is picked up again by a later sweep.
+macro_rules! not_borrowed {
+    () => {
+        "NOT EXISTS (SELECT 1 FROM jobs AS borrower WHERE borrower.source_job_id = jobs.id
+         AND borrower.status NOT IN ('Completed', 'Failed', 'Cancelled', 'Expired'))"
+    };
+}
+
+/// Terminal jobs whose files are past retention and haven't been removed yet; binds the cutoff, then now.
+/// Jobs with `expires_at` use it instead of the cutoff.
+const FILE_RETENTION_FILTER: &str = concat!(
+    "files_expired_at IS NULL AND status IN ('Completed', 'Failed', 'Cancelled')
+     AND ((expires_at IS NULL AND updated_at < ?) OR expires_at < ?) AND ",
+    not_borrowed!()
+);
+/// Terminal jobs whose records are past retention; binds the cutoff, then now.
+/// A record is never deleted before its `expires_at`, so a longer per-job retention keeps it too.
+const RECORD_RETENTION_FILTER: &str = concat!(
+    "updated_at < ? AND (expires_at IS NULL OR expires_at < ?) AND status IN ('Completed', 'Failed', 'Cancelled', 'Expired') AND ",
+    not_borrowed!()
+);
+/// Deleted jobs whose restore window has passed; binds the cutoff
+const DELETED_JOBS_FILTER: &str = concat!("deleted_at < ? AND ", not_borrowed!());
+
+/// Every `jobs` column plus the job's tags and concat sources as JSON arrays, for queries that
+/// return full jobs. The (job_id, tag) and (job_id, position) primary keys keep both in order.
+const JOB_COLUMNS: &str =
+    "jobs.*, (SELECT json_group_array(tag) FROM job_tags WHERE job_tags.job_id = jobs.id) AS tags,
+     (SELECT json_group_array(json_object('url', url, 'status', status, 'downloaded_path', downloaded_path))
+      FROM job_sources WHERE job_sources.job_id = jobs.id) AS sources";
+/// Jobs that haven't been deleted. Every query serving jobs to clients includes it, so a
+/// deleted job disappears everywhere until it is restored.
+const NOT_DELETED: &str = "jobs.deleted_at IS NULL";
+/// Tables holding rows per job, removed along with the job
+const JOB_CHILD_TABLES: [&str; 3] = ["job_tags", "job_logs", "job_sources"];
+/// Statuses a job can no longer leave
+const TERMINAL_STATUSES: &str = "('Completed', 'Failed', 'Cancelled', 'Expired')";
+/// Log lines kept per job; the oldest are dropped as new ones arrive
+const MAX_JOB_LOG_ENTRIES: i64 = 200;
+
+/// How serious a job log line is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobLogLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl JobLogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobLogLevel::Info => "info",
+            JobLogLevel::Warn => "warn",
+            JobLogLevel::Error => "error",
+        }
+    }
+}
+
+/// One line of a job's log
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct JobLogEntry {
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// `info`, `warn` or `error`
+    pub level: String,
+    pub message: String,
+}
+
+/// A job selected by a retention stage, with when it finished
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct RetentionCandidate {
+    pub id: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A job's status and age, for reports that only need to label jobs found on disk
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct JobStatusSummary {
+    pub id: String,
+    pub status: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Which jobs a listing includes; unset fields match every job
+#[derive(Debug, Clone, Default)]
+pub struct JobFilter {
+    /// Jobs in any of these statuses; empty matches every status
+    pub statuses: Vec<JobStatus>,
+    pub client_id: Option<String>,
+    pub tag: Option<String>,
+    pub external_id: Option<String>,
+    /// Also match deleted jobs
+    pub include_deleted: bool,
+    /// Jobs created at or after this time
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Jobs created before this time
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl JobFilter {
+    /// Add the filter's WHERE clause, returning how to join any further condition to it
+    fn push_where(&self, query: &mut QueryBuilder<'_, Sqlite>) -> &'static str {
+        let mut clause = " WHERE ";
+        if !self.include_deleted {
+            query.push(clause).push(NOT_DELETED);
+            clause = " AND ";
+        }
+        if !self.statuses.is_empty() {
+            query.push(clause).push("status IN (");
+            let mut statuses = query.separated(", ");
+            for status in &self.statuses {
+                statuses.push_bind(status.to_string());
+            }
+            query.push(")");
+            clause = " AND ";
+        }
+        if let Some(created_after) = self.created_after {
+            query.push(clause).push("created_at >= ").push_bind(created_after);
+            clause = " AND ";
+        }
+        if let Some(created_before) = self.created_before {
+            query.push(clause).push("created_at < ").push_bind(created_before);
+            clause = " AND ";
+        }
+        if let Some(client_id) = &self.client_id {
+            query.push(clause).push("client_id = ").push_bind(client_id.clone());
+            clause = " AND ";
+        }
+        if let Some(tag) = &self.tag {
+            query.push(clause).push("id IN (SELECT job_id FROM job_tags WHERE tag = ").push_bind(tag.clone()).push(")");
+            clause = " AND ";
+        }
+        if let Some(external_id) = &self.external_id {
+            query.push(clause).push("external_id = ").push_bind(external_id.clone());
+            clause = " AND ";
+        }
+        clause
+    }
+}
+
+/// Number of jobs matching `filter`
+fn job_count_query(filter: &JobFilter) -> QueryBuilder<'static, Sqlite> {
+    let mut query = QueryBuilder::new("SELECT COUNT(*) as total FROM jobs");
+    filter.push_where(&mut query);
+    query
+}
+
+/// One page of the jobs matching `filter`, newest first
+fn job_page_query(filter: &JobFilter, page_size: u32, offset: u32) -> QueryBuilder<'static, Sqlite> {
+    let mut query = QueryBuilder::new(format!("SELECT {JOB_COLUMNS} FROM jobs"));
+    filter.push_where(&mut query);
+    query.push(" ORDER BY created_at DESC LIMIT ")
+        .push_bind(page_size as i64)
+        .push(" OFFSET ")
+        .push_bind(offset as i64);
+    query
+}
+
+/// Up to `limit` jobs matching `filter` past the `(created_at, id)` key `from`, in key order:
+/// newest first, or oldest first when `ascending`
+fn job_keyset_query(
+    filter: &JobFilter,
+    from: Option<&(chrono::DateTime<chrono::Utc>, String)>,
+    limit: u32,
+    ascending: bool,
+) -> QueryBuilder<'static, Sqlite> {
+    let (comparison, order) = if ascending { (">", "ASC") } else { ("<", "DESC") };
+    let mut query = QueryBuilder::new(format!("SELECT {JOB_COLUMNS} FROM jobs"));
+    let clause = filter.push_where(&mut query);
+    if let Some((created_at, id)) = from {
+        query.push(clause)
+            .push(format!("(created_at, id) {comparison} ("))
+            .push_bind(*created_at)
+            .push(", ")
+            .push_bind(id.clone())
+            .push(")");
+    }
+    query.push(format!(" ORDER BY created_at {order}, id {order} LIMIT ")).push_bind(limit as i64);
+    query
+}
+
+fn pending_jobs_sql() -> String {
+    format!("SELECT {JOB_COLUMNS} FROM jobs WHERE status = 'Pending' AND created_at < ? ORDER BY created_at ASC")
+}
+
+fn active_job_by_url_sql() -> String {
+    format!(
+        "SELECT {JOB_COLUMNS} FROM jobs WHERE normalized_url = ? AND status IN ('Pending', 'Downloading', 'Processing', 'Claimed')
+         ORDER BY created_at DESC LIMIT 1"
+    )
+}
+
+fn completed_jobs_by_url_sql() -> String {
+    format!(
+        "SELECT {JOB_COLUMNS} FROM jobs WHERE normalized_url = ? AND status = 'Completed' AND processed_path IS NOT NULL
+         AND {NOT_DELETED} ORDER BY updated_at DESC LIMIT 20"
+    )
+}
+
+fn recent_status_counts_sql() -> &'static str {
+    // Grouping by +status keeps the planner from walking the whole status index to avoid a sort;
+    // the hour's jobs are few, so a range on (updated_at, status) and a small sort is far cheaper
+    "SELECT status, COUNT(*) FROM jobs WHERE updated_at >= ? GROUP BY +status"
+}
+
+fn retention_candidates_sql(filter: &str) -> String {
+    format!("SELECT id, updated_at FROM jobs WHERE {filter}")
+}
+
+/// Jobs one client has in one status
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ClientJobCount {
+    pub client_id: Option<String>,
+    pub status: String,
+    pub jobs: i64,
+    /// Of those, how many were submitted in the counted window
+    pub recent: i64,
+}
+
+/// What an API key consumed over some period
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow, ToSchema)]
+pub struct UsageTotals {
+    pub jobs_submitted: i64,
+    pub jobs_completed: i64,
+    /// Sources fetched by the downloader; uploads and re-used originals don't count
+    pub bytes_downloaded: i64,
+    /// Outputs stored at completion: the processed video, its preview and a kept original
+    pub bytes_output: i64,
+    /// Wall-clock time from a job starting to completing, download included
+    pub processing_seconds: i64,
+}
+
+/// One API key's usage on one UTC day
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct DailyUsage {
+    pub day: NaiveDate,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub usage: UsageTotals,
+}
+
+/// One API key's usage over all time
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ClientUsage {
+    pub client_id: String,
+    #[sqlx(flatten)]
+    pub usage: UsageTotals,
+}
+
+/// Terminal jobs by status, plus how many have had their files removed and only keep a record
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CleanupStats {
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    pub expired: i64,
+    pub files_expired: i64,
+}
+
+/// Status changes buffered per subscriber; one that falls further behind re-reads the job instead
+const STATUS_CHANGE_CAPACITY: usize = 1024;
+
+/// A job's status as written by the repository
+#[derive(Debug, Clone)]
+pub struct StatusChange {
+    pub job_id: String,
+    pub status: JobStatus,
+}
 
 #[derive(Clone)]
 pub struct JobRepository {
     pool: SqlitePool,
+    status_changes: broadcast::Sender<StatusChange>,
+}
+
+/// Map a `jobs` row to a Job, shared by every query that selects full rows
+fn job_from_row(row: &SqliteRow) -> Job {
+    let status_str: String = row.get("status");
+    let status = match status_str.as_str() {
+        "Pending" => JobStatus::Pending,
+        "Claimed" => JobStatus::Claimed,
+        "Downloading" => JobStatus::Downloading,
+        "Processing" => JobStatus::Processing,
+        "Completed" => JobStatus::Completed,
+        "Failed" => JobStatus::Failed,
+        "Cancelled" => JobStatus::Cancelled,
+        "Expired" => JobStatus::Expired,
+        _ => JobStatus::Failed,
+    };
+
+    let url: String = row.get("url");
+
+    Job {
+        id: row.get("id"),
+        normalized_url: row.get::<Option<String>, _>("normalized_url").unwrap_or_else(|| url.clone()),
+        url,
+        status,
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        downloaded_path: row.get("downloaded_path"),
+        processed_path: row.get("processed_path"),
+        processed_sha256: row.get("processed_sha256"),
+        error_message: row.get("error_message"),
+        failure_reason: row.get::<Option<String>, _>("failure_reason")
+            .as_deref()
+            .and_then(FailureReason::from_db),
+        processing_time_seconds: row.get("processing_time_seconds"),
+        keep_original: row.get("keep_original"),
+        options: row.get::<Option<String>, _>("options")
+            .and_then(|options| serde_json::from_str::<ProcessingOptions>(&options).ok())
+            .unwrap_or_default(),
+        parent_job_id: row.get("parent_job_id"),
+        source_job_id: row.get("source_job_id"),
+        preview_path: row.get("preview_path"),
+        metadata: row.get::<Option<String>, _>("metadata")
+            .and_then(|metadata| serde_json::from_str::<JobMetadata>(&metadata).ok())
+            .unwrap_or_default(),
+        idempotency_key: row.get("idempotency_key"),
+        request_fingerprint: row.get("request_fingerprint"),
+        last_accessed_at: row.get("last_accessed_at"),
+        retain_days: row.get::<Option<i64>, _>("retain_days").map(|days| days as u32),
+        expires_at: row.get("expires_at"),
+        client_id: row.get("client_id"),
+        external_id: row.get("external_id"),
+        tags: row.get::<Option<String>, _>("tags")
+            .and_then(|tags| serde_json::from_str(&tags).ok())
+            .unwrap_or_default(),
+        publish_status: row.get::<Option<String>, _>("publish_status")
+            .as_deref()
+            .and_then(PublishStatus::from_db),
+        published_url: row.get("published_url"),
+        published_sha256: row.get("published_sha256"),
+        sources: row.get::<Option<String>, _>("sources")
+            .and_then(|sources| serde_json::from_str(&sources).ok())
+            .unwrap_or_default(),
+        deleted_at: row.get("deleted_at"),
+        correlation_id: row.get("correlation_id"),
+    }
+}
+
+/// Log the move to `job`'s status, if it is one; run before the update so the old status can be read
+async fn log_status_transition(conn: &mut SqliteConnection, job_id: &str, status: &JobStatus, error: Option<&str>) -> AppResult<()> {
+    let (level, transition) = match (status, error) {
+        (JobStatus::Failed, Some(error)) => (JobLogLevel::Error, format!(" to Failed: {error}")),
+        (JobStatus::Failed, None) => (JobLogLevel::Error, " to Failed".to_string()),
+        (status, _) => (JobLogLevel::Info, format!(" to {status}")),
+    };
+
+    sqlx::query(
+        "INSERT INTO job_logs (job_id, created_at, level, message)
+         SELECT id, ?, ?, 'Status changed from ' || status || ? FROM jobs WHERE id = ? AND status != ?"
+    )
+    .bind(chrono::Utc::now())
+    .bind(level.as_str())
+    .bind(transition)
+    .bind(job_id)
+    .bind(status.to_string())
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::database("Failed to log status change"))?;
+
+    prune_job_log(conn, job_id).await
+}
+
+/// Why an update matched no row: the job is gone, or its status doesn't allow moving to `next`,
+/// e.g. because it was cancelled while a worker was still running it
+async fn refused_update(conn: &mut SqliteConnection, job_id: &str, next: &JobStatus) -> AppError {
+    let current = sqlx::query_scalar::<_, String>("SELECT status FROM jobs WHERE id = ?")
+        .bind(job_id)
+        .fetch_optional(&mut *conn)
+        .await;
+
+    match current {
+        Ok(Some(current)) => AppError::Conflict(format!("Job {job_id} is {current} and can't move to {next}")),
+        Ok(None) => AppError::NotFound(format!("Job not found: {job_id}")),
+        Err(e) => AppError::database("Failed to read job status")(e),
+    }
+}
+
+/// Drop the job's oldest log lines beyond MAX_JOB_LOG_ENTRIES
+async fn prune_job_log(conn: &mut SqliteConnection, job_id: &str) -> AppResult<()> {
+    sqlx::query(
+        "DELETE FROM job_logs WHERE job_id = ?
+         AND id <= (SELECT id FROM job_logs WHERE job_id = ? ORDER BY id DESC LIMIT 1 OFFSET ?)"
+    )
+    .bind(job_id)
+    .bind(job_id)
+    .bind(MAX_JOB_LOG_ENTRIES)
+    .execute(conn)
+    .await
+    .map_err(AppError::database("Failed to prune job log"))?;
+
+    Ok(())
 }
 
 impl JobRepository {
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        let (status_changes, _) = broadcast::channel(STATUS_CHANGE_CAPACITY);
+        Self { pool, status_changes }
+    }
+
+    /// Every status this repository writes from now on, for any job
+    pub fn subscribe_status_changes(&self) -> broadcast::Receiver<StatusChange> {
+        self.status_changes.subscribe()
+    }
+
+    fn publish_status(&self, job_id: &str, status: JobStatus) {
+        // Sending only fails when nobody is listening
+        let _ = self.status_changes.send(StatusChange { job_id: job_id.to_string(), status });
     }
 
+    /// Insert the job and its tags together, so a job is never visible without them
     pub async fn create_job(&self, job: &Job) -> AppResult<()> {
+        let options = serde_json::to_string(&job.options)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize job options: {e}")))?;
+
+        let mut tx = self.pool.begin().await
+            .map_err(AppError::database("Failed to start transaction"))?;
+
         sqlx::query(
             r#"
-            INSERT INTO jobs (id, url, status, created_at, updated_at, downloaded_path, processed_path, error_message, processing_time_seconds)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO jobs (id, url, status, created_at, updated_at, downloaded_path, processed_path, error_message, processing_time_seconds,
+                              keep_original, options, parent_job_id, normalized_url, idempotency_key, request_fingerprint, retain_days,
+                              client_id, external_id, correlation_id, source_job_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&job.id)
@@ -28,136 +446,220 @@ impl JobRepository {
         .bind(&job.processed_path)
         .bind(&job.error_message)
         .bind(job.processing_time_seconds)
-        .execute(&self.pool)
+        .bind(job.keep_original)
+        .bind(options)
+        .bind(&job.parent_job_id)
+        .bind(&job.normalized_url)
+        .bind(&job.idempotency_key)
+        .bind(&job.request_fingerprint)
+        .bind(job.retain_days)
+        .bind(&job.client_id)
+        .bind(&job.external_id)
+        .bind(&job.correlation_id)
+        .bind(&job.source_job_id)
+        .execute(&mut *tx)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to create job: {e}")))?;
+        .map_err(AppError::database("Failed to create job"))?;
+
+        if let Some(client_id) = &job.client_id {
+            sqlx::query(
+                "INSERT INTO key_usage (client_id, day, jobs_submitted) VALUES (?, ?, 1)
+                 ON CONFLICT (client_id, day) DO UPDATE SET jobs_submitted = jobs_submitted + 1"
+            )
+            .bind(client_id)
+            .bind(job.created_at.date_naive())
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to record key usage"))?;
+        }
+
+        for tag in &job.tags {
+            sqlx::query("INSERT INTO job_tags (job_id, tag) VALUES (?, ?)")
+                .bind(&job.id)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::database("Failed to tag job"))?;
+        }
+
+        for (position, source) in job.sources.iter().enumerate() {
+            sqlx::query("INSERT INTO job_sources (job_id, position, url, status) VALUES (?, ?, ?, ?)")
+                .bind(&job.id)
+                .bind(position as i64)
+                .bind(&source.url)
+                .bind(source.status.as_str())
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::database("Failed to record job source"))?;
+        }
+
+        sqlx::query("INSERT INTO job_logs (job_id, created_at, level, message) VALUES (?, ?, ?, ?)")
+            .bind(&job.id)
+            .bind(job.created_at)
+            .bind(JobLogLevel::Info.as_str())
+            .bind(format!("Job created as {}", job.status))
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to log job creation"))?;
+
+        tx.commit().await
+            .map_err(AppError::database("Failed to commit transaction"))?;
 
         Ok(())
     }
 
     pub async fn get_job(&self, job_id: &str) -> AppResult<Option<Job>> {
-        let row = sqlx::query(
-            r#"
-            SELECT id, url, status, created_at, updated_at,
-                   downloaded_path, processed_path, error_message, processing_time_seconds
-            FROM jobs
-            WHERE id = ?
-            "#
-        )
+        let row = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = ? AND {NOT_DELETED}"))
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to get job"))?;
+
+        Ok(row.as_ref().map(job_from_row))
+    }
+
+    /// The job whether or not it was deleted, for restoring it and for housekeeping that must
+    /// not mistake a deleted job's files for orphans
+    pub async fn get_job_including_deleted(&self, job_id: &str) -> AppResult<Option<Job>> {
+        let row = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = ?"))
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to get job"))?;
+
+        Ok(row.as_ref().map(job_from_row))
+    }
+
+    /// Hide a finished job until it is restored or retention removes it. False when the job
+    /// isn't finished or was already deleted.
+    pub async fn soft_delete_job(&self, job_id: &str) -> AppResult<bool> {
+        let result = sqlx::query(&format!(
+            "UPDATE jobs SET deleted_at = ? WHERE id = ? AND {NOT_DELETED} AND status IN {TERMINAL_STATUSES}"
+        ))
+        .bind(chrono::Utc::now())
         .bind(job_id)
-        .fetch_optional(&self.pool)
+        .execute(&self.pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to get job: {e}")))?;
+        .map_err(AppError::database("Failed to delete job"))?;
 
-        if let Some(row) = row {
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Claimed" => JobStatus::Claimed,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                _ => JobStatus::Failed,
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
-            Ok(Some(job))
-        } else {
-            Ok(None)
-        }
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Bring back a job deleted after `deleted_after`. False when it isn't deleted or was
+    /// deleted earlier than that.
+    pub async fn restore_job(&self, job_id: &str, deleted_after: chrono::DateTime<chrono::Utc>) -> AppResult<bool> {
+        let result = sqlx::query("UPDATE jobs SET deleted_at = NULL WHERE id = ? AND deleted_at >= ?")
+            .bind(job_id)
+            .bind(deleted_after)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to restore job"))?;
+
+        Ok(result.rows_affected() > 0)
     }
 
     pub async fn update_job(&self, job: &Job) -> AppResult<()> {
+        let metadata = serde_json::to_string(&job.metadata)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize job metadata: {e}")))?;
+
         // Use transaction for atomic update
         let mut tx = self.pool.begin().await
-            .map_err(|e| AppError::Internal(format!("Failed to start transaction: {e}")))?;
+            .map_err(AppError::database("Failed to start transaction"))?;
 
         let updated_at = chrono::Utc::now();
-        
-        let result = sqlx::query(
+        let allowed_from = job.status.allowed_from();
+        let placeholders = vec!["?"; allowed_from.len()].join(", ");
+
+        // Every status change goes through here or update_job_status, so none can miss the job log
+        log_status_transition(&mut tx, &job.id, &job.status, job.error_message.as_deref()).await?;
+
+        let sql = format!(
             r#"
             UPDATE jobs
-            SET status = ?, updated_at = ?, downloaded_path = ?, processed_path = ?,
-                error_message = ?, processing_time_seconds = ?
-            WHERE id = ?
+            SET status = ?, updated_at = ?, downloaded_path = ?, processed_path = ?, processed_sha256 = ?,
+                error_message = ?, failure_reason = ?, processing_time_seconds = ?, preview_path = ?, metadata = ?,
+                expires_at = ?
+            WHERE id = ? AND status IN ({placeholders})
             "#
-        )
-        .bind(job.status.to_string())
-        .bind(updated_at)
-        .bind(&job.downloaded_path)
-        .bind(&job.processed_path)
-        .bind(&job.error_message)
-        .bind(job.processing_time_seconds)
-        .bind(&job.id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to update job: {e}")))?;
+        );
+        let mut query = sqlx::query(&sql)
+            .bind(job.status.to_string())
+            .bind(updated_at)
+            .bind(&job.downloaded_path)
+            .bind(&job.processed_path)
+            .bind(&job.processed_sha256)
+            .bind(&job.error_message)
+            .bind(job.failure_reason.map(|reason| reason.as_str()))
+            .bind(job.processing_time_seconds)
+            .bind(&job.preview_path)
+            .bind(metadata)
+            .bind(job.expires_at)
+            .bind(&job.id);
+        for status in &allowed_from {
+            query = query.bind(status.to_string());
+        }
+        let result = query
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to update job"))?;
 
         if result.rows_affected() == 0 {
+            let refused = refused_update(&mut tx, &job.id, &job.status).await;
             tx.rollback().await
-                .map_err(|e| AppError::Internal(format!("Failed to rollback transaction: {e}")))?;
-            return Err(AppError::NotFound(format!("Job not found: {}", job.id)));
+                .map_err(AppError::database("Failed to rollback transaction"))?;
+            return Err(refused);
         }
 
         tx.commit().await
-            .map_err(|e| AppError::Internal(format!("Failed to commit transaction: {e}")))?;
+            .map_err(AppError::database("Failed to commit transaction"))?;
+        self.publish_status(&job.id, job.status.clone());
 
         Ok(())
     }
 
-    /// Atomically update job status with validation
+    /// Atomically update job status with validation. False when the job isn't in `from_status`,
+    /// when given, or in any status it may move to `new_status` from.
     #[allow(dead_code)]
     pub async fn update_job_status(&self, job_id: &str, new_status: JobStatus, from_status: Option<JobStatus>) -> AppResult<bool> {
+        let allowed_from: Vec<JobStatus> = new_status.allowed_from().into_iter()
+            .filter(|status| from_status.as_ref().is_none_or(|expected| expected == status))
+            .collect();
+        if allowed_from.is_empty() {
+            return Ok(false);
+        }
+
         let mut tx = self.pool.begin().await
-            .map_err(|e| AppError::Internal(format!("Failed to start transaction: {e}")))?;
+            .map_err(AppError::database("Failed to start transaction"))?;
 
         let updated_at = chrono::Utc::now();
-        
-        let result = if let Some(expected_status) = from_status {
-            // Conditional update: only update if current status matches expected
-            sqlx::query(
-                "UPDATE jobs SET status = ?, updated_at = ? WHERE id = ? AND status = ?"
-            )
-            .bind(new_status.to_string())
-            .bind(updated_at)
-            .bind(job_id)
-            .bind(expected_status.to_string())
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to update job status: {e}")))?
-        } else {
-            // Unconditional update
-            sqlx::query(
-                "UPDATE jobs SET status = ?, updated_at = ? WHERE id = ?"
-            )
+
+        if from_status.as_ref().is_none_or(|expected| *expected != new_status) {
+            log_status_transition(&mut tx, job_id, &new_status, None).await?;
+        }
+
+        let placeholders = vec!["?"; allowed_from.len()].join(", ");
+        let sql = format!("UPDATE jobs SET status = ?, updated_at = ? WHERE id = ? AND status IN ({placeholders})");
+        let mut query = sqlx::query(&sql)
             .bind(new_status.to_string())
             .bind(updated_at)
-            .bind(job_id)
+            .bind(job_id);
+        for status in &allowed_from {
+            query = query.bind(status.to_string());
+        }
+        let result = query
             .execute(&mut *tx)
             .await
-            .map_err(|e| AppError::Internal(format!("Failed to update job status: {e}")))?
-        };
+            .map_err(AppError::database("Failed to update job status"))?;
 
         let success = result.rows_affected() > 0;
-        
+
         if success {
             tx.commit().await
-                .map_err(|e| AppError::Internal(format!("Failed to commit transaction: {e}")))?;
+                .map_err(AppError::database("Failed to commit transaction"))?;
+            self.publish_status(job_id, new_status);
         } else {
             tx.rollback().await
-                .map_err(|e| AppError::Internal(format!("Failed to rollback transaction: {e}")))?;
+                .map_err(AppError::database("Failed to rollback transaction"))?;
         }
 
         Ok(success)
@@ -165,275 +667,422 @@ impl JobRepository {
 
     #[allow(dead_code)]
     pub async fn list_jobs_by_status(&self, status: JobStatus) -> AppResult<Vec<Job>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT id, url, status, created_at, updated_at,
-                   downloaded_path, processed_path, error_message, processing_time_seconds
-            FROM jobs
-            WHERE status = ?
-            ORDER BY created_at DESC
-            "#
-        )
-        .bind(status.to_string())
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to list jobs: {e}")))?;
-
-        let mut jobs = Vec::new();
-        for row in rows {
-            let status_str: String = row.get("status");
-            let job_status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                _ => JobStatus::Failed,
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status: job_status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
-            jobs.push(job);
+        let rows = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE status = ? AND {NOT_DELETED} ORDER BY created_at DESC"))
+            .bind(status.to_string())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list jobs"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// Undo `create_job` for a job the queue refused, including its count against the key's
+    /// daily usage, so the failed submission leaves nothing behind
+    pub async fn discard_job(&self, job: &Job) -> AppResult<()> {
+        let mut tx = self.pool.begin().await
+            .map_err(AppError::database("Failed to start transaction"))?;
+
+        for table in JOB_CHILD_TABLES {
+            sqlx::query(&format!("DELETE FROM {table} WHERE job_id = ?"))
+                .bind(&job.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::database("Failed to discard job"))?;
         }
 
-        Ok(jobs)
+        sqlx::query("DELETE FROM jobs WHERE id = ?")
+            .bind(&job.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to discard job"))?;
+
+        if let Some(client_id) = &job.client_id {
+            sqlx::query(
+                "UPDATE key_usage SET jobs_submitted = jobs_submitted - 1
+                 WHERE client_id = ? AND day = ? AND jobs_submitted > 0"
+            )
+            .bind(client_id)
+            .bind(job.created_at.date_naive())
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to record key usage"))?;
+        }
+
+        tx.commit().await
+            .map_err(AppError::database("Failed to commit transaction"))?;
+
+        Ok(())
     }
 
     #[allow(dead_code)]
     pub async fn delete_job(&self, job_id: &str) -> AppResult<()> {
+        sqlx::query("DELETE FROM job_tags WHERE job_id = ?")
+            .bind(job_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to delete job tags"))?;
+
+        sqlx::query("DELETE FROM job_logs WHERE job_id = ?")
+            .bind(job_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to delete job log"))?;
+
+        sqlx::query("DELETE FROM job_sources WHERE job_id = ?")
+            .bind(job_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to delete job sources"))?;
+
         sqlx::query("DELETE FROM jobs WHERE id = ?")
             .bind(job_id)
             .execute(&self.pool)
             .await
-            .map_err(|e| AppError::Internal(format!("Failed to delete job: {e}")))?;
+            .map_err(AppError::database("Failed to delete job"))?;
 
         Ok(())
     }
 
     #[allow(dead_code)]
     pub async fn list_all_jobs(&self) -> AppResult<Vec<Job>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT id, url, status, created_at, updated_at,
-                   downloaded_path, processed_path, error_message, processing_time_seconds
-            FROM jobs
-            ORDER BY created_at DESC
-            "#
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to list all jobs: {e}")))?;
-
-        let mut jobs = Vec::new();
-        for row in rows {
-            let status_str: String = row.get("status");
-            let job_status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                _ => JobStatus::Failed,
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status: job_status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
-            jobs.push(job);
-        }
+        let rows = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE {NOT_DELETED} ORDER BY created_at DESC"))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list all jobs"))?;
 
-        Ok(jobs)
+        Ok(rows.iter().map(job_from_row).collect())
     }
 
     pub async fn list_jobs_paginated(
-        &self, 
-        page: u32, 
-        page_size: u32, 
-        status_filter: Option<JobStatus>
+        &self,
+        page: u32,
+        page_size: u32,
+        filter: &JobFilter,
     ) -> AppResult<(Vec<Job>, u32)> {
         let offset = page * page_size;
-        
-        // Build query based on whether we have a status filter
-        let (query, count_query) = if let Some(ref _status) = status_filter {
-            (
-                r#"
-                SELECT id, url, status, created_at, updated_at,
-                       downloaded_path, processed_path, error_message, processing_time_seconds
-                FROM jobs
-                WHERE status = ?
-                ORDER BY created_at DESC
-                LIMIT ? OFFSET ?
-                "#,
-                r#"
-                SELECT COUNT(*) as total
-                FROM jobs
-                WHERE status = ?
-                "#
-            )
-        } else {
-            (
-                r#"
-                SELECT id, url, status, created_at, updated_at,
-                       downloaded_path, processed_path, error_message, processing_time_seconds
-                FROM jobs
-                ORDER BY created_at DESC
-                LIMIT ? OFFSET ?
-                "#,
-                r#"
-                SELECT COUNT(*) as total
-                FROM jobs
-                "#
-            )
-        };
 
-        // Get total count
-        let total_count: i64 = if let Some(status) = &status_filter {
-            sqlx::query(count_query)
-                .bind(status.to_string())
-                .fetch_one(&self.pool)
-                .await
-                .map_err(|e| AppError::Internal(format!("Failed to count jobs: {e}")))?
-                .get("total")
-        } else {
-            sqlx::query(count_query)
-                .fetch_one(&self.pool)
-                .await
-                .map_err(|e| AppError::Internal(format!("Failed to count jobs: {e}")))?
-                .get("total")
-        };
-
-        // Get jobs
-        let rows = if let Some(status) = status_filter {
-            sqlx::query(query)
-                .bind(status.to_string())
-                .bind(page_size as i64)
-                .bind(offset as i64)
-                .fetch_all(&self.pool)
-                .await
-                .map_err(|e| AppError::Internal(format!("Failed to list jobs: {e}")))?
-        } else {
-            sqlx::query(query)
-                .bind(page_size as i64)
-                .bind(offset as i64)
-                .fetch_all(&self.pool)
-                .await
-                .map_err(|e| AppError::Internal(format!("Failed to list jobs: {e}")))?
-        };
-
-        let mut jobs = Vec::new();
-        for row in rows {
-            let status_str: String = row.get("status");
-            let job_status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                _ => JobStatus::Failed,
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status: job_status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
-            jobs.push(job);
-        }
+        let total_count: i64 = job_count_query(filter).build()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to count jobs"))?
+            .get("total");
+
+        let rows = job_page_query(filter, page_size, offset).build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list jobs"))?;
+
+        let jobs: Vec<Job> = rows.iter().map(job_from_row).collect();
 
         let total_pages = ((total_count as f64) / (page_size as f64)).ceil() as u32;
         Ok((jobs, total_pages))
     }
 
-    /// Get all pending jobs for queue restoration on startup
-    pub async fn get_pending_jobs(&self) -> AppResult<Vec<Job>> {
-        let rows = sqlx::query("SELECT * FROM jobs WHERE status = 'Pending' ORDER BY created_at ASC")
+    /// Every job matching `filter`, oldest first, for bulk actions
+    pub async fn list_matching_jobs(&self, filter: &JobFilter) -> AppResult<Vec<Job>> {
+        let mut query = QueryBuilder::new(format!("SELECT {JOB_COLUMNS} FROM jobs"));
+        filter.push_where(&mut query);
+        query.push(" ORDER BY created_at ASC");
+        let rows = query.build()
             .fetch_all(&self.pool)
             .await
-            .map_err(|e| AppError::Internal(format!("Failed to get pending jobs: {e}")))?;
-
-        let mut jobs = Vec::new();
-        for row in rows {
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                _ => return Err(AppError::Internal(format!("Unknown job status: {status_str}"))),
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
-            jobs.push(job);
-        }
+            .map_err(AppError::database("Failed to list jobs"))?;
 
-        Ok(jobs)
+        Ok(rows.iter().map(job_from_row).collect())
     }
 
-    /// Atomically claim a pending job for processing (prevents race conditions)
-    pub async fn try_claim_pending_job(&self, job_id: &str) -> AppResult<bool> {
-        let result = sqlx::query(
-            "UPDATE jobs SET status = ? WHERE id = ? AND status = ?"
-        )
-        .bind(JobStatus::Claimed.to_string())
-        .bind(job_id)
-        .bind(JobStatus::Pending.to_string())
-        .execute(&self.pool)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to claim job: {e}")))?;
+    /// Up to `limit` jobs matching `filter`, oldest first, starting after the job created at
+    /// `after`. Paging by position rather than offset keeps long exports cheap and stable while
+    /// new jobs arrive.
+    pub async fn list_jobs_after(
+        &self,
+        filter: &JobFilter,
+        after: Option<&(chrono::DateTime<chrono::Utc>, String)>,
+        limit: u32,
+    ) -> AppResult<Vec<Job>> {
+        let rows = job_keyset_query(filter, after, limit, true).build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list jobs"))?;
 
-        Ok(result.rows_affected() > 0)
+        Ok(rows.iter().map(job_from_row).collect())
     }
 
-    /// Unclaim a job (set back to pending) if processing failed to start
-    pub async fn unclaim_job(&self, job_id: &str) -> AppResult<()> {
-        sqlx::query(
-            "UPDATE jobs SET status = ? WHERE id = ? AND status = ?"
-        )
-        .bind(JobStatus::Pending.to_string())
-        .bind(job_id)
+    /// Up to `limit` jobs matching `filter`, newest first, starting before the job created at
+    /// `before`; the cursor pages of `/jobs`
+    pub async fn list_jobs_before(
+        &self,
+        filter: &JobFilter,
+        before: Option<&(chrono::DateTime<chrono::Utc>, String)>,
+        limit: u32,
+    ) -> AppResult<Vec<Job>> {
+        let rows = job_keyset_query(filter, before, limit, false).build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list jobs"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// Delete the records and tags of those of `job_ids` that are still terminal, returning
+    /// the ids deleted so the caller can remove their files
+    pub async fn purge_jobs(&self, job_ids: &[String]) -> AppResult<Vec<String>> {
+        if job_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut tx = self.pool.begin().await
+            .map_err(AppError::database("Failed to start transaction"))?;
+
+        // Tags, logs and sources go first, while the status check still finds their jobs
+        for table in JOB_CHILD_TABLES {
+            let mut query = QueryBuilder::new(format!("DELETE FROM {table} WHERE job_id IN (SELECT id FROM jobs WHERE status IN "));
+            query.push(TERMINAL_STATUSES).push(" AND id IN (");
+            let mut ids = query.separated(", ");
+            for job_id in job_ids {
+                ids.push_bind(job_id.clone());
+            }
+            query.push("))");
+            query.build()
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::database(format!("Failed to delete from {table}")))?;
+        }
+
+        let mut query = QueryBuilder::new("DELETE FROM jobs WHERE status IN ");
+        query.push(TERMINAL_STATUSES).push(" AND id IN (");
+        let mut ids = query.separated(", ");
+        for job_id in job_ids {
+            ids.push_bind(job_id.clone());
+        }
+        query.push(") RETURNING id");
+        let purged: Vec<String> = query.build_query_scalar()
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to purge jobs"))?;
+
+        tx.commit().await
+            .map_err(AppError::database("Failed to commit transaction"))?;
+
+        Ok(purged)
+    }
+
+    /// Add a line to the job's log, dropping the oldest beyond MAX_JOB_LOG_ENTRIES
+    pub async fn append_job_log(&self, job_id: &str, level: JobLogLevel, message: &str) -> AppResult<()> {
+        let mut conn = self.pool.acquire().await
+            .map_err(AppError::database("Failed to acquire connection"))?;
+
+        sqlx::query("INSERT INTO job_logs (job_id, created_at, level, message) VALUES (?, ?, ?, ?)")
+            .bind(job_id)
+            .bind(chrono::Utc::now())
+            .bind(level.as_str())
+            .bind(message)
+            .execute(&mut *conn)
+            .await
+            .map_err(AppError::database("Failed to write job log"))?;
+
+        prune_job_log(&mut conn, job_id).await
+    }
+
+    /// The job's log, oldest first
+    pub async fn get_job_logs(&self, job_id: &str) -> AppResult<Vec<JobLogEntry>> {
+        sqlx::query_as::<_, JobLogEntry>(
+            "SELECT created_at, level, message FROM job_logs WHERE job_id = ? ORDER BY id"
+        )
+        .bind(job_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to get job log"))
+    }
+
+    /// A client's pending or running jobs, and the jobs it submitted since `since`
+    pub async fn count_client_jobs(&self, client_id: &str, since: chrono::DateTime<chrono::Utc>) -> AppResult<(i64, i64)> {
+        let row = sqlx::query(
+            "SELECT
+                 (SELECT COUNT(*) FROM jobs WHERE client_id = ? AND status IN ('Pending', 'Claimed', 'Downloading', 'Processing')) AS active,
+                 (SELECT COUNT(*) FROM jobs WHERE client_id = ? AND created_at >= ?) AS recent"
+        )
+        .bind(client_id)
+        .bind(client_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to count client jobs"))?;
+
+        Ok((row.get("active"), row.get("recent")))
+    }
+
+    /// Job counts by client and status, plus each client's submissions since `since`; one client's when given
+    pub async fn get_client_job_counts(
+        &self,
+        client_id: Option<&str>,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> AppResult<Vec<ClientJobCount>> {
+        let mut query = QueryBuilder::new(
+            "SELECT client_id, status, COUNT(*) AS jobs, SUM(created_at >= "
+        );
+        query.push_bind(since).push(") AS recent FROM jobs");
+        if let Some(client_id) = client_id {
+            query.push(" WHERE client_id = ").push_bind(client_id);
+        }
+        query.push(" GROUP BY client_id, status");
+
+        query.build_query_as::<ClientJobCount>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to count jobs by client"))
+    }
+
+    /// Add a completed job to its API key's usage for today; jobs without a key aren't tracked
+    pub async fn record_completion_usage(&self, job: &Job, output_bytes: u64) -> AppResult<()> {
+        let Some(client_id) = &job.client_id else { return Ok(()) };
+
+        sqlx::query(
+            "INSERT INTO key_usage (client_id, day, jobs_completed, bytes_downloaded, bytes_output, processing_seconds)
+             VALUES (?, ?, 1, ?, ?, ?)
+             ON CONFLICT (client_id, day) DO UPDATE SET
+                 jobs_completed = jobs_completed + 1,
+                 bytes_downloaded = bytes_downloaded + excluded.bytes_downloaded,
+                 bytes_output = bytes_output + excluded.bytes_output,
+                 processing_seconds = processing_seconds + excluded.processing_seconds"
+        )
+        .bind(client_id)
+        .bind(chrono::Utc::now().date_naive())
+        .bind(job.metadata.downloaded_bytes.unwrap_or_default() as i64)
+        .bind(output_bytes as i64)
+        .bind(job.processing_time_seconds.unwrap_or_default())
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to record key usage"))?;
+
+        Ok(())
+    }
+
+    /// An API key's usage per day from `from` to `to` inclusive, leaving out days without any
+    pub async fn get_key_usage(&self, client_id: &str, from: NaiveDate, to: NaiveDate) -> AppResult<Vec<DailyUsage>> {
+        sqlx::query_as::<_, DailyUsage>(
+            "SELECT day, jobs_submitted, jobs_completed, bytes_downloaded, bytes_output, processing_seconds
+             FROM key_usage WHERE client_id = ? AND day BETWEEN ? AND ? ORDER BY day ASC"
+        )
+        .bind(client_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to get key usage"))
+    }
+
+    /// Every API key's usage over all time, or just `client_id`'s
+    pub async fn get_usage_totals(&self, client_id: Option<&str>) -> AppResult<Vec<ClientUsage>> {
+        let mut query = QueryBuilder::new(
+            "SELECT client_id, SUM(jobs_submitted) AS jobs_submitted, SUM(jobs_completed) AS jobs_completed,
+                    SUM(bytes_downloaded) AS bytes_downloaded, SUM(bytes_output) AS bytes_output,
+                    SUM(processing_seconds) AS processing_seconds
+             FROM key_usage"
+        );
+        if let Some(client_id) = client_id {
+            query.push(" WHERE client_id = ").push_bind(client_id);
+        }
+        query.push(" GROUP BY client_id");
+
+        query.build_query_as::<ClientUsage>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to get key usage totals"))
+    }
+
+    /// Source files unfinished jobs are reading from another job's directory, which must stay
+    /// even when the job that owns them is gone
+    pub async fn list_borrowed_sources(&self) -> AppResult<HashSet<String>> {
+        let paths: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT downloaded_path FROM jobs
+             WHERE source_job_id IS NOT NULL AND downloaded_path IS NOT NULL AND status NOT IN {TERMINAL_STATUSES}"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to list borrowed source files"))?;
+
+        Ok(paths.into_iter().collect())
+    }
+
+    /// List jobs that were re-processed from the given parent job
+    pub async fn list_child_jobs(&self, parent_job_id: &str) -> AppResult<Vec<Job>> {
+        let rows = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE parent_job_id = ? AND {NOT_DELETED} ORDER BY created_at ASC"))
+            .bind(parent_job_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list child jobs"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// Get all pending jobs for queue restoration on startup
+    /// Pending jobs created before `created_before`, oldest first
+    pub async fn get_pending_jobs(&self, created_before: chrono::DateTime<chrono::Utc>) -> AppResult<Vec<Job>> {
+        let rows = sqlx::query(&pending_jobs_sql())
+            .bind(created_before)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to get pending jobs"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// Claim a job the queue is about to start. False when another run already started it or it
+    /// finished, e.g. because it was cancelled while queued.
+    pub async fn try_claim_queued_job(&self, job_id: &str) -> AppResult<bool> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = ? WHERE id = ? AND status IN (?, ?)"
+        )
+        .bind(JobStatus::Claimed.to_string())
+        .bind(job_id)
+        .bind(JobStatus::Pending.to_string())
+        .bind(JobStatus::Claimed.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to claim job"))?;
+
+        let claimed = result.rows_affected() > 0;
+        if claimed {
+            self.publish_status(job_id, JobStatus::Claimed);
+        }
+        Ok(claimed)
+    }
+
+    /// Atomically claim a pending job for processing (prevents race conditions)
+    pub async fn try_claim_pending_job(&self, job_id: &str) -> AppResult<bool> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = ? WHERE id = ? AND status = ?"
+        )
+        .bind(JobStatus::Claimed.to_string())
+        .bind(job_id)
+        .bind(JobStatus::Pending.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to claim job"))?;
+
+        let claimed = result.rows_affected() > 0;
+        if claimed {
+            self.publish_status(job_id, JobStatus::Claimed);
+        }
+        Ok(claimed)
+    }
+
+    /// Unclaim a job (set back to pending) if processing failed to start
+    pub async fn unclaim_job(&self, job_id: &str) -> AppResult<()> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = ? WHERE id = ? AND status = ?"
+        )
+        .bind(JobStatus::Pending.to_string())
+        .bind(job_id)
         .bind(JobStatus::Claimed.to_string())
         .execute(&self.pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to unclaim job: {e}")))?;
+        .map_err(AppError::database("Failed to unclaim job"))?;
 
+        if result.rows_affected() > 0 {
+            self.publish_status(job_id, JobStatus::Pending);
+        }
         Ok(())
     }
 
@@ -442,149 +1091,664 @@ impl JobRepository {
     pub async fn get_job_for_update(&self, job_id: &str) -> AppResult<Option<Job>> {
         // SQLite doesn't have SELECT FOR UPDATE, so we use a transaction
         let mut tx = self.pool.begin().await
-            .map_err(|e| AppError::Internal(format!("Failed to start transaction: {e}")))?;
+            .map_err(AppError::database("Failed to start transaction"))?;
 
-        let row = sqlx::query(
-            "SELECT id, url, status, created_at, updated_at, downloaded_path, processed_path, error_message, processing_time_seconds 
-             FROM jobs WHERE id = ?"
-        )
-        .bind(job_id)
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to get job: {e}")))?;
+        let row = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = ?"))
+            .bind(job_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to get job"))?;
 
         if let Some(row) = row {
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                "Claimed" => JobStatus::Pending, // Treat claimed as pending for now
-                _ => return Err(AppError::Internal(format!("Unknown job status: {status_str}"))),
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
+            let job = job_from_row(&row);
 
             tx.commit().await
-                .map_err(|e| AppError::Internal(format!("Failed to commit transaction: {e}")))?;
+                .map_err(AppError::database("Failed to commit transaction"))?;
 
             Ok(Some(job))
         } else {
             tx.rollback().await
-                .map_err(|e| AppError::Internal(format!("Failed to rollback transaction: {e}")))?;
+                .map_err(AppError::database("Failed to rollback transaction"))?;
             Ok(None)
         }
     }
 
     /// Find an active job (pending, downloading, processing) by URL for deduplication
-    pub async fn find_active_job_by_url(&self, url: &str) -> AppResult<Option<Job>> {
-        let row = sqlx::query(
-            "SELECT id, url, status, created_at, updated_at, downloaded_path, processed_path, error_message, processing_time_seconds 
-             FROM jobs WHERE url = ? AND status IN ('Pending', 'Downloading', 'Processing', 'Claimed') 
-             ORDER BY created_at DESC LIMIT 1"
-        )
-        .bind(url)
+    pub async fn find_active_job_by_url(&self, normalized_url: &str) -> AppResult<Option<Job>> {
+        let row = sqlx::query(&active_job_by_url_sql())
+        .bind(normalized_url)
         .fetch_optional(&self.pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to find job by URL: {e}")))?;
+        .map_err(AppError::database("Failed to find job by URL"))?;
 
-        if let Some(row) = row {
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Pending" => JobStatus::Pending,
-                "Downloading" => JobStatus::Downloading,
-                "Processing" => JobStatus::Processing,
-                "Completed" => JobStatus::Completed,
-                "Failed" => JobStatus::Failed,
-                "Cancelled" => JobStatus::Cancelled,
-                "Claimed" => JobStatus::Pending, // Treat claimed as pending
-                _ => return Err(AppError::Internal(format!("Unknown job status: {status_str}"))),
-            };
-
-            let job = Job {
-                id: row.get("id"),
-                url: row.get("url"),
-                status,
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-                downloaded_path: row.get("downloaded_path"),
-                processed_path: row.get("processed_path"),
-                error_message: row.get("error_message"),
-                processing_time_seconds: row.get("processing_time_seconds"),
-            };
+        Ok(row.as_ref().map(job_from_row))
+    }
 
-            Ok(Some(job))
-        } else {
-            Ok(None)
+    /// Record how publishing the job's output went. Written on its own so it never races the
+    /// job's other updates.
+    pub async fn update_publish_state(
+        &self,
+        job_id: &str,
+        status: PublishStatus,
+        published_url: Option<&str>,
+        published_sha256: Option<&str>,
+    ) -> AppResult<()> {
+        sqlx::query("UPDATE jobs SET publish_status = ?, published_url = ?, published_sha256 = ? WHERE id = ?")
+            .bind(status.as_str())
+            .bind(published_url)
+            .bind(published_sha256)
+            .bind(job_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to update publish status"))?;
+        Ok(())
+    }
+
+    /// Record how far fetching one of a concat job's sources got; `position` counts from 0
+    pub async fn update_job_source(&self, job_id: &str, position: usize, source: &JobSource) -> AppResult<()> {
+        sqlx::query("UPDATE job_sources SET status = ?, downloaded_path = ? WHERE job_id = ? AND position = ?")
+            .bind(source.status.as_str())
+            .bind(&source.downloaded_path)
+            .bind(job_id)
+            .bind(position as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to update job source"))?;
+        Ok(())
+    }
+
+    /// Completed jobs whose output was still being published when the server stopped
+    pub async fn list_jobs_pending_publish(&self) -> AppResult<Vec<Job>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {JOB_COLUMNS} FROM jobs WHERE publish_status = 'pending' AND status = 'Completed' ORDER BY updated_at"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to list jobs pending publish"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// The newest job for a URL in any status, for feeds that must not submit an entry twice
+    pub async fn find_latest_job_by_url(&self, normalized_url: &str) -> AppResult<Option<Job>> {
+        let row = sqlx::query(&format!(
+            "SELECT {JOB_COLUMNS} FROM jobs WHERE normalized_url = ? ORDER BY created_at DESC LIMIT 1"
+        ))
+        .bind(normalized_url)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to find job by URL"))?;
+
+        Ok(row.as_ref().map(job_from_row))
+    }
+
+    pub async fn find_job_by_idempotency_key(&self, idempotency_key: &str) -> AppResult<Option<Job>> {
+        let row = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE idempotency_key = ?"))
+            .bind(idempotency_key)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to find job by idempotency key"))?;
+
+        Ok(row.as_ref().map(job_from_row))
+    }
+
+    /// The job a client submitted under its own external id, which is unique per client
+    pub async fn find_client_job_by_external_id(&self, client_id: &str, external_id: &str) -> AppResult<Option<Job>> {
+        let row = sqlx::query(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE client_id = ? AND external_id = ?"))
+            .bind(client_id)
+            .bind(external_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to find job by external id"))?;
+
+        Ok(row.as_ref().map(job_from_row))
+    }
+
+    /// Forget idempotency keys of jobs created before the cutoff so they can be reused
+    pub async fn expire_idempotency_keys(&self, created_before: chrono::DateTime<chrono::Utc>) -> AppResult<u64> {
+        let result = sqlx::query(
+            "UPDATE jobs SET idempotency_key = NULL, request_fingerprint = NULL
+             WHERE idempotency_key IS NOT NULL AND created_at < ?"
+        )
+        .bind(created_before)
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to expire idempotency keys"))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Completed jobs for a URL, newest first, as result cache candidates
+    pub async fn find_completed_jobs_by_url(&self, normalized_url: &str) -> AppResult<Vec<Job>> {
+        let rows = sqlx::query(&completed_jobs_by_url_sql())
+        .bind(normalized_url)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to find completed jobs by URL"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// Status and age of each of `job_ids` that still has a row
+    pub async fn get_status_summaries(&self, job_ids: &[String]) -> AppResult<Vec<JobStatusSummary>> {
+        let mut summaries = Vec::with_capacity(job_ids.len());
+        // Stay well under SQLite's bound-parameter limit
+        for chunk in job_ids.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!("SELECT id, status, updated_at FROM jobs WHERE id IN ({placeholders})");
+            let mut query = sqlx::query_as::<_, JobStatusSummary>(&sql);
+            for job_id in chunk {
+                query = query.bind(job_id);
+            }
+            summaries.extend(query.fetch_all(&self.pool).await
+                .map_err(AppError::database("Failed to get job statuses"))?);
         }
+        Ok(summaries)
     }
 
-    /// Delete jobs older than specified days and return their IDs for file cleanup
-    pub async fn cleanup_old_jobs(&self, retention_days: u32) -> AppResult<Vec<String>> {
-        let cutoff_date = chrono::Utc::now() - chrono::Duration::days(retention_days as i64);
-        
-        // First, get the IDs of jobs to be deleted
-        let job_ids: Vec<String> = sqlx::query_scalar(
-            "SELECT id FROM jobs WHERE updated_at < ? AND status IN ('Completed', 'Failed', 'Cancelled')"
+    /// Record that a job's output was just served, without touching `updated_at`
+    pub async fn touch_last_accessed(&self, job_id: &str) -> AppResult<()> {
+        sqlx::query("UPDATE jobs SET last_accessed_at = ? WHERE id = ?")
+            .bind(chrono::Utc::now())
+            .bind(job_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to update last access time"))?;
+
+        Ok(())
+    }
+
+    /// Metadata of the `limit` most recently finished jobs that completed, newest first, for their stage times
+    pub async fn list_recent_completed_metadata(&self, limit: u32) -> AppResult<Vec<JobMetadata>> {
+        let rows = sqlx::query(
+            "SELECT metadata FROM jobs WHERE status IN ('Completed', 'Expired') AND metadata IS NOT NULL
+             ORDER BY updated_at DESC LIMIT ?"
         )
-        .bind(cutoff_date)
+        .bind(limit)
         .fetch_all(&self.pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to get old job IDs: {e}")))?;
+        .map_err(AppError::database("Failed to list recent completed jobs"))?;
 
-        if job_ids.is_empty() {
+        Ok(rows.iter()
+            .filter_map(|row| row.get::<Option<String>, _>("metadata"))
+            .filter_map(|metadata| serde_json::from_str(&metadata).ok())
+            .collect())
+    }
+
+    /// Completed jobs with outputs on disk, least recently served first.
+    /// Jobs never served count from when they completed.
+    pub async fn list_eviction_candidates(&self, limit: u32) -> AppResult<Vec<Job>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {JOB_COLUMNS} FROM jobs WHERE status = 'Completed' AND processed_path IS NOT NULL AND {}
+             ORDER BY COALESCE(last_accessed_at, updated_at) ASC LIMIT ?",
+            not_borrowed!()
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to list eviction candidates"))?;
+
+        Ok(rows.iter().map(job_from_row).collect())
+    }
+
+    /// Move a completed job to Expired and forget its file paths.
+    /// Returns false if the job is no longer Completed, e.g. it was deleted meanwhile.
+    pub async fn expire_job(&self, job_id: &str) -> AppResult<bool> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = 'Expired', files_expired_at = ?, processed_path = NULL, preview_path = NULL,
+                 downloaded_path = NULL
+             WHERE id = ? AND status = 'Completed'"
+        )
+        .bind(chrono::Utc::now())
+        .bind(job_id)
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to expire job"))?;
+
+        let expired = result.rows_affected() > 0;
+        if expired {
+            self.publish_status(job_id, JobStatus::Expired);
+        }
+        Ok(expired)
+    }
+
+    /// Change a job's retention override and expiry without touching `updated_at`
+    pub async fn update_retention(&self, job_id: &str, retain_days: u32, expires_at: Option<chrono::DateTime<chrono::Utc>>) -> AppResult<()> {
+        sqlx::query("UPDATE jobs SET retain_days = ?, expires_at = ? WHERE id = ?")
+            .bind(retain_days)
+            .bind(expires_at)
+            .bind(job_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to update job retention"))?;
+
+        Ok(())
+    }
+
+    /// Terminal jobs whose files are past the file retention period and haven't been removed yet.
+    /// Read-only, for previewing what `expire_old_job_files` would do.
+    pub async fn list_jobs_past_file_retention(&self, file_retention_days: u32) -> AppResult<Vec<RetentionCandidate>> {
+        self.list_retention_candidates(FILE_RETENTION_FILTER, file_retention_days).await
+    }
+
+    /// Terminal jobs past the record retention period.
+    /// Read-only, for previewing what `delete_old_jobs` would do.
+    pub async fn list_jobs_past_record_retention(&self, retention_days: u32) -> AppResult<Vec<RetentionCandidate>> {
+        self.list_retention_candidates(RECORD_RETENTION_FILTER, retention_days).await
+    }
+
+    async fn list_retention_candidates(&self, filter: &str, days: u32) -> AppResult<Vec<RetentionCandidate>> {
+        let cutoff_date = chrono::Utc::now() - chrono::Duration::days(days as i64);
+        sqlx::query_as::<_, RetentionCandidate>(&retention_candidates_sql(filter))
+            .bind(cutoff_date)
+            .bind(chrono::Utc::now())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list jobs past retention"))
+    }
+
+    /// File retention stage: flag terminal jobs older than the file retention period as having
+    /// no files, moving Completed ones to Expired, and return them for file cleanup.
+    /// `updated_at` is left alone so the record stage still counts from when the job finished.
+    pub async fn expire_old_job_files(&self, file_retention_days: u32) -> AppResult<Vec<RetentionCandidate>> {
+        let now = chrono::Utc::now();
+        let cutoff_date = now - chrono::Duration::days(file_retention_days as i64);
+
+        let mut tx = self.pool.begin().await
+            .map_err(AppError::database("Failed to start transaction"))?;
+
+        let jobs = sqlx::query_as::<_, RetentionCandidate>(&retention_candidates_sql(FILE_RETENTION_FILTER))
+        .bind(cutoff_date)
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(AppError::database("Failed to get job IDs for file expiry"))?;
+
+        if jobs.is_empty() {
             return Ok(vec![]);
         }
 
-        // Delete the jobs
-        let deleted_count = sqlx::query(
-            "DELETE FROM jobs WHERE updated_at < ? AND status IN ('Completed', 'Failed', 'Cancelled')"
-        )
+        sqlx::query(&format!(
+            "UPDATE jobs SET files_expired_at = ?, processed_path = NULL, preview_path = NULL, downloaded_path = NULL,
+                 status = CASE WHEN status = 'Completed' THEN 'Expired' ELSE status END
+             WHERE {FILE_RETENTION_FILTER}"
+        ))
+        .bind(now)
         .bind(cutoff_date)
-        .execute(&self.pool)
+        .bind(now)
+        .execute(&mut *tx)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to delete old jobs: {e}")))?
-        .rows_affected();
+        .map_err(AppError::database("Failed to expire job files"))?;
+
+        tx.commit().await
+            .map_err(AppError::database("Failed to commit transaction"))?;
+
+        tracing::info!("Expired files of {} jobs (older than {} days)", jobs.len(), file_retention_days);
+        Ok(jobs)
+    }
+
+    /// Record retention stage: delete jobs older than specified days and return them for file cleanup
+    pub async fn delete_old_jobs(&self, retention_days: u32) -> AppResult<Vec<RetentionCandidate>> {
+        let now = chrono::Utc::now();
+        let cutoff_date = now - chrono::Duration::days(retention_days as i64);
+
+        let mut tx = self.pool.begin().await
+            .map_err(AppError::database("Failed to start transaction"))?;
+
+        // First, get the jobs to be deleted
+        let jobs = sqlx::query_as::<_, RetentionCandidate>(&retention_candidates_sql(RECORD_RETENTION_FILTER))
+        .bind(cutoff_date)
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(AppError::database("Failed to get old job IDs"))?;
+
+        if jobs.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Tags, logs and sources go first, while the filter still finds their jobs
+        for table in JOB_CHILD_TABLES {
+            sqlx::query(&format!("DELETE FROM {table} WHERE job_id IN (SELECT id FROM jobs WHERE {RECORD_RETENTION_FILTER})"))
+                .bind(cutoff_date)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::database(format!("Failed to delete old rows from {table}")))?;
+        }
+
+        // Delete the jobs
+        let deleted_count = sqlx::query(&format!("DELETE FROM jobs WHERE {RECORD_RETENTION_FILTER}"))
+            .bind(cutoff_date)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to delete old jobs"))?
+            .rows_affected();
+
+        tx.commit().await
+            .map_err(AppError::database("Failed to commit transaction"))?;
 
         tracing::info!("Deleted {} old jobs (older than {} days)", deleted_count, retention_days);
-        Ok(job_ids)
+        Ok(jobs)
+    }
+
+    /// Jobs deleted before the cutoff, whose restore window has passed
+    pub async fn list_jobs_deleted_before(&self, deleted_before: chrono::DateTime<chrono::Utc>) -> AppResult<Vec<RetentionCandidate>> {
+        sqlx::query_as::<_, RetentionCandidate>(&retention_candidates_sql(DELETED_JOBS_FILTER))
+            .bind(deleted_before)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list deleted jobs"))
     }
 
-    /// Get count of jobs by status for cleanup statistics
-    pub async fn get_cleanup_stats(&self) -> AppResult<(i64, i64, i64)> {
-        let stats = sqlx::query_as::<_, (String, i64)>(
-            "SELECT status, COUNT(*) as count FROM jobs WHERE status IN ('Completed', 'Failed', 'Cancelled') GROUP BY status"
+    /// Remove the records of jobs deleted before the cutoff, returning them for file cleanup
+    pub async fn purge_jobs_deleted_before(&self, deleted_before: chrono::DateTime<chrono::Utc>) -> AppResult<Vec<RetentionCandidate>> {
+        let mut tx = self.pool.begin().await
+            .map_err(AppError::database("Failed to start transaction"))?;
+
+        for table in JOB_CHILD_TABLES {
+            sqlx::query(&format!("DELETE FROM {table} WHERE job_id IN (SELECT id FROM jobs WHERE {DELETED_JOBS_FILTER})"))
+                .bind(deleted_before)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::database(format!("Failed to delete rows of deleted jobs from {table}")))?;
+        }
+
+        let jobs = sqlx::query_as::<_, RetentionCandidate>(&format!("DELETE FROM jobs WHERE {DELETED_JOBS_FILTER} RETURNING id, updated_at"))
+            .bind(deleted_before)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(AppError::database("Failed to purge deleted jobs"))?;
+
+        tx.commit().await
+            .map_err(AppError::database("Failed to commit transaction"))?;
+
+        Ok(jobs)
+    }
+
+    /// Get count of jobs by status, and how many of them no longer have files, for cleanup statistics
+    pub async fn get_cleanup_stats(&self) -> AppResult<CleanupStats> {
+        let stats = sqlx::query_as::<_, (String, i64, i64)>(
+            "SELECT status, COUNT(*) as count, COUNT(files_expired_at) as files_expired FROM jobs
+             WHERE status IN ('Completed', 'Failed', 'Cancelled', 'Expired') GROUP BY status"
         )
         .fetch_all(&self.pool)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to get cleanup stats: {e}")))?;
+        .map_err(AppError::database("Failed to get cleanup stats"))?;
 
-        let mut completed = 0i64;
-        let mut failed = 0i64;
-        let mut cancelled = 0i64;
-
-        for (status, count) in stats {
+        let mut cleanup_stats = CleanupStats::default();
+        for (status, count, files_expired) in stats {
             match status.as_str() {
-                "Completed" => completed = count,
-                "Failed" => failed = count,
-                "Cancelled" => cancelled = count,
+                "Completed" => cleanup_stats.completed = count,
+                "Failed" => cleanup_stats.failed = count,
+                "Cancelled" => cleanup_stats.cancelled = count,
+                "Expired" => cleanup_stats.expired = count,
                 _ => {}
             }
+            cleanup_stats.files_expired += files_expired;
+        }
+
+        Ok(cleanup_stats)
+    }
+
+    /// Jobs last updated since `since`, per current status. Jobs move on with every status
+    /// change, so this is roughly what reached each status in that time.
+    pub async fn count_recent_jobs_by_status(&self, since: chrono::DateTime<chrono::Utc>) -> AppResult<BTreeMap<String, i64>> {
+        let counts = sqlx::query_as::<_, (String, i64)>(recent_status_counts_sql())
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to count recent jobs"))?;
+
+        Ok(counts.into_iter().collect())
+    }
+}
+
+#[cfg(feature = "query-plan-checks")]
+impl JobRepository {
+    /// Fail if any query that runs against the whole jobs table would scan it rather than use
+    /// an index, so an edited query or a dropped index is caught before the table is large
+    pub async fn check_query_plans(&self) -> AppResult<()> {
+        let failed = JobFilter { statuses: vec![JobStatus::Failed], ..Default::default() };
+        let key = (chrono::Utc::now(), String::new());
+        let queries = [
+            ("job count", job_count_query(&failed).sql().to_string()),
+            ("job page", job_page_query(&JobFilter::default(), 20, 0).sql().to_string()),
+            ("job page by status", job_page_query(&failed, 20, 0).sql().to_string()),
+            ("job cursor page", job_keyset_query(&failed, Some(&key), 20, false).sql().to_string()),
+            ("job export", job_keyset_query(&JobFilter::default(), Some(&key), 500, true).sql().to_string()),
+            ("pending jobs", pending_jobs_sql()),
+            ("active job by url", active_job_by_url_sql()),
+            ("completed jobs by url", completed_jobs_by_url_sql()),
+            ("file retention", retention_candidates_sql(FILE_RETENTION_FILTER)),
+            ("record retention", retention_candidates_sql(RECORD_RETENTION_FILTER)),
+            ("deleted jobs", retention_candidates_sql(DELETED_JOBS_FILTER)),
+            ("recent jobs by status", recent_status_counts_sql().to_string()),
+        ];
+
+        let mut scans = Vec::new();
+        for (name, sql) in queries {
+            // Parameters left unbound are NULL, which doesn't change the plan
+            let plan = sqlx::raw_sql(&format!("EXPLAIN QUERY PLAN {sql}"))
+                .fetch_all(&self.pool)
+                .await
+                .map_err(AppError::database(format!("Failed to explain the {name} query")))?;
+            let steps: Vec<String> = plan.iter().map(|row| row.get("detail")).collect();
+            for step in &steps {
+                tracing::debug!("Query plan of {}: {}", name, step);
+            }
+            // Walking an index in order is fine when it also gives the ORDER BY, so LIMIT stops
+            // early; otherwise every row is visited. The borrow check's subquery runs once per
+            // outer row, so a scan there visits the table once per candidate.
+            let sorted = steps.iter().any(|step| step == "USE TEMP B-TREE FOR ORDER BY");
+            let scans_jobs = |step: &String| step.starts_with("SCAN jobs") || step.starts_with("SCAN borrower");
+            if let Some(scan) = steps.iter().find(|step| scans_jobs(step) && (sorted || !step.contains("INDEX"))) {
+                scans.push(format!("{name} ({scan})"));
+            }
         }
 
-        Ok((completed, failed, cancelled))
+        if !scans.is_empty() {
+            return Err(AppError::Internal(format!("Queries scan the jobs table: {}", scans.join(", "))));
+        }
+        tracing::info!("Query plans checked: every hot query uses an index");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn repository(dir: &std::path::Path) -> JobRepository {
+        let pool = crate::database::create_database_pool(&format!("sqlite://{}", dir.join("aperio.db").display()), Some(2))
+            .await
+            .unwrap();
+        crate::database::run_migrations(&pool).await.unwrap();
+        JobRepository::new(pool)
+    }
+
+    async fn set_status(repository: &JobRepository, job_id: &str, status: &JobStatus) {
+        sqlx::query("UPDATE jobs SET status = ? WHERE id = ?")
+            .bind(status.to_string())
+            .bind(job_id)
+            .execute(&repository.pool)
+            .await
+            .unwrap();
+    }
+
+    async fn stored_status(repository: &JobRepository, job_id: &str) -> String {
+        sqlx::query_scalar("SELECT status FROM jobs WHERE id = ?")
+            .bind(job_id)
+            .fetch_one(&repository.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn status_updates_only_follow_allowed_transitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository(dir.path()).await;
+        let job = Job::new("https://youtube.com/watch?v=matrix".to_string());
+        repository.create_job(&job).await.unwrap();
+
+        for from in &JobStatus::ALL {
+            for to in &JobStatus::ALL {
+                set_status(&repository, &job.id, from).await;
+                let moved = repository.update_job_status(&job.id, to.clone(), None).await.unwrap();
+                assert_eq!(moved, from.can_transition_to(to), "{from} -> {to}");
+                let expected = if moved { to } else { from };
+                assert_eq!(stored_status(&repository, &job.id).await, expected.to_string(), "{from} -> {to}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn full_update_of_a_job_cancelled_underneath_it_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository(dir.path()).await;
+        let mut job = Job::new("https://youtube.com/watch?v=cancelled".to_string());
+        repository.create_job(&job).await.unwrap();
+
+        repository.update_job_status(&job.id, JobStatus::Cancelled, Some(JobStatus::Pending)).await.unwrap();
+        job.status = JobStatus::Downloading;
+        job.error_message = Some("stale".to_string());
+        let error = repository.update_job(&job).await.unwrap_err();
+
+        assert!(matches!(&error, AppError::Conflict(message) if message.contains("is Cancelled and can't move to Downloading")), "{error}");
+        let stored = repository.get_job(&job.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, JobStatus::Cancelled);
+        assert_eq!(stored.error_message, None);
+    }
+
+    #[tokio::test]
+    async fn lookups_report_a_claimed_job_as_claimed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository(dir.path()).await;
+        let job = Job::new("https://youtube.com/watch?v=claimed".to_string());
+        repository.create_job(&job).await.unwrap();
+        assert!(repository.try_claim_queued_job(&job.id).await.unwrap());
+
+        let by_url = repository.find_active_job_by_url(&job.normalized_url).await.unwrap().unwrap();
+        let for_update = repository.get_job_for_update(&job.id).await.unwrap().unwrap();
+        let by_id = repository.get_job(&job.id).await.unwrap().unwrap();
+        assert_eq!(by_url.status, JobStatus::Claimed);
+        assert_eq!(for_update.status, JobStatus::Claimed);
+        assert_eq!(by_id.status, JobStatus::Claimed);
+    }
+
+    #[tokio::test]
+    async fn unknown_status_reads_as_failed_and_takes_no_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository(dir.path()).await;
+        let job = Job::new("https://youtube.com/watch?v=unknown".to_string());
+        repository.create_job(&job).await.unwrap();
+        sqlx::query("UPDATE jobs SET status = 'Paused' WHERE id = ?")
+            .bind(&job.id)
+            .execute(&repository.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(repository.get_job(&job.id).await.unwrap().unwrap().status, JobStatus::Failed);
+        assert!(repository.get_pending_jobs(chrono::Utc::now()).await.unwrap().is_empty());
+        for next in JobStatus::ALL {
+            assert!(!repository.update_job_status(&job.id, next, None).await.unwrap());
+        }
+        assert_eq!(stored_status(&repository, &job.id).await, "Paused");
+    }
+
+    #[cfg(feature = "query-plan-checks")]
+    const SEEDED_JOBS: u32 = 100_000;
+
+    /// Indexes migration 025 replaced, to measure the table as it was before it
+    #[cfg(feature = "query-plan-checks")]
+    const PRE_025_INDEXES: &str = r#"
+        DROP INDEX idx_jobs_live_created_at;
+        DROP INDEX idx_jobs_live_status_created_at;
+        DROP INDEX idx_jobs_normalized_url_status;
+        DROP INDEX idx_jobs_updated_at_status;
+        DROP INDEX idx_jobs_files_kept;
+        DROP INDEX idx_jobs_deleted_at;
+        CREATE INDEX idx_jobs_deleted_at ON jobs(deleted_at);
+        CREATE INDEX idx_jobs_normalized_url ON jobs(normalized_url);
+        CREATE INDEX idx_jobs_updated_at ON jobs(updated_at);
+        ANALYZE;
+    "#;
+
+    /// A jobs table shaped like a long-running instance: mostly finished jobs, a few pending,
+    /// one in fifty deleted, and URLs submitted five times each
+    #[cfg(feature = "query-plan-checks")]
+    async fn seeded(dir: &std::path::Path) -> JobRepository {
+        // One connection, so every plan is made with the statistics and indexes the test last set up
+        let pool = crate::database::create_database_pool(&format!("sqlite://{}", dir.join("aperio.db").display()), Some(1))
+            .await
+            .unwrap();
+        crate::database::run_migrations(&pool).await.unwrap();
+        let repository = JobRepository::new(pool);
+        sqlx::raw_sql(&format!(
+            r#"
+            WITH RECURSIVE seq(n) AS (SELECT 0 UNION ALL SELECT n + 1 FROM seq WHERE n + 1 < {SEEDED_JOBS})
+            INSERT INTO jobs (id, url, normalized_url, status, created_at, updated_at, keep_original, options, deleted_at, files_expired_at)
+            SELECT 'job-' || n,
+                   'https://example.com/video/' || (n % 20000),
+                   'https://example.com/video/' || (n % 20000),
+                   CASE WHEN n % 100 = 0 THEN 'Pending' WHEN n % 10 = 0 THEN 'Failed' ELSE 'Completed' END,
+                   strftime('%Y-%m-%dT%H:%M:%S+00:00', '2024-01-01', '+' || (n * 60) || ' seconds'),
+                   strftime('%Y-%m-%dT%H:%M:%S+00:00', '2024-01-01', '+' || (n * 60 + 30) || ' seconds'),
+                   0,
+                   '{{}}',
+                   CASE WHEN n % 50 = 1 THEN '2024-06-01T00:00:00+00:00' END,
+                   CASE WHEN n < {SEEDED_JOBS} - 5000 THEN '2024-03-01T00:00:00+00:00' END
+            FROM seq;
+            ANALYZE;
+            "#
+        ))
+        .execute(&repository.pool)
+        .await
+        .unwrap();
+        repository
+    }
+
+    /// Run each hot query against the seeded jobs and check what it finds
+    #[cfg(feature = "query-plan-checks")]
+    async fn check_hot_query_results(repository: &JobRepository) {
+        let failed = JobFilter { statuses: vec![JobStatus::Failed], ..Default::default() };
+        let (jobs, _) = repository.list_jobs_paginated(10, 20, &failed).await.unwrap();
+        assert_eq!(jobs.len(), 20);
+
+        let job = repository.find_active_job_by_url("https://example.com/video/0").await.unwrap();
+        assert_eq!(job.map(|job| job.status), Some(JobStatus::Pending));
+
+        let jobs = repository.get_pending_jobs(chrono::Utc::now()).await.unwrap();
+        assert_eq!(jobs.len() as u32, SEEDED_JOBS / 100);
+
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+        assert!(repository.count_recent_jobs_by_status(since).await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "query-plan-checks")]
+    #[tokio::test]
+    async fn hot_queries_use_indexes_on_a_large_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = seeded(dir.path()).await;
+
+        repository.check_query_plans().await.unwrap();
+        check_hot_query_results(&repository).await;
+
+        // The full index on the mostly NULL source_job_id sends the borrow check to a scan
+        sqlx::raw_sql("DROP INDEX idx_jobs_source_job_id; CREATE INDEX idx_jobs_source_job_id ON jobs(source_job_id); ANALYZE;")
+            .execute(&repository.pool)
+            .await
+            .unwrap();
+        let error = repository.check_query_plans().await.unwrap_err().to_string();
+        assert!(error.contains("(SCAN borrower)"), "{error}");
+        sqlx::raw_sql("DROP INDEX idx_jobs_source_job_id; CREATE INDEX idx_jobs_source_job_id ON jobs(source_job_id) WHERE source_job_id IS NOT NULL; ANALYZE;")
+            .execute(&repository.pool)
+            .await
+            .unwrap();
+
+        sqlx::raw_sql(PRE_025_INDEXES).execute(&repository.pool).await.unwrap();
+        let error = repository.check_query_plans().await.unwrap_err().to_string();
+        assert!(error.contains("recent jobs by status (SCAN jobs)"), "{error}");
     }
 }
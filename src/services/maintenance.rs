@@ -0,0 +1,224 @@
+use crate::error::{AppError, AppResult};
+use crate::services::JobQueue;
+use crate::services::retry::{retry_with_backoff, RetryConfig};
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+use utoipa::ToSchema;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::{interval, sleep};
+use tracing::{debug, error, info, warn};
+
+/// How often a scheduled run checks whether the queue has gone quiet
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a scheduled run waits for the queue to go quiet before running anyway
+const MAX_IDLE_WAIT: Duration = Duration::from_secs(3600);
+
+/// Sizes of the database's files on disk
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct DbFileSizes {
+    pub database_bytes: u64,
+    pub wal_bytes: u64,
+}
+
+impl DbFileSizes {
+    fn total(&self) -> u64 {
+        self.database_bytes + self.wal_bytes
+    }
+}
+
+/// What a maintenance run did
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct MaintenanceReport {
+    /// Why nothing was done, when the database isn't a SQLite file
+    pub skipped: Option<String>,
+    pub before: DbFileSizes,
+    pub after: DbFileSizes,
+    pub bytes_reclaimed: u64,
+    /// Free pages returned to the filesystem by the incremental vacuum
+    pub pages_freed: i64,
+    /// False when readers kept the WAL checkpoint from finishing; the WAL is truncated on a later run
+    pub checkpoint_complete: bool,
+    pub duration_ms: u64,
+}
+
+/// Keeps a long-running SQLite database compact: checkpoints and truncates the WAL, refreshes
+/// the query planner's statistics with ANALYZE, and returns free pages to the filesystem
+pub struct DbMaintenance {
+    pool: SqlitePool,
+    /// None when APERIO_DATABASE_URL doesn't name a SQLite file, e.g. an in-memory database
+    db_path: Option<PathBuf>,
+    job_queue: Arc<JobQueue>,
+    /// Held for the length of a run so scheduled and manual runs don't overlap
+    running: Mutex<()>,
+    /// Waits between attempts of a step that found the database busy
+    retry: RetryConfig,
+}
+
+impl DbMaintenance {
+    pub fn new(pool: SqlitePool, database_url: &str, job_queue: Arc<JobQueue>) -> Self {
+        Self {
+            pool,
+            db_path: sqlite_file_path(database_url),
+            job_queue,
+            running: Mutex::new(()),
+            retry: RetryConfig {
+                max_attempts: 5,
+                max_delay: Duration::from_secs(10),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Run maintenance every `every`, each time once the queue is idle or after MAX_IDLE_WAIT
+    pub async fn start_scheduler(self: Arc<Self>, every: Duration) {
+        if self.db_path.is_none() {
+            info!("Database maintenance disabled: the database is not a SQLite file");
+            return;
+        }
+        info!("Starting database maintenance every {} seconds", every.as_secs());
+
+        let mut interval = interval(every);
+        // The first tick fires immediately, while startup traffic is still settling
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.wait_for_idle_queue().await;
+            let _run = self.running.lock().await;
+            if let Err(e) = self.run().await {
+                error!("Database maintenance failed: {}", e);
+            }
+        }
+    }
+
+    /// Run maintenance on request, refusing if a run is already in progress
+    pub async fn run_now(&self) -> AppResult<MaintenanceReport> {
+        let _run = self.running.try_lock()
+            .map_err(|_| AppError::Conflict("Database maintenance is already running".to_string()))?;
+        self.run().await
+    }
+
+    async fn wait_for_idle_queue(&self) {
+        let started = Instant::now();
+        loop {
+            let (queued, active) = self.job_queue.get_queue_info().await;
+            if queued + active == 0 {
+                return;
+            }
+            if started.elapsed() >= MAX_IDLE_WAIT {
+                warn!("Running database maintenance with {} queued and {} active jobs; the queue never went idle", queued, active);
+                return;
+            }
+            debug!("Deferring database maintenance: {} queued and {} active jobs", queued, active);
+            sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn run(&self) -> AppResult<MaintenanceReport> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(MaintenanceReport {
+                skipped: Some("The database is not a SQLite file".to_string()),
+                ..Default::default()
+            });
+        };
+
+        let started = Instant::now();
+        let before = file_sizes(db_path).await;
+        info!("Starting database maintenance: {} bytes in the database, {} in the WAL", before.database_bytes, before.wal_bytes);
+
+        self.checkpoint().await?;
+        self.execute("ANALYZE").await?;
+
+        let freelist_before = self.freelist_count().await?;
+        self.execute("PRAGMA incremental_vacuum").await?;
+        let pages_freed = freelist_before - self.freelist_count().await?;
+
+        // In WAL mode the vacuum's truncation only reaches the database file on checkpoint
+        let checkpoint_complete = self.checkpoint().await?;
+
+        let after = file_sizes(db_path).await;
+        let report = MaintenanceReport {
+            skipped: None,
+            before,
+            after,
+            bytes_reclaimed: before.total().saturating_sub(after.total()),
+            pages_freed,
+            checkpoint_complete,
+            duration_ms: started.elapsed().as_millis() as u64,
+        };
+        info!(
+            "Database maintenance finished in {} ms: {} bytes reclaimed, {} pages freed, {} bytes in the database, {} in the WAL",
+            report.duration_ms, report.bytes_reclaimed, report.pages_freed, after.database_bytes, after.wal_bytes
+        );
+        Ok(report)
+    }
+
+    /// Copy the WAL into the database and truncate it. Readers can keep it from finishing;
+    /// after retrying, that is reported rather than failing the run.
+    async fn checkpoint(&self) -> AppResult<bool> {
+        let result = retry_with_backoff(|| async {
+            let row = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                .fetch_one(&self.pool)
+                .await
+                .map_err(AppError::database("Failed to checkpoint the WAL"))?;
+            if row.get::<i64, _>(0) != 0 {
+                return Err(AppError::Conflict("WAL checkpoint blocked by active readers".to_string()));
+            }
+            Ok(())
+        }, &self.retry, "db_checkpoint").await;
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(AppError::Conflict(reason)) => {
+                warn!("WAL checkpoint incomplete: {}", reason);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Run a statement to completion, retrying while the database is busy
+    async fn execute(&self, sql: &'static str) -> AppResult<()> {
+        retry_with_backoff(|| async {
+            sqlx::query(sql)
+                .execute(&self.pool)
+                .await
+                .map(|_| ())
+                .map_err(AppError::database(format!("Failed to run {sql}")))
+        }, &self.retry, "db_maintenance").await
+    }
+
+    async fn freelist_count(&self) -> AppResult<i64> {
+        sqlx::query("PRAGMA freelist_count")
+            .fetch_one(&self.pool)
+            .await
+            .map(|row| row.get(0))
+            .map_err(AppError::database("Failed to read the freelist count"))
+    }
+}
+
+/// The file a SQLite URL names, or None for in-memory databases and other backends
+fn sqlite_file_path(database_url: &str) -> Option<PathBuf> {
+    let path = database_url.strip_prefix("sqlite://")
+        .or_else(|| database_url.strip_prefix("sqlite:"))?;
+    let (path, options) = path.split_once('?').unwrap_or((path, ""));
+    if path.is_empty() || path == ":memory:" || options.contains("mode=memory") {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+async fn file_sizes(db_path: &Path) -> DbFileSizes {
+    let size = |path: PathBuf| async move {
+        tokio::fs::metadata(&path).await.map(|metadata| metadata.len()).unwrap_or(0)
+    };
+    let mut wal_path = db_path.as_os_str().to_owned();
+    wal_path.push("-wal");
+    DbFileSizes {
+        database_bytes: size(db_path.to_path_buf()).await,
+        wal_bytes: size(PathBuf::from(wal_path)).await,
+    }
+}
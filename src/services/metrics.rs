@@ -1,9 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use tracing::info;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Bucket bounds of histograms recorded with `record_histogram`; every histogram also has a +Inf bucket
+const DEFAULT_BOUNDS: [f64; 9] = [1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+/// Bucket bounds for durations in seconds, the same as Prometheus client libraries use
+pub const SECONDS_BOUNDS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// The series of one metric, keyed by their labels as `series_key` writes them
+type Family<T> = BTreeMap<String, T>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricPoint {
@@ -33,19 +43,69 @@ pub struct Histogram {
     pub labels: HashMap<String, String>,
 }
 
+impl Histogram {
+    fn new(bounds: &[f64], labels: HashMap<String, String>) -> Self {
+        Self {
+            buckets: bounds.iter().chain([f64::INFINITY].iter()).map(|bound| (*bound, 0)).collect(),
+            sum: 0.0,
+            count: 0,
+            labels,
+        }
+    }
+
+    /// The finite bucket bounds, leaving out the +Inf bucket every histogram has
+    fn bounds(&self) -> Vec<f64> {
+        self.buckets.iter().map(|(bound, _)| *bound).filter(|bound| bound.is_finite()).collect()
+    }
+}
+
+/// A histogram with its +Inf bound left out, since JSON can't hold infinity
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProcessingTimeMetrics {
-    pub download_duration_ms: Vec<f64>,
-    pub processing_duration_ms: Vec<f64>,
-    pub total_duration_ms: Vec<f64>,
-    pub success_rate: f64,
-    pub error_count_by_type: HashMap<String, u64>,
+pub struct HistogramSummary {
+    pub bounds: Vec<f64>,
+    /// One per bound, then the +Inf bucket
+    pub bucket_counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+    pub labels: HashMap<String, String>,
+}
+
+/// Counters and histograms saved across restarts, each metric's series in a list. Gauges
+/// describe the running process and the history is only a recent window, so neither is kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub counters: HashMap<String, Vec<Counter>>,
+    pub histograms: HashMap<String, Vec<HistogramSummary>>,
+}
+
+/// Which metrics a JSON request wants; unset fields match every metric
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MetricsFilter {
+    /// Only metrics whose name starts with this, e.g. `aperio_http_`
+    pub prefix: Option<String>,
+    /// Only the metric with exactly this name, with all its series
+    pub name: Option<String>,
 }
 
+impl MetricsFilter {
+    fn matches(&self, name: &str) -> bool {
+        self.prefix.as_deref().is_none_or(|prefix| name.starts_with(prefix))
+            && self.name.as_deref().is_none_or(|wanted| name == wanted)
+    }
+}
+
+/// How many series a reset cleared
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MetricsReset {
+    pub counters_cleared: usize,
+    pub histograms_cleared: usize,
+}
+
+/// Metrics by name, each with one series per distinct set of labels
 pub struct MetricsRegistry {
-    counters: Arc<RwLock<HashMap<String, Counter>>>,
-    gauges: Arc<RwLock<HashMap<String, Gauge>>>,
-    histograms: Arc<RwLock<HashMap<String, Histogram>>>,
+    counters: Arc<RwLock<HashMap<String, Family<Counter>>>>,
+    gauges: Arc<RwLock<HashMap<String, Family<Gauge>>>>,
+    histograms: Arc<RwLock<HashMap<String, Family<Histogram>>>>,
     metrics_history: Arc<RwLock<Vec<MetricPoint>>>,
 }
 
@@ -61,41 +121,42 @@ impl MetricsRegistry {
 
     /// Increment a counter metric
     pub async fn increment_counter(&self, name: &str, labels: HashMap<String, String>) {
+        self.add_to_counter(name, 1, labels).await;
+    }
+
+    /// Increase a counter metric by an arbitrary amount, e.g. bytes
+    pub async fn add_to_counter(&self, name: &str, amount: u64, labels: HashMap<String, String>) {
         let mut counters = self.counters.write().await;
-        let counter = counters.entry(name.to_string()).or_insert(Counter {
-            value: 0,
-            labels: labels.clone(),
-        });
-        counter.value += 1;
-        
+        let counter = counters.entry(name.to_string()).or_default()
+            .entry(series_key(&labels))
+            .or_insert_with(|| Counter { value: 0, labels: labels.clone() });
+        counter.value += amount;
+
         self.record_metric_point(name, counter.value as f64, labels).await;
     }
 
     /// Set a gauge metric value
     pub async fn set_gauge(&self, name: &str, value: f64, labels: HashMap<String, String>) {
         let mut gauges = self.gauges.write().await;
-        gauges.insert(name.to_string(), Gauge {
+        gauges.entry(name.to_string()).or_default().insert(series_key(&labels), Gauge {
             value,
             labels: labels.clone(),
         });
-        
+
         self.record_metric_point(name, value, labels).await;
     }
 
     /// Record a histogram value
     pub async fn record_histogram(&self, name: &str, value: f64, labels: HashMap<String, String>) {
+        self.record_histogram_with_bounds(name, value, labels, &DEFAULT_BOUNDS).await;
+    }
+
+    /// Record a histogram value, with the given bucket bounds if this is the series' first value
+    pub async fn record_histogram_with_bounds(&self, name: &str, value: f64, labels: HashMap<String, String>, bounds: &[f64]) {
         let mut histograms = self.histograms.write().await;
-        let histogram = histograms.entry(name.to_string()).or_insert_with(|| {
-            Histogram {
-                buckets: vec![
-                    (1.0, 0), (5.0, 0), (10.0, 0), (25.0, 0), (50.0, 0),
-                    (100.0, 0), (250.0, 0), (500.0, 0), (1000.0, 0), (f64::INFINITY, 0)
-                ],
-                sum: 0.0,
-                count: 0,
-                labels: labels.clone(),
-            }
-        });
+        let histogram = histograms.entry(name.to_string()).or_default()
+            .entry(series_key(&labels))
+            .or_insert_with(|| Histogram::new(bounds, labels.clone()));
 
         histogram.sum += value;
         histogram.count += 1;
@@ -126,67 +187,139 @@ impl MetricsRegistry {
         }
     }
 
-    /// Get all current metrics as Prometheus format
+    /// Get all current metrics in the Prometheus text exposition format, families sorted by name
     pub async fn get_prometheus_format(&self) -> String {
         let mut output = String::new();
-        
+
         // Counters
         let counters = self.counters.read().await;
-        for (name, counter) in counters.iter() {
-            output.push_str(&format!("# TYPE {name} counter\n"));
-            let labels_str = self.format_labels(&counter.labels);
-            output.push_str(&format!("{}{{{}}} {}\n", name, labels_str, counter.value));
+        for (name, family) in counters.iter().collect::<BTreeMap<_, _>>() {
+            let name = sanitize_metric_name(name);
+            write_family_header(&mut output, &name, "counter");
+            for counter in family.values() {
+                output.push_str(&format!("{}{} {}\n", name, format_labels(&counter.labels, None), counter.value));
+            }
         }
 
         // Gauges
         let gauges = self.gauges.read().await;
-        for (name, gauge) in gauges.iter() {
-            output.push_str(&format!("# TYPE {name} gauge\n"));
-            let labels_str = self.format_labels(&gauge.labels);
-            output.push_str(&format!("{}{{{}}} {}\n", name, labels_str, gauge.value));
+        for (name, family) in gauges.iter().collect::<BTreeMap<_, _>>() {
+            let name = sanitize_metric_name(name);
+            write_family_header(&mut output, &name, "gauge");
+            for gauge in family.values() {
+                output.push_str(&format!("{}{} {}\n", name, format_labels(&gauge.labels, None), format_value(gauge.value)));
+            }
         }
 
         // Histograms
         let histograms = self.histograms.read().await;
-        for (name, histogram) in histograms.iter() {
-            output.push_str(&format!("# TYPE {name} histogram\n"));
-            let labels_str = self.format_labels(&histogram.labels);
-            
-            // Buckets
-            for (upper_bound, count) in &histogram.buckets {
-                let bucket_label = if upper_bound.is_infinite() {
-                    "+Inf".to_string()
-                } else {
-                    upper_bound.to_string()
-                };
-                let label_prefix = if labels_str.is_empty() { 
-                    String::new() 
-                } else { 
-                    format!("{labels_str},") 
-                };
-                output.push_str(&format!(
-                    "{name}_bucket{{{label_prefix}le=\"{bucket_label}\"}} {count}\n"
-                ));
+        for (name, family) in histograms.iter().collect::<BTreeMap<_, _>>() {
+            let name = sanitize_metric_name(name);
+            write_family_header(&mut output, &name, "histogram");
+
+            for histogram in family.values() {
+                // Each bucket already counts every value at or below its bound, as the format expects
+                for (upper_bound, count) in &histogram.buckets {
+                    let le = format_value(*upper_bound);
+                    output.push_str(&format!(
+                        "{}_bucket{} {}\n", name, format_labels(&histogram.labels, Some(&le)), count
+                    ));
+                }
+
+                let labels_str = format_labels(&histogram.labels, None);
+                output.push_str(&format!("{}_sum{} {}\n", name, labels_str, format_value(histogram.sum)));
+                output.push_str(&format!("{}_count{} {}\n", name, labels_str, histogram.count));
             }
-            
-            // Sum and count
-            output.push_str(&format!("{}_sum{{{}}} {}\n", name, labels_str, histogram.sum));
-            output.push_str(&format!("{}_count{{{}}} {}\n", name, labels_str, histogram.count));
         }
 
         output
     }
 
-    /// Get metrics in JSON format
-    pub async fn get_json_format(&self) -> serde_json::Value {
+    /// Current value of a counter summed over its series, or 0 before it was first incremented
+    pub async fn counter_value(&self, name: &str) -> u64 {
+        self.counters.read().await.get(name)
+            .map_or(0, |family| family.values().map(|counter| counter.value).sum())
+    }
+
+    /// Get the metrics `filter` matches in JSON format, keyed by metric name, with labels
+    /// appended as in the Prometheus format for series that have any
+    pub async fn get_json_format(&self, filter: &MetricsFilter) -> serde_json::Value {
         serde_json::json!({
-            "counters": *self.counters.read().await,
-            "gauges": *self.gauges.read().await,
-            "histograms": *self.histograms.read().await,
+            "counters": flatten_series(&*self.counters.read().await, filter, |counter| &counter.labels),
+            "gauges": flatten_series(&*self.gauges.read().await, filter, |gauge| &gauge.labels),
+            "histograms": flatten_series(&*self.histograms.read().await, filter, |histogram| &histogram.labels),
             "timestamp": Utc::now()
         })
     }
 
+    /// Clear every counter and histogram, e.g. between test runs. Gauges describe the current
+    /// state and are left alone, as is the history.
+    pub async fn reset(&self) -> MetricsReset {
+        let mut counters = self.counters.write().await;
+        let mut histograms = self.histograms.write().await;
+        let reset = MetricsReset {
+            counters_cleared: counters.values().map(|family| family.len()).sum(),
+            histograms_cleared: histograms.values().map(|family| family.len()).sum(),
+        };
+        counters.clear();
+        histograms.clear();
+        reset
+    }
+
+    /// Copy the counters and histogram summaries for saving
+    pub async fn snapshot(&self) -> MetricsSnapshot {
+        let counters = self.counters.read().await.iter()
+            .map(|(name, family)| (name.clone(), family.values().cloned().collect()))
+            .collect();
+        let histograms = self.histograms.read().await.iter()
+            .map(|(name, family)| (name.clone(), family.values().map(|histogram| HistogramSummary {
+                bounds: histogram.bounds(),
+                bucket_counts: histogram.buckets.iter().map(|(_, count)| *count).collect(),
+                sum: histogram.sum,
+                count: histogram.count,
+                labels: histogram.labels.clone(),
+            }).collect()))
+            .collect();
+        MetricsSnapshot { counters, histograms }
+    }
+
+    /// Add a saved snapshot onto the current values, so counters resume where the last run stopped
+    pub async fn restore(&self, snapshot: MetricsSnapshot) {
+        let mut counters = self.counters.write().await;
+        for (name, series) in snapshot.counters {
+            let family = counters.entry(name).or_default();
+            for saved in series {
+                family.entry(series_key(&saved.labels))
+                    .and_modify(|counter| counter.value += saved.value)
+                    .or_insert(saved);
+            }
+        }
+        drop(counters);
+
+        let mut histograms = self.histograms.write().await;
+        for (name, series) in snapshot.histograms {
+            let family = histograms.entry(name.clone()).or_default();
+            for saved in series {
+                if saved.bucket_counts.len() != saved.bounds.len() + 1 {
+                    warn!("Not restoring histogram {}: saved with {} bounds but {} buckets",
+                          name, saved.bounds.len(), saved.bucket_counts.len());
+                    continue;
+                }
+                let histogram = family.entry(series_key(&saved.labels))
+                    .or_insert_with(|| Histogram::new(&saved.bounds, saved.labels.clone()));
+                if histogram.bounds() != saved.bounds {
+                    warn!("Not restoring histogram {}: its bucket bounds changed since it was saved", name);
+                    continue;
+                }
+                histogram.sum += saved.sum;
+                histogram.count += saved.count;
+                for ((_, count), saved_count) in histogram.buckets.iter_mut().zip(saved.bucket_counts) {
+                    *count += saved_count;
+                }
+            }
+        }
+    }
+
     /// Get recent metrics history
     pub async fn get_metrics_history(&self, limit: Option<usize>) -> Vec<MetricPoint> {
         let history = self.metrics_history.read().await;
@@ -197,16 +330,141 @@ impl MetricsRegistry {
             history.clone()
         }
     }
+}
 
-    fn format_labels(&self, labels: &HashMap<String, String>) -> String {
-        if labels.is_empty() {
-            return String::new();
-        }
-        
-        labels.iter()
-            .map(|(k, v)| format!("{k}=\"{v}\""))
-            .collect::<Vec<_>>()
-            .join(",")
+/// `# HELP` text of the metrics Aperio records; anything missing gets a generic line
+const HELP_TEXTS: &[(&str, &str)] = &[
+    ("aperio_admission_rejected_total", "Jobs refused because admitting them would exceed the storage quota"),
+    ("aperio_cleanup_bytes_freed_total", "Bytes freed by removing job files"),
+    ("aperio_cleanup_errors_total", "Job files that could not be removed"),
+    ("aperio_cleanup_files_removed_total", "Job files removed"),
+    ("aperio_client_quota_rejections_total", "Job submissions refused by an API key's quota"),
+    ("aperio_draining", "1 while the server is draining for a restart"),
+    ("aperio_evicted_jobs_total", "Jobs whose files were evicted to stay under the storage quota"),
+    ("aperio_http_request_duration_seconds", "Time spent serving HTTP requests by route template, in seconds"),
+    ("aperio_http_requests_in_flight", "HTTP requests being served"),
+    ("aperio_http_requests_shed_total", "HTTP requests refused by load shedding"),
+    ("aperio_http_requests_total", "HTTP requests served by route template, method and status class"),
+    ("aperio_job_duration_ms", "Time from a job's submission to its completion, including time queued, in milliseconds"),
+    ("aperio_job_errors_total", "Internal errors while running jobs"),
+    ("aperio_job_failures_total", "Failed jobs by failure reason"),
+    ("aperio_job_requests_refused_total", "Job submissions refused, e.g. while draining"),
+    ("aperio_job_requests_total", "Job submissions received"),
+    ("aperio_jobs_active", "1 while a job is being downloaded or processed"),
+    ("aperio_jobs_completed_total", "Jobs completed"),
+    ("aperio_jobs_created_total", "Jobs created"),
+    ("aperio_jobs_failed_total", "Jobs failed"),
+    ("aperio_jobs_processing_total", "Jobs that started processing"),
+    ("aperio_jobs_requeued_total", "Jobs put back in the queue"),
+    ("aperio_notifications_dropped_total", "Failure notifications dropped"),
+    ("aperio_notifications_failed_total", "Failure notifications that could not be delivered"),
+    ("aperio_notifications_sent_total", "Failure notifications delivered"),
+    ("aperio_orphan_bytes_swept_total", "Bytes freed by removing files that belong to no job"),
+    ("aperio_orphan_files_swept_total", "Files removed because they belong to no job"),
+    ("aperio_permit_wait_seconds", "Time spent waiting for a download or processing slot, in seconds"),
+    ("aperio_post_hook_runs_total", "Post-processing hook runs"),
+    ("aperio_process_child_processes", "Processes started by Aperio that are still running"),
+    ("aperio_process_open_fds", "File descriptors the Aperio process holds open"),
+    ("aperio_process_resident_memory_bytes", "Resident memory of the Aperio process, in bytes"),
+    ("aperio_processing_duration_ms", "Time spent processing a job's media, in milliseconds"),
+    ("aperio_publish_total", "Attempts to publish job outputs"),
+    ("aperio_rate_limited_total", "Downloads rate limited by the source"),
+    ("aperio_request_duration_ms", "Time spent handling a job submission, in milliseconds"),
+    ("aperio_result_cache_hits_total", "Jobs completed from a cached result"),
+    ("aperio_retention_bytes_freed_total", "Bytes freed by retention"),
+    ("aperio_retention_errors_total", "Errors during retention cleanup cycles"),
+    ("aperio_retention_failures_total", "Retention cleanup cycles that stopped with an error"),
+    ("aperio_retention_files_expired_jobs_total", "Jobs whose files were expired by retention"),
+    ("aperio_retention_files_removed_total", "Files removed by retention, including orphans"),
+    ("aperio_retention_jobs_deleted_total", "Job records deleted by retention"),
+    ("aperio_retention_last_duration_seconds", "How long the last retention cleanup cycle took, in seconds"),
+    ("aperio_retention_last_success_timestamp_seconds", "Unix time the last retention cleanup cycle finished without stopping"),
+    ("aperio_retention_runs_total", "Retention cleanup cycles run"),
+    ("aperio_retries_total", "Retried operations"),
+    ("aperio_storage_used_bytes", "Bytes held in the working and storage directories"),
+    ("aperio_subscription_entries_rejected_total", "Subscription feed entries refused"),
+    ("aperio_subscription_jobs_created_total", "Jobs created from subscription feeds"),
+    ("aperio_subscription_polls_total", "Subscription feed polls"),
+    ("aperio_tokio_alive_tasks", "Tasks alive on the main runtime"),
+    ("aperio_tokio_workers", "Worker threads of the main runtime"),
+];
+
+fn write_family_header(output: &mut String, name: &str, kind: &str) {
+    let help = HELP_TEXTS.iter()
+        .find(|(known, _)| *known == name)
+        .map_or_else(|| format!("Aperio metric {name}"), |(_, help)| help.to_string());
+    output.push_str(&format!("# HELP {} {}\n", name, help.replace('\\', "\\\\").replace('\n', "\\n")));
+    output.push_str(&format!("# TYPE {name} {kind}\n"));
+}
+
+/// Identifies a series within its metric: its labels, sorted and escaped
+fn series_key(labels: &HashMap<String, String>) -> String {
+    format_labels(labels, None)
+}
+
+/// Every series of every metric under one key each: the metric name, followed by the labels for
+/// series that have any
+fn flatten_series<T: Serialize>(
+    metrics: &HashMap<String, Family<T>>,
+    filter: &MetricsFilter,
+    labels: impl Fn(&T) -> &HashMap<String, String>,
+) -> BTreeMap<String, serde_json::Value> {
+    metrics.iter()
+        .filter(|(name, _)| filter.matches(name))
+        .flat_map(|(name, family)| family.values().map(move |series| (name, series)))
+        .map(|(name, series)| (format!("{}{}", name, format_labels(labels(series), None)), serde_json::json!(series)))
+        .collect()
+}
+
+/// Replace characters a metric name may not hold with `_`, so it matches `[a-zA-Z_:][a-zA-Z0-9_:]*`
+fn sanitize_metric_name(name: &str) -> String {
+    sanitize_name(name, true)
+}
+
+fn sanitize_name(name: &str, allow_colon: bool) -> String {
+    let mut sanitized: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':') { c } else { '_' })
+        .collect();
+    if sanitized.is_empty() || sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+/// Escape a label value as the exposition format requires: backslash, double quote and newline
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// `{k="v",...}` sorted by label name, with `le` last for histogram buckets, or nothing at all
+/// when there are no labels
+fn format_labels(labels: &HashMap<String, String>, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels.iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        // Label names may not hold colons, and a histogram's `le` is reserved for the bucket bound
+        .map(|(k, v)| (sanitize_name(k, false), v))
+        .filter(|(k, _)| le.is_none() || k != "le")
+        .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{le}\""));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+/// Float values as the format spells them, notably `+Inf` rather than Rust's `inf`
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
     }
 }
 
@@ -236,6 +494,13 @@ macro_rules! counter_inc {
     };
 }
 
+#[macro_export]
+macro_rules! counter_add {
+    ($name:expr, $amount:expr) => {
+        $crate::services::metrics::get_metrics().add_to_counter($name, $amount, std::collections::HashMap::new()).await
+    };
+}
+
 #[macro_export]
 macro_rules! gauge_set {
     ($name:expr, $value:expr) => {
@@ -262,4 +527,306 @@ macro_rules! histogram_record {
             $crate::services::metrics::get_metrics().record_histogram($name, $value, labels).await
         }
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    /// Label names and values in the order a sample line gives them
+    type Labels = Vec<(String, String)>;
+
+    /// One sample line of a scrape, label values unescaped
+    #[derive(Debug)]
+    struct Sample {
+        name: String,
+        labels: Labels,
+        value: f64,
+    }
+
+    #[derive(Debug)]
+    struct ParsedFamily {
+        name: String,
+        kind: String,
+        help: String,
+        samples: Vec<Sample>,
+    }
+
+    fn is_metric_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    fn is_label_name(name: &str) -> bool {
+        is_metric_name(name) && !name.contains(':')
+    }
+
+    fn parse_value(value: &str) -> Result<f64, String> {
+        match value {
+            "+Inf" => Ok(f64::INFINITY),
+            "-Inf" => Ok(f64::NEG_INFINITY),
+            "NaN" => Ok(f64::NAN),
+            // Rust would also take "inf" and "infinity", which the format doesn't
+            other if other.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)) => {
+                other.parse().map_err(|_| format!("bad value {other:?}"))
+            }
+            other => Err(format!("bad value {other:?}")),
+        }
+    }
+
+    /// `{name="value",...}` at the start of `line`: the labels and the rest of the line
+    fn parse_labels(line: &str) -> Result<(Labels, &str), String> {
+        let mut labels = Labels::new();
+        let mut rest = &line[1..];
+        loop {
+            if let Some(after) = rest.strip_prefix('}') {
+                return Ok((labels, after));
+            }
+            let (name, after) = rest.split_once("=\"").ok_or_else(|| format!("label without a value in {line:?}"))?;
+            if !is_label_name(name) {
+                return Err(format!("bad label name {name:?}"));
+            }
+            if labels.iter().any(|(seen, _)| seen == name) {
+                return Err(format!("label {name} repeated in {line:?}"));
+            }
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, '\\')) => value.push('\\'),
+                        Some((_, '"')) => value.push('"'),
+                        Some((_, 'n')) => value.push('\n'),
+                        other => return Err(format!("bad escape {other:?} in {line:?}")),
+                    },
+                    Some((i, '"')) => break i,
+                    Some((_, c)) => value.push(c),
+                    None => return Err(format!("unterminated label value in {line:?}")),
+                }
+            };
+            labels.push((name.to_string(), value));
+            rest = &after[end + 1..];
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+
+    /// Parse a scrape the way Prometheus reads the text exposition format, rejecting anything it
+    /// would reject: malformed lines, bad names or escapes, samples outside a declared family,
+    /// families declared twice, repeated series and histograms that don't add up
+    fn parse_exposition(text: &str) -> Result<Vec<ParsedFamily>, String> {
+        let mut families: Vec<ParsedFamily> = Vec::new();
+        let mut help: Option<(String, String)> = None;
+        if !text.is_empty() && !text.ends_with('\n') {
+            return Err("scrape doesn't end in a newline".to_string());
+        }
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                let (name, doc) = rest.split_once(' ').ok_or_else(|| format!("HELP without text: {line:?}"))?;
+                let mut unescaped = String::new();
+                let mut chars = doc.chars();
+                while let Some(c) = chars.next() {
+                    if c != '\\' {
+                        unescaped.push(c);
+                        continue;
+                    }
+                    match chars.next() {
+                        Some('\\') => unescaped.push('\\'),
+                        Some('n') => unescaped.push('\n'),
+                        other => return Err(format!("bad escape {other:?} in {line:?}")),
+                    }
+                }
+                help = Some((name.to_string(), unescaped));
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').ok_or_else(|| format!("TYPE without a type: {line:?}"))?;
+                if !is_metric_name(name) {
+                    return Err(format!("bad metric name {name:?}"));
+                }
+                if !["counter", "gauge", "histogram", "summary", "untyped"].contains(&kind) {
+                    return Err(format!("unknown type {kind:?}"));
+                }
+                if families.iter().any(|family| family.name == name) {
+                    return Err(format!("{name} declared twice"));
+                }
+                let help = match help.take() {
+                    Some((help_name, help)) if help_name == name => help,
+                    _ => return Err(format!("{name} has no HELP line")),
+                };
+                families.push(ParsedFamily { name: name.to_string(), kind: kind.to_string(), help, samples: Vec::new() });
+            } else if line.starts_with('#') || line.is_empty() {
+                continue;
+            } else {
+                let name_end = line.find(['{', ' ']).ok_or_else(|| format!("sample without a value: {line:?}"))?;
+                let name = &line[..name_end];
+                if !is_metric_name(name) {
+                    return Err(format!("bad metric name {name:?}"));
+                }
+                let (labels, rest) = if line[name_end..].starts_with('{') {
+                    parse_labels(&line[name_end..])?
+                } else {
+                    (Vec::new(), &line[name_end..])
+                };
+                let value = rest.strip_prefix(' ').ok_or_else(|| format!("no space before the value: {line:?}"))?;
+                let value = parse_value(value)?;
+
+                let family = families.last_mut().ok_or_else(|| format!("sample before any TYPE: {line:?}"))?;
+                let suffixes: &[&str] = if family.kind == "histogram" { &["_bucket", "_sum", "_count"] } else { &[""] };
+                if !suffixes.iter().any(|suffix| name.strip_suffix(suffix) == Some(family.name.as_str())) {
+                    return Err(format!("{name} sample in the {} family", family.name));
+                }
+                if family.samples.iter().any(|sample| sample.name == name && sample.labels == labels) {
+                    return Err(format!("series repeated: {line:?}"));
+                }
+                family.samples.push(Sample { name: name.to_string(), labels, value });
+            }
+        }
+
+        for family in families.iter().filter(|family| family.kind == "histogram") {
+            check_histogram(family)?;
+        }
+        Ok(families)
+    }
+
+    /// Every series of a histogram has cumulative buckets ending in +Inf, which equals its count
+    fn check_histogram(family: &ParsedFamily) -> Result<(), String> {
+        let without_le = |sample: &Sample| -> Labels {
+            sample.labels.iter().filter(|(name, _)| name != "le").cloned().collect()
+        };
+        let count_name = format!("{}_count", family.name);
+        for count in family.samples.iter().filter(|sample| sample.name == count_name) {
+            let buckets: Vec<&Sample> = family.samples.iter()
+                .filter(|sample| sample.name.ends_with("_bucket") && without_le(sample) == count.labels)
+                .collect();
+            let mut previous = (f64::NEG_INFINITY, 0.0);
+            for bucket in &buckets {
+                let le = bucket.labels.iter().find(|(name, _)| name == "le")
+                    .ok_or_else(|| format!("{} bucket without le", family.name))?;
+                let bound = parse_value(&le.1)?;
+                if bound <= previous.0 || bucket.value < previous.1 {
+                    return Err(format!("{} buckets aren't cumulative at le={}", family.name, le.1));
+                }
+                previous = (bound, bucket.value);
+            }
+            if previous.0 != f64::INFINITY || previous.1 != count.value {
+                return Err(format!("{} +Inf bucket doesn't match its count", family.name));
+            }
+            let sum_name = format!("{}_sum", family.name);
+            if !family.samples.iter().any(|sample| sample.name == sum_name && sample.labels == count.labels) {
+                return Err(format!("{} series without a sum", family.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn family<'a>(families: &'a [ParsedFamily], name: &str) -> &'a ParsedFamily {
+        families.iter().find(|family| family.name == name).unwrap_or_else(|| panic!("no {name} family"))
+    }
+
+    #[test]
+    fn parser_rejects_what_prometheus_rejects() {
+        let valid = "# HELP up Up\n# TYPE up gauge\nup{job=\"a\\\"b\"} 1\n";
+        assert!(parse_exposition(valid).is_ok());
+        for invalid in [
+            "# HELP up Up\n# TYPE up gauge\nup{job=\"a\"b\"} 1\n",
+            "# HELP up Up\n# TYPE up gauge\nup{job=\"a\\tb\"} 1\n",
+            "# HELP up Up\n# TYPE up gauge\nup{} inf\n",
+            "# HELP up-time Up\n# TYPE up-time gauge\nup-time 1\n",
+            "# TYPE up gauge\nup 1\n",
+            "# HELP up Up\n# TYPE up gauge\nup 1\nup 2\n",
+            "# HELP h H\n# TYPE h histogram\nh_bucket{le=\"1\"} 2\nh_bucket{le=\"+Inf\"} 1\nh_sum 1\nh_count 1\n",
+        ] {
+            assert!(parse_exposition(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn hostile_labels_and_names_still_make_a_valid_scrape() {
+        let hostile = [
+            "quote \" inside",
+            "back\\slash",
+            "new\nline",
+            "\\\"\n\\n",
+            "} 1\nfake_metric 2",
+            "ünïcödé ✓",
+            "",
+        ];
+        let metrics = MetricsRegistry::new();
+        for value in hostile {
+            metrics.increment_counter("aperio_errors_total", labels(&[("message", value)])).await;
+            metrics.set_gauge("aperio.host-load", 0.5, labels(&[("host", value), ("region:zone", "eu-1")])).await;
+            metrics.record_histogram_with_bounds("aperio_job_seconds", 0.3, labels(&[("url", value)]), &SECONDS_BOUNDS).await;
+        }
+        metrics.increment_counter("9_lives", HashMap::new()).await;
+        metrics.record_histogram("aperio_unlabelled", 7.0, HashMap::new()).await;
+        metrics.record_histogram("aperio_unlabelled", 700.0, HashMap::new()).await;
+
+        let scrape = metrics.get_prometheus_format().await;
+        let families = parse_exposition(&scrape).unwrap_or_else(|e| panic!("{e}\n{scrape}"));
+
+        let mut names: Vec<&str> = families.iter().map(|family| family.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["_9_lives", "aperio_errors_total", "aperio_host_load", "aperio_job_seconds", "aperio_unlabelled"]);
+        assert!(families.iter().all(|family| !family.help.is_empty()));
+
+        // Every label value reads back exactly as recorded
+        let mut messages: Vec<&str> = family(&families, "aperio_errors_total").samples.iter()
+            .map(|sample| sample.labels[0].1.as_str())
+            .collect();
+        messages.sort();
+        let mut expected = hostile.to_vec();
+        expected.sort();
+        assert_eq!(messages, expected);
+        let load = family(&families, "aperio_host_load");
+        assert_eq!(load.samples.len(), hostile.len());
+        assert!(load.samples.iter().all(|sample| sample.labels[1] == ("region_zone".to_string(), "eu-1".to_string())));
+        assert!(!scrape.contains("\nfake_metric"));
+
+        // Unlabelled histogram series carry no `{}`, and their buckets count every value below them
+        assert!(scrape.contains("\naperio_unlabelled_sum 707\n"), "{scrape}");
+        assert!(scrape.contains("\naperio_unlabelled_count 2\n"), "{scrape}");
+        assert!(!scrape.contains("{}"));
+        let buckets: Vec<f64> = family(&families, "aperio_unlabelled").samples.iter()
+            .filter(|sample| sample.name == "aperio_unlabelled_bucket")
+            .map(|sample| sample.value)
+            .collect();
+        assert_eq!(buckets, [0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 2.0, 2.0]);
+    }
+
+    #[tokio::test]
+    async fn snapshot_survives_json_and_restores_into_a_fresh_registry() {
+        let before = MetricsRegistry::new();
+        before.increment_counter("aperio_jobs_failed_total", labels(&[("phase", "download")])).await;
+        before.add_to_counter("aperio_downloaded_bytes_total", 1 << 40, HashMap::new()).await;
+        before.record_histogram_with_bounds("aperio_permit_wait_seconds", 0.2, labels(&[("pool", "download")]), &SECONDS_BOUNDS).await;
+        before.record_histogram_with_bounds("aperio_permit_wait_seconds", 30.0, labels(&[("pool", "download")]), &SECONDS_BOUNDS).await;
+
+        let json = serde_json::to_string(&before.snapshot().await).unwrap();
+        let after = MetricsRegistry::new();
+        after.restore(serde_json::from_str(&json).unwrap()).await;
+
+        assert_eq!(after.get_prometheus_format().await, before.get_prometheus_format().await);
+    }
+
+    #[tokio::test]
+    async fn histogram_with_changed_bounds_is_not_restored() {
+        let before = MetricsRegistry::new();
+        before.record_histogram("aperio_job_seconds", 3.0, HashMap::new()).await;
+        let snapshot = before.snapshot().await;
+
+        let after = MetricsRegistry::new();
+        after.record_histogram_with_bounds("aperio_job_seconds", 3.0, HashMap::new(), &SECONDS_BOUNDS).await;
+        after.restore(snapshot.clone()).await;
+        assert!(after.get_prometheus_format().await.contains("aperio_job_seconds_count 1\n"));
+
+        // Bucket counts that don't match the bounds are skipped too
+        let mut truncated = snapshot;
+        truncated.histograms.get_mut("aperio_job_seconds").unwrap()[0].bucket_counts.pop();
+        let fresh = MetricsRegistry::new();
+        fresh.restore(truncated).await;
+        assert!(!fresh.get_prometheus_format().await.contains("aperio_job_seconds_count"));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::{AppError, AppResult};
+use crate::services::metrics::{get_metrics, MetricsRegistry, MetricsSnapshot};
+use chrono::Utc;
+use sqlx::{Row, SqlitePool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+
+/// Saves the global metrics registry's counters and histograms to the database, so they
+/// survive restarts instead of dropping to zero with every deploy
+pub struct MetricsSnapshotStore {
+    pool: SqlitePool,
+    metrics: &'static MetricsRegistry,
+}
+
+impl MetricsSnapshotStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self::with_registry(pool, get_metrics())
+    }
+
+    fn with_registry(pool: SqlitePool, metrics: &'static MetricsRegistry) -> Self {
+        Self { pool, metrics }
+    }
+
+    /// Add the last saved snapshot to the registry. A snapshot that can't be read or parsed
+    /// is logged and skipped, so the metrics start fresh rather than the server not starting.
+    pub async fn restore(&self) {
+        let row = match sqlx::query("SELECT snapshot, saved_at FROM metrics_snapshot WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(row) => row,
+            Err(e) => {
+                warn!("Failed to read the metrics snapshot, starting with fresh metrics: {}", e);
+                return;
+            }
+        };
+        let Some(row) = row else {
+            info!("No metrics snapshot saved yet");
+            return;
+        };
+
+        let saved_at: String = row.try_get("saved_at").unwrap_or_default();
+        let snapshot: String = row.try_get("snapshot").unwrap_or_default();
+        match serde_json::from_str::<MetricsSnapshot>(&snapshot) {
+            Ok(snapshot) => {
+                info!(
+                    "Restoring {} counters and {} histograms from the metrics snapshot saved at {}",
+                    snapshot.counters.len(), snapshot.histograms.len(), saved_at
+                );
+                self.metrics.restore(snapshot).await;
+            }
+            Err(e) => warn!("Ignoring corrupted metrics snapshot saved at {}, starting with fresh metrics: {}", saved_at, e),
+        }
+    }
+
+    /// Replace the saved snapshot with the registry's current counters and histograms
+    pub async fn save(&self) -> AppResult<()> {
+        let snapshot = self.metrics.snapshot().await;
+        let json = serde_json::to_string(&snapshot)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize metrics snapshot: {e}")))?;
+
+        sqlx::query(
+            "INSERT INTO metrics_snapshot (id, snapshot, saved_at) VALUES (1, ?, ?) \
+             ON CONFLICT(id) DO UPDATE SET snapshot = excluded.snapshot, saved_at = excluded.saved_at",
+        )
+        .bind(&json)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to save metrics snapshot"))?;
+
+        debug!("Saved metrics snapshot: {} bytes", json.len());
+        Ok(())
+    }
+
+    /// Save a snapshot every `every`
+    pub async fn start_scheduler(self: Arc<Self>, every: Duration) {
+        info!("Saving metrics snapshots every {} seconds", every.as_secs());
+
+        let mut interval = interval(every);
+        // The first tick fires immediately, before anything new was counted
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.save().await {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    /// A store for the database in `dir` with a registry of its own, as a fresh process has
+    async fn start(dir: &Path) -> MetricsSnapshotStore {
+        let pool = crate::database::create_database_pool(&format!("sqlite://{}", dir.join("aperio.db").display()), Some(2))
+            .await
+            .unwrap();
+        crate::database::run_migrations(&pool).await.unwrap();
+        MetricsSnapshotStore::with_registry(pool, Box::leak(Box::new(MetricsRegistry::new())))
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn counters_and_histograms_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = start(dir.path()).await;
+        before.metrics.add_to_counter("aperio_jobs_completed_total", 41, HashMap::new()).await;
+        before.metrics.increment_counter("aperio_jobs_completed_total", HashMap::new()).await;
+        before.metrics.increment_counter("aperio_job_failures_total", labels(&[("reason", "network")])).await;
+        before.metrics.record_histogram("aperio_job_seconds", 7.0, HashMap::new()).await;
+        before.metrics.record_histogram("aperio_job_seconds", 70.0, HashMap::new()).await;
+        before.metrics.set_gauge("aperio_jobs_active", 3.0, HashMap::new()).await;
+        before.save().await.unwrap();
+        let scrape_before = before.metrics.get_prometheus_format().await;
+        before.pool.close().await;
+
+        let after = start(dir.path()).await;
+        after.restore().await;
+
+        assert_eq!(after.metrics.counter_value("aperio_jobs_completed_total").await, 42);
+        assert_eq!(after.metrics.counter_value("aperio_job_failures_total").await, 1);
+        let scrape_after = after.metrics.get_prometheus_format().await;
+        for line in ["aperio_job_failures_total{reason=\"network\"} 1", "aperio_job_seconds_bucket{le=\"10\"} 1", "aperio_job_seconds_bucket{le=\"100\"} 2", "aperio_job_seconds_sum 77", "aperio_job_seconds_count 2"] {
+            assert!(scrape_before.contains(line) && scrape_after.contains(line), "{line} missing from\n{scrape_after}");
+        }
+        // Gauges describe the process that set them and start over
+        assert!(!scrape_after.contains("aperio_jobs_active"), "{scrape_after}");
+    }
+
+    #[tokio::test]
+    async fn restored_values_add_to_what_was_counted_since_startup() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = start(dir.path()).await;
+        before.metrics.add_to_counter("aperio_jobs_completed_total", 10, HashMap::new()).await;
+        before.save().await.unwrap();
+        // Later saves replace the earlier one
+        before.metrics.add_to_counter("aperio_jobs_completed_total", 5, HashMap::new()).await;
+        before.save().await.unwrap();
+
+        let after = start(dir.path()).await;
+        after.metrics.increment_counter("aperio_jobs_completed_total", HashMap::new()).await;
+        after.restore().await;
+        assert_eq!(after.metrics.counter_value("aperio_jobs_completed_total").await, 16);
+    }
+
+    #[tokio::test]
+    async fn missing_or_corrupted_snapshot_starts_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = start(dir.path()).await;
+        store.restore().await;
+        assert_eq!(store.metrics.counter_value("aperio_jobs_completed_total").await, 0);
+
+        sqlx::query("INSERT INTO metrics_snapshot (id, snapshot, saved_at) VALUES (1, '{\"counters\": [', ?)")
+            .bind(Utc::now())
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        store.restore().await;
+        assert_eq!(store.metrics.snapshot().await.counters.len(), 0);
+
+        // The next save replaces it
+        store.metrics.increment_counter("aperio_jobs_completed_total", HashMap::new()).await;
+        store.save().await.unwrap();
+        let restarted = start(dir.path()).await;
+        restarted.restore().await;
+        assert_eq!(restarted.metrics.counter_value("aperio_jobs_completed_total").await, 1);
+    }
+}
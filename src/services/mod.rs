@@ -1,5 +1,7 @@
 pub mod download;
+pub mod downloader;
 pub mod process;
+pub mod processor;
 pub mod job_repository;
 pub mod cleanup;
 pub mod retry;
@@ -8,12 +10,46 @@ pub mod pool_manager;
 pub mod job_queue;
 pub mod retention;
 pub mod metrics;
+pub mod upload;
+pub mod result_cache;
+pub mod error_mapping;
+pub mod checksum;
+pub mod bundle;
+pub mod quota;
+pub mod storage;
+pub mod config_reload;
+pub mod audit;
+pub mod tls;
+pub mod notify;
+pub mod subscriptions;
+pub mod publish;
+pub mod hooks;
+pub mod nice;
+pub mod estimator;
+pub mod export;
+pub mod maintenance;
+pub mod metrics_snapshot;
+pub mod resources;
 
 pub use download::DownloadService;
+pub use downloader::Downloader;
 pub use process::ProcessService;
+pub use processor::Processor;
 pub use job_repository::JobRepository;
 pub use cleanup::CleanupService;
 pub use security::SecurityValidator;
 pub use pool_manager::ConnectionPoolManager;
 pub use job_queue::{JobQueue, JobPriority};
 pub use retention::RetentionService;
+pub use upload::UploadService;
+pub use result_cache::ResultCache;
+pub use quota::StorageQuota;
+pub use storage::StorageService;
+pub use config_reload::ConfigReloader;
+pub use audit::AuditLog;
+pub use notify::FailureAlerts;
+pub use subscriptions::SubscriptionService;
+pub use publish::PublishService;
+pub use estimator::Estimator;
+pub use maintenance::DbMaintenance;
+pub use metrics_snapshot::MetricsSnapshotStore;
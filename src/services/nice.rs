@@ -0,0 +1,21 @@
+use tokio::process::Command;
+
+/// Start the command at a lower CPU priority, so a long encode or download leaves room for the
+/// API and the database writer. `None` and 0 keep the server's own priority.
+pub fn lower_priority(command: &mut Command, nice: Option<u32>) {
+    let Some(nice) = nice.filter(|nice| *nice > 0) else {
+        return;
+    };
+
+    #[cfg(unix)]
+    // SAFETY: setpriority is async-signal-safe and only affects the forked child
+    unsafe {
+        command.pre_exec(move || {
+            // A child that can't be reniced still runs, at normal priority
+            let _ = libc::setpriority(libc::PRIO_PROCESS, 0, nice as libc::c_int);
+            Ok(())
+        });
+    }
+    #[cfg(not(unix))]
+    let _ = (command, nice);
+}
@@ -1,67 +1,150 @@
+use crate::error::{AppError, AppResult};
+use crate::{gauge_set, histogram_record};
+use serde::Serialize;
 use std::sync::Arc;
-use tokio::sync::Semaphore;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
 use tracing::debug;
+use utoipa::ToSchema;
 
 pub struct ConnectionPoolManager {
-    download_semaphore: Arc<Semaphore>,
-    processing_semaphore: Arc<Semaphore>,
-    max_downloads: usize,
-    max_processing: usize,
+    download_slots: SlotPool,
+    processing_slots: SlotPool,
+    /// Longest a job waits for a slot before giving up with `AppError::NoCapacity`
+    slot_wait_timeout: Duration,
+}
+
+/// One kind of slot, e.g. downloads, and the gauge reporting how many are taken
+#[derive(Clone)]
+struct SlotPool {
+    semaphore: Arc<Semaphore>,
+    total: usize,
+    name: &'static str,
+    in_use_gauge: &'static str,
+}
+
+impl SlotPool {
+    fn new(total: usize, name: &'static str, in_use_gauge: &'static str) -> Self {
+        Self { semaphore: Arc::new(Semaphore::new(total)), total, name, in_use_gauge }
+    }
+
+    fn stats(&self) -> PoolStats {
+        let available = self.semaphore.available_permits();
+        PoolStats { available, in_use: self.total.saturating_sub(available), total: self.total }
+    }
+
+    async fn publish_in_use(&self) {
+        gauge_set!(self.in_use_gauge, self.stats().in_use as f64);
+    }
+}
+
+/// A held download or processing slot; the in-use gauge is updated when it is dropped
+pub struct SlotPermit {
+    _permit: OwnedSemaphorePermit,
+    pool: SlotPool,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        let pool = self.pool.clone();
+        // Read the count once the permit is back in the semaphore
+        tokio::spawn(async move { pool.publish_in_use().await });
+    }
 }
 
 impl ConnectionPoolManager {
-    pub fn new(max_concurrent_downloads: usize, max_concurrent_processing: usize) -> Self {
+    pub fn new(max_concurrent_downloads: usize, max_concurrent_processing: usize, slot_wait_timeout: Duration) -> Self {
         debug!("Initializing connection pool manager with {} download slots and {} processing slots", 
                max_concurrent_downloads, max_concurrent_processing);
         
         Self {
-            download_semaphore: Arc::new(Semaphore::new(max_concurrent_downloads)),
-            processing_semaphore: Arc::new(Semaphore::new(max_concurrent_processing)),
-            max_downloads: max_concurrent_downloads,
-            max_processing: max_concurrent_processing,
+            download_slots: SlotPool::new(max_concurrent_downloads, "download", "aperio_download_slots_in_use"),
+            processing_slots: SlotPool::new(max_concurrent_processing, "processing", "aperio_processing_slots_in_use"),
+            slot_wait_timeout,
         }
     }
 
-    pub async fn acquire_download_permit(&self) -> Result<tokio::sync::SemaphorePermit<'_>, tokio::sync::AcquireError> {
-        debug!("Acquiring download permit. Available: {}", self.download_semaphore.available_permits());
-        let permit = self.download_semaphore.acquire().await?;
-        debug!("Download permit acquired. Remaining: {}", self.download_semaphore.available_permits());
-        Ok(permit)
+    pub async fn acquire_download_permit(&self) -> AppResult<SlotPermit> {
+        self.acquire(&self.download_slots).await
     }
 
-    pub async fn acquire_processing_permit(&self) -> Result<tokio::sync::SemaphorePermit<'_>, tokio::sync::AcquireError> {
-        debug!("Acquiring processing permit. Available: {}", self.processing_semaphore.available_permits());
-        let permit = self.processing_semaphore.acquire().await?;
-        debug!("Processing permit acquired. Remaining: {}", self.processing_semaphore.available_permits());
-        Ok(permit)
+    pub async fn acquire_processing_permit(&self) -> AppResult<SlotPermit> {
+        self.acquire(&self.processing_slots).await
     }
 
-    #[allow(dead_code)]
-    pub fn get_download_stats(&self) -> PoolStats {
-        PoolStats {
-            available: self.download_semaphore.available_permits(),
-            total: self.max_downloads,
+    /// Wait up to the slot wait timeout for a permit, recording how long the wait took
+    async fn acquire(&self, pool: &SlotPool) -> AppResult<SlotPermit> {
+        debug!("Acquiring {} permit. Available: {}", pool.name, pool.semaphore.available_permits());
+        let started = Instant::now();
+        let result = timeout(self.slot_wait_timeout, pool.semaphore.clone().acquire_owned()).await;
+        histogram_record!("aperio_permit_wait_seconds", started.elapsed().as_secs_f64(), "pool" => pool.name);
+
+        match result {
+            Ok(Ok(permit)) => {
+                debug!("{} permit acquired. Remaining: {}", pool.name, pool.semaphore.available_permits());
+                pool.publish_in_use().await;
+                Ok(SlotPermit { _permit: permit, pool: pool.clone() })
+            }
+            Ok(Err(e)) => Err(AppError::Internal(format!("Failed to acquire {} permit: {e}", pool.name))),
+            Err(_) => Err(AppError::NoCapacity { pool: pool.name, waited: self.slot_wait_timeout }),
         }
     }
 
-    #[allow(dead_code)]
+    pub fn get_download_stats(&self) -> PoolStats {
+        self.download_slots.stats()
+    }
+
     pub fn get_processing_stats(&self) -> PoolStats {
-        PoolStats {
-            available: self.processing_semaphore.available_permits(),
-            total: self.max_processing,
-        }
+        self.processing_slots.stats()
     }
 }
 
-#[derive(Debug, Clone)]
+/// How many slots of one kind are taken
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct PoolStats {
     pub available: usize,
+    pub in_use: usize,
     pub total: usize,
 }
 
-impl PoolStats {
-    #[allow(dead_code)]
-    pub fn active(&self) -> usize {
-        self.total.saturating_sub(self.available)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pools(slot_wait_timeout: Duration) -> ConnectionPoolManager {
+        ConnectionPoolManager::new(1, 1, slot_wait_timeout)
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn exhausted_pool_times_out_with_no_capacity() {
+        let pools = pools(Duration::from_millis(50));
+        let _held = pools.acquire_processing_permit().await.unwrap();
+
+        let started = Instant::now();
+        let error = pools.acquire_processing_permit().await.err().expect("the only slot is held");
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(matches!(error, AppError::NoCapacity { pool: "processing", waited } if waited == Duration::from_millis(50)));
+
+        // The pools are separate
+        assert!(pools.acquire_download_permit().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn released_slot_goes_to_the_waiter() {
+        let pools = Arc::new(pools(Duration::from_secs(5)));
+        let held = pools.acquire_download_permit().await.unwrap();
+        assert_eq!(pools.get_download_stats().in_use, 1);
+
+        let waiter = tokio::spawn({
+            let pools = pools.clone();
+            async move { pools.acquire_download_permit().await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        drop(held);
+        waiter.await.unwrap().unwrap();
+        assert_eq!(pools.get_download_stats().available, 1);
+    }
+}
@@ -1,15 +1,341 @@
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::process::Command;
 use tokio::time::timeout;
-use tracing::info;
-use crate::config::ProcessingConfig;
-use crate::error::{AppError, AppResult};
-use crate::models::job::Job;
+use tracing::{info, warn};
+use crate::config::{ProcessingConfig, WatermarkConfig, WatermarkPosition};
+use crate::error::{AppError, AppResult, Phase};
+use crate::models::job::{AudioStreamInfo, AudioTrack, CropOptions, Job, LoudnessMeasurement, SubtitleMode, TonemapMode};
 use crate::services::ConnectionPoolManager;
+use crate::services::error_mapping::processing_failure;
+use crate::services::nice::lower_priority;
+use crate::services::security::job_working_dir;
+
+/// Suffix for outputs that are still being written; renamed away only after a successful encode
+pub const PARTIAL_OUTPUT_SUFFIX: &str = ".part";
+
+/// Largest source that is stream-copied instead of re-encoded
+const STREAM_COPY_MAX_WIDTH: u64 = 1920;
+const STREAM_COPY_MAX_HEIGHT: u64 = 1080;
+
+/// Highest H.264 level we emit when encoding, as reported by ffprobe (4.0)
+const STREAM_COPY_MAX_H264_LEVEL: i64 = 40;
+
+/// Transfer characteristics, as named by ffprobe, that mark a source as HDR
+const HDR_TRANSFERS: [&str; 2] = ["smpte2084", "arib-std-b67"];
+
+/// Properties of a source's first video stream (and its audio streams) as reported by ffprobe
+#[derive(Debug, Clone, Default)]
+pub struct VideoStreamInfo {
+    pub frame_rate: Option<f64>,
+    pub codec_name: Option<String>,
+    pub profile: Option<String>,
+    pub level: Option<i64>,
+    pub pix_fmt: Option<String>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub audio_codec: Option<String>,
+    pub audio_streams: Vec<AudioStreamInfo>,
+    pub color_transfer: Option<String>,
+    pub color_primaries: Option<String>,
+    pub color_space: Option<String>,
+}
+
+impl VideoStreamInfo {
+    /// Whether the stream is tagged with an HDR transfer (PQ or HLG)
+    pub fn is_hdr(&self) -> bool {
+        self.color_transfer.as_deref().is_some_and(|transfer| HDR_TRANSFERS.contains(&transfer))
+    }
+}
+
+/// Find the requested audio track among the source's audio streams
+fn select_audio_track(track: &AudioTrack, streams: &[AudioStreamInfo]) -> AppResult<AudioStreamInfo> {
+    let selected = match track {
+        AudioTrack::Index(index) => streams.iter().find(|stream| stream.index == *index),
+        AudioTrack::Language(language) => streams.iter().find(|stream| {
+            stream.language.as_deref().is_some_and(|tag| tag.eq_ignore_ascii_case(language))
+        }),
+    };
+
+    selected.cloned().ok_or_else(|| {
+        let available = if streams.is_empty() {
+            "the source has no audio streams".to_string()
+        } else {
+            format!(
+                "available tracks: {}",
+                streams.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+            )
+        };
+        AppError::Processing(format!("Audio {track} not found; {available}"))
+    })
+}
+
+/// Check a watermark file is a PNG, JPEG or WebP image
+fn check_watermark(watermark: &WatermarkConfig) -> AppResult<()> {
+    let mut header = [0u8; 12];
+    let mut file = std::fs::File::open(&watermark.path).map_err(|e| AppError::BadRequest(format!(
+        "Watermark {} cannot be opened: {e}",
+        watermark.path.display()
+    )))?;
+    std::io::Read::read_exact(&mut file, &mut header).map_err(|_| AppError::BadRequest(format!(
+        "Watermark {} is too small to be an image",
+        watermark.path.display()
+    )))?;
+
+    let is_image = header.starts_with(b"\x89PNG\r\n\x1a\n")
+        || header.starts_with(&[0xFF, 0xD8, 0xFF])
+        || (header.starts_with(b"RIFF") && &header[8..12] == b"WEBP");
+    if !is_image {
+        return Err(AppError::BadRequest(format!(
+            "Watermark {} is not a PNG, JPEG or WebP image",
+            watermark.path.display()
+        )));
+    }
+
+    info!("Watermark enabled: {}", watermark.path.display());
+    Ok(())
+}
+
+/// A window of the source frame, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u64,
+    pub y: u64,
+    pub width: u64,
+    pub height: u64,
+}
+
+/// Resolve a job's crop against a `width`x`height` source; Err says why it doesn't fit
+pub fn crop_rect(crop: &CropOptions, width: u64, height: u64) -> Result<CropRect, String> {
+    if let Some(preset) = crop.preset {
+        // The largest window of the preset's ratio, placed along the side that is cut
+        let (ratio_width, ratio_height) = preset.aspect_ratio();
+        let focus = crop.focus.unwrap_or(0.5);
+        let rect = if width * ratio_height > height * ratio_width {
+            let crop_width = (height * ratio_width / ratio_height) & !1;
+            CropRect { x: ((width - crop_width) as f64 * focus).round() as u64, y: 0, width: crop_width, height }
+        } else {
+            let crop_height = (width * ratio_height / ratio_width) & !1;
+            CropRect { x: 0, y: ((height - crop_height) as f64 * focus).round() as u64, width, height: crop_height }
+        };
+        if rect.width < 2 || rect.height < 2 {
+            return Err(format!("a {width}x{height} source is too small to crop"));
+        }
+        return Ok(rect);
+    }
+
+    let rect = CropRect {
+        x: crop.x.unwrap_or(0) as u64,
+        y: crop.y.unwrap_or(0) as u64,
+        width: crop.width.unwrap_or(0) as u64,
+        height: crop.height.unwrap_or(0) as u64,
+    };
+    if rect.x + rect.width > width || rect.y + rect.height > height {
+        return Err(format!(
+            "crop {}x{} at {},{} doesn't fit within the {width}x{height} source",
+            rect.width, rect.height, rect.x, rect.y
+        ));
+    }
+    Ok(rect)
+}
+
+/// The ffmpeg filter that converts HDR to SDR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemapFilter {
+    /// zscale linearizes the picture so tonemap can compress it, then converts to bt709
+    Zscale,
+    /// One libplacebo pass, for builds without zimg
+    Libplacebo,
+}
+
+/// HDR to SDR conversion of a source with the given transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tonemap {
+    pub filter: TonemapFilter,
+    pub transfer: String,
+}
+
+/// One of the sources `concat_filter` joins; `duration` is set when it has no audio of its own
+struct ConcatSource {
+    stream: VideoStreamInfo,
+    duration: Option<f64>,
+}
+
+/// Sources joined into `[joined]` video and, when `audio` is set, `[joined_a]` audio
+struct ConcatGraph {
+    graph: String,
+    audio: bool,
+}
+
+/// The concat filter needs uniform streams, so every source is scaled and padded to the first
+/// one's frame size and rate. Audio is joined when it is wanted and any source has some; a
+/// source without audio contributes silence.
+fn concat_filter(sources: &[ConcatSource], audio: bool) -> AppResult<ConcatGraph> {
+    let Some((width, height)) = sources.first().and_then(|first| first.stream.width.zip(first.stream.height)) else {
+        return Err(AppError::Processing("The first source has no frame size to join the others at".to_string()));
+    };
+    // yuv420p needs even dimensions
+    let (width, height) = (width & !1, height & !1);
+    let fps = sources.first().and_then(|first| first.stream.frame_rate).unwrap_or(30.0);
+    let audio = audio && sources.iter().any(|source| source.stream.audio_codec.is_some());
+
+    let mut graph = String::new();
+    let mut segments = String::new();
+    for (index, source) in sources.iter().enumerate() {
+        graph.push_str(&format!(
+            "[{index}:v:0]scale={width}:{height}:force_original_aspect_ratio=decrease,\
+             pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps},format=yuv420p[v{index}];"
+        ));
+        segments.push_str(&format!("[v{index}]"));
+        if audio {
+            match source.stream.audio_codec {
+                Some(_) => graph.push_str(&format!("[{index}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{index}];")),
+                None => graph.push_str(&format!(
+                    "anullsrc=r=48000:cl=stereo,atrim=duration={:.3}[a{index}];", source.duration.unwrap_or_default()
+                )),
+            }
+            segments.push_str(&format!("[a{index}]"));
+        }
+    }
+    graph.push_str(&format!(
+        "{segments}concat=n={}:v=1:a={}[joined]{}",
+        sources.len(), u8::from(audio), if audio { "[joined_a]" } else { "" }
+    ));
+    Ok(ConcatGraph { graph, audio })
+}
+
+/// Assembles the `-vf` chain from the enabled components so options don't clobber each other
+#[derive(Debug, Clone, Default)]
+pub struct VideoFilterBuilder {
+    crop: Option<CropRect>,
+    tonemap: Option<Tonemap>,
+    speed: Option<f64>,
+    fps: Option<u32>,
+    subtitles: Option<PathBuf>,
+    watermark: Option<WatermarkConfig>,
+}
+
+impl VideoFilterBuilder {
+    pub fn crop(mut self, rect: CropRect) -> Self {
+        self.crop = Some(rect);
+        self
+    }
+
+    pub fn tonemap(mut self, tonemap: Tonemap) -> Self {
+        self.tonemap = Some(tonemap);
+        self
+    }
+
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+
+    pub fn subtitles(mut self, path: &Path) -> Self {
+        self.subtitles = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn watermark(mut self, watermark: &WatermarkConfig) -> Self {
+        self.watermark = Some(watermark.clone());
+        self
+    }
+
+    pub fn build(&self) -> String {
+        match &self.watermark {
+            None => self.chain(),
+            Some(_) => self.graph("in", "out"),
+        }
+    }
+
+    /// The same filters as a `-filter_complex` fragment that reads the `input` label and writes
+    /// the `output` one
+    pub fn graph(&self, input: &str, output: &str) -> String {
+        let Some(watermark) = &self.watermark else {
+            return format!("[{input}]{}[{output}]", self.chain());
+        };
+
+        // The logo is loaded with movie= so the chain needs no extra inputs; W/H in the overlay
+        // expression are the scaled output dimensions
+        let margin = watermark.margin;
+        let (x, y) = match watermark.position {
+            WatermarkPosition::TopLeft => (margin.to_string(), margin.to_string()),
+            WatermarkPosition::TopRight => (format!("W-w-{margin}"), margin.to_string()),
+            WatermarkPosition::BottomLeft => (margin.to_string(), format!("H-h-{margin}")),
+            WatermarkPosition::BottomRight => (format!("W-w-{margin}"), format!("H-h-{margin}")),
+        };
+        let mut overlay = vec![format!("overlay={x}:{y}")];
+        overlay.extend(self.subtitles_filter());
+
+        format!(
+            "movie={},format=rgba,colorchannelmixer=aa={}[watermark];[{input}]{}[base];[base][watermark]{}[{output}]",
+            escape_filter_value(&watermark.path.to_string_lossy()),
+            watermark.opacity,
+            self.filters().join(","),
+            overlay.join(","),
+        )
+    }
+
+    /// The filters without a watermark, as a single chain
+    fn chain(&self) -> String {
+        self.filters().into_iter().chain(self.subtitles_filter()).collect::<Vec<_>>().join(",")
+    }
+
+    /// Everything before the watermark and subtitles, in the order it is applied
+    fn filters(&self) -> Vec<String> {
+        // Cropping comes first so everything after it works on the window that is kept
+        let mut filters: Vec<String> = self.crop
+            .map(|rect| format!("crop={}:{}:{}:{}", rect.width, rect.height, rect.x, rect.y))
+            .into_iter()
+            .collect();
+
+        // Tone-mapped before scaling so everything downstream sees bt709 SDR
+        if let Some(tonemap) = &self.tonemap {
+            filters.push(match tonemap.filter {
+                TonemapFilter::Zscale => format!(
+                    "zscale=tin={}:t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+                     tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+                    tonemap.transfer
+                ),
+                TonemapFilter::Libplacebo => "libplacebo=tonemapping=hable:colorspace=bt709:\
+                     color_primaries=bt709:color_trc=bt709:range=tv:format=yuv420p".to_string(),
+            });
+        }
+
+        // Even dimensions are required by yuv420p, so scaling is always present
+        filters.push("scale=trunc(iw/2)*2:trunc(ih/2)*2".to_string());
+
+        // Retimed before the rate cap, so the cap applies to the output's frame rate
+        if let Some(speed) = self.speed {
+            filters.push(format!("setpts=PTS/{speed}"));
+        }
+
+        if let Some(fps) = self.fps {
+            filters.push(format!("fps={fps}"));
+        }
+
+        filters
+    }
+
+    /// Subtitles go last so they're rendered at the output resolution
+    fn subtitles_filter(&self) -> Option<String> {
+        self.subtitles.as_ref().map(|path| format!("subtitles={}", escape_filter_value(&path.to_string_lossy())))
+    }
+
+    /// Whether anything beyond the baseline even-dimension scale was requested
+    pub fn has_transforms(&self) -> bool {
+        self.crop.is_some() || self.tonemap.is_some() || self.speed.is_some() || self.fps.is_some() || self.subtitles.is_some() || self.watermark.is_some()
+    }
+}
 
 pub struct ProcessService {
-    config: ProcessingConfig,
+    /// Swapped whole on config reload; each operation reads one snapshot
+    config: RwLock<Arc<ProcessingConfig>>,
     working_dir: PathBuf,
     pool_manager: Arc<ConnectionPoolManager>,
 }
@@ -17,82 +343,1432 @@ pub struct ProcessService {
 impl ProcessService {
     pub fn new(config: ProcessingConfig, working_dir: PathBuf, pool_manager: Arc<ConnectionPoolManager>) -> Self {
         Self {
-            config,
+            config: RwLock::new(Arc::new(config)),
             working_dir,
             pool_manager,
         }
     }
 
+    /// The processing defaults in effect
+    pub fn config(&self) -> Arc<ProcessingConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// ffmpeg at the configured nice level, for the runs that do real encoding work. It dies with
+    /// the run, so a cancelled or timed-out job doesn't leave an encode behind.
+    fn ffmpeg(&self, config: &ProcessingConfig) -> Command {
+        let mut command = Command::new(&config.ffmpeg_command);
+        command.kill_on_drop(true);
+        lower_priority(&mut command, config.ffmpeg_nice);
+        command
+    }
+
+    /// The `-threads` a job encodes with: its own request up to the configured cap, else the default
+    fn ffmpeg_threads(config: &ProcessingConfig, job: &Job) -> u32 {
+        job.options.threads
+            .map(|threads| threads.min(config.max_ffmpeg_threads))
+            .unwrap_or(config.ffmpeg_threads)
+    }
+
+    /// Replace the processing defaults used by jobs that start from now on
+    pub fn set_config(&self, config: ProcessingConfig) -> AppResult<()> {
+        if let Some(watermark) = &config.watermark {
+            check_watermark(watermark)?;
+        }
+        *self.config.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
+
     pub async fn process(&self, job: &mut Job, input_path: &Path) -> AppResult<PathBuf> {
-        // Acquire processing permit before starting
-        info!("Waiting for processing permit for job {}", job.id);
-        let _permit = self.pool_manager.acquire_processing_permit().await
-            .map_err(|e| AppError::Internal(format!("Failed to acquire processing permit: {e}")))?;
-        
-        info!("Processing permit acquired for job {}", job.id);
-        // Note: Job status is updated to Processing at the higher level
-
-        // Create output path
+        let config = self.config();
+        // The caller holds a processing permit; see process_with_retry
+        // Encode to a temporary name and only rename once the output is known to be good,
+        // so a killed ffmpeg never leaves a truncated file under the final name
+        let job_dir = self.ensure_job_dir(&job.id).await?;
         let output_filename = format!("{}_processed.mp4", job.id);
-        let output_path = self.working_dir.join(&output_filename);
+        let output_path = job_dir.join(&output_filename);
+        let temp_output_path = job_dir.join(format!("{output_filename}{PARTIAL_OUTPUT_SUFFIX}"));
+        if tokio::fs::try_exists(&temp_output_path).await.unwrap_or(false) {
+            let _ = tokio::fs::remove_file(&temp_output_path).await;
+        }
 
-        // Build optimized ffmpeg command with better compatibility and compression
-        let process_result = timeout(
-            self.config.processing_timeout,
-            Command::new(&self.config.ffmpeg_command)
-                .args([
-                    "-i", input_path.to_str().ok_or_else(|| 
-                        AppError::Processing("Invalid input path".to_string()))?,
-                    "-c:v", &self.config.video_codec,
-                    "-preset", &self.config.preset,
-                    "-crf", &self.config.crf.to_string(),
-                    "-profile:v", "high",
-                    "-level", "4.0",
-                    "-pix_fmt", "yuv420p",
-                    "-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2",
-                    "-c:a", &self.config.audio_codec,
-                    "-b:a", &self.config.audio_bitrate,
+        // Per-job options override the configured encoder defaults
+        let video_codec = job.options.video_codec.as_deref().unwrap_or(&config.video_codec);
+        let audio_codec = job.options.audio_codec.as_deref().unwrap_or(&config.audio_codec);
+        let preset = job.options.preset.as_deref().unwrap_or(&config.preset);
+        let crf = job.options.crf.unwrap_or(config.crf);
+        let audio_bitrate = job.options.audio_bitrate.as_deref().unwrap_or(&config.audio_bitrate);
+
+        let input = input_path.to_str().ok_or_else(||
+            AppError::Processing("Invalid input path".to_string()))?;
+        let output = temp_output_path.to_str().ok_or_else(||
+            AppError::Processing("Invalid output path".to_string()))?;
+
+        // Subtitle files fetched alongside the source, if the job asked for them
+        let subtitle_mode = job.subtitle_mode();
+        let subtitle_files = if subtitle_mode == SubtitleMode::None {
+            Vec::new()
+        } else {
+            self.find_subtitle_files(input_path).await
+        };
+        if subtitle_mode != SubtitleMode::None && subtitle_files.is_empty() {
+            job.metadata.notes.push("No subtitles were available for the requested languages".to_string());
+        }
+
+        let mut video_filters = VideoFilterBuilder::default();
+
+        // Cap the frame rate only when the source actually exceeds it
+        let source_stream = match self.probe_video_stream(input_path).await {
+            Ok(stream) => {
+                job.metadata.source_fps = stream.frame_rate;
+                job.metadata.source_width = stream.width;
+                job.metadata.source_height = stream.height;
+                job.metadata.audio_streams = stream.audio_streams.clone();
+                job.metadata.output_fps = stream.frame_rate;
+                if let (Some(max_fps), Some(source_fps)) = (job.options.max_fps.or(config.max_fps), stream.frame_rate) {
+                    if source_fps > max_fps as f64 {
+                        video_filters = video_filters.fps(max_fps);
+                        job.metadata.output_fps = Some(max_fps as f64);
+                    }
+                }
+                Some(stream)
+            }
+            Err(e) => {
+                warn!("Failed to probe video stream for job {}: {}", job.id, e);
+                None
+            }
+        };
+
+        if let Some(crop) = &job.options.crop {
+            let (Some(width), Some(height)) = (job.metadata.source_width, job.metadata.source_height) else {
+                return Err(AppError::Processing("Cropping needs the source's frame size, which could not be probed".to_string()));
+            };
+            let rect = crop_rect(crop, width, height)
+                .map_err(|reason| AppError::Processing(format!("Invalid crop: {reason}")))?;
+            video_filters = video_filters.crop(rect);
+        }
+
+        // HDR sources come out washed out through the bt709 pipeline unless they are tone-mapped
+        if let Some(stream) = &source_stream {
+            job.metadata.source_color_transfer = stream.color_transfer.clone();
+            job.metadata.source_color_primaries = stream.color_primaries.clone();
+            job.metadata.source_color_space = stream.color_space.clone();
+            job.metadata.source_hdr = Some(stream.is_hdr());
+        }
+        let source_hdr = job.metadata.source_hdr.unwrap_or(false);
+        let tonemap = match job.options.tonemap.unwrap_or_default() {
+            TonemapMode::Auto => source_hdr,
+            TonemapMode::Off => false,
+            TonemapMode::Force => true,
+        };
+        if tonemap {
+            match self.tonemap_filter().await {
+                Some(filter) => {
+                    // Untagged sources forced through are assumed to be PQ
+                    let transfer = job.metadata.source_color_transfer.clone()
+                        .filter(|_| source_hdr)
+                        .unwrap_or_else(|| HDR_TRANSFERS[0].to_string());
+                    video_filters = video_filters.tonemap(Tonemap { filter, transfer });
+                }
+                None if job.options.tonemap == Some(TonemapMode::Force) => {
+                    return Err(AppError::Processing(
+                        "Tone mapping was forced, but this ffmpeg has neither the zscale nor the libplacebo filter".to_string()
+                    ));
+                }
+                None => job.metadata.notes.push(
+                    "HDR source was not tone-mapped: this ffmpeg has neither the zscale nor the libplacebo filter".to_string()
+                ),
+            }
+        }
+        let tonemapped = video_filters.tonemap.is_some();
+        job.metadata.tonemapped = Some(tonemapped);
+
+        let mute = job.options.mute.unwrap_or(false);
+        let audio_track = match &job.options.audio_track {
+            Some(track) => {
+                let Some(stream) = &source_stream else {
+                    return Err(AppError::Processing("Selecting an audio track needs the source's streams, which could not be probed".to_string()));
+                };
+                Some(select_audio_track(track, &stream.audio_streams)?)
+            }
+            None => None,
+        };
+        job.metadata.audio_track = audio_track.as_ref().map(|track| track.index);
+
+        // A concat job's sources are normalised and joined in this same encode
+        let concat = match job.concat_inputs() {
+            Some(inputs) => {
+                let joined = self.concat_graph(&inputs, !mute).await?;
+                info!("Joining {} sources for job {}", inputs.len(), job.id);
+                Some((inputs, joined))
+            }
+            None => None,
+        };
+
+        if job.options.watermark.unwrap_or(true) {
+            match &config.watermark {
+                Some(watermark) => video_filters = video_filters.watermark(watermark),
+                None if job.options.watermark == Some(true) => {
+                    job.metadata.notes.push("Watermark requested but none is configured".to_string());
+                }
+                None => {}
+            }
+        }
+
+        let mut audio_filters = Vec::new();
+
+        if !mute && job.options.normalize_audio.unwrap_or(config.normalize_audio) {
+            if concat.is_some() {
+                // One source's measurement doesn't hold for the others, so joined audio gets a single pass
+                audio_filters.push(loudnorm_target(&config));
+            } else {
+                let (filter, measured) = self.loudnorm_filter(&job.id, input_path, job.metadata.audio_track).await;
+                match measured {
+                    Some(measured) => job.metadata.input_loudness = Some(measured),
+                    None => job.metadata.notes.push("Loudness measurement failed; single-pass normalization applied".to_string()),
+                }
+                audio_filters.push(filter);
+            }
+        }
+
+        if let Some(speed) = job.options.speed {
+            video_filters = video_filters.speed(speed);
+            if !mute {
+                audio_filters.push(format!("atempo={speed}"));
+            }
+        }
+
+        let threads = Self::ffmpeg_threads(&config, job);
+        if job.options.threads.is_some_and(|requested| requested > threads) {
+            job.metadata.notes.push(format!("threads capped at {threads}"));
+        }
+        job.metadata.ffmpeg_threads = Some(threads);
+        job.metadata.ffmpeg_nice = config.ffmpeg_nice;
+
+        let mut command = self.ffmpeg(&config);
+        match &concat {
+            Some((inputs, _)) => {
+                for input in inputs {
+                    command.arg("-i").arg(input);
+                }
+            }
+            None => {
+                command.args(["-i", input]);
+            }
+        }
+
+        let embed_subtitles = subtitle_mode == SubtitleMode::Embed && !subtitle_files.is_empty();
+        match subtitle_mode {
+            SubtitleMode::Embed if embed_subtitles => {
+                for (_, subtitle_path) in &subtitle_files {
+                    command.arg("-i").arg(subtitle_path);
+                }
+                job.metadata.subtitle_languages = subtitle_files.iter().map(|(language, _)| language.clone()).collect();
+            }
+            SubtitleMode::Burn => {
+                // Only one track can be burned in; use the first available language
+                if let Some((language, subtitle_path)) = subtitle_files.first() {
+                    video_filters = video_filters.subtitles(subtitle_path);
+                    job.metadata.subtitle_languages = vec![language.clone()];
+                }
+            }
+            _ => {}
+        }
+
+        // Explicit maps replace ffmpeg's own stream pick, so only the chosen audio track (or none) is kept
+        if concat.is_none() && (embed_subtitles || mute || audio_track.is_some()) {
+            command.args(["-map", "0:v:0"]);
+            if let Some(track) = &audio_track {
+                command.args(["-map", &format!("0:a:{}", track.index)]);
+            } else if mute {
+                command.arg("-an");
+            } else {
+                command.args(["-map", "0:a?"]);
+            }
+            if embed_subtitles {
+                for index in 1..=subtitle_files.len() {
+                    command.args(["-map", &index.to_string()]);
+                }
+            }
+        }
+
+        // Sources that already meet the output constraints are remuxed instead of re-encoded
+        let reencode_reason = if let Some((inputs, _)) = &concat {
+            Some(format!("{} sources are joined", inputs.len()))
+        } else if job.options.remux_if_compatible.unwrap_or(config.remux_if_compatible) {
+            self.reencode_reason(job, source_stream.as_ref(), video_codec, audio_codec, &video_filters, &audio_filters)
+        } else {
+            Some("stream copy disabled".to_string())
+        };
+        let stream_copy = reencode_reason.is_none();
+        match &reencode_reason {
+            Some(reason) => info!("Re-encoding job {}: {}", job.id, reason),
+            None => info!("Source for job {} is already compatible, remuxing with stream copy", job.id),
+        }
+        job.metadata.stream_copy = Some(stream_copy);
+        job.metadata.reencode_reason = reencode_reason;
+
+        // A size budget switches from CRF to a two-pass encode at a computed bitrate
+        let passlog_prefix = job_dir.join(format!("{}_passlog", job.id));
+        let target_bitrate = match job.options.max_output_size_mb {
+            Some(max_output_size_mb) if !stream_copy => {
+                if concat.is_some() {
+                    return Err(AppError::Processing("max_output_size_mb can't be used when joining sources".to_string()));
+                }
+                if video_codec != "libx264" {
+                    return Err(AppError::Processing(format!(
+                        "max_output_size_mb requires libx264, but the configured video codec is {video_codec}"
+                    )));
+                }
+                // The budget is spread over the output's duration, which speed changes
+                let duration = self.probe_duration(input_path).await? / job.options.speed.unwrap_or(1.0);
+                let video_kbps = self.target_video_bitrate(max_output_size_mb, duration, if mute { "0" } else { audio_bitrate })?;
+                job.metadata.target_video_bitrate_kbps = Some(video_kbps);
+
+                if let Err(e) = self.run_first_pass(input, preset, video_kbps, threads, &video_filters.build(), &passlog_prefix).await {
+                    self.remove_passlog_files(&passlog_prefix).await;
+                    return Err(e);
+                }
+                Some((video_kbps, duration))
+            }
+            _ => None,
+        };
+
+        let rate_control = match target_bitrate {
+            Some((video_kbps, _)) => vec![
+                "-b:v".to_string(),
+                format!("{video_kbps}k"),
+                "-pass".to_string(),
+                "2".to_string(),
+                "-passlogfile".to_string(),
+                passlog_prefix.to_string_lossy().into_owned(),
+            ],
+            None => vec!["-crf".to_string(), crf.to_string()],
+        };
+
+        if stream_copy {
+            command.args(["-c:v", "copy"]);
+            if !mute {
+                command.args(["-c:a", "copy"]);
+            }
+        } else {
+            // Build optimized ffmpeg command with better compatibility and compression
+            command.args(["-c:v", video_codec, "-preset", preset]);
+            command.args(&rate_control);
+            command.args([
+                "-profile:v", "high",
+                "-level", "4.0",
+                "-pix_fmt", "yuv420p",
+                "-threads", &threads.to_string(),
+            ]);
+            match &concat {
+                // The job's filters run on the joined stream, so the whole encode is one graph
+                Some((_, joined)) => {
+                    let mut graph = format!("{};{}", joined.graph, video_filters.graph("joined", "v"));
+                    command.args(["-map", "[v]"]);
+                    if joined.audio && audio_filters.is_empty() {
+                        command.args(["-map", "[joined_a]"]);
+                    } else if joined.audio {
+                        graph.push_str(&format!(";[joined_a]{}[a]", audio_filters.join(",")));
+                        command.args(["-map", "[a]"]);
+                    }
+                    command.args(["-filter_complex", &graph]);
+                }
+                None => {
+                    command.args(["-vf", &video_filters.build()]);
+                }
+            }
+
+            // Tag the output as SDR so players don't treat it as the source's HDR
+            if tonemapped {
+                command.args(["-colorspace", "bt709", "-color_primaries", "bt709", "-color_trc", "bt709"]);
+            }
+
+            if !mute {
+                command.args([
+                    "-c:a", audio_codec,
+                    "-b:a", audio_bitrate,
                     "-ac", "2", // Force stereo for compatibility
-                    "-threads", "0", // Use all available cores since we limit concurrent processing
-                    "-movflags", "+faststart",
-                    "-max_muxing_queue_size", "1024",
-                    output_path.to_str().ok_or_else(|| 
-                        AppError::Processing("Invalid output path".to_string()))?,
-])
-                .output(),
-        ).await;
+                ]);
+                if !audio_filters.is_empty() && concat.is_none() {
+                    command.args(["-af", &audio_filters.join(",")]);
+                }
+            }
+        }
+
+        // Drop source tags (titles, encoder, GPS) unless the job wants them carried over
+        if job.options.strip_metadata.unwrap_or(config.strip_metadata) {
+            let keep_title_and_chapters = job.options.keep_title_and_chapters.unwrap_or(false);
+            let title = match keep_title_and_chapters {
+                true => self.probe_title(input_path).await,
+                false => None,
+            };
+            command.args(strip_metadata_args(keep_title_and_chapters, title.as_deref()));
+        }
+
+        if embed_subtitles {
+            command.args(["-c:s", "mov_text"]);
+            for (index, (language, _)) in subtitle_files.iter().enumerate() {
+                command.arg(format!("-metadata:s:s:{index}")).arg(format!("language={language}"));
+            }
+        }
+
+        command.args([
+            "-movflags", "+faststart",
+            "-max_muxing_queue_size", "1024",
+            "-f", "mp4", // The temporary extension hides the container from ffmpeg
+            output,
+        ]);
+
+        let process_result = timeout(config.processing_timeout, command.output()).await;
+
+        if target_bitrate.is_some() {
+            self.remove_passlog_files(&passlog_prefix).await;
+        }
 
         match process_result {
             Ok(Ok(output)) => {
+                let temp_exists = temp_output_path.exists();
+
                 if !output.status.success() {
-                    // Clean up partial output file on processing failure
-                    if output_path.exists() {
-                        let _ = tokio::fs::remove_file(&output_path).await;
-                    }
                     let error_message = String::from_utf8_lossy(&output.stderr).to_string();
-                    return Err(AppError::Processing(error_message));
+                    if temp_exists {
+                        // Clean up partial output file on processing failure
+                        let _ = tokio::fs::remove_file(&temp_output_path).await;
+                        return Err(AppError::Processing(format!(
+                            "Encode failed and the partial output was discarded: {error_message}"
+                        )));
+                    }
+                    return Err(processing_failure(&output));
                 }
 
-                if !output_path.exists() {
+                if !temp_exists {
                     return Err(AppError::Processing(format!(
                         "Output file not created: {}",
-                        output_path.display()
+                        temp_output_path.display()
+                    )));
+                }
+
+                // A zero exit status is not enough; make sure the file is readable media before publishing it
+                if let Err(e) = self.probe_duration(&temp_output_path).await {
+                    let _ = tokio::fs::remove_file(&temp_output_path).await;
+                    return Err(AppError::Processing(format!(
+                        "Encoded output failed the sanity check and was discarded: {e}"
                     )));
                 }
 
+                if let Err(e) = tokio::fs::rename(&temp_output_path, &output_path).await {
+                    let _ = tokio::fs::remove_file(&temp_output_path).await;
+                    return Err(AppError::Processing(format!("Failed to finalize output file: {e}")));
+                }
+
+                if let Ok(stream) = self.probe_video_stream(&output_path).await {
+                    job.metadata.output_width = stream.width;
+                    job.metadata.output_height = stream.height;
+                }
+
+                if let Ok(file_metadata) = tokio::fs::metadata(&output_path).await {
+                    let output_size = file_metadata.len();
+                    job.metadata.output_size_bytes = Some(output_size);
+
+                    if let (Some((_, duration)), Some(max_output_size_mb)) = (target_bitrate, job.options.max_output_size_mb) {
+                        if duration > 0.0 {
+                            job.metadata.output_bitrate_kbps = Some(output_size as f64 * 8.0 / 1000.0 / duration);
+                        }
+                        if output_size > max_output_size_mb as u64 * 1024 * 1024 {
+                            job.metadata.notes.push(format!(
+                                "Output is {output_size} bytes, exceeding the {max_output_size_mb} MB budget"
+                            ));
+                        }
+                    }
+                }
+
                 Ok(output_path)
             }
-            Ok(Err(error)) => Err(AppError::Processing(format!("FFmpeg command failed: {error}"))),
+            Ok(Err(error)) => Err(AppError::spawn(Phase::Processing, "FFmpeg", error)),
             Err(_) => {
                 // Clean up partial output file on timeout
-                if output_path.exists() {
-                    let _ = tokio::fs::remove_file(&output_path).await;
+                if temp_output_path.exists() {
+                    let _ = tokio::fs::remove_file(&temp_output_path).await;
                 }
                 Err(AppError::Timeout(format!(
                     "Processing timed out after {} seconds",
-                    self.config.processing_timeout.as_secs()
+                    config.processing_timeout.as_secs()
                 )))
             }
         }
     }
-}
\ No newline at end of file
+
+    /// The `-filter_complex` fragment joining a concat job's sources, in order, for the encode
+    /// that processes them. The sources are probed here; see `concat_filter` for the graph.
+    async fn concat_graph(&self, inputs: &[PathBuf], audio: bool) -> AppResult<ConcatGraph> {
+        let mut sources = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let stream = self.probe_video_stream(input).await?;
+            // Silence as long as the source stands in for its missing audio
+            let duration = match stream.audio_codec {
+                None if audio => Some(self.probe_duration(input).await?),
+                _ => None,
+            };
+            sources.push(ConcatSource { stream, duration });
+        }
+        concat_filter(&sources, audio)
+    }
+
+    /// Create the job's working directory if needed; reprocessed jobs may not have one yet
+    async fn ensure_job_dir(&self, job_id: &str) -> AppResult<PathBuf> {
+        let job_dir = job_working_dir(&self.working_dir, job_id);
+        tokio::fs::create_dir_all(&job_dir).await
+            .map_err(AppError::io("Failed to create job directory"))?;
+        Ok(job_dir)
+    }
+
+    /// Check the configured watermark is a readable PNG, JPEG or WebP image; no-op when unconfigured
+    pub fn validate_watermark(&self) -> AppResult<()> {
+        match &self.config().watermark {
+            Some(watermark) => check_watermark(watermark),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether a preview should be generated for this job
+    pub fn wants_preview(&self, job: &Job) -> bool {
+        let config = self.config();
+        job.options.preview.unwrap_or(config.preview_enabled)
+    }
+
+    /// Generate a short looping GIF preview from the processed file
+    pub async fn generate_preview(&self, job: &Job, processed_path: &Path) -> AppResult<PathBuf> {
+        let config = self.config();
+        let _permit = self.pool_manager.acquire_processing_permit().await?;
+
+        let preview_path = self.ensure_job_dir(&job.id).await?.join(format!("{}_preview.gif", job.id));
+
+        // palettegen/paletteuse keeps the GIF small without the usual banding
+        let preview_result = timeout(
+            config.processing_timeout,
+            self.ffmpeg(&config)
+                .args(["-y", "-t", &config.preview_duration_secs.to_string(), "-i"])
+                .arg(processed_path)
+                .args([
+                    "-vf", "fps=10,scale=320:-2:flags=lanczos,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse",
+                    "-loop", "0",
+                    "-an",
+                ])
+                .arg(&preview_path)
+                .output(),
+        ).await;
+
+        match preview_result {
+            Ok(Ok(output)) if output.status.success() && preview_path.exists() => {
+                info!("Generated preview for job {}: {:?}", job.id, preview_path);
+                Ok(preview_path)
+            }
+            Ok(Ok(output)) => {
+                let _ = tokio::fs::remove_file(&preview_path).await;
+                Err(processing_failure(&output))
+            }
+            Ok(Err(error)) => Err(AppError::spawn(Phase::Processing, "FFmpeg", error)),
+            Err(_) => {
+                let _ = tokio::fs::remove_file(&preview_path).await;
+                Err(AppError::Timeout("Preview generation timed out".to_string()))
+            }
+        }
+    }
+
+    /// Extract a single JPEG frame, reusing a cached copy keyed by job, timestamp and width
+    pub async fn extract_frame(&self, job: &Job, processed_path: &Path, timestamp: f64, width: Option<u32>) -> AppResult<PathBuf> {
+        let config = self.config();
+        let timestamp_ms = (timestamp * 1000.0).round() as u64;
+        let frame_path = self.ensure_job_dir(&job.id).await?.join(format!(
+            "{}_frame_{}_{}.jpg",
+            job.id,
+            timestamp_ms,
+            width.unwrap_or(0)
+        ));
+
+        if tokio::fs::try_exists(&frame_path).await.unwrap_or(false) {
+            return Ok(frame_path);
+        }
+
+        // Share the processing semaphore so frame bursts can't starve real encodes
+        let _permit = self.pool_manager.acquire_processing_permit().await?;
+
+        // Encode under a name of its own and rename into place, so a concurrent request for the same
+        // frame never sees a half-written file in the cache
+        let partial_path = frame_path.with_extension(format!("{}.partial.jpg", uuid::Uuid::new_v4()));
+
+        let mut command = Command::new(&config.ffmpeg_command);
+        command
+            .args(["-y", "-ss", &format!("{:.3}", timestamp_ms as f64 / 1000.0), "-i"])
+            .arg(processed_path)
+            .args(["-frames:v", "1", "-q:v", "3"]);
+        if let Some(width) = width {
+            command.args(["-vf", &format!("scale={width}:-2")]);
+        }
+        command.arg(&partial_path);
+
+        let frame_result = timeout(std::time::Duration::from_secs(30), command.output()).await;
+
+        match frame_result {
+            Ok(Ok(output)) if output.status.success() && partial_path.exists() => {
+                if let Err(e) = tokio::fs::rename(&partial_path, &frame_path).await {
+                    let _ = tokio::fs::remove_file(&partial_path).await;
+                    return Err(AppError::io("Failed to store extracted frame")(e));
+                }
+                Ok(frame_path)
+            }
+            Ok(Ok(output)) => {
+                let _ = tokio::fs::remove_file(&partial_path).await;
+                Err(processing_failure(&output))
+            }
+            Ok(Err(error)) => Err(AppError::spawn(Phase::Processing, "FFmpeg", error)),
+            Err(_) => {
+                let _ = tokio::fs::remove_file(&partial_path).await;
+                Err(AppError::Timeout("Frame extraction timed out after 30 seconds".to_string()))
+            }
+        }
+    }
+
+    /// Why a job must be re-encoded, or `None` when the source can be stream-copied as is
+    fn reencode_reason(
+        &self,
+        job: &Job,
+        stream: Option<&VideoStreamInfo>,
+        video_codec: &str,
+        audio_codec: &str,
+        video_filters: &VideoFilterBuilder,
+        audio_filters: &[String],
+    ) -> Option<String> {
+        if job.options.crf.is_some() || job.options.preset.is_some() {
+            return Some("encoder settings were requested".to_string());
+        }
+        if job.options.max_output_size_mb.is_some() {
+            return Some("an output size budget was requested".to_string());
+        }
+        if video_filters.has_transforms() {
+            return Some("video filters were requested".to_string());
+        }
+        if !audio_filters.is_empty() {
+            return Some("audio filters were requested".to_string());
+        }
+
+        let Some(stream) = stream else {
+            return Some("source could not be probed".to_string());
+        };
+
+        let expected_video_codec = ffprobe_codec_name(video_codec);
+        let source_video_codec = stream.codec_name.as_deref().unwrap_or("unknown");
+        if source_video_codec != expected_video_codec {
+            return Some(format!("video codec {source_video_codec} is not {expected_video_codec}"));
+        }
+
+        if expected_video_codec == "h264" {
+            let profile = stream.profile.as_deref().unwrap_or("unknown");
+            if !["High", "Main", "Constrained Baseline", "Baseline"].contains(&profile) {
+                return Some(format!("H.264 profile {profile} is not supported"));
+            }
+            match stream.level {
+                Some(level) if level <= STREAM_COPY_MAX_H264_LEVEL => {}
+                Some(level) => return Some(format!("H.264 level {level} exceeds {STREAM_COPY_MAX_H264_LEVEL}")),
+                None => return Some("H.264 level is unknown".to_string()),
+            }
+        }
+
+        let pix_fmt = stream.pix_fmt.as_deref().unwrap_or("unknown");
+        if pix_fmt != "yuv420p" {
+            return Some(format!("pixel format {pix_fmt} is not yuv420p"));
+        }
+
+        match (stream.width, stream.height) {
+            (Some(width), Some(height)) => {
+                if width > STREAM_COPY_MAX_WIDTH || height > STREAM_COPY_MAX_HEIGHT {
+                    return Some(format!(
+                        "resolution {width}x{height} exceeds {STREAM_COPY_MAX_WIDTH}x{STREAM_COPY_MAX_HEIGHT}"
+                    ));
+                }
+                if width % 2 != 0 || height % 2 != 0 {
+                    return Some(format!("resolution {width}x{height} has odd dimensions"));
+                }
+            }
+            _ => return Some("resolution is unknown".to_string()),
+        }
+
+        // Muted jobs and sources without audio are fine; otherwise the kept track must already match the audio codec
+        let source_audio_codec = match job.metadata.audio_track {
+            _ if job.options.mute == Some(true) => None,
+            Some(index) => stream.audio_streams.iter()
+                .find(|audio| audio.index == index)
+                .and_then(|audio| audio.codec.as_deref()),
+            None => stream.audio_codec.as_deref(),
+        };
+        if let Some(source_audio_codec) = source_audio_codec {
+            let expected_audio_codec = ffprobe_codec_name(audio_codec);
+            if source_audio_codec != expected_audio_codec {
+                return Some(format!("audio codec {source_audio_codec} is not {expected_audio_codec}"));
+            }
+        }
+
+        None
+    }
+
+    /// Video bitrate that fits the size budget once audio and container overhead are set aside
+    fn target_video_bitrate(&self, max_output_size_mb: u32, duration: f64, audio_bitrate: &str) -> AppResult<u32> {
+        let config = self.config();
+        if duration <= 0.0 {
+            return Err(AppError::Processing("Cannot target an output size without a known duration".to_string()));
+        }
+
+        // Reserve ~2% for container overhead
+        let budget_kbits = max_output_size_mb as f64 * 1024.0 * 1024.0 * 8.0 / 1000.0 * 0.98;
+        let audio_kbps = parse_bitrate_kbps(audio_bitrate).unwrap_or(0.0);
+        let video_kbps = budget_kbits / duration - audio_kbps;
+
+        if video_kbps < config.min_video_bitrate_kbps as f64 {
+            return Err(AppError::Processing(format!(
+                "A {max_output_size_mb} MB budget cannot fit {duration:.1}s of video: it leaves {:.0} kbps for video, below the minimum of {} kbps",
+                video_kbps.max(0.0),
+                config.min_video_bitrate_kbps
+            )));
+        }
+
+        Ok(video_kbps.floor() as u32)
+    }
+
+    /// Run the analysis pass of a two-pass libx264 encode, writing stats to the job's passlog
+    async fn run_first_pass(&self, input: &str, preset: &str, video_kbps: u32, threads: u32, video_filters: &str, passlog_prefix: &Path) -> AppResult<()> {
+        let config = self.config();
+        let first_pass = timeout(
+            config.processing_timeout,
+            self.ffmpeg(&config)
+                .args([
+                    "-y",
+                    "-i", input,
+                    "-c:v", "libx264",
+                    "-preset", preset,
+                    "-threads", &threads.to_string(),
+                    "-b:v", &format!("{video_kbps}k"),
+                    "-pass", "1",
+                    "-passlogfile",
+                ])
+                .arg(passlog_prefix)
+                .args([
+                    "-profile:v", "high",
+                    "-level", "4.0",
+                    "-pix_fmt", "yuv420p",
+                    "-vf", video_filters,
+                    "-an",
+                    "-f", "null",
+                    "-",
+                ])
+                .output(),
+        ).await;
+
+        match first_pass {
+            Ok(Ok(output)) if output.status.success() => Ok(()),
+            Ok(Ok(output)) => Err(AppError::Processing(format!(
+                "First encoding pass failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ))),
+            Ok(Err(error)) => Err(AppError::spawn(Phase::Processing, "FFmpeg", error)),
+            Err(_) => Err(AppError::Timeout(format!(
+                "First encoding pass timed out after {} seconds",
+                config.processing_timeout.as_secs()
+            ))),
+        }
+    }
+
+    /// Remove the stats files libx264 writes next to the passlog prefix
+    async fn remove_passlog_files(&self, passlog_prefix: &Path) {
+        let prefix = passlog_prefix.to_string_lossy();
+        for suffix in ["-0.log", "-0.log.mbtree", "-0.log.temp", "-0.log.mbtree.temp"] {
+            let _ = tokio::fs::remove_file(format!("{prefix}{suffix}")).await;
+        }
+    }
+
+    /// Build the loudnorm filter, using two-pass measured values when the first pass succeeds
+    async fn loudnorm_filter(&self, job_id: &str, input_path: &Path, audio_track: Option<u32>) -> (String, Option<LoudnessMeasurement>) {
+        let target = loudnorm_target(&self.config());
+
+        match self.measure_loudness(input_path, &target, audio_track).await {
+            Ok(measured) => {
+                let filter = format!(
+                    "{target}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+                    measured.integrated_lufs,
+                    measured.true_peak_dbtp,
+                    measured.loudness_range_lu,
+                    measured.threshold_lufs,
+                    measured.target_offset_lu,
+                );
+                (filter, Some(measured))
+            }
+            Err(e) => {
+                warn!("Loudness measurement failed for job {}, falling back to single-pass: {}", job_id, e);
+                (target, None)
+            }
+        }
+    }
+
+    /// First loudnorm pass: analyse the input (or the selected audio track) and parse the JSON
+    /// summary ffmpeg prints to stderr
+    async fn measure_loudness(&self, input_path: &Path, target: &str, audio_track: Option<u32>) -> AppResult<LoudnessMeasurement> {
+        let config = self.config();
+        let mut command = self.ffmpeg(&config);
+        command.args(["-hide_banner", "-nostats", "-i"]).arg(input_path);
+        if let Some(index) = audio_track {
+            command.args(["-map", &format!("0:a:{index}")]);
+        }
+        command.args(["-vn", "-af", &format!("{target}:print_format=json"), "-f", "null", "-"]);
+        let measure_result = timeout(config.processing_timeout, command.output()).await;
+
+        let output = match measure_result {
+            Ok(Ok(output)) if output.status.success() => output,
+            Ok(Ok(output)) => return Err(processing_failure(&output)),
+            Ok(Err(error)) => return Err(AppError::spawn(Phase::Processing, "FFmpeg", error)),
+            Err(_) => return Err(AppError::Timeout("Loudness measurement timed out".to_string())),
+        };
+
+        parse_loudness_summary(&String::from_utf8_lossy(&output.stderr))
+    }
+
+    /// Find subtitle files written next to the source as {stem}.{lang}.{vtt,srt}
+    async fn find_subtitle_files(&self, input_path: &Path) -> Vec<(String, PathBuf)> {
+        let mut subtitles = Vec::new();
+        let (Some(dir), Some(stem)) = (input_path.parent(), input_path.file_stem()) else {
+            return subtitles;
+        };
+        let prefix = format!("{}.", stem.to_string_lossy());
+
+        if let Ok(mut entries) = tokio::fs::read_dir(dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let filename = entry.file_name().to_string_lossy().to_string();
+                let Some(rest) = filename.strip_prefix(&prefix) else {
+                    continue;
+                };
+                let language = rest.strip_suffix(".vtt").or_else(|| rest.strip_suffix(".srt"));
+                if let Some(language) = language {
+                    if !language.is_empty() {
+                        subtitles.push((language.to_string(), entry.path()));
+                    }
+                }
+            }
+        }
+
+        subtitles.sort();
+        subtitles
+    }
+
+    /// Probe the first video stream of a media file
+    pub async fn probe_video_stream(&self, input_path: &Path) -> AppResult<VideoStreamInfo> {
+        let config = self.config();
+        let probe_result = timeout(
+            std::time::Duration::from_secs(30),
+            Command::new(&config.ffprobe_command)
+                .args([
+                    "-v", "error",
+                    "-show_entries", "stream=codec_type,codec_name,profile,level,pix_fmt,width,height,avg_frame_rate,channels,color_transfer,color_primaries,color_space:stream_tags=language",
+                    "-of", "json",
+                ])
+                .arg(input_path)
+                .output(),
+        ).await;
+
+        let output = match probe_result {
+            Ok(Ok(output)) if output.status.success() => output,
+            Ok(Ok(output)) => return Err(processing_failure(&output)),
+            Ok(Err(error)) => return Err(AppError::spawn(Phase::Processing, "FFprobe", error)),
+            Err(_) => return Err(AppError::Timeout("Media probe timed out after 30 seconds".to_string())),
+        };
+
+        let probe: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| AppError::Processing(format!("Invalid ffprobe output: {e}")))?;
+        video_stream_info(&probe)
+    }
+
+    /// The tone-mapping filter this ffmpeg build offers. zscale is preferred: libplacebo
+    /// needs a Vulkan device, which a build listing the filter may still lack at runtime.
+    async fn tonemap_filter(&self) -> Option<TonemapFilter> {
+        let config = self.config();
+        let output = timeout(
+            std::time::Duration::from_secs(30),
+            Command::new(&config.ffmpeg_command)
+                .args(["-hide_banner", "-filters"])
+                .output(),
+        ).await.ok()?.ok()?;
+
+        let filters = String::from_utf8_lossy(&output.stdout);
+        let has_filter = |name: &str| filters.lines().any(|line| line.split_whitespace().nth(1) == Some(name));
+        if has_filter("zscale") && has_filter("tonemap") {
+            Some(TonemapFilter::Zscale)
+        } else if has_filter("libplacebo") {
+            Some(TonemapFilter::Libplacebo)
+        } else {
+            None
+        }
+    }
+
+    /// Read the container title tag, if the source has one
+    async fn probe_title(&self, input_path: &Path) -> Option<String> {
+        let config = self.config();
+        let output = timeout(
+            std::time::Duration::from_secs(30),
+            Command::new(&config.ffprobe_command)
+                .args([
+                    "-v", "error",
+                    "-show_entries", "format_tags=title",
+                    "-of", "default=noprint_wrappers=1:nokey=1",
+                ])
+                .arg(input_path)
+                .output(),
+        ).await.ok()?.ok()?;
+
+        let title = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !title.is_empty()).then_some(title)
+    }
+
+    /// Probe a media file with ffprobe and return its duration in seconds
+    pub async fn probe_duration(&self, input_path: &Path) -> AppResult<f64> {
+        let config = self.config();
+        let probe_result = timeout(
+            std::time::Duration::from_secs(30),
+            Command::new(&config.ffprobe_command)
+                .args([
+                    "-v", "error",
+                    "-show_entries", "format=duration",
+                    "-of", "default=noprint_wrappers=1:nokey=1",
+                ])
+                .arg(input_path)
+                .output(),
+        ).await;
+
+        match probe_result {
+            Ok(Ok(output)) => {
+                if !output.status.success() {
+                    let error_message = String::from_utf8_lossy(&output.stderr).to_string();
+                    return Err(AppError::Processing(format!("File is not a valid media file: {error_message}")));
+                }
+
+                String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| AppError::Processing("File has no readable media duration".to_string()))
+            }
+            Ok(Err(error)) => Err(AppError::spawn(Phase::Processing, "FFprobe", error)),
+            Err(_) => Err(AppError::Timeout("Media probe timed out after 30 seconds".to_string())),
+        }
+    }
+}
+
+/// Single-pass loudnorm at the configured targets
+fn loudnorm_target(config: &ProcessingConfig) -> String {
+    format!("loudnorm=I={}:TP={}:LRA={}", config.loudnorm_integrated, config.loudnorm_true_peak, config.loudnorm_lra)
+}
+
+/// Parse the JSON summary loudnorm prints at the end of ffmpeg's stderr. Values are strings, and
+/// silence measures as "-inf", which can't be fed back into the second pass.
+fn parse_loudness_summary(stderr: &str) -> AppResult<LoudnessMeasurement> {
+    let json = stderr.rfind('{')
+        .and_then(|start| stderr[start..].find('}').map(|end| &stderr[start..=start + end]))
+        .ok_or_else(|| AppError::Processing("No loudness summary in ffmpeg output".to_string()))?;
+
+    let summary: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| AppError::Processing(format!("Invalid loudness summary: {e}")))?;
+    let field = |name: &str| -> AppResult<f64> {
+        summary.get(name)
+            .and_then(|value| value.as_str())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| AppError::Processing(format!("Loudness summary missing {name}")))
+    };
+
+    Ok(LoudnessMeasurement {
+        integrated_lufs: field("input_i")?,
+        true_peak_dbtp: field("input_tp")?,
+        loudness_range_lu: field("input_lra")?,
+        threshold_lufs: field("input_thresh")?,
+        target_offset_lu: field("target_offset")?,
+    })
+}
+
+/// Read the first video stream and the audio streams from `ffprobe -of json` output
+fn video_stream_info(probe: &serde_json::Value) -> AppResult<VideoStreamInfo> {
+    let streams = probe.get("streams")
+        .and_then(|streams| streams.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let first_of_type = |codec_type: &str| {
+        streams.iter().find(|stream| stream.get("codec_type").and_then(|v| v.as_str()) == Some(codec_type))
+    };
+    let audio_streams = streams.iter()
+        .filter(|stream| stream.get("codec_type").and_then(|v| v.as_str()) == Some("audio"))
+        .enumerate()
+        .map(|(index, audio)| AudioStreamInfo {
+            index: index as u32,
+            codec: audio.get("codec_name").and_then(|v| v.as_str()).map(str::to_string),
+            language: audio.pointer("/tags/language")
+                .and_then(|v| v.as_str())
+                .filter(|language| !language.is_empty() && *language != "und")
+                .map(str::to_string),
+            channels: audio.get("channels").and_then(|v| v.as_u64()),
+        })
+        .collect();
+    let stream = first_of_type("video")
+        .ok_or_else(|| AppError::Processing("No video stream found".to_string()))?;
+    let string_field = |field: &str| stream.get(field).and_then(|v| v.as_str()).map(str::to_string);
+
+    Ok(VideoStreamInfo {
+        frame_rate: stream.get("avg_frame_rate").and_then(|v| v.as_str()).and_then(parse_frame_rate),
+        codec_name: string_field("codec_name"),
+        profile: string_field("profile"),
+        level: stream.get("level").and_then(|v| v.as_i64()),
+        pix_fmt: string_field("pix_fmt"),
+        width: stream.get("width").and_then(|v| v.as_u64()),
+        height: stream.get("height").and_then(|v| v.as_u64()),
+        audio_codec: first_of_type("audio")
+            .and_then(|audio| audio.get("codec_name"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        audio_streams,
+        color_transfer: string_field("color_transfer"),
+        color_primaries: string_field("color_primaries"),
+        color_space: string_field("color_space"),
+    })
+}
+
+/// Output options that drop the source's tags, optionally carrying over its chapters and title
+fn strip_metadata_args(keep_title_and_chapters: bool, title: Option<&str>) -> Vec<String> {
+    let mut args: Vec<String> = ["-map_metadata", "-1", "-fflags", "+bitexact"].map(String::from).into();
+    if keep_title_and_chapters {
+        args.extend(["-map_chapters".to_string(), "0".to_string()]);
+        if let Some(title) = title {
+            args.extend(["-metadata".to_string(), format!("title={title}")]);
+        }
+    } else {
+        args.extend(["-map_chapters".to_string(), "-1".to_string()]);
+    }
+    args
+}
+
+/// Escape a value for use as a filter option inside an ffmpeg filtergraph
+fn escape_filter_value(value: &str) -> String {
+    // First level: the filter option parser
+    let option_escaped = value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace(':', "\\:");
+
+    // Second level: the filtergraph parser
+    let mut escaped = String::with_capacity(option_escaped.len());
+    for c in option_escaped.chars() {
+        if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Map an ffmpeg encoder name to the codec name ffprobe reports for its output
+fn ffprobe_codec_name(encoder: &str) -> &str {
+    match encoder {
+        "libx264" => "h264",
+        "libx265" => "hevc",
+        "libvpx-vp9" => "vp9",
+        "libaom-av1" | "libsvtav1" => "av1",
+        "libfdk_aac" => "aac",
+        "libopus" => "opus",
+        "libmp3lame" => "mp3",
+        other => other,
+    }
+}
+
+/// Parse an ffmpeg bitrate such as "128k" or "96000" into kbps
+fn parse_bitrate_kbps(bitrate: &str) -> Option<f64> {
+    let bitrate = bitrate.trim().to_lowercase();
+    match bitrate.strip_suffix('k') {
+        Some(kbps) => kbps.parse().ok(),
+        None => bitrate.parse::<f64>().ok().map(|bps| bps / 1000.0),
+    }
+}
+
+/// Parse an ffprobe rational frame rate such as "30000/1001"
+fn parse_frame_rate(value: &str) -> Option<f64> {
+    let rate = match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator: f64 = denominator.parse().ok()?;
+            if denominator == 0.0 {
+                return None;
+            }
+            numerator.parse::<f64>().ok()? / denominator
+        }
+        None => value.parse().ok()?,
+    };
+    (rate > 0.0).then_some(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// The tail of `ffmpeg -af loudnorm=print_format=json` stderr, as ffmpeg 6 prints it
+    const LOUDNORM_STDERR: &str = r#"Output #0, null, to 'pipe:':
+  Stream #0:0: Audio: pcm_s16le, 48000 Hz, stereo, s16, 1536 kb/s
+size=N/A time=00:00:12.00 bitrate=N/A speed= 312x
+[Parsed_loudnorm_0 @ 0x5581c2d0a8c0]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-16.58",
+	"output_tp" : "-1.50",
+	"output_lra" : "14.78",
+	"output_thresh" : "-27.71",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.58"
+}
+"#;
+
+    #[test]
+    fn stripping_drops_tags_and_chapters() {
+        assert_eq!(
+            strip_metadata_args(false, Some("ignored")),
+            ["-map_metadata", "-1", "-fflags", "+bitexact", "-map_chapters", "-1"]
+        );
+    }
+
+    #[test]
+    fn stripping_can_keep_title_and_chapters() {
+        assert_eq!(
+            strip_metadata_args(true, Some("Talk: part 1")),
+            ["-map_metadata", "-1", "-fflags", "+bitexact", "-map_chapters", "0", "-metadata", "title=Talk: part 1"]
+        );
+        assert_eq!(
+            strip_metadata_args(true, None),
+            ["-map_metadata", "-1", "-fflags", "+bitexact", "-map_chapters", "0"]
+        );
+    }
+
+    /// Tags ffprobe reports for a file, at the container level and on every stream
+    async fn probe_tags(path: &Path) -> (serde_json::Value, Vec<serde_json::Value>) {
+        let output = Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", "format_tags:stream_tags", "-of", "json"])
+            .arg(path)
+            .output()
+            .await
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let probe: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let stream_tags = probe["streams"].as_array().into_iter().flatten()
+            .map(|stream| stream["tags"].clone())
+            .collect();
+        (probe["format"]["tags"].clone(), stream_tags)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffmpeg and ffprobe on PATH"]
+    async fn stripped_output_has_no_source_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mp4");
+        let status = Command::new("ffmpeg")
+            .args(["-v", "error", "-f", "lavfi", "-i", "testsrc=duration=1:size=320x240:rate=25",
+                   "-f", "lavfi", "-i", "sine=duration=1", "-shortest",
+                   "-metadata", "title=Holiday", "-metadata", "comment=shot on a phone",
+                   "-metadata", "location=+52.3700+004.8900/",
+                   "-metadata:s:v:0", "handler_name=Camera"])
+            .arg(&source)
+            .status()
+            .await
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(probe_tags(&source).await.0["title"], "Holiday");
+
+        let mut config = Config::default().processing;
+        config.ffmpeg_command = "ffmpeg".to_string();
+        config.ffprobe_command = "ffprobe".to_string();
+        config.strip_metadata = true;
+        let pool_manager = Arc::new(ConnectionPoolManager::new(1, 1, std::time::Duration::from_secs(5)));
+        let service = ProcessService::new(config, dir.path().to_path_buf(), pool_manager);
+        let mut job = Job::new("https://example.com/holiday.mp4".to_string());
+        let output = service.process(&mut job, &source).await.unwrap();
+
+        // Only what the mp4 demuxer itself reports may be left: the brand and per-stream defaults
+        let (format_tags, stream_tags) = probe_tags(&output).await;
+        let source_tags = ["title", "comment", "location", "encoder"];
+        for tag in source_tags {
+            assert!(format_tags.get(tag).is_none(), "format tag {tag} survived: {format_tags}");
+        }
+        for tags in stream_tags {
+            assert_ne!(tags["handler_name"], "Camera", "stream tags survived: {tags}");
+            assert!(tags.get("encoder").is_none(), "stream tags survived: {tags}");
+        }
+    }
+
+    fn service() -> ProcessService {
+        let mut config = Config::default().processing;
+        config.video_codec = "libx264".to_string();
+        config.audio_codec = "aac".to_string();
+        let pool_manager = Arc::new(ConnectionPoolManager::new(1, 1, std::time::Duration::from_secs(5)));
+        ProcessService::new(config, std::env::temp_dir(), pool_manager)
+    }
+
+    /// `ffprobe -show_entries stream=...` for a 1080p H.264/AAC upload that can be stream-copied
+    fn compatible_probe() -> serde_json::Value {
+        serde_json::json!({
+            "programs": [],
+            "streams": [
+                {
+                    "codec_name": "h264", "profile": "High", "codec_type": "video", "width": 1920, "height": 1080,
+                    "pix_fmt": "yuv420p", "level": 40, "color_space": "bt709", "color_transfer": "bt709",
+                    "color_primaries": "bt709", "avg_frame_rate": "30000/1001", "tags": { "language": "und" }
+                },
+                { "codec_name": "aac", "profile": "LC", "codec_type": "audio", "channels": 2, "avg_frame_rate": "0/0", "tags": { "language": "eng" } }
+            ]
+        })
+    }
+
+    fn reencode_reason(job: &Job, probe: &serde_json::Value) -> Option<String> {
+        let stream = video_stream_info(probe).unwrap();
+        service().reencode_reason(job, Some(&stream), "libx264", "aac", &VideoFilterBuilder::default(), &[])
+    }
+
+    fn job() -> Job {
+        Job::new("https://example.com/clip.mp4".to_string())
+    }
+
+    #[test]
+    fn compatible_source_is_stream_copied() {
+        assert_eq!(reencode_reason(&job(), &compatible_probe()), None);
+    }
+
+    #[test]
+    fn incompatible_sources_name_the_property_that_forced_a_reencode() {
+        let cases: [(&str, serde_json::Value, &str); 8] = [
+            ("codec_name", "hevc".into(), "video codec hevc is not h264"),
+            ("profile", "High 10".into(), "H.264 profile High 10 is not supported"),
+            ("level", 51.into(), "H.264 level 51 exceeds 40"),
+            ("pix_fmt", "yuv420p10le".into(), "pixel format yuv420p10le is not yuv420p"),
+            ("width", 3840.into(), "resolution 3840x1080 exceeds 1920x1080"),
+            ("height", 721.into(), "resolution 1920x721 has odd dimensions"),
+            ("level", serde_json::Value::Null, "H.264 level is unknown"),
+            ("width", serde_json::Value::Null, "resolution is unknown"),
+        ];
+        for (field, value, expected) in cases {
+            let mut probe = compatible_probe();
+            probe["streams"][0][field] = value;
+            assert_eq!(reencode_reason(&job(), &probe).as_deref(), Some(expected), "{field}");
+        }
+
+        let mut probe = compatible_probe();
+        probe["streams"][1]["codec_name"] = "opus".into();
+        assert_eq!(reencode_reason(&job(), &probe).as_deref(), Some("audio codec opus is not aac"));
+    }
+
+    #[test]
+    fn audio_is_only_checked_when_it_is_kept() {
+        let mut probe = compatible_probe();
+        probe["streams"][1]["codec_name"] = "opus".into();
+        let mut muted = job();
+        muted.options.mute = Some(true);
+        assert_eq!(reencode_reason(&muted, &probe), None);
+
+        probe["streams"].as_array_mut().unwrap().pop();
+        assert_eq!(reencode_reason(&job(), &probe), None);
+    }
+
+    #[test]
+    fn the_selected_audio_track_decides_the_audio_codec() {
+        let mut probe = compatible_probe();
+        probe["streams"][1]["codec_name"] = "opus".into();
+        probe["streams"].as_array_mut().unwrap().push(serde_json::json!(
+            { "codec_name": "aac", "codec_type": "audio", "channels": 2, "tags": { "language": "deu" } }
+        ));
+        let mut job = job();
+        job.metadata.audio_track = Some(1);
+        assert_eq!(reencode_reason(&job, &probe), None);
+        job.metadata.audio_track = Some(0);
+        assert_eq!(reencode_reason(&job, &probe).as_deref(), Some("audio codec opus is not aac"));
+    }
+
+    #[test]
+    fn requested_transforms_force_a_reencode() {
+        let stream = video_stream_info(&compatible_probe()).unwrap();
+        let service = service();
+
+        let mut tuned = job();
+        tuned.options.crf = Some(20);
+        assert_eq!(
+            service.reencode_reason(&tuned, Some(&stream), "libx264", "aac", &VideoFilterBuilder::default(), &[]).as_deref(),
+            Some("encoder settings were requested")
+        );
+
+        let filters = VideoFilterBuilder::default().fps(24);
+        assert_eq!(
+            service.reencode_reason(&job(), Some(&stream), "libx264", "aac", &filters, &[]).as_deref(),
+            Some("video filters were requested")
+        );
+
+        let audio_filters = ["atempo=1.5".to_string()];
+        assert_eq!(
+            service.reencode_reason(&job(), Some(&stream), "libx264", "aac", &VideoFilterBuilder::default(), &audio_filters).as_deref(),
+            Some("audio filters were requested")
+        );
+
+        assert_eq!(
+            service.reencode_reason(&job(), None, "libx264", "aac", &VideoFilterBuilder::default(), &[]).as_deref(),
+            Some("source could not be probed")
+        );
+    }
+
+    #[test]
+    fn probe_without_a_video_stream_is_rejected() {
+        let mut probe = compatible_probe();
+        probe["streams"].as_array_mut().unwrap().remove(0);
+        assert!(video_stream_info(&probe).is_err());
+    }
+
+    fn watermark(position: WatermarkPosition) -> WatermarkConfig {
+        WatermarkConfig { path: PathBuf::from("/etc/aperio/logo.png"), position, margin: 24, opacity: 0.8 }
+    }
+
+    #[test]
+    fn watermark_is_placed_in_each_corner() {
+        let corners = [
+            (WatermarkPosition::TopLeft, "overlay=24:24"),
+            (WatermarkPosition::TopRight, "overlay=W-w-24:24"),
+            (WatermarkPosition::BottomLeft, "overlay=24:H-h-24"),
+            (WatermarkPosition::BottomRight, "overlay=W-w-24:H-h-24"),
+        ];
+        for (position, overlay) in corners {
+            let filter = VideoFilterBuilder::default().watermark(&watermark(position)).build();
+            assert_eq!(
+                filter,
+                format!(
+                    "movie=/etc/aperio/logo.png,format=rgba,colorchannelmixer=aa=0.8[watermark];\
+                     [in]scale=trunc(iw/2)*2:trunc(ih/2)*2[base];[base][watermark]{overlay}[out]"
+                ),
+                "{position:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn watermark_composes_with_the_other_filters() {
+        let filter = VideoFilterBuilder::default()
+            .crop(CropRect { x: 420, y: 0, width: 1080, height: 1080 })
+            .fps(30)
+            .subtitles(Path::new("/work/clip.en.vtt"))
+            .watermark(&watermark(WatermarkPosition::BottomRight))
+            .build();
+        assert_eq!(
+            filter,
+            "movie=/etc/aperio/logo.png,format=rgba,colorchannelmixer=aa=0.8[watermark];\
+             [in]crop=1080:1080:420:0,scale=trunc(iw/2)*2:trunc(ih/2)*2,fps=30[base];\
+             [base][watermark]overlay=W-w-24:H-h-24,subtitles=/work/clip.en.vtt[out]"
+        );
+    }
+
+    #[test]
+    fn watermark_path_is_escaped() {
+        let mut config = watermark(WatermarkPosition::TopLeft);
+        config.path = PathBuf::from("/logos/a:b's,logo.png");
+        let filter = VideoFilterBuilder::default().watermark(&config).build();
+        assert!(filter.starts_with(r"movie=/logos/a\\:b\\\'s\,logo.png,format=rgba"), "{filter}");
+    }
+
+    #[test]
+    fn filters_without_a_watermark_are_a_plain_chain() {
+        let filter = VideoFilterBuilder::default().speed(1.5).fps(24).build();
+        assert_eq!(filter, "scale=trunc(iw/2)*2:trunc(ih/2)*2,setpts=PTS/1.5,fps=24");
+    }
+
+    #[test]
+    fn filter_graph_reads_and_writes_the_given_labels() {
+        let builder = VideoFilterBuilder::default().fps(24);
+        assert_eq!(builder.graph("joined", "v"), "[joined]scale=trunc(iw/2)*2:trunc(ih/2)*2,fps=24[v]");
+
+        let filter = builder.watermark(&watermark(WatermarkPosition::TopLeft)).graph("joined", "v");
+        assert!(filter.contains("[joined]scale=trunc(iw/2)*2:trunc(ih/2)*2,fps=24[base];"), "{filter}");
+        assert!(filter.ends_with("[base][watermark]overlay=24:24[v]"), "{filter}");
+    }
+
+    fn concat_source(width: u64, height: u64, audio: bool) -> ConcatSource {
+        let stream = VideoStreamInfo {
+            width: Some(width),
+            height: Some(height),
+            frame_rate: Some(25.0),
+            audio_codec: audio.then(|| "aac".to_string()),
+            ..VideoStreamInfo::default()
+        };
+        ConcatSource { stream, duration: (!audio).then_some(4.5) }
+    }
+
+    #[test]
+    fn concat_joins_every_source_at_the_first_ones_size_and_rate() {
+        let joined = concat_filter(&[concat_source(1281, 720, true), concat_source(640, 480, false)], true).unwrap();
+        assert!(joined.audio);
+        assert_eq!(
+            joined.graph,
+            "[0:v:0]scale=1280:720:force_original_aspect_ratio=decrease,pad=1280:720:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=25,format=yuv420p[v0];\
+             [0:a:0]aresample=48000,aformat=channel_layouts=stereo[a0];\
+             [1:v:0]scale=1280:720:force_original_aspect_ratio=decrease,pad=1280:720:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=25,format=yuv420p[v1];\
+             anullsrc=r=48000:cl=stereo,atrim=duration=4.500[a1];\
+             [v0][a0][v1][a1]concat=n=2:v=1:a=1[joined][joined_a]"
+        );
+    }
+
+    #[test]
+    fn concat_without_wanted_or_available_audio_joins_video_only() {
+        let muted = concat_filter(&[concat_source(1280, 720, true), concat_source(1280, 720, true)], false).unwrap();
+        assert!(!muted.audio);
+        assert!(muted.graph.ends_with("[v0][v1]concat=n=2:v=1:a=0[joined]"), "{}", muted.graph);
+
+        let silent = concat_filter(&[concat_source(1280, 720, false), concat_source(1280, 720, false)], true).unwrap();
+        assert!(!silent.audio);
+        assert!(!silent.graph.contains("anullsrc"), "{}", silent.graph);
+    }
+
+    #[test]
+    fn loudness_summary_reads_the_input_measurements() {
+        let measured = parse_loudness_summary(LOUDNORM_STDERR).unwrap();
+        assert_eq!(measured, LoudnessMeasurement {
+            integrated_lufs: -27.61,
+            true_peak_dbtp: -4.47,
+            loudness_range_lu: 18.06,
+            threshold_lufs: -39.20,
+            target_offset_lu: 0.58,
+        });
+    }
+
+    #[test]
+    fn loudness_summary_uses_the_last_json_block() {
+        // Source metadata can contain braces earlier in the log
+        let stderr = format!("  title           : {{live}} session\n{LOUDNORM_STDERR}");
+        assert_eq!(parse_loudness_summary(&stderr).unwrap().integrated_lufs, -27.61);
+    }
+
+    #[test]
+    fn loudness_summary_rejects_silence() {
+        let stderr = LOUDNORM_STDERR.replace(r#""input_i" : "-27.61""#, r#""input_i" : "-inf""#);
+        let error = parse_loudness_summary(&stderr).unwrap_err().to_string();
+        assert!(error.contains("missing input_i"), "{error}");
+    }
+
+    #[test]
+    fn loudness_summary_rejects_missing_fields() {
+        let stderr = LOUDNORM_STDERR.replace(r#"	"target_offset" : "0.58"
+"#, "");
+        let stderr = stderr.replace(r#""normalization_type" : "dynamic","#, r#""normalization_type" : "dynamic""#);
+        let error = parse_loudness_summary(&stderr).unwrap_err().to_string();
+        assert!(error.contains("missing target_offset"), "{error}");
+    }
+
+    #[test]
+    fn loudness_summary_needs_a_summary() {
+        let error = parse_loudness_summary("Conversion failed!\n").unwrap_err().to_string();
+        assert!(error.contains("No loudness summary"), "{error}");
+        let error = parse_loudness_summary("{ \"input_i\" : ").unwrap_err().to_string();
+        assert!(error.contains("No loudness summary"), "{error}");
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::ProcessorBackend;
+use crate::error::{AppError, AppResult};
+use crate::models::job::Job;
+use crate::services::ProcessService;
+use futures::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+/// Turns a job's source file into its `{job_id}_processed.mp4` output, recording what it did in the job's metadata
+pub trait Processor: Send + Sync {
+    fn process<'a>(&'a self, job: &'a mut Job, input_path: &'a Path) -> BoxFuture<'a, AppResult<PathBuf>>;
+}
+
+/// Pick the processor configured by APERIO_PROCESSOR
+pub fn build_processor(backend: &ProcessorBackend, ffmpeg: Arc<ProcessService>) -> Box<dyn Processor> {
+    match backend {
+        ProcessorBackend::Ffmpeg => Box::new(FfmpegProcessor { service: ffmpeg }),
+        ProcessorBackend::Passthrough => Box::new(PassthroughProcessor),
+    }
+}
+
+/// The ffmpeg backed `ProcessService`
+pub struct FfmpegProcessor {
+    service: Arc<ProcessService>,
+}
+
+impl Processor for FfmpegProcessor {
+    fn process<'a>(&'a self, job: &'a mut Job, input_path: &'a Path) -> BoxFuture<'a, AppResult<PathBuf>> {
+        Box::pin(self.service.process(job, input_path))
+    }
+}
+
+/// Serves the source unchanged, for running the pipeline where ffmpeg isn't installed. A concat
+/// job is served its first source.
+pub struct PassthroughProcessor;
+
+impl Processor for PassthroughProcessor {
+    fn process<'a>(&'a self, job: &'a mut Job, input_path: &'a Path) -> BoxFuture<'a, AppResult<PathBuf>> {
+        Box::pin(async move {
+            let job_dir = input_path.parent()
+                .ok_or_else(|| AppError::Processing("Input file has no parent directory".to_string()))?;
+            let output_path = job_dir.join(format!("{}_processed.mp4", job.id));
+
+            // Copy rather than rename so keep_original and re-processing still find the source
+            tokio::fs::copy(input_path, &output_path).await
+                .map_err(|e| AppError::Processing(format!("Failed to copy source to output: {e}")))?;
+
+            job.metadata.stream_copy = Some(true);
+            job.metadata.output_size_bytes = tokio::fs::metadata(&output_path).await.ok().map(|m| m.len());
+            info!("Passed job {} source through unprocessed", job.id);
+            Ok(output_path)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn passthrough_copies_the_source_next_to_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = Job::new("https://example.com/clip.mp4".to_string());
+        let input_path = dir.path().join(format!("{}_original.webm", job.id));
+        std::fs::write(&input_path, b"source bytes").unwrap();
+
+        let output_path = PassthroughProcessor.process(&mut job, &input_path).await.unwrap();
+
+        assert_eq!(output_path, dir.path().join(format!("{}_processed.mp4", job.id)));
+        assert_eq!(std::fs::read(&output_path).unwrap(), b"source bytes");
+        assert!(input_path.exists());
+        assert_eq!(job.metadata.stream_copy, Some(true));
+        assert_eq!(job.metadata.output_size_bytes, Some(12));
+    }
+
+    #[tokio::test]
+    async fn passthrough_fails_as_processing_without_a_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut job = Job::new("https://example.com/clip.mp4".to_string());
+        let error = PassthroughProcessor.process(&mut job, &dir.path().join("missing.mp4")).await.unwrap_err();
+        assert!(matches!(error, AppError::Processing(_)), "{error:?}");
+    }
+}
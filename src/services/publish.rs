@@ -0,0 +1,308 @@
+use crate::config::PublishConfig;
+use crate::counter_inc;
+use crate::error::{AppError, AppResult};
+use crate::models::job::{Job, PublishStatus};
+use crate::services::checksum::sha256_file;
+use crate::services::job_repository::JobLogLevel;
+use crate::services::retry::{retry_with_backoff, RetryConfig};
+use crate::services::security::redact_text;
+use crate::services::JobRepository;
+use base64::Engine;
+use futures::future::BoxFuture;
+use k256::schnorr::SigningKey;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Nostr event kind of a Blossom authorization (BUD-01)
+const BLOSSOM_AUTH_KIND: u32 = 24242;
+
+/// How long a signed upload authorization stays valid
+const AUTH_VALIDITY_SECS: i64 = 300;
+
+/// An upload slower than this many bytes per second for a minute is abandoned
+const MIN_UPLOAD_SPEED: u32 = 1024;
+
+/// Where a publisher put an output
+#[derive(Debug, Clone)]
+pub struct PublishedBlob {
+    pub url: String,
+    pub sha256: String,
+}
+
+/// Copies completed outputs somewhere clients fetch them from directly
+pub trait Publisher: Send + Sync {
+    /// Short name for logs and metrics
+    fn name(&self) -> &'static str;
+
+    /// Upload the file at `path`, whose hex SHA-256 is `sha256`
+    fn publish<'a>(&'a self, path: &'a Path, sha256: &'a str) -> BoxFuture<'a, AppResult<PublishedBlob>>;
+}
+
+/// Pick the publisher configured by APERIO_PUBLISH_*, if any
+pub fn build_publisher(config: &PublishConfig, command: &str) -> AppResult<Option<Box<dyn Publisher>>> {
+    let Some(server_url) = &config.blossom_url else {
+        return Ok(None);
+    };
+    let auth = match (&config.nostr_secret_key, &config.auth_token) {
+        (Some(key), _) => BlossomAuth::Key(Box::new(parse_secret_key(key)?)),
+        (None, Some(token)) => BlossomAuth::Token(token.trim().to_string()),
+        (None, None) => return Err(AppError::Internal("Blossom publishing needs a Nostr key or an auth token".to_string())),
+    };
+    Ok(Some(Box::new(BlossomPublisher::new(command, server_url, auth))))
+}
+
+fn parse_secret_key(hex_key: &str) -> AppResult<SigningKey> {
+    let hex_key = hex_key.trim();
+    let bytes = (0..hex_key.len())
+        .step_by(2)
+        .map(|i| hex_key.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| AppError::Internal("APERIO_PUBLISH_NOSTR_KEY is not hex".to_string()))?;
+    SigningKey::from_bytes(&bytes)
+        .map_err(|_| AppError::Internal("APERIO_PUBLISH_NOSTR_KEY is not a valid secp256k1 secret key".to_string()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// How uploads to a Blossom server are authorized
+enum BlossomAuth {
+    /// Sign a kind 24242 event for each blob
+    Key(Box<SigningKey>),
+    /// A base64 authorization event signed elsewhere, sent unchanged
+    Token(String),
+}
+
+/// Blob descriptor a Blossom server answers an upload with (BUD-02)
+#[derive(Deserialize)]
+struct BlobDescriptor {
+    url: String,
+    sha256: String,
+}
+
+/// Uploads outputs to a Blossom media server with `PUT /upload`, for serving them to Nostr clients
+pub struct BlossomPublisher {
+    command: String,
+    upload_url: String,
+    auth: BlossomAuth,
+}
+
+impl BlossomPublisher {
+    fn new(command: &str, server_url: &str, auth: BlossomAuth) -> Self {
+        Self {
+            command: command.to_string(),
+            upload_url: format!("{}/upload", server_url.trim_end_matches('/')),
+            auth,
+        }
+    }
+
+    /// `Authorization` header value for uploading the blob `sha256`
+    fn authorization(&self, sha256: &str, file_name: &str) -> AppResult<String> {
+        let key = match &self.auth {
+            BlossomAuth::Token(token) => return Ok(format!("Nostr {token}")),
+            BlossomAuth::Key(key) => key,
+        };
+
+        let created_at = chrono::Utc::now().timestamp();
+        let pubkey = hex(&key.verifying_key().to_bytes());
+        let tags = serde_json::json!([
+            ["t", "upload"],
+            ["x", sha256],
+            ["expiration", (created_at + AUTH_VALIDITY_SECS).to_string()],
+        ]);
+        let content = format!("Upload {file_name}");
+
+        // NIP-01: the event id is the SHA-256 of this exact serialization, which it signs
+        let serialized = serde_json::json!([0, pubkey, created_at, BLOSSOM_AUTH_KIND, tags, content]).to_string();
+        let id: [u8; 32] = Sha256::digest(serialized.as_bytes()).into();
+        let aux_rand: [u8; 32] = std::array::from_fn(|_| fastrand::u8(..));
+        let signature = key.sign_raw(&id, &aux_rand)
+            .map_err(|e| AppError::Internal(format!("Failed to sign Blossom authorization: {e}")))?;
+
+        let event = serde_json::json!({
+            "id": hex(&id),
+            "pubkey": pubkey,
+            "created_at": created_at,
+            "kind": BLOSSOM_AUTH_KIND,
+            "tags": tags,
+            "content": content,
+            "sig": hex(&signature.to_bytes()),
+        });
+        Ok(format!("Nostr {}", base64::engine::general_purpose::STANDARD.encode(event.to_string())))
+    }
+
+    async fn upload(&self, path: &Path, sha256: &str) -> AppResult<PublishedBlob> {
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("output.mp4");
+        let authorization = self.authorization(sha256, file_name)?;
+
+        // Headers go through stdin so the authorization never shows in the process list
+        let mut child = Command::new(&self.command)
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--proto")
+            .arg("=https")
+            .arg("--connect-timeout")
+            .arg("30")
+            .arg("--speed-limit")
+            .arg(MIN_UPLOAD_SPEED.to_string())
+            .arg("--speed-time")
+            .arg("60")
+            .arg("--header")
+            .arg("@-")
+            .arg("--upload-file")
+            .arg(path)
+            .arg(&self.upload_url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| AppError::Internal(format!("Failed to run {}: {e}", self.command)))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            let headers = format!("Authorization: {authorization}\nContent-Type: video/mp4\nX-SHA-256: {sha256}\n");
+            stdin.write_all(headers.as_bytes()).await
+                .map_err(AppError::io("Failed to write upload headers"))?;
+        }
+
+        let output = child.wait_with_output().await
+            .map_err(|e| AppError::Internal(format!("Failed to run {}: {e}", self.command)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(AppError::Storage(format!("Blossom upload failed: {}", redact_text(stderr.trim()))));
+        }
+
+        let descriptor: BlobDescriptor = serde_json::from_slice(&output.stdout)
+            .map_err(|e| AppError::Storage(format!("Blossom server answered with an invalid blob descriptor: {e}")))?;
+        if !descriptor.sha256.eq_ignore_ascii_case(sha256) {
+            return Err(AppError::Storage(format!(
+                "Blossom server stored hash {} but the output hashes to {sha256}", descriptor.sha256
+            )));
+        }
+        Ok(PublishedBlob { url: descriptor.url, sha256: descriptor.sha256.to_lowercase() })
+    }
+}
+
+impl Publisher for BlossomPublisher {
+    fn name(&self) -> &'static str {
+        "blossom"
+    }
+
+    fn publish<'a>(&'a self, path: &'a Path, sha256: &'a str) -> BoxFuture<'a, AppResult<PublishedBlob>> {
+        Box::pin(self.upload(path, sha256))
+    }
+}
+
+/// Publishes completed outputs in the background, retrying failed uploads. Publishing never
+/// fails a job; how it went is kept in the job's `publish_status`.
+pub struct PublishService {
+    publisher: Option<Box<dyn Publisher>>,
+    job_repository: JobRepository,
+    retry: RetryConfig,
+}
+
+impl PublishService {
+    pub fn new(publisher: Option<Box<dyn Publisher>>, job_repository: JobRepository, max_attempts: u32) -> Self {
+        if let Some(publisher) = &publisher {
+            info!("Publishing completed outputs to {}, {} attempts each", publisher.name(), max_attempts);
+        }
+        Self {
+            publisher,
+            job_repository,
+            retry: RetryConfig {
+                max_attempts,
+                base_delay: Duration::from_secs(10),
+                max_delay: Duration::from_secs(300),
+                backoff_multiplier: 3.0,
+                max_elapsed: None,
+            },
+        }
+    }
+
+    /// Start publishing a completed job's output and mark it pending. Does nothing without a
+    /// publisher or an output.
+    pub async fn publish_job(self: &Arc<Self>, job: &mut Job) {
+        if self.publisher.is_none() {
+            return;
+        }
+        let Some(processed_path) = job.get_processed_path() else {
+            return;
+        };
+
+        job.publish_status = Some(PublishStatus::Pending);
+        if let Err(e) = self.job_repository.update_publish_state(&job.id, PublishStatus::Pending, None, None).await {
+            warn!("Failed to mark job {} for publishing: {}", job.id, e);
+        }
+
+        let service = self.clone();
+        let job_id = job.id.clone();
+        let sha256 = job.processed_sha256.clone();
+        tokio::spawn(async move { service.run(&job_id, processed_path, sha256).await });
+    }
+
+    /// Pick up uploads a restart interrupted
+    pub async fn resume_pending(self: &Arc<Self>) {
+        if self.publisher.is_none() {
+            return;
+        }
+        match self.job_repository.list_jobs_pending_publish().await {
+            Ok(jobs) => {
+                if !jobs.is_empty() {
+                    info!("Resuming publishing of {} jobs", jobs.len());
+                }
+                for mut job in jobs {
+                    self.publish_job(&mut job).await;
+                }
+            }
+            Err(e) => warn!("Failed to list jobs pending publish: {}", e),
+        }
+    }
+
+    async fn run(&self, job_id: &str, path: PathBuf, sha256: Option<String>) {
+        let Some(publisher) = &self.publisher else {
+            return;
+        };
+
+        let result = async {
+            let sha256 = match sha256 {
+                Some(sha256) => sha256,
+                None => sha256_file(&path).await.map_err(AppError::io("Failed to hash output for publishing"))?,
+            };
+            retry_with_backoff(|| publisher.publish(&path, &sha256), &self.retry, "publish").await
+        }.await;
+
+        let (status, log_level, message) = match &result {
+            Ok(blob) => {
+                info!("Published output of job {} to {}", job_id, blob.url);
+                (PublishStatus::Published, JobLogLevel::Info, format!("Published output to {}", blob.url))
+            }
+            Err(e) => {
+                warn!("Failed to publish output of job {}: {}", job_id, e);
+                (PublishStatus::Failed, JobLogLevel::Error, format!("Failed to publish output: {e}"))
+            }
+        };
+        counter_inc!("aperio_publish_total", "publisher" => publisher.name(), "outcome" => status.as_str());
+
+        let blob = result.ok();
+        if let Err(e) = self.job_repository.update_publish_state(
+            job_id,
+            status,
+            blob.as_ref().map(|blob| blob.url.as_str()),
+            blob.as_ref().map(|blob| blob.sha256.as_str()),
+        ).await {
+            warn!("Failed to record publish status of job {}: {}", job_id, e);
+        }
+        if let Err(e) = self.job_repository.append_job_log(job_id, log_level, &message).await {
+            warn!("Failed to write log line for job {}: {}", job_id, e);
+        }
+    }
+}
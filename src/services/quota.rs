@@ -0,0 +1,218 @@
+use crate::error::{AppError, AppResult};
+use crate::models::job::Job;
+use crate::{counter_inc, gauge_set};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+/// Logical cap on bytes held in the working and storage dirs, enforced when jobs are submitted.
+/// Usage is tracked incrementally as jobs complete and are cleaned up, and reset from a
+/// directory scan periodically so drift from crashes or manual deletes doesn't accumulate.
+pub struct StorageQuota {
+    max_bytes: Option<u64>,
+    headroom_bytes: u64,
+    /// Scanned on reconcile: the working dir for in-flight jobs, the storage dir for outputs
+    dirs: Vec<PathBuf>,
+    used_bytes: AtomicU64,
+    /// Woken when a submission is rejected, so eviction can free space without waiting for its interval
+    pressure: Notify,
+    /// Breakdown from the last reconcile, so usage reports don't walk the disk per request
+    snapshot: RwLock<StorageSnapshot>,
+}
+
+/// What the last reconcile scan found in each directory
+#[derive(Debug, Clone, Default)]
+pub struct StorageSnapshot {
+    pub scanned_at: Option<DateTime<Utc>>,
+    /// Total bytes per scanned directory, in the order they were configured
+    pub dir_bytes: Vec<(PathBuf, u64)>,
+    /// Bytes per job id across all directories; entries not named after a job aren't included
+    pub job_bytes: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StorageUsage {
+    pub used_bytes: u64,
+    pub max_bytes: Option<u64>,
+    pub headroom_bytes: u64,
+}
+
+impl StorageQuota {
+    pub fn new(max_bytes: Option<u64>, headroom_bytes: u64, dirs: Vec<PathBuf>) -> Self {
+        Self {
+            max_bytes,
+            headroom_bytes,
+            dirs,
+            used_bytes: AtomicU64::new(0),
+            pressure: Notify::new(),
+            snapshot: RwLock::new(StorageSnapshot::default()),
+        }
+    }
+
+    pub fn usage(&self) -> StorageUsage {
+        StorageUsage {
+            used_bytes: self.used_bytes.load(Ordering::Relaxed),
+            max_bytes: self.max_bytes,
+            headroom_bytes: self.headroom_bytes,
+        }
+    }
+
+    /// Bytes that can still be written before the cap is reached; None when no cap is configured
+    pub fn remaining_bytes(&self) -> Option<u64> {
+        self.max_bytes.map(|max| max.saturating_sub(self.used_bytes.load(Ordering::Relaxed)))
+    }
+
+    pub async fn add(&self, bytes: u64) {
+        let used = self.used_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        gauge_set!("aperio_storage_used_bytes", used as f64);
+    }
+
+    pub async fn release(&self, bytes: u64) {
+        let previous = self.used_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| Some(used.saturating_sub(bytes)))
+            .unwrap_or_default();
+        gauge_set!("aperio_storage_used_bytes", previous.saturating_sub(bytes) as f64);
+    }
+
+    /// Refuse new work when usage plus headroom would exceed the cap
+    pub async fn check_admission(&self) -> AppResult<()> {
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(());
+        };
+        let used = self.used_bytes.load(Ordering::Relaxed);
+
+        if used.saturating_add(self.headroom_bytes) > max_bytes {
+            warn!(
+                "Rejecting submission: {} bytes used + {} headroom exceeds quota of {} bytes",
+                used, self.headroom_bytes, max_bytes
+            );
+            counter_inc!("aperio_admission_rejected_total", "reason" => "storage_quota");
+            self.pressure.notify_one();
+            return Err(AppError::InsufficientStorage(
+                "Storage quota exhausted; try again after retention frees space".to_string()
+            ));
+        }
+
+        debug!(
+            "Admitting submission: {} bytes used + {} headroom within quota of {} bytes",
+            used, self.headroom_bytes, max_bytes
+        );
+        Ok(())
+    }
+
+    /// Resolves after a submission is rejected for lack of space
+    pub async fn wait_for_pressure(&self) {
+        self.pressure.notified().await
+    }
+
+    /// Replace the tracked total with the actual size of the working and storage dirs
+    pub async fn reconcile(&self) -> u64 {
+        let mut snapshot = StorageSnapshot {
+            scanned_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        for dir in &self.dirs {
+            let (total, job_bytes) = scan_job_dirs(dir).await;
+            for (job_id, bytes) in job_bytes {
+                *snapshot.job_bytes.entry(job_id).or_default() += bytes;
+            }
+            snapshot.dir_bytes.push((dir.clone(), total));
+        }
+        let scanned = snapshot.dir_bytes.iter().map(|(_, bytes)| bytes).sum();
+        *self.snapshot.write().unwrap_or_else(|e| e.into_inner()) = snapshot;
+
+        let previous = self.used_bytes.swap(scanned, Ordering::Relaxed);
+        gauge_set!("aperio_storage_used_bytes", scanned as f64);
+        if previous != scanned {
+            info!("Storage usage reconciled: tracked {} bytes, scanned {} bytes", previous, scanned);
+        }
+        scanned
+    }
+
+    /// The breakdown from the last reconcile
+    pub fn snapshot(&self) -> StorageSnapshot {
+        self.snapshot.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Rescan on a fixed interval for the life of the process
+    pub async fn start_reconciler(self: Arc<Self>, every: Duration) {
+        let mut interval = interval(every);
+        // The first tick fires immediately and startup has just scanned
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.reconcile().await;
+        }
+    }
+}
+
+/// Bytes of a completed job's files that stay on disk: the output, its preview, and a kept original
+pub async fn job_output_bytes(job: &Job) -> u64 {
+    let mut paths = vec![job.get_processed_path(), job.get_preview_path()];
+    if job.keep_original {
+        paths.push(job.get_downloaded_path());
+    }
+
+    let mut total = 0;
+    for path in paths.into_iter().flatten() {
+        if let Ok(metadata) = tokio::fs::metadata(&path).await {
+            total += metadata.len();
+        }
+    }
+    total
+}
+
+/// Total size of a directory, and the bytes of each `{job_id}/` directory or legacy `{job_id}_*` file in it,
+/// walked on the blocking pool
+async fn scan_job_dirs(path: &Path) -> (u64, HashMap<String, u64>) {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut total = 0;
+        let mut job_bytes: HashMap<String, u64> = HashMap::new();
+        let Ok(entries) = std::fs::read_dir(&path) else {
+            return (total, job_bytes);
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (bytes, job_id) = match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => (dir_size_blocking(entry.path()), Some(name.as_str())),
+                Ok(metadata) => (metadata.len(), name.split_once('_').map(|(job_id, _)| job_id)),
+                Err(_) => continue,
+            };
+            total += bytes;
+            if let Some(job_id) = job_id.filter(|job_id| uuid::Uuid::parse_str(job_id).is_ok()) {
+                *job_bytes.entry(job_id.to_string()).or_default() += bytes;
+            }
+        }
+        (total, job_bytes)
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Total size of the regular files under `path`
+fn dir_size_blocking(path: PathBuf) -> u64 {
+    let mut total = 0;
+    let mut pending = vec![path];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => pending.push(entry.path()),
+                Ok(metadata) => total += metadata.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    total
+}
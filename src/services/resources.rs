@@ -0,0 +1,146 @@
+use crate::gauge_set;
+use crate::services::ConnectionPoolManager;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::info;
+
+/// How often the process's resource usage is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Resource usage of the Aperio process at one point in time. The `/proc` readings are None
+/// on platforms without it, or when it can't be read.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceStats {
+    pub sampled_at: DateTime<Utc>,
+    pub rss_bytes: Option<u64>,
+    pub open_fds: Option<u64>,
+    /// Every process started by Aperio and still running: yt-dlp, ffmpeg, ffprobe, hooks, curl
+    pub child_processes: Option<u64>,
+    /// Download slots taken, each one a running yt-dlp or HTTP download
+    pub download_slots_in_use: usize,
+    /// Processing slots taken, each one a running ffmpeg
+    pub processing_slots_in_use: usize,
+    /// Tasks alive on the main runtime, which runs the queue worker, jobs and background services
+    pub tokio_alive_tasks: usize,
+    pub tokio_workers: usize,
+}
+
+/// Samples the process's memory, file descriptors, child processes and tasks on a timer,
+/// publishing them as gauges and keeping the latest sample for the detailed health check
+pub struct ResourceMonitor {
+    pool_manager: Arc<ConnectionPoolManager>,
+    /// APERIO_MAX_RSS_MB, over which the process health check is degraded
+    max_rss_bytes: Option<u64>,
+    latest: RwLock<Option<ResourceStats>>,
+}
+
+impl ResourceMonitor {
+    pub fn new(pool_manager: Arc<ConnectionPoolManager>, max_rss_bytes: Option<u64>) -> Self {
+        Self { pool_manager, max_rss_bytes, latest: RwLock::new(None) }
+    }
+
+    pub fn max_rss_bytes(&self) -> Option<u64> {
+        self.max_rss_bytes
+    }
+
+    /// The last sample; None until the first one is taken
+    pub fn latest(&self) -> Option<ResourceStats> {
+        self.latest.read().unwrap().clone()
+    }
+
+    /// Sample every SAMPLE_INTERVAL, starting at once
+    pub async fn start_sampler(self: Arc<Self>) {
+        if !cfg!(target_os = "linux") {
+            info!("Process memory, file descriptor and child process stats are only available on Linux");
+        }
+        let mut interval = interval(SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.sample().await;
+        }
+    }
+
+    async fn sample(&self) {
+        let runtime = tokio::runtime::Handle::current().metrics();
+        let stats = ResourceStats {
+            sampled_at: Utc::now(),
+            rss_bytes: proc_self::rss_bytes(),
+            open_fds: proc_self::open_fds(),
+            child_processes: proc_self::child_processes(),
+            download_slots_in_use: self.pool_manager.get_download_stats().in_use,
+            processing_slots_in_use: self.pool_manager.get_processing_stats().in_use,
+            tokio_alive_tasks: runtime.num_alive_tasks(),
+            tokio_workers: runtime.num_workers(),
+        };
+
+        if let Some(rss_bytes) = stats.rss_bytes {
+            gauge_set!("aperio_process_resident_memory_bytes", rss_bytes as f64);
+        }
+        if let Some(open_fds) = stats.open_fds {
+            gauge_set!("aperio_process_open_fds", open_fds as f64);
+        }
+        if let Some(child_processes) = stats.child_processes {
+            gauge_set!("aperio_process_child_processes", child_processes as f64);
+        }
+        gauge_set!("aperio_tokio_alive_tasks", stats.tokio_alive_tasks as f64);
+        gauge_set!("aperio_tokio_workers", stats.tokio_workers as f64);
+
+        *self.latest.write().unwrap() = Some(stats);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod proc_self {
+    use std::fs;
+
+    /// VmRSS from /proc/self/status, which the kernel reports in kB
+    pub fn rss_bytes() -> Option<u64> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let kilobytes = status.lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kilobytes * 1024)
+    }
+
+    pub fn open_fds() -> Option<u64> {
+        let entries = fs::read_dir("/proc/self/fd").ok()?;
+        // Listing the directory holds one descriptor open itself
+        Some((entries.count() as u64).saturating_sub(1))
+    }
+
+    /// Children of every thread, since tokio spawns processes from whichever worker is running
+    pub fn child_processes() -> Option<u64> {
+        let tasks = fs::read_dir("/proc/self/task").ok()?;
+        // None if no thread's list could be read, e.g. a kernel built without it; a thread that
+        // exited while listing is skipped
+        let mut children = None;
+        for task in tasks.flatten() {
+            if let Ok(listed) = fs::read_to_string(task.path().join("children")) {
+                *children.get_or_insert(0) += listed.split_whitespace().count() as u64;
+            }
+        }
+        children
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod proc_self {
+    pub fn rss_bytes() -> Option<u64> {
+        None
+    }
+
+    pub fn open_fds() -> Option<u64> {
+        None
+    }
+
+    pub fn child_processes() -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,88 @@
+use crate::error::{AppError, AppResult};
+use crate::models::job::{Job, JobStatus, ProcessingOptions};
+use crate::services::{JobRepository, StorageService};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Serves repeat submissions from earlier completed jobs whose outputs are still on disk
+pub struct ResultCache {
+    enabled: bool,
+    storage_service: Arc<StorageService>,
+    job_repository: JobRepository,
+}
+
+impl ResultCache {
+    pub fn new(enabled: bool, storage_service: Arc<StorageService>, job_repository: JobRepository) -> Self {
+        Self {
+            enabled,
+            storage_service,
+            job_repository,
+        }
+    }
+
+    /// Find a completed job for the same normalized URL and options whose processed file still exists
+    pub async fn lookup(&self, normalized_url: &str, options: &ProcessingOptions) -> AppResult<Option<Job>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        // Retention may have removed the file without touching newer rows, so existence is checked here
+        for candidate in self.job_repository.find_completed_jobs_by_url(normalized_url).await? {
+            if &candidate.options != options {
+                continue;
+            }
+            if let Some(processed_path) = candidate.get_processed_path() {
+                if tokio::fs::try_exists(&processed_path).await.unwrap_or(false) {
+                    return Ok(Some(candidate));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Complete `job` immediately by hard-linking the cached job's outputs into its own storage directory
+    pub async fn materialize(&self, job: &mut Job, cached: &Job) -> AppResult<()> {
+        let cached_processed = cached.get_processed_path()
+            .ok_or_else(|| AppError::Internal(format!("Cached job {} has no output", cached.id)))?;
+
+        let job_dir = self.storage_service.job_dir(&job.id);
+        tokio::fs::create_dir_all(&job_dir).await
+            .map_err(AppError::io("Failed to create job directory"))?;
+
+        let processed_path = job_dir.join(format!("{}_processed.mp4", job.id));
+        link_or_copy(&cached_processed, &processed_path).await?;
+        job.set_processed_path(processed_path);
+
+        // The preview is a nice-to-have; a missing one doesn't invalidate the hit
+        if let Some(cached_preview) = cached.get_preview_path() {
+            let preview_path = job_dir.join(format!("{}_preview.gif", job.id));
+            match link_or_copy(&cached_preview, &preview_path).await {
+                Ok(()) => job.set_preview_path(preview_path),
+                Err(e) => warn!("Failed to reuse preview of cached job {}: {}", cached.id, e),
+            }
+        }
+
+        job.metadata = cached.metadata.clone();
+        job.processed_sha256 = cached.processed_sha256.clone();
+        job.metadata.cached_from = Some(cached.id.clone());
+        job.processing_time_seconds = Some(0);
+        job.status = JobStatus::Completed;
+        self.job_repository.update_job(job).await?;
+
+        info!("Served job {} from cached output of job {}", job.id, cached.id);
+        Ok(())
+    }
+}
+
+/// Hard-link so each job owns its file for retention purposes, copying if linking isn't possible
+async fn link_or_copy(source: &Path, destination: &Path) -> AppResult<()> {
+    if tokio::fs::hard_link(source, destination).await.is_ok() {
+        return Ok(());
+    }
+
+    tokio::fs::copy(source, destination).await
+        .map(|_| ())
+        .map_err(|e| AppError::Storage(format!("Failed to reuse cached output: {e}")))
+}
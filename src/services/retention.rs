@@ -1,40 +1,326 @@
-use crate::error::AppResult;
-use crate::services::{JobRepository, CleanupService};
-use std::sync::Arc;
-use std::time::Duration;
+use crate::config::{RetentionConfig, RetentionMode};
+use crate::{counter_add, counter_inc, gauge_set};
+use crate::error::{AppError, AppResult};
+use crate::models::job::JobStatus;
+use crate::services::{AuditLog, JobRepository, CleanupService, StorageQuota};
+use crate::services::job_repository::RetentionCandidate;
+use crate::services::security::job_working_dir;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
 use tokio::time::{interval, sleep};
-use tracing::{info, warn, error};
+use tracing::{debug, info, warn, error};
+
+/// Jobs whose files are removed at once during a cleanup cycle
+const CLEANUP_CONCURRENCY: usize = 8;
+
+/// Candidates fetched per eviction query; more are fetched while usage stays above the target
+const EVICTION_BATCH_SIZE: u32 = 100;
+
+/// Why the orphan sweep picked a file
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanReason {
+    /// No job with this id exists, e.g. its row was deleted or it never got one
+    UnknownJob,
+    /// The job finished past the grace period and the file is not one of its outputs
+    FinishedJob,
+}
+
+/// A working file removed by the orphan sweep, or that would be on a dry run
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrphanFile {
+    /// Relative to the working dir
+    pub path: String,
+    pub job_id: String,
+    pub reason: OrphanReason,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct OrphanSweepReport {
+    pub dry_run: bool,
+    pub files: Vec<OrphanFile>,
+    pub bytes: u64,
+    /// Orphans left alone because they are being processed or borrowed
+    pub skipped_active: usize,
+}
+
+/// Which retention stage selected a job
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionStage {
+    /// The job's files were removed; its record stays as history
+    Files,
+    /// The job's record was deleted along with any files left
+    Record,
+    /// The job was deleted by a client and its restore window passed; record and files removed
+    Deleted,
+}
+
+/// A job handled by a cleanup cycle, or that would be on a dry run
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RetentionJob {
+    pub job_id: String,
+    pub stage: RetentionStage,
+    /// Seconds since the job finished
+    pub age_seconds: i64,
+    /// Bytes freed, or on a dry run the current size of its files
+    pub bytes: u64,
+}
+
+/// What a retention cleanup cycle did; on a dry run, what it would do
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CleanupSummary {
+    pub dry_run: bool,
+    pub jobs: Vec<RetentionJob>,
+    pub idempotency_keys_expired: u64,
+    /// Audit log entries past APERIO_AUDIT_RETENTION_DAYS that were deleted
+    pub audit_entries_deleted: u64,
+    /// Jobs whose files passed the file retention period
+    pub files_expired_jobs: usize,
+    /// Job records past the record retention period that were deleted
+    pub records_deleted: usize,
+    /// Deleted jobs past the restore window that were removed for good
+    pub deleted_jobs_purged: usize,
+    /// Jobs whose files were removed without error
+    pub jobs_cleaned: usize,
+    /// Files removed, including orphans
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+    pub errors: Vec<String>,
+}
+
+/// How the last cleanup cycle that wasn't a dry run went, scheduled or requested
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RetentionRun {
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+    pub duration_ms: u64,
+    /// `success`, `partial` when some files could not be removed, or `failure` when the cycle stopped
+    pub outcome: String,
+    pub records_deleted: usize,
+    pub files_expired_jobs: usize,
+    pub deleted_jobs_purged: usize,
+    pub bytes_freed: u64,
+    /// Why the cycle stopped, or how many files could not be removed
+    pub error: Option<String>,
+}
+
+/// When LRU eviction runs and how much it frees, resolved against the storage quota
+#[derive(Clone, Debug)]
+pub struct EvictionPolicy {
+    pub high_water_bytes: u64,
+    /// Usage eviction frees down to: the low-water mark, or lower if admission needs it
+    pub target_bytes: u64,
+    pub interval: Duration,
+}
+
+impl EvictionPolicy {
+    /// None unless `APERIO_RETENTION_MODE=lru`, which needs a storage quota to measure against
+    pub fn from_config(
+        config: &RetentionConfig,
+        max_storage_bytes: Option<u64>,
+        headroom_bytes: u64,
+    ) -> Result<Option<Self>, String> {
+        if config.mode != RetentionMode::Lru {
+            return Ok(None);
+        }
+        let max_bytes = max_storage_bytes
+            .ok_or("APERIO_RETENTION_MODE=lru requires APERIO_MAX_STORAGE_BYTES")?;
+        if config.eviction_low_water_percent >= config.eviction_high_water_percent {
+            return Err("APERIO_EVICTION_LOW_WATER_PERCENT must be below APERIO_EVICTION_HIGH_WATER_PERCENT".to_string());
+        }
+
+        let low_water_bytes = max_bytes / 100 * config.eviction_low_water_percent;
+        Ok(Some(Self {
+            high_water_bytes: max_bytes / 100 * config.eviction_high_water_percent,
+            target_bytes: low_water_bytes.min(max_bytes.saturating_sub(headroom_bytes)),
+            interval: config.eviction_interval,
+        }))
+    }
+}
 
 #[derive(Clone)]
 pub struct RetentionService {
     job_repository: Arc<JobRepository>,
+    audit_log: AuditLog,
     cleanup_service: Arc<CleanupService>,
-    retention_days: u32,
+    storage_quota: Arc<StorageQuota>,
+    /// Retention periods, swapped on config reload; read once per cycle
+    config: Arc<RwLock<RetentionConfig>>,
     cleanup_interval_hours: u64,
+    eviction: Option<EvictionPolicy>,
+    /// Held for the length of a cleanup cycle or orphan sweep so two never overlap
+    cycle_lock: Arc<Mutex<()>>,
+    last_run: Arc<RwLock<Option<RetentionRun>>>,
+    /// When a cycle last finished, even if some files could not be removed
+    last_success_at: Arc<RwLock<Option<chrono::DateTime<chrono::Utc>>>>,
 }
 
 impl RetentionService {
     pub fn new(
         job_repository: Arc<JobRepository>,
+        audit_log: AuditLog,
         cleanup_service: Arc<CleanupService>,
-        retention_days: u32,
-        cleanup_interval_hours: u64,
+        storage_quota: Arc<StorageQuota>,
+        config: &RetentionConfig,
+        eviction: Option<EvictionPolicy>,
     ) -> Self {
         Self {
             job_repository,
+            audit_log,
             cleanup_service,
-            retention_days,
-            cleanup_interval_hours,
+            storage_quota,
+            config: Arc::new(RwLock::new(config.clone())),
+            cleanup_interval_hours: config.cleanup_interval_hours,
+            eviction,
+            cycle_lock: Arc::new(Mutex::new(())),
+            last_run: Arc::new(RwLock::new(None)),
+            last_success_at: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// The retention settings in effect
+    pub fn config(&self) -> RetentionConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replace the retention periods used from the next cleanup cycle on. Whether retention runs,
+    /// its interval and the eviction policy are fixed at startup.
+    pub fn set_config(&self, config: &RetentionConfig) {
+        let mut current = self.config.write().unwrap();
+        current.file_retention_days = config.file_retention_days;
+        current.record_retention_days = config.record_retention_days;
+        current.max_retain_days = config.max_retain_days;
+        current.idempotency_window_hours = config.idempotency_window_hours;
+        current.orphan_grace_hours = config.orphan_grace_hours;
+        current.soft_delete_window_hours = config.soft_delete_window_hours;
+        current.audit_retention_days = config.audit_retention_days;
+    }
+
+    /// File retention applied to jobs without `retain_days`; None when retention is disabled
+    pub fn default_retain_days(&self) -> Option<u32> {
+        let config = self.config();
+        config.enabled.then_some(config.file_retention_days)
+    }
+
+    /// Upper bound on the `retain_days` a job may request
+    pub fn max_retain_days(&self) -> u32 {
+        self.config().max_retain_days
+    }
+
+    /// How far back a repeated Idempotency-Key returns the original job
+    pub fn idempotency_window(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.config().idempotency_window_hours as i64)
+    }
+
+    /// How long a deleted job can still be restored
+    pub fn soft_delete_window(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.config().soft_delete_window_hours as i64)
+    }
+
+    /// The LRU eviction thresholds, when `APERIO_RETENTION_MODE=lru`
+    pub fn eviction_policy(&self) -> Option<&EvictionPolicy> {
+        self.eviction.as_ref()
+    }
+
+    /// Evict least recently served outputs whenever usage passes the high-water mark.
+    /// Runs on its own interval, and immediately when a submission is rejected for space.
+    pub async fn start_background_eviction(&self) {
+        let Some(policy) = self.eviction.clone() else {
+            return;
+        };
+        info!(
+            "Starting LRU eviction: above {} bytes, free down to {} bytes, checked every {} seconds",
+            policy.high_water_bytes, policy.target_bytes, policy.interval.as_secs()
+        );
+
+        let mut interval = interval(policy.interval);
+        loop {
+            let under_pressure = tokio::select! {
+                _ = interval.tick() => false,
+                _ = self.storage_quota.wait_for_pressure() => true,
+            };
+
+            if let Err(e) = self.run_eviction(&policy, under_pressure).await {
+                error!("LRU eviction failed: {}", e);
+            }
         }
     }
 
+    /// Delete outputs of the least recently served completed jobs until usage is at the target.
+    /// Job rows are kept as Expired so clients learn to resubmit rather than seeing a bare 404.
+    pub async fn run_eviction(&self, policy: &EvictionPolicy, under_pressure: bool) -> AppResult<()> {
+        let mut used = self.storage_quota.usage().used_bytes;
+        if used <= policy.target_bytes || (used <= policy.high_water_bytes && !under_pressure) {
+            debug!("No eviction needed: {} bytes used", used);
+            return Ok(());
+        }
+
+        info!("Evicting outputs: {} bytes used, target {} bytes", used, policy.target_bytes);
+        let mut evicted = 0;
+        let mut freed_bytes = 0;
+
+        'batches: loop {
+            let candidates = self.job_repository.list_eviction_candidates(EVICTION_BATCH_SIZE).await?;
+            let mut progressed = false;
+
+            for job in &candidates {
+                if used <= policy.target_bytes {
+                    break 'batches;
+                }
+                if self.cleanup_service.has_busy_files(&job.id).await {
+                    debug!("Skipping eviction of job {}: files in use", job.id);
+                    continue;
+                }
+
+                // Expire first so /video answers 410 rather than racing the deletion with a 404
+                if !self.job_repository.expire_job(&job.id).await? {
+                    continue;
+                }
+                progressed = true;
+
+                match self.cleanup_service.cleanup_job_files(&job.id).await {
+                    Ok(outcome) => {
+                        self.storage_quota.release(outcome.bytes_freed).await;
+                        used = used.saturating_sub(outcome.bytes_freed);
+                        freed_bytes += outcome.bytes_freed;
+                        evicted += 1;
+                        counter_inc!("aperio_evicted_jobs_total");
+                    }
+                    Err(e) => warn!("Failed to evict files for job {}: {}", job.id, e),
+                }
+            }
+
+            // Everything left is busy or already gone, so another query would return the same rows
+            if candidates.len() < EVICTION_BATCH_SIZE as usize || !progressed {
+                break;
+            }
+        }
+
+        if used > policy.target_bytes {
+            warn!(
+                "Eviction stopped above target: {} bytes used, target {} bytes, no more evictable outputs",
+                used, policy.target_bytes
+            );
+        }
+        info!("Evicted {} job outputs, freeing {} bytes", evicted, freed_bytes);
+        Ok(())
+    }
+
     /// Start the background retention cleanup task
     pub async fn start_background_cleanup(&self) {
         let mut interval = interval(Duration::from_secs(self.cleanup_interval_hours * 3600));
+        let config = self.config();
         
         info!(
-            "Starting retention cleanup service: {} day retention, {} hour intervals",
-            self.retention_days, self.cleanup_interval_hours
+            "Starting retention cleanup service: files kept {} days, records {} days, {} hour intervals",
+            config.file_retention_days, config.record_retention_days, self.cleanup_interval_hours
         );
 
         // Initial delay to avoid startup conflicts
@@ -49,63 +335,379 @@ impl RetentionService {
         }
     }
 
-    /// Run a single cleanup cycle
-    pub async fn run_cleanup(&self) -> AppResult<()> {
-        info!("Starting retention cleanup cycle");
+    /// Run a single cleanup cycle, waiting for any manual run in progress to finish first
+    pub async fn run_cleanup(&self) -> AppResult<CleanupSummary> {
+        let _cycle = self.cycle_lock.lock().await;
+        self.recorded_cycle(false).await
+    }
+
+    /// Run a cleanup cycle on request, refusing if one is already in progress
+    pub async fn run_cleanup_now(&self, dry_run: bool) -> AppResult<CleanupSummary> {
+        let _cycle = self.try_lock_cycle()?;
+        self.recorded_cycle(dry_run).await
+    }
+
+    /// The last cleanup cycle that wasn't a dry run; None until one finishes
+    pub fn last_run(&self) -> Option<RetentionRun> {
+        self.last_run.read().unwrap().clone()
+    }
+
+    /// When a cleanup cycle last finished without stopping, even with files left behind; None until one does
+    pub fn last_success_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        *self.last_success_at.read().unwrap()
+    }
+
+    /// Run a cleanup cycle, keeping how it went for `last_run` and the metrics unless it's a dry run
+    async fn recorded_cycle(&self, dry_run: bool) -> AppResult<CleanupSummary> {
+        let started = std::time::Instant::now();
+        let result = self.cleanup_cycle(dry_run).await;
+        if dry_run {
+            return result;
+        }
+
+        let run = match &result {
+            Ok(summary) => RetentionRun {
+                finished_at: chrono::Utc::now(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                outcome: if summary.errors.is_empty() { "success" } else { "partial" }.to_string(),
+                records_deleted: summary.records_deleted,
+                files_expired_jobs: summary.files_expired_jobs,
+                deleted_jobs_purged: summary.deleted_jobs_purged,
+                bytes_freed: summary.bytes_freed,
+                error: (!summary.errors.is_empty()).then(|| format!("{} errors, first: {}", summary.errors.len(), summary.errors[0])),
+            },
+            Err(e) => RetentionRun {
+                finished_at: chrono::Utc::now(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                outcome: "failure".to_string(),
+                records_deleted: 0,
+                files_expired_jobs: 0,
+                deleted_jobs_purged: 0,
+                bytes_freed: 0,
+                error: Some(e.to_string()),
+            },
+        };
+
+        counter_inc!("aperio_retention_runs_total");
+        gauge_set!("aperio_retention_last_duration_seconds", run.duration_ms as f64 / 1000.0);
+        match &result {
+            Ok(summary) => {
+                counter_add!("aperio_retention_jobs_deleted_total", (summary.records_deleted + summary.deleted_jobs_purged) as u64);
+                counter_add!("aperio_retention_files_expired_jobs_total", summary.files_expired_jobs as u64);
+                counter_add!("aperio_retention_files_removed_total", summary.files_removed as u64);
+                counter_add!("aperio_retention_errors_total", summary.errors.len() as u64);
+                gauge_set!("aperio_retention_last_success_timestamp_seconds", run.finished_at.timestamp() as f64);
+                *self.last_success_at.write().unwrap() = Some(run.finished_at);
+            }
+            Err(_) => {
+                counter_inc!("aperio_retention_failures_total");
+                counter_inc!("aperio_retention_errors_total");
+            }
+        }
+        *self.last_run.write().unwrap() = Some(run);
+        result
+    }
+
+    /// Sweep orphaned files on request, refusing if a cleanup cycle is in progress
+    pub async fn sweep_orphans_now(&self, dry_run: bool) -> AppResult<OrphanSweepReport> {
+        let _cycle = self.try_lock_cycle()?;
+        self.sweep_orphans(dry_run).await
+    }
+
+    fn try_lock_cycle(&self) -> AppResult<tokio::sync::MutexGuard<'_, ()>> {
+        self.cycle_lock.try_lock()
+            .map_err(|_| AppError::Conflict("A retention cleanup cycle is already running".to_string()))
+    }
+
+    /// Delete records past their retention, then expire files past theirs, then sweep orphans.
+    /// Records go first so a job past both periods is handled once, by the record stage.
+    async fn cleanup_cycle(&self, dry_run: bool) -> AppResult<CleanupSummary> {
+        info!("Starting retention cleanup cycle{}", if dry_run { " (dry run)" } else { "" });
+        let mut summary = CleanupSummary { dry_run, ..Default::default() };
+        let config = self.config();
 
         // Get statistics before cleanup
-        let (completed_before, failed_before, cancelled_before) = self.job_repository.get_cleanup_stats().await?;
+        let before = self.job_repository.get_cleanup_stats().await?;
         info!(
-            "Jobs before cleanup - Completed: {}, Failed: {}, Cancelled: {}",
-            completed_before, failed_before, cancelled_before
+            "Jobs before cleanup - Completed: {}, Failed: {}, Cancelled: {}, Expired: {}, without files: {}",
+            before.completed, before.failed, before.cancelled, before.expired, before.files_expired
         );
 
-        // Get old job IDs and delete from database
-        let old_job_ids = self.job_repository.cleanup_old_jobs(self.retention_days).await?;
-        
-        if old_job_ids.is_empty() {
-            info!("No old jobs found for cleanup");
-            return Ok(());
+        let deleted_before = chrono::Utc::now() - self.soft_delete_window();
+
+        if dry_run {
+            let purged = self.job_repository.list_jobs_deleted_before(deleted_before).await?;
+            summary.deleted_jobs_purged = purged.len();
+            self.measure_files(&purged, RetentionStage::Deleted, &mut summary).await;
+
+            let deleted = self.job_repository.list_jobs_past_record_retention(config.record_retention_days).await?;
+            summary.records_deleted = deleted.len();
+            self.measure_files(&deleted, RetentionStage::Record, &mut summary).await;
+
+            let file_expired = self.job_repository.list_jobs_past_file_retention(config.file_retention_days).await?;
+            summary.files_expired_jobs = file_expired.len();
+            self.measure_files(&file_expired, RetentionStage::Files, &mut summary).await;
+
+            let orphans = self.sweep_orphans(true).await?;
+            summary.files_removed += orphans.files.len();
+            summary.bytes_freed += orphans.bytes;
+
+            info!(
+                "Retention dry run - would remove {} database records and {} deleted jobs, and expire files of {} jobs: {} files, {} bytes",
+                summary.records_deleted, summary.deleted_jobs_purged, summary.files_expired_jobs, summary.files_removed, summary.bytes_freed
+            );
+            return Ok(summary);
         }
 
-        info!("Found {} old jobs to clean up", old_job_ids.len());
+        // Idempotency keys only matter within their window; drop them so the column doesn't accumulate
+        let key_cutoff = chrono::Utc::now() - chrono::Duration::hours(config.idempotency_window_hours as i64);
+        summary.idempotency_keys_expired = self.job_repository.expire_idempotency_keys(key_cutoff).await?;
+        if summary.idempotency_keys_expired > 0 {
+            info!("Expired {} idempotency keys", summary.idempotency_keys_expired);
+        }
 
-        // Clean up associated files
-        let mut file_cleanup_errors = Vec::new();
-        let mut successful_file_cleanups = 0;
+        // The audit log has its own period, independent of the jobs it mentions
+        summary.audit_entries_deleted = self.audit_log.delete_older_than(config.audit_retention_days).await?;
+        if summary.audit_entries_deleted > 0 {
+            info!("Deleted {} audit log entries", summary.audit_entries_deleted);
+        }
 
-        for job_id in &old_job_ids {
-            match self.cleanup_service.cleanup_job_files(job_id).await {
-                Ok(_) => {
-                    successful_file_cleanups += 1;
-                }
-                Err(e) => {
-                    warn!("Failed to clean up files for job {}: {}", job_id, e);
-                    file_cleanup_errors.push(format!("Job {job_id}: {e}"));
-                }
+        // Deleted jobs go for good once they can no longer be restored, whatever their retention
+        let purged = self.job_repository.purge_jobs_deleted_before(deleted_before).await?;
+        summary.deleted_jobs_purged = purged.len();
+        if !purged.is_empty() {
+            info!("Removed {} deleted jobs past the restore window", purged.len());
+        }
+        self.cleanup_files(&purged, RetentionStage::Deleted, &mut summary).await;
+
+        // Records go after the record retention period, along with any files left behind
+        let deleted = self.job_repository.delete_old_jobs(config.record_retention_days).await?;
+        summary.records_deleted = deleted.len();
+        self.cleanup_files(&deleted, RetentionStage::Record, &mut summary).await;
+
+        // Files go after the file retention period, leaving the job as history
+        let file_expired = self.job_repository.expire_old_job_files(config.file_retention_days).await?;
+        summary.files_expired_jobs = file_expired.len();
+        self.cleanup_files(&file_expired, RetentionStage::Files, &mut summary).await;
+
+        // Files neither stage knows about: crashes, aborted tasks, rows deleted by hand
+        match self.sweep_orphans(false).await {
+            Ok(report) => {
+                summary.files_removed += report.files.len();
+                summary.bytes_freed += report.bytes;
+            }
+            Err(e) => {
+                error!("Orphan sweep failed: {}", e);
+                summary.errors.push(format!("Orphan sweep: {e}"));
             }
         }
 
+        counter_add!("aperio_retention_bytes_freed_total", summary.bytes_freed);
+
+        if summary.jobs.is_empty() {
+            info!("No old jobs found for cleanup; {} bytes freed", summary.bytes_freed);
+            return Ok(summary);
+        }
+
         // Get statistics after cleanup
-        let (completed_after, failed_after, cancelled_after) = self.job_repository.get_cleanup_stats().await?;
+        let after = self.job_repository.get_cleanup_stats().await?;
 
         info!(
-            "Retention cleanup completed - Removed {} database records, cleaned {} file sets",
-            old_job_ids.len(), successful_file_cleanups
+            "Retention cleanup completed - Removed {} database records, expired files of {} jobs, cleaned {} file sets: {} files, {} bytes freed",
+            summary.records_deleted, summary.files_expired_jobs, summary.jobs_cleaned, summary.files_removed, summary.bytes_freed
         );
         info!(
-            "Jobs after cleanup - Completed: {}, Failed: {}, Cancelled: {}",
-            completed_after, failed_after, cancelled_after
+            "Jobs after cleanup - Completed: {}, Failed: {}, Cancelled: {}, Expired: {}, without files: {}",
+            after.completed, after.failed, after.cancelled, after.expired, after.files_expired
         );
 
-        if !file_cleanup_errors.is_empty() {
+        if !summary.errors.is_empty() {
             warn!(
                 "File cleanup had {} errors: {}",
-                file_cleanup_errors.len(),
-                file_cleanup_errors.join("; ")
+                summary.errors.len(),
+                summary.errors.join("; ")
             );
         }
 
-        Ok(())
+        Ok(summary)
+    }
+
+    /// Remove the files of each job, a few jobs at a time, releasing their space from the quota.
+    /// A failure for one job is recorded in the summary and doesn't stop the others.
+    async fn cleanup_files(&self, jobs: &[RetentionCandidate], stage: RetentionStage, summary: &mut CleanupSummary) {
+        let mut results = stream::iter(jobs.to_vec())
+            .map(|job| {
+                let cleanup_service = self.cleanup_service.clone();
+                async move {
+                    let result = cleanup_service.cleanup_job_files(&job.id).await;
+                    (job, result)
+                }
+            })
+            .buffer_unordered(CLEANUP_CONCURRENCY);
+
+        while let Some((job, result)) = results.next().await {
+            let bytes = match result {
+                Ok(outcome) => {
+                    summary.jobs_cleaned += 1;
+                    summary.files_removed += outcome.files_removed;
+                    summary.bytes_freed += outcome.bytes_freed;
+                    self.storage_quota.release(outcome.bytes_freed).await;
+                    outcome.bytes_freed
+                }
+                Err(e) => {
+                    warn!("Failed to clean up files for job {}: {}", job.id, e);
+                    summary.errors.push(format!("Job {}: {e}", job.id));
+                    0
+                }
+            };
+            summary.jobs.push(retention_job(job, stage, bytes));
+        }
+    }
+
+    /// Dry-run counterpart of `cleanup_files`: what each job's cleanup would free right now
+    async fn measure_files(&self, jobs: &[RetentionCandidate], stage: RetentionStage, summary: &mut CleanupSummary) {
+        for job in jobs {
+            let outcome = self.cleanup_service.measure_job_files(&job.id).await;
+            summary.files_removed += outcome.files_removed;
+            summary.bytes_freed += outcome.bytes_freed;
+            summary.jobs.push(retention_job(job.clone(), stage, outcome.bytes_freed));
+        }
     }
-}
\ No newline at end of file
+
+    /// Remove working files no live job accounts for: files of jobs without a row, and leftovers of
+    /// jobs finished longer than the grace period ago that aren't among their outputs.
+    /// Files modified within the grace period, and files in use, are always kept.
+    pub async fn sweep_orphans(&self, dry_run: bool) -> AppResult<OrphanSweepReport> {
+        let working_dir = self.cleanup_service.working_dir();
+        let files_by_job = list_job_files(working_dir).await?;
+        let orphan_grace = Duration::from_secs(self.config().orphan_grace_hours * 3600);
+        let modified_cutoff = SystemTime::now() - orphan_grace;
+        let finished_cutoff = chrono::Utc::now() - chrono::Duration::seconds(orphan_grace.as_secs() as i64);
+
+        // A parent's kept original stays while a re-processing job reads it, even once the parent is gone
+        let borrowed: HashSet<PathBuf> = self.job_repository.list_borrowed_sources().await?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+
+        let mut report = OrphanSweepReport { dry_run, ..Default::default() };
+
+        for (job_id, files) in files_by_job {
+            let (reason, outputs) = match self.job_repository.get_job_including_deleted(&job_id).await? {
+                None => (OrphanReason::UnknownJob, HashSet::new()),
+                Some(job) if job.updated_at < finished_cutoff => match job.status {
+                    JobStatus::Completed => {
+                        let outputs = [job.get_processed_path(), job.get_preview_path(), job.get_downloaded_path()]
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|path| path.file_name().map(|name| name.to_os_string()))
+                            .collect();
+                        (OrphanReason::FinishedJob, outputs)
+                    }
+                    JobStatus::Failed | JobStatus::Cancelled | JobStatus::Expired => (OrphanReason::FinishedJob, HashSet::new()),
+                    _ => continue,
+                },
+                Some(_) => continue,
+            };
+
+            for path in files {
+                if path.file_name().is_some_and(|name| outputs.contains(name)) {
+                    continue;
+                }
+                let Ok(metadata) = tokio::fs::metadata(&path).await else {
+                    continue;
+                };
+                if metadata.modified().map(|modified| modified > modified_cutoff).unwrap_or(true) {
+                    continue;
+                }
+                if borrowed.contains(&path) || self.cleanup_service.is_file_busy(&path).await {
+                    report.skipped_active += 1;
+                    continue;
+                }
+
+                if !dry_run {
+                    if let Err(e) = tokio::fs::remove_file(&path).await {
+                        warn!("Failed to remove orphaned file {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
+                report.bytes += metadata.len();
+                report.files.push(OrphanFile {
+                    path: path.strip_prefix(working_dir).unwrap_or(&path).to_string_lossy().to_string(),
+                    job_id: job_id.clone(),
+                    reason,
+                    size_bytes: metadata.len(),
+                });
+            }
+
+            // Only succeeds once the directory is empty, which is the point
+            if !dry_run && matches!(reason, OrphanReason::UnknownJob) {
+                let _ = tokio::fs::remove_dir(job_working_dir(working_dir, &job_id)).await;
+            }
+        }
+
+        if dry_run {
+            info!("Orphan sweep dry run: {} files, {} bytes would be removed", report.files.len(), report.bytes);
+        } else if !report.files.is_empty() {
+            self.storage_quota.release(report.bytes).await;
+            counter_add!("aperio_orphan_files_swept_total", report.files.len() as u64);
+            counter_add!("aperio_orphan_bytes_swept_total", report.bytes);
+            info!("Orphan sweep removed {} files, freeing {} bytes", report.files.len(), report.bytes);
+        } else {
+            debug!("Orphan sweep found nothing to remove");
+        }
+        if report.skipped_active > 0 {
+            info!("Orphan sweep skipped {} files in use", report.skipped_active);
+        }
+
+        Ok(report)
+    }
+}
+
+fn retention_job(job: RetentionCandidate, stage: RetentionStage, bytes: u64) -> RetentionJob {
+    RetentionJob {
+        age_seconds: (chrono::Utc::now() - job.updated_at).num_seconds(),
+        job_id: job.id,
+        stage,
+        bytes,
+    }
+}
+
+/// Working files grouped by the job they belong to: everything in a `{job_id}/` directory, plus
+/// legacy flat `{job_id}_*` files. Entries not named after a job id are never touched.
+async fn list_job_files(working_dir: &Path) -> AppResult<BTreeMap<String, Vec<PathBuf>>> {
+    let mut files_by_job: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    let mut entries = tokio::fs::read_dir(working_dir).await
+        .map_err(AppError::io("Failed to read working directory"))?;
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(file_type) = entry.file_type().await else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            if uuid::Uuid::parse_str(&name).is_err() {
+                continue;
+            }
+            let job_files = files_by_job.entry(name).or_default();
+            if let Ok(mut dir_entries) = tokio::fs::read_dir(&path).await {
+                while let Ok(Some(dir_entry)) = dir_entries.next_entry().await {
+                    if dir_entry.file_type().await.map(|file_type| file_type.is_file()).unwrap_or(false) {
+                        job_files.push(dir_entry.path());
+                    }
+                }
+            }
+        } else if file_type.is_file() {
+            let Some((job_id, _)) = name.split_once('_') else {
+                continue;
+            };
+            if uuid::Uuid::parse_str(job_id).is_ok() {
+                files_by_job.entry(job_id.to_string()).or_default().push(path);
+            }
+        }
+    }
+
+    Ok(files_by_job)
+}
@@ -1,12 +1,22 @@
 use crate::error::{AppError, AppResult};
-use std::time::Duration;
+use crate::services::error_mapping::classify_download_error;
+use crate::counter_inc;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
+use tracing::{info, warn};
+
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
 
 pub struct RetryConfig {
     pub max_attempts: u32,
     pub base_delay: Duration,
     pub max_delay: Duration,
     pub backoff_multiplier: f64,
+    /// Wall-clock cap on all attempts and the waits between them; no retry is started that
+    /// would wait past it. None retries until `max_attempts`.
+    pub max_elapsed: Option<Duration>,
 }
 
 impl Default for RetryConfig {
@@ -16,98 +26,268 @@ impl Default for RetryConfig {
             base_delay: Duration::from_secs(1),
             max_delay: Duration::from_secs(30),
             backoff_multiplier: 2.0,
+            max_elapsed: None,
         }
     }
 }
 
 pub async fn retry_with_backoff<F, Fut, T>(
+    operation: F,
+    config: &RetryConfig,
+    operation_name: &str,
+) -> AppResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = AppResult<T>>,
+{
+    retry_with_backoff_if(operation, config, operation_name, |_| true).await
+}
+
+/// Like `retry_with_backoff`, but gives up at once on an error `should_retry` rejects
+pub async fn retry_with_backoff_if<F, Fut, T, P>(
     mut operation: F,
     config: &RetryConfig,
     operation_name: &str,
+    should_retry: P,
 ) -> AppResult<T>
 where
     F: FnMut() -> Fut,
     Fut: std::future::Future<Output = AppResult<T>>,
+    P: Fn(&AppError) -> bool,
 {
-    let mut last_error = None;
+    let started = Instant::now();
+    let mut attempt = 1;
 
-    for attempt in 1..=config.max_attempts {
-        match operation().await {
+    loop {
+        let error = match operation().await {
             Ok(result) => {
                 if attempt > 1 {
-                    println!("{operation_name} succeeded on attempt {attempt}");
+                    info!(operation = operation_name, attempt, "Operation succeeded after retrying");
                 }
                 return Ok(result);
             }
-            Err(e) => {
-                last_error = Some(e);
-
-                if attempt < config.max_attempts {
-                    let delay = calculate_backoff_delay(attempt, config);
-                    println!(
-                        "{} failed on attempt {} ({}), retrying in {:?}",
-                        operation_name, attempt, last_error.as_ref().unwrap(), delay
-                    );
-                    sleep(delay).await;
-                } else {
-                    println!(
-                        "{} failed on final attempt {} ({})",
-                        operation_name, attempt, last_error.as_ref().unwrap()
-                    );
-                }
+            Err(e) => e,
+        };
+
+        if !should_retry(&error) {
+            warn!(operation = operation_name, attempt, error = %error, "Operation failed and retrying would fail the same way");
+            return Err(error);
+        }
+
+        if attempt >= config.max_attempts {
+            warn!(operation = operation_name, attempt, error = %error, "Operation failed on its final attempt");
+            return Err(error);
+        }
+
+        let delay = calculate_backoff_delay(attempt, config);
+        if let Some(budget) = config.max_elapsed {
+            if started.elapsed() + delay > budget {
+                warn!(
+                    operation = operation_name, attempt, error = %error, budget_ms = budget.as_millis() as u64,
+                    "Operation failed and its retry budget is spent"
+                );
+                return Err(error);
             }
         }
-    }
 
-    Err(last_error.unwrap())
+        warn!(
+            operation = operation_name, attempt, error = %error, delay_ms = delay.as_millis() as u64,
+            "Operation failed, retrying"
+        );
+        counter_inc!("aperio_retries_total", "operation" => operation_name);
+        sleep(delay).await;
+        attempt += 1;
+    }
 }
 
+/// Full jitter: a random wait up to the exponential bound, so operations that failed together
+/// don't all retry at the same moment
 fn calculate_backoff_delay(attempt: u32, config: &RetryConfig) -> Duration {
-    let delay_secs = config.base_delay.as_secs_f64()
-        * config.backoff_multiplier.powi((attempt - 1) as i32);
+    let bound_secs = (config.base_delay.as_secs_f64()
+        * config.backoff_multiplier.powi((attempt - 1) as i32))
+        .min(config.max_delay.as_secs_f64());
 
-    
-    Duration::from_secs_f64(delay_secs.min(config.max_delay.as_secs_f64()))
+    Duration::from_secs_f64(bound_secs * fastrand::f64())
 }
 
 pub fn is_retryable_error(error: &AppError) -> bool {
     match error {
         AppError::Timeout(_) => true,
-        AppError::Download(msg) => {
-            let msg_lower = msg.to_lowercase();
-            // Retry on network-related download errors
-            msg_lower.contains("timeout")
-                || msg_lower.contains("connection")
-                || msg_lower.contains("network")
-                || msg_lower.contains("temporary")
-                || msg_lower.contains("unavailable")
-                || msg_lower.contains("reset")
-                || msg_lower.contains("refused")
-                // HTTP status codes that indicate temporary issues
-                || msg_lower.contains("502")
-                || msg_lower.contains("503")
-                || msg_lower.contains("504")
-                || msg_lower.contains("429") // Rate limited
-        }
-        AppError::Processing(msg) => {
-            let msg_lower = msg.to_lowercase();
-            // Retry on temporary processing errors
-            msg_lower.contains("resource temporarily unavailable")
-                || msg_lower.contains("device busy")
-                || msg_lower.contains("temporary failure")
-                || msg_lower.contains("disk full") // Could be temporary
-        }
-        AppError::Internal(msg) => {
-            let msg_lower = msg.to_lowercase();
-            // Retry on database connection issues and temporary internal errors
-            msg_lower.contains("database") && (
-                msg_lower.contains("busy") 
-                || msg_lower.contains("locked")
-                || msg_lower.contains("connection")
-            )
+        // Only transient network failures; unavailable/private/rate-limited sources fail the same way again
+        AppError::DownloadFailed { reason, .. } => reason.is_retryable(),
+        AppError::Download(msg) => classify_download_error(msg).is_retryable(),
+        // ffmpeg only reports the cause in its output
+        AppError::ProcessingFailed { stderr, .. } => {
+            let stderr = stderr.to_lowercase();
+            stderr.contains("resource temporarily unavailable")
+                || stderr.contains("device or resource busy")
+                || stderr.contains("no space left on device") // Could be temporary
         }
+        AppError::Database { source, .. } => is_transient_database_error(source),
+        AppError::Io { source, .. } => is_transient_io_error(source),
+        // A missing or unexecutable binary stays missing; running out of processes doesn't
+        AppError::Spawn { source, .. } => is_transient_io_error(source),
+        AppError::Processing(_) => false, // Our own checks on the output; it fails the same way again
+        AppError::Internal(_) => false,
         AppError::Storage(_) => false, // Don't retry storage errors
-        AppError::BadRequest(_) => false, // Don't retry client errors
+        AppError::BadRequest(_) | AppError::OutOfRange(_) => false, // Don't retry client errors
         AppError::NotFound(_) => false, // Don't retry not found errors
+        AppError::Unprocessable(_) => false, // Don't retry client errors
+        AppError::InsufficientStorage(_) => false, // Space only frees up on the retention schedule
+        AppError::Expired(_) => false, // The output is gone until the job is resubmitted
+        AppError::Conflict(_) => false, // Whatever conflicted is still running
+        AppError::PayloadTooLarge(_) => false, // Don't retry client errors
+        AppError::Overloaded(_) => false, // Backing off is the client's job, per Retry-After
+        AppError::NoCapacity { .. } => false, // Jobs are requeued instead
+        AppError::QuotaExceeded(_) => false,
+        AppError::Draining(_) => false, // Another instance should take the job
+    }
+}
+
+/// SQLite busy or locked, or the pool out of connections for now
+fn is_transient_database_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => true,
+        sqlx::Error::Database(db_error) => {
+            // Extended result codes keep the primary code in the low byte
+            let primary_code = db_error.code()
+                .and_then(|code| code.parse::<i32>().ok())
+                .map(|code| code & 0xff);
+            matches!(primary_code, Some(SQLITE_BUSY | SQLITE_LOCKED))
+        }
+        _ => false,
+    }
+}
+
+fn is_transient_io_error(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ResourceBusy
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Phase;
+    use crate::models::job::FailureReason;
+    use std::cell::Cell;
+
+    fn download_failed(reason: FailureReason) -> AppError {
+        AppError::DownloadFailed { exit_code: Some(1), stderr: String::new(), reason }
+    }
+
+    fn processing_failed(stderr: &str) -> AppError {
+        AppError::ProcessingFailed { exit_code: Some(1), stderr: stderr.to_string() }
+    }
+
+    fn spawn(kind: ErrorKind) -> AppError {
+        AppError::spawn(Phase::Download, "yt-dlp", std::io::Error::from(kind))
+    }
+
+    fn io(kind: ErrorKind) -> AppError {
+        AppError::Io { context: "Failed to write output".to_string(), source: std::io::Error::from(kind) }
+    }
+
+    fn database(source: sqlx::Error) -> AppError {
+        AppError::Database { context: "Failed to fetch job".to_string(), source }
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let retryable = [
+            AppError::Timeout("Download timed out".to_string()),
+            download_failed(FailureReason::Network),
+            download_failed(FailureReason::IncompleteDownload),
+            download_failed(FailureReason::Timeout),
+            AppError::Download("Connection reset by peer".to_string()),
+            processing_failed("av_interleaved_write_frame(): Resource temporarily unavailable"),
+            processing_failed("Error writing trailer: No space left on device"),
+            database(sqlx::Error::PoolTimedOut),
+            io(ErrorKind::Interrupted),
+            io(ErrorKind::TimedOut),
+            spawn(ErrorKind::WouldBlock),
+        ];
+        for error in retryable {
+            assert!(is_retryable_error(&error), "{error:?} should be retried");
+        }
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let permanent = [
+            download_failed(FailureReason::Unavailable),
+            download_failed(FailureReason::Private),
+            download_failed(FailureReason::LoginRequired),
+            download_failed(FailureReason::GeoBlocked),
+            download_failed(FailureReason::Removed),
+            download_failed(FailureReason::RateLimited),
+            download_failed(FailureReason::UnsupportedUrl),
+            download_failed(FailureReason::LiveStream),
+            download_failed(FailureReason::DownloadFailed),
+            AppError::Download("Video unavailable".to_string()),
+            processing_failed("Invalid data found when processing input"),
+            AppError::Processing("output has no audio stream".to_string()),
+            database(sqlx::Error::RowNotFound),
+            io(ErrorKind::PermissionDenied),
+            spawn(ErrorKind::NotFound),
+            spawn(ErrorKind::PermissionDenied),
+            AppError::Storage("disk full".to_string()),
+            AppError::BadRequest("invalid url".to_string()),
+            AppError::NoCapacity { pool: "download", waited: Duration::from_secs(30) },
+            AppError::Draining("shutting down".to_string()),
+        ];
+        for error in permanent {
+            assert!(!is_retryable_error(&error), "{error:?} should not be retried");
+        }
+    }
+
+    #[tokio::test]
+    async fn attempts_stop_at_the_configured_maximum() {
+        let config = RetryConfig {
+            max_attempts: 3,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..RetryConfig::default()
+        };
+        let attempts = Cell::new(0);
+
+        let result: AppResult<()> = retry_with_backoff(|| {
+            attempts.set(attempts.get() + 1);
+            async { Err(AppError::Timeout("timed out".to_string())) }
+        }, &config, "test").await;
+
+        assert!(matches!(result, Err(AppError::Timeout(_))));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_later_success_is_returned() {
+        let config = RetryConfig { base_delay: Duration::ZERO, max_delay: Duration::ZERO, ..RetryConfig::default() };
+        let attempts = Cell::new(0);
+
+        let result = retry_with_backoff(|| {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                if attempt < 2 { Err(AppError::Timeout("timed out".to_string())) } else { Ok(attempt) }
+            }
+        }, &config, "test").await;
+
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_error_is_not_retried() {
+        let config = RetryConfig { base_delay: Duration::ZERO, max_delay: Duration::ZERO, ..RetryConfig::default() };
+        let attempts = Cell::new(0);
+
+        let result: AppResult<()> = retry_with_backoff_if(|| {
+            attempts.set(attempts.get() + 1);
+            async { Err(download_failed(FailureReason::RateLimited)) }
+        }, &config, "test", is_retryable_error).await;
+
+        assert!(matches!(result, Err(AppError::DownloadFailed { reason: FailureReason::RateLimited, .. })));
+        assert_eq!(attempts.get(), 1);
     }
 }
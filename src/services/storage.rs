@@ -3,7 +3,11 @@ use std::path::{Path, PathBuf};
 use crate::config::{StorageConfig, StorageType};
 use crate::error::{AppError, AppResult};
 use crate::models::job::Job;
+use crate::services::process::PARTIAL_OUTPUT_SUFFIX;
+use crate::services::security::job_working_dir;
+use tracing::debug;
 
+/// Where completed outputs live, apart from the temporary files in the working dir
 pub struct StorageService {
     config: StorageConfig,
 }
@@ -26,18 +30,33 @@ impl StorageService {
         Ok(Self { config })
     }
 
+    /// Root of the stored outputs
+    pub fn root(&self) -> PathBuf {
+        match &self.config.storage_type {
+            StorageType::Local => PathBuf::from(self.config.local_path.as_ref().unwrap()),
+        }
+    }
+
+    /// Directory holding a job's stored outputs
+    pub fn job_dir(&self, job_id: &str) -> PathBuf {
+        job_working_dir(&self.root(), job_id)
+    }
+
+    /// Move a file into the job's storage directory, returning its new location
     pub async fn store(&self, job: &Job, source_path: &Path) -> AppResult<PathBuf> {
         match &self.config.storage_type {
             StorageType::Local => self.store_local(job, source_path).await,
         }
     }
 
+    #[allow(dead_code)]
     pub async fn get(&self, job_id: &str) -> AppResult<Option<PathBuf>> {
         match &self.config.storage_type {
             StorageType::Local => self.get_local(job_id), 
         }
     }
     
+    #[allow(dead_code)]
     pub async fn read(&self, path: &Path) -> AppResult<Vec<u8>> {
         tokio::fs::read(path)
             .await
@@ -45,8 +64,7 @@ impl StorageService {
     }
     
     async fn store_local(&self, job: &Job, source_path: &Path) -> AppResult<PathBuf> {
-        let local_path = self.config.local_path.as_ref().unwrap();
-        let job_dir = Path::new(local_path).join(&job.id);
+        let job_dir = self.job_dir(&job.id);
 
         // Create job directory
         tokio::fs::create_dir_all(&job_dir)
@@ -61,10 +79,8 @@ impl StorageService {
         // Create destination path
         let dest_path = job_dir.join(filename);
 
-        // Copy file
-        tokio::fs::copy(source_path, &dest_path)
-            .await
-            .map_err(|e| AppError::Storage(format!("Failed to copy file: {}", e)))?;
+        move_file(source_path, &dest_path).await?;
+        debug!("Stored {} at {}", source_path.display(), dest_path.display());
 
         Ok(dest_path)
     }
@@ -84,7 +100,8 @@ impl StorageService {
                 if path.is_file() {
                     if let Some(filename) = path.file_name() {
                         let filename_str = filename.to_string_lossy();
-                        if filename_str.contains("_processed") {
+                        // Never hand out an output that is still being encoded
+                        if filename_str.contains("_processed") && !filename_str.ends_with(PARTIAL_OUTPUT_SUFFIX) {
                             return Ok(Some(path));
                         }
                     }
@@ -93,4 +110,21 @@ impl StorageService {
         }
         Ok(None)
     }
-}
\ No newline at end of file
+}
+
+/// Rename, falling back to copy and delete when the storage dir is on another filesystem.
+/// Moving rather than copying keeps a finished job from briefly taking twice its size on disk.
+async fn move_file(source: &Path, destination: &Path) -> AppResult<()> {
+    match tokio::fs::rename(source, destination).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            tokio::fs::copy(source, destination)
+                .await
+                .map_err(|e| AppError::Storage(format!("Failed to copy file: {}", e)))?;
+            tokio::fs::remove_file(source)
+                .await
+                .map_err(|e| AppError::Storage(format!("Failed to remove moved file: {}", e)))
+        }
+        Err(e) => Err(AppError::Storage(format!("Failed to move file: {}", e))),
+    }
+}
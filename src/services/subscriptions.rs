@@ -0,0 +1,480 @@
+use crate::api::routes::{self, AppState, FeedEntryOutcome};
+use crate::counter_inc;
+use crate::error::{AppError, AppResult};
+use crate::models::job::ProcessingOptions;
+use crate::services::security::redact_text;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// How often the poller looks for subscriptions that are due
+const POLLER_TICK: Duration = Duration::from_secs(30);
+
+/// How long one feed fetch may take
+const FEED_TIMEOUT_SECS: u64 = 30;
+
+/// Largest feed document accepted, in bytes
+const MAX_FEED_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Failed polls back off by doubling the interval, up to this many times
+const MAX_BACKOFF_DOUBLINGS: u32 = 6;
+
+/// Longest a failing subscription waits between polls
+const MAX_BACKOFF: Duration = Duration::from_secs(24 * 3600);
+
+/// Longest `last_error` kept, in bytes
+const MAX_ERROR_LENGTH: usize = 500;
+
+/// Where a subscription stands, derived from its last poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    /// Not polled while disabled
+    Disabled,
+    /// Not polled yet
+    Pending,
+    /// The last poll worked
+    Ok,
+    /// The last poll failed; the next one is backed off
+    Failing,
+}
+
+/// A feed whose new entries are submitted as jobs
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Subscription {
+    pub id: String,
+    /// RSS or Atom feed, e.g. a YouTube channel's `feeds/videos.xml`
+    pub feed_url: String,
+    /// Processing options every job from this feed is submitted with
+    pub options: ProcessingOptions,
+    /// `high`, `normal` or `low`; normal when unset
+    pub priority: Option<String>,
+    /// Tags added to every job, besides `subscription:{id}`
+    pub tags: Vec<String>,
+    pub enabled: bool,
+    pub status: SubscriptionStatus,
+    /// Id of the newest entry already handled
+    pub last_seen_entry: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    /// Why the last poll failed
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub next_poll_at: DateTime<Utc>,
+}
+
+impl Subscription {
+    /// Tag identifying the jobs this subscription submitted
+    pub fn job_tag(&self) -> String {
+        format!("subscription:{}", self.id)
+    }
+}
+
+fn subscription_from_row(row: &SqliteRow) -> Subscription {
+    let enabled: bool = row.get("enabled");
+    let last_polled_at: Option<DateTime<Utc>> = row.get("last_polled_at");
+    let consecutive_failures: i64 = row.get("consecutive_failures");
+    let status = if !enabled {
+        SubscriptionStatus::Disabled
+    } else if consecutive_failures > 0 {
+        SubscriptionStatus::Failing
+    } else if last_polled_at.is_none() {
+        SubscriptionStatus::Pending
+    } else {
+        SubscriptionStatus::Ok
+    };
+
+    Subscription {
+        id: row.get("id"),
+        feed_url: row.get("feed_url"),
+        options: serde_json::from_str(row.get("options")).unwrap_or_default(),
+        priority: row.get("priority"),
+        tags: serde_json::from_str(row.get("tags")).unwrap_or_default(),
+        enabled,
+        status,
+        last_seen_entry: row.get("last_seen_entry"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        last_polled_at,
+        last_error: row.get("last_error"),
+        consecutive_failures: consecutive_failures as u32,
+        next_poll_at: row.get("next_poll_at"),
+    }
+}
+
+/// One item of a feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    /// The RSS `guid` or Atom `id`, falling back to the link
+    pub id: String,
+    pub url: String,
+}
+
+/// Entries of an RSS 2.0 or Atom document, in document order (newest first for the feeds we
+/// know of). Entries without a link are skipped.
+pub fn parse_feed(document: &str) -> AppResult<Vec<FeedEntry>> {
+    let document = roxmltree::Document::parse(document)
+        .map_err(|e| AppError::BadRequest(format!("Feed is not valid XML: {e}")))?;
+    let root = document.root_element();
+
+    let entries = match root.tag_name().name() {
+        "rss" | "RDF" => root
+            .descendants()
+            .filter(|node| node.has_tag_name("item"))
+            .filter_map(|item| {
+                let child_text = |name: &str| {
+                    item.children()
+                        .find(|child| child.has_tag_name(name))
+                        .and_then(|child| child.text())
+                        .map(str::trim)
+                        .filter(|text| !text.is_empty())
+                };
+                let url = child_text("link")?;
+                let id = child_text("guid").unwrap_or(url);
+                Some(FeedEntry { id: id.to_string(), url: url.to_string() })
+            })
+            .collect(),
+        "feed" => root
+            .children()
+            .filter(|node| node.has_tag_name("entry"))
+            .filter_map(|entry| {
+                let url = entry.children()
+                    .filter(|child| child.has_tag_name("link"))
+                    .find(|link| matches!(link.attribute("rel"), None | Some("alternate")))
+                    .and_then(|link| link.attribute("href"))
+                    .map(str::trim)
+                    .filter(|href| !href.is_empty())?;
+                let id = entry.children()
+                    .find(|child| child.has_tag_name("id"))
+                    .and_then(|child| child.text())
+                    .map(str::trim)
+                    .filter(|text| !text.is_empty())
+                    .unwrap_or(url);
+                Some(FeedEntry { id: id.to_string(), url: url.to_string() })
+            })
+            .collect(),
+        other => return Err(AppError::BadRequest(format!("Not an RSS or Atom feed: root element is <{other}>"))),
+    };
+
+    Ok(entries)
+}
+
+/// Entries after `last_seen`, oldest first. When `last_seen` has dropped off the feed every
+/// entry is new, since there is no telling how many were missed.
+fn new_entries(entries: Vec<FeedEntry>, last_seen: &str) -> Vec<FeedEntry> {
+    let mut new: Vec<FeedEntry> = entries.into_iter()
+        .take_while(|entry| entry.id != last_seen)
+        .collect();
+    new.reverse();
+    new
+}
+
+/// Fields a subscription is created with
+#[derive(Debug, Clone)]
+pub struct NewSubscription {
+    pub feed_url: String,
+    pub options: ProcessingOptions,
+    pub priority: Option<String>,
+    pub tags: Vec<String>,
+    pub enabled: bool,
+}
+
+/// Changes to a subscription; unset fields stay as they are
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct SubscriptionUpdate {
+    pub options: Option<ProcessingOptions>,
+    pub priority: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// Re-enabling clears the failure backoff and polls at once
+    pub enabled: Option<bool>,
+}
+
+pub struct SubscriptionService {
+    pool: SqlitePool,
+    fetch_command: String,
+    poll_interval: Duration,
+}
+
+impl SubscriptionService {
+    pub fn new(pool: SqlitePool, fetch_command: &str, poll_interval: Duration) -> Self {
+        Self { pool, fetch_command: fetch_command.to_string(), poll_interval }
+    }
+
+    pub async fn list(&self) -> AppResult<Vec<Subscription>> {
+        let rows = sqlx::query("SELECT * FROM subscriptions ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list subscriptions"))?;
+
+        Ok(rows.iter().map(subscription_from_row).collect())
+    }
+
+    pub async fn get(&self, id: &str) -> AppResult<Option<Subscription>> {
+        let row = sqlx::query("SELECT * FROM subscriptions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to get subscription"))?;
+
+        Ok(row.as_ref().map(subscription_from_row))
+    }
+
+    /// Create a subscription, due for its first poll right away. A feed can only be subscribed once.
+    pub async fn create(&self, new: NewSubscription) -> AppResult<Subscription> {
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+        let result = sqlx::query(
+            "INSERT INTO subscriptions (id, feed_url, options, priority, tags, enabled, created_at, updated_at, next_poll_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&id)
+        .bind(&new.feed_url)
+        .bind(serde_json::to_string(&new.options).unwrap_or_else(|_| "{}".to_string()))
+        .bind(&new.priority)
+        .bind(serde_json::to_string(&new.tags).unwrap_or_else(|_| "[]".to_string()))
+        .bind(new.enabled)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Err(AppError::Conflict(format!("Feed {} already has a subscription", redact_text(&new.feed_url))));
+            }
+            Err(e) => return Err(AppError::database("Failed to create subscription")(e)),
+        }
+
+        self.get(&id).await?
+            .ok_or_else(|| AppError::Internal(format!("Subscription {id} vanished after insert")))
+    }
+
+    /// Apply `update`, returning the changed subscription, or None when there is none by that id
+    pub async fn update(&self, id: &str, update: SubscriptionUpdate) -> AppResult<Option<Subscription>> {
+        let Some(mut subscription) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        if let Some(options) = update.options {
+            subscription.options = options;
+        }
+        if update.priority.is_some() {
+            subscription.priority = update.priority;
+        }
+        if let Some(tags) = update.tags {
+            subscription.tags = tags;
+        }
+        if let Some(enabled) = update.enabled {
+            if enabled && !subscription.enabled {
+                subscription.consecutive_failures = 0;
+                subscription.next_poll_at = Utc::now();
+            }
+            subscription.enabled = enabled;
+        }
+
+        sqlx::query(
+            "UPDATE subscriptions SET options = ?, priority = ?, tags = ?, enabled = ?,
+             consecutive_failures = ?, next_poll_at = ?, updated_at = ? WHERE id = ?"
+        )
+        .bind(serde_json::to_string(&subscription.options).unwrap_or_else(|_| "{}".to_string()))
+        .bind(&subscription.priority)
+        .bind(serde_json::to_string(&subscription.tags).unwrap_or_else(|_| "[]".to_string()))
+        .bind(subscription.enabled)
+        .bind(subscription.consecutive_failures as i64)
+        .bind(subscription.next_poll_at)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to update subscription"))?;
+
+        self.get(id).await
+    }
+
+    /// Delete a subscription, returning whether there was one. Jobs it submitted are kept.
+    pub async fn delete(&self, id: &str) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM subscriptions WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to delete subscription"))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_due(&self, now: DateTime<Utc>) -> AppResult<Vec<Subscription>> {
+        let rows = sqlx::query("SELECT * FROM subscriptions WHERE enabled = 1 AND next_poll_at <= ? ORDER BY next_poll_at")
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to list due subscriptions"))?;
+
+        Ok(rows.iter().map(subscription_from_row).collect())
+    }
+
+    /// Remember the newest entry handled so far; saved after each entry so a failure part way
+    /// through doesn't resubmit the ones before it
+    async fn record_seen(&self, id: &str, entry_id: &str) -> AppResult<()> {
+        sqlx::query("UPDATE subscriptions SET last_seen_entry = ? WHERE id = ?")
+            .bind(entry_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::database("Failed to record last seen entry"))?;
+        Ok(())
+    }
+
+    async fn record_poll(&self, subscription: &Subscription, result: Result<(), String>) -> AppResult<()> {
+        let now = Utc::now();
+        let (failures, last_error, delay) = match result {
+            Ok(()) => (0, None, self.poll_interval),
+            Err(reason) => {
+                let failures = subscription.consecutive_failures + 1;
+                let delay = self.poll_interval
+                    .saturating_mul(1 << failures.min(MAX_BACKOFF_DOUBLINGS))
+                    .min(MAX_BACKOFF);
+                (failures, Some(truncate(&redact_text(&reason), MAX_ERROR_LENGTH)), delay)
+            }
+        };
+        let next_poll_at = now + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::hours(24));
+
+        sqlx::query(
+            "UPDATE subscriptions SET last_polled_at = ?, last_error = ?, consecutive_failures = ?, next_poll_at = ?
+             WHERE id = ?"
+        )
+        .bind(now)
+        .bind(last_error)
+        .bind(failures as i64)
+        .bind(next_poll_at)
+        .bind(&subscription.id)
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::database("Failed to record subscription poll"))?;
+        Ok(())
+    }
+
+    /// Fetch a feed with curl. Redirects are refused, since only the feed URL itself was checked
+    /// against the allowlist.
+    async fn fetch_feed(&self, feed_url: &str) -> AppResult<String> {
+        let output = Command::new(&self.fetch_command)
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--max-time")
+            .arg(FEED_TIMEOUT_SECS.to_string())
+            .arg("--max-filesize")
+            .arg(MAX_FEED_BYTES.to_string())
+            .arg("--max-redirs")
+            .arg("0")
+            .arg("--proto")
+            .arg("=https")
+            .arg(feed_url)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to run {}: {e}", self.fetch_command)))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(AppError::Download(format!("Failed to fetch feed: {}", stderr.trim())));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| AppError::Download("Feed is not valid UTF-8".to_string()))
+    }
+
+    /// Poll one subscription: fetch its feed and submit the entries it hasn't seen. The first
+    /// poll only records where the feed stands, so subscribing doesn't submit its whole backlog.
+    async fn poll(&self, subscription: &Subscription, app_state: &AppState) -> AppResult<usize> {
+        let feed_url = app_state.security_validator.validate_url(&subscription.feed_url)?;
+        if let Some(host) = feed_url.host_str() {
+            app_state.security_validator.validate_resolved_host(host).await?;
+        }
+        let entries = parse_feed(&self.fetch_feed(feed_url.as_str()).await?)?;
+
+        let Some(last_seen) = &subscription.last_seen_entry else {
+            if let Some(newest) = entries.first() {
+                info!("Subscription {} starts at entry {}", subscription.id, newest.id);
+                self.record_seen(&subscription.id, &newest.id).await?;
+            }
+            return Ok(0);
+        };
+
+        let mut submitted = 0;
+        for entry in new_entries(entries, last_seen) {
+            match routes::submit_feed_entry(app_state, subscription, &entry.url).await? {
+                FeedEntryOutcome::Submitted(job_id) => {
+                    info!("Subscription {} submitted job {} for {}", subscription.id, job_id, redact_text(&entry.url));
+                    counter_inc!("aperio_subscription_jobs_created_total");
+                    submitted += 1;
+                }
+                FeedEntryOutcome::Duplicate(job_id) => {
+                    info!("Subscription {} skipped {}, already job {}", subscription.id, redact_text(&entry.url), job_id);
+                }
+                FeedEntryOutcome::Rejected(reason) => {
+                    warn!("Subscription {} skipped {}: {}", subscription.id, redact_text(&entry.url), reason);
+                    counter_inc!("aperio_subscription_entries_rejected_total");
+                }
+            }
+            self.record_seen(&subscription.id, &entry.id).await?;
+        }
+        Ok(submitted)
+    }
+
+    /// Poll due subscriptions until the server stops. Nothing is polled while draining.
+    pub async fn start_poller(self: Arc<Self>, app_state: Arc<AppState>) {
+        info!("Polling subscriptions every {:?}", self.poll_interval);
+        let mut interval = tokio::time::interval(POLLER_TICK);
+        loop {
+            interval.tick().await;
+            if app_state.job_queue.is_draining().await {
+                continue;
+            }
+
+            let due = match self.list_due(Utc::now()).await {
+                Ok(due) => due,
+                Err(e) => {
+                    error!("Failed to load due subscriptions: {}", e);
+                    continue;
+                }
+            };
+
+            for subscription in due {
+                let result = self.poll(&subscription, &app_state).await;
+                let outcome = match &result {
+                    Ok(_) => "success",
+                    Err(_) => "failure",
+                };
+                counter_inc!("aperio_subscription_polls_total", "outcome" => outcome);
+                if let Err(e) = &result {
+                    warn!("Polling subscription {} failed: {}", subscription.id, e);
+                }
+                if let Err(e) = self.record_poll(&subscription, result.map(|_| ()).map_err(|e| e.to_string())).await {
+                    error!("Failed to record poll of subscription {}: {}", subscription.id, e);
+                }
+            }
+        }
+    }
+}
+
+fn truncate(text: &str, max_len: usize) -> String {
+    if text.len() <= max_len {
+        return text.to_string();
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &text[..end])
+}
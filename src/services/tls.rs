@@ -0,0 +1,119 @@
+use rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info};
+
+/// How often the certificate and key files are checked for renewal
+const CERTIFICATE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Serves the certificate from APERIO_TLS_CERT and APERIO_TLS_KEY, swapping in a renewed one
+/// when the files change so certificate renewals apply without a restart
+#[derive(Debug)]
+pub struct CertificateReloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// Modification times of the certificate and key when last loaded
+    loaded_versions: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl CertificateReloader {
+    /// Load the certificate and key, failing with what is wrong with them
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Arc<Self>, String> {
+        let versions = file_versions(cert_path, key_path);
+        let certified_key = load_certified_key(cert_path, key_path)?;
+        Ok(Arc::new(Self {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            current: RwLock::new(Arc::new(certified_key)),
+            loaded_versions: Mutex::new(versions),
+        }))
+    }
+
+    /// A rustls server config that always presents the current certificate
+    pub fn server_config(self: &Arc<Self>) -> Result<rustls::ServerConfig, String> {
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Failed to configure TLS: {e}"))?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        Ok(config)
+    }
+
+    /// Re-read the certificate and key. A broken pair is reported and the running one kept.
+    pub fn reload(&self) -> Result<(), String> {
+        let versions = file_versions(&self.cert_path, &self.key_path);
+        let certified_key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(certified_key);
+        *self.loaded_versions.lock().unwrap() = versions;
+        info!("TLS certificate reloaded from {}", self.cert_path.display());
+        Ok(())
+    }
+
+    /// Reload when the files' modification times change, and on SIGHUP
+    pub async fn start_watcher(self: Arc<Self>) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => Some(hangups),
+            Err(e) => {
+                error!("Failed to install SIGHUP handler for TLS reloads: {}", e);
+                None
+            }
+        };
+        let mut interval = tokio::time::interval(CERTIFICATE_CHECK_INTERVAL);
+        interval.tick().await;
+
+        loop {
+            let forced = tokio::select! {
+                _ = interval.tick() => false,
+                Some(()) = async { hangups.as_mut()?.recv().await } => true,
+            };
+
+            let changed = file_versions(&self.cert_path, &self.key_path) != *self.loaded_versions.lock().unwrap();
+            if !forced && !changed {
+                continue;
+            }
+            if let Err(e) = self.reload() {
+                error!("TLS certificate reload failed, keeping the current certificate: {}", e);
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for CertificateReloader {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn file_versions(cert_path: &Path, key_path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey, String> {
+    let chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read TLS certificate {}: {e}", cert_path.display()))?;
+    if chain.is_empty() {
+        return Err(format!("TLS certificate {} contains no certificates", cert_path.display()));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("Failed to read TLS private key {}: {e}", key_path.display()))?;
+    let signing_key = any_supported_type(&key)
+        .map_err(|e| format!("Unsupported TLS private key {}: {e}", key_path.display()))?;
+
+    let certified_key = CertifiedKey::new(chain, signing_key);
+    certified_key.keys_match()
+        .map_err(|e| format!(
+            "TLS private key {} does not match certificate {}: {e}", key_path.display(), cert_path.display()
+        ))?;
+    Ok(certified_key)
+}
@@ -0,0 +1,137 @@
+use crate::config::SecurityConfig;
+use crate::error::{AppError, AppResult};
+use crate::services::SecurityValidator;
+use crate::services::security::job_working_dir;
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// Source containers accepted by the upload route
+pub const UPLOAD_EXTENSIONS: [&str; 6] = ["mp4", "mkv", "avi", "mov", "webm", "m4v"];
+
+pub struct UploadService {
+    working_dir: PathBuf,
+    security_validator: SecurityValidator,
+    max_payload_size: usize,
+}
+
+impl UploadService {
+    pub fn new(working_dir: PathBuf, security_config: &SecurityConfig, max_payload_size: usize) -> Self {
+        // Uploads never fetch URLs, so no domains are allowed here
+        let security_validator = SecurityValidator::new(
+            Vec::new(),
+            security_config.max_file_size_mb as u32,
+            security_config.max_url_length as u32,
+        );
+        Self {
+            working_dir,
+            security_validator,
+            max_payload_size,
+        }
+    }
+
+    /// Reject an upload up front when its declared length is over `APERIO_MAX_PAYLOAD`
+    pub fn check_content_length(&self, content_length: Option<u64>) -> AppResult<()> {
+        match content_length {
+            Some(length) if length > self.max_payload_size as u64 => Err(AppError::PayloadTooLarge(format!(
+                "Upload of {length} bytes exceeds the {} byte request limit",
+                self.max_payload_size
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reduce a client-supplied filename to a safe display name for the synthetic upload URL
+    pub fn sanitize_filename(&self, filename: &str) -> String {
+        // Drop any directory components the client may have sent
+        let base = filename
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default();
+
+        let sanitized: String = base
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+            .collect();
+        let sanitized = sanitized.trim_start_matches('.').to_string();
+
+        if sanitized.is_empty() {
+            "upload".to_string()
+        } else {
+            sanitized.chars().take(200).collect()
+        }
+    }
+
+    /// Stream an uploaded file to the working directory under the job's id, enforcing the size limit
+    pub async fn store_upload<S, E>(&self, job_id: &str, filename: &str, mut stream: S) -> AppResult<PathBuf>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .filter(|ext| UPLOAD_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or_else(|| "mp4".to_string());
+
+        let output_path = self.security_validator.safe_job_file_path(
+            &self.working_dir,
+            job_id,
+            &format!("original.{extension}"),
+        )?;
+
+        tokio::fs::create_dir_all(job_working_dir(&self.working_dir, job_id)).await
+            .map_err(AppError::io("Failed to create job directory"))?;
+
+        let mut file = tokio::fs::File::create(&output_path).await
+            .map_err(AppError::io("Failed to create upload file"))?;
+
+        let max_size = self.security_validator.get_max_file_size();
+        let mut written: u64 = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    self.discard(&output_path).await;
+                    return Err(AppError::BadRequest(format!("Upload interrupted: {e}")));
+                }
+            };
+
+            written += chunk.len() as u64;
+            if written > max_size {
+                self.discard(&output_path).await;
+                return Err(AppError::BadRequest(format!(
+                    "Uploaded file exceeds maximum size limit of {max_size} bytes"
+                )));
+            }
+
+            if let Err(e) = file.write_all(&chunk).await {
+                self.discard(&output_path).await;
+                return Err(AppError::Internal(format!("Failed to write upload: {e}")));
+            }
+        }
+
+        if let Err(e) = file.flush().await {
+            self.discard(&output_path).await;
+            return Err(AppError::Internal(format!("Failed to write upload: {e}")));
+        }
+
+        if written == 0 {
+            self.discard(&output_path).await;
+            return Err(AppError::BadRequest("Uploaded file is empty".to_string()));
+        }
+
+        info!("Stored upload for job {} ({} bytes) at {:?}", job_id, written, output_path);
+        Ok(output_path)
+    }
+
+    /// Remove a partially written or rejected upload
+    pub async fn discard(&self, path: &PathBuf) {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            warn!("Failed to remove rejected upload {:?}: {}", path, e);
+        }
+    }
+}
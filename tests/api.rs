@@ -0,0 +1,223 @@
+//! Request handling through the real server: body limits, queue admission, the API's versioned
+//! paths and request metrics
+
+mod common;
+
+use common::Server;
+use serde_json::json;
+
+const FIXTURE: &[u8] = b"\x00\x00\x00\x18ftypmp42 fixture standing in for a downloaded video";
+
+fn concat_body(sources: usize) -> serde_json::Value {
+    let urls: Vec<String> = (0..sources).map(|n| format!("https://youtube.com/watch?v=source{n:06}")).collect();
+    json!({ "urls": urls, "mode": "concat" })
+}
+
+#[test]
+fn oversized_batch_submission_is_rejected_with_a_json_413() {
+    let server = Server::start(FIXTURE, &[("APERIO_MAX_JSON_PAYLOAD", "4096")]);
+    let body = concat_body(200);
+    assert!(body.to_string().len() > 4096);
+
+    let response = server.post_json("/api/v1/process/concat", body);
+    assert_eq!(response.status, 413, "{}", String::from_utf8_lossy(&response.body));
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    let error = response.json();
+    assert_eq!(error["error_type"], "payload_too_large");
+    assert_eq!(error["message"], "JSON body exceeds the 4096 byte limit");
+
+    // Nothing was queued for the rejected batch
+    let jobs = server.get("/api/v1/jobs").json();
+    assert_eq!(jobs["jobs"], json!([]), "{jobs}");
+}
+
+#[test]
+fn batch_submission_within_the_limit_is_accepted() {
+    let server = Server::start(FIXTURE, &[("APERIO_MAX_JSON_PAYLOAD", "4096")]);
+    let body = concat_body(2);
+    assert!(body.to_string().len() < 4096);
+
+    let response = server.post_json("/api/v1/process/concat", body);
+    assert!(matches!(response.status, 200..=202), "{}: {}", response.status, String::from_utf8_lossy(&response.body));
+}
+
+#[test]
+fn submission_to_a_full_queue_leaves_no_job_behind() {
+    let server = Server::start(FIXTURE, &[("APERIO_MAX_CONCURRENT_DOWNLOADS", "1"), ("APERIO_MAX_QUEUE_SIZE", "1")]);
+    // Reading a FIFO blocks until something writes to it, so the first job holds the only
+    // download slot and the second one stays queued
+    let fixture = server.path().join("fixture.mp4");
+    std::fs::remove_file(&fixture).unwrap();
+    assert!(std::process::Command::new("mkfifo").arg(&fixture).status().unwrap().success());
+
+    let running = server.submit(json!({ "url": "https://youtube.com/watch?v=aaaaaaaaaaa" }));
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+    while server.get(&format!("/api/v1/status/{running}")).json()["status"] != "Downloading" {
+        assert!(std::time::Instant::now() < deadline, "{running} never started:\n{}", server.log());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let queued = server.submit(json!({ "url": "https://youtube.com/watch?v=bbbbbbbbbbb" }));
+
+    let refused = server.post_json("/api/v1/process", json!({ "url": "https://youtube.com/watch?v=ccccccccccc" }));
+    assert_eq!(refused.status, 500, "{}", String::from_utf8_lossy(&refused.body));
+    let message = refused.json()["message"].as_str().unwrap_or_default().to_string();
+    assert!(message.contains("Queue is full (max 1 jobs)"), "{message}");
+
+    // Only the two admitted jobs exist, so a restart has nothing extra to resume
+    let jobs = server.get("/api/v1/jobs").json();
+    let mut ids: Vec<&str> = jobs["jobs"].as_array().unwrap().iter().filter_map(|job| job["id"].as_str()).collect();
+    ids.sort();
+    let mut expected = [running.as_str(), queued.as_str()];
+    expected.sort();
+    assert_eq!(ids, expected, "{jobs}");
+}
+
+#[test]
+fn new_job_is_accepted_with_a_location_and_a_duplicate_returns_the_existing_one() {
+    let server = Server::start(FIXTURE, &[]);
+    // A FIFO download never finishes, so the job stays active and the resubmission matches it
+    let fixture = server.path().join("fixture.mp4");
+    std::fs::remove_file(&fixture).unwrap();
+    assert!(std::process::Command::new("mkfifo").arg(&fixture).status().unwrap().success());
+    let url = "https://youtube.com/watch?v=aaaaaaaaaaa";
+
+    let created = server.post_json("/api/v1/process", json!({ "url": url }));
+    assert_eq!(created.status, 202, "{}", String::from_utf8_lossy(&created.body));
+    let id = created.json()["id"].as_str().unwrap().to_string();
+    let location = created.header("location").expect("no Location header").to_string();
+    assert_eq!(location, format!("/api/v1/status/{id}"));
+    assert_eq!(server.get(&location).json()["id"], id.as_str());
+
+    let duplicate = server.post_json("/api/v1/process", json!({ "url": url }));
+    assert_eq!(duplicate.status, 200, "{}", String::from_utf8_lossy(&duplicate.body));
+    assert_eq!(duplicate.json()["id"], id.as_str());
+
+    let forced = server.post_json("/api/v1/process", json!({ "url": url, "force": true }));
+    assert_eq!(forced.status, 202, "{}", String::from_utf8_lossy(&forced.body));
+    assert_ne!(forced.json()["id"], id.as_str());
+}
+
+#[test]
+fn unversioned_paths_are_deprecated_aliases_of_v1() {
+    let server = Server::start(FIXTURE, &[]);
+
+    let alias = server.post_json("/process", json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+    assert!(matches!(alias.status, 200..=202), "{}: {}", alias.status, String::from_utf8_lossy(&alias.body));
+    assert_eq!(alias.header("deprecation"), Some("true"));
+    assert_eq!(alias.header("link"), Some("</api/v1>; rel=\"successor-version\""));
+    let id = alias.json()["id"].as_str().unwrap().to_string();
+
+    let versioned = server.post_json("/api/v1/process", json!({ "url": "https://youtube.com/watch?v=bcdefghijkl" }));
+    assert!(matches!(versioned.status, 200..=202), "{}", versioned.status);
+    assert_eq!(versioned.header("deprecation"), None);
+
+    // Both paths reach the same jobs
+    let status = server.get(&format!("/status/{id}"));
+    assert_eq!(status.status, 200);
+    assert_eq!(status.header("deprecation"), Some("true"));
+    assert_eq!(status.json()["id"], id.as_str());
+    assert_eq!(server.get(&format!("/api/v1/status/{id}")).json()["id"], id.as_str());
+
+    // Monitoring and the spec are not part of the versioned API
+    for path in ["/health/live", "/openapi.json"] {
+        let response = server.get(path);
+        assert_eq!(response.status, 200, "{path}");
+        assert_eq!(response.header("deprecation"), None, "{path}");
+    }
+}
+
+#[test]
+fn request_metrics_are_scraped_by_route_template() {
+    let server = Server::start(FIXTURE, &[]);
+    let first = server.submit(json!({ "url": "https://youtube.com/watch?v=aaaaaaaaaaa" }));
+    let second = server.submit(json!({ "url": "https://youtube.com/watch?v=bbbbbbbbbbb" }));
+    server.wait_for_job(&first);
+    server.wait_for_job(&second);
+    assert_eq!(server.get("/api/v1/status/not-a-job").status, 404);
+    assert_eq!(server.get("/no/such/path/1").status, 404);
+    assert_eq!(server.get("/no/such/path/2").status, 404);
+
+    let response = server.get("/metrics/prometheus");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-type"), Some("text/plain; version=0.0.4; charset=utf-8"));
+    let scrape = String::from_utf8(response.body).unwrap();
+    let value = |series: &str| -> Option<f64> {
+        scrape.lines().find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok())
+    };
+
+    let requests = |labels: &str| value(&format!("aperio_http_requests_total{{{labels}}}"));
+    assert_eq!(requests(r#"method="POST",route="/api/v1/process",status="2xx""#), Some(2.0), "{scrape}");
+    assert!(requests(r#"method="GET",route="/api/v1/status/{job_id}",status="2xx""#) >= Some(2.0), "{scrape}");
+    assert_eq!(requests(r#"method="GET",route="/api/v1/status/{job_id}",status="4xx""#), Some(1.0), "{scrape}");
+    // Paths no route matched share one series, however many different ones were requested
+    assert_eq!(requests(r#"method="GET",route="unmatched",status="4xx""#), Some(2.0), "{scrape}");
+
+    let durations = |labels: &str| value(&format!("aperio_http_request_duration_seconds_count{{{labels}}}"));
+    assert_eq!(durations(r#"method="POST",route="/api/v1/process""#), Some(2.0), "{scrape}");
+    assert_eq!(durations(r#"method="GET",route="unmatched""#), Some(2.0), "{scrape}");
+    assert_eq!(
+        value(r#"aperio_http_request_duration_seconds_bucket{method="POST",route="/api/v1/process",le="+Inf"}"#),
+        Some(2.0),
+        "{scrape}"
+    );
+
+    // Raw paths and job ids never become label values
+    for raw in [first.as_str(), second.as_str(), "not-a-job", "/no/such/path"] {
+        assert!(!scrape.contains(raw), "{raw} leaked into a label:\n{scrape}");
+    }
+}
+
+#[test]
+fn metrics_filter_by_name_and_reset_is_audited() {
+    let server = Server::start(FIXTURE, &[]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=aaaaaaaaaaa" }));
+    server.wait_for_job(&id);
+
+    let series = |query: &str| -> Vec<String> {
+        let metrics = server.get(&format!("/metrics{query}")).json();
+        ["counters", "gauges", "histograms"].iter()
+            .flat_map(|kind| metrics[kind].as_object().unwrap().keys().cloned().collect::<Vec<_>>())
+            .collect()
+    };
+    let all = series("");
+    assert!(all.iter().any(|name| name.starts_with("aperio_jobs_")), "{all:?}");
+    let http = series("?prefix=aperio_http_");
+    assert!(!http.is_empty() && http.len() < all.len(), "{http:?}");
+    assert!(http.iter().all(|name| name.starts_with("aperio_http_")), "{http:?}");
+    let requests = series("?name=aperio_http_requests_total");
+    assert!(!requests.is_empty(), "{all:?}");
+    assert!(requests.iter().all(|name| name.starts_with("aperio_http_requests_total{")), "{requests:?}");
+
+    let reset = server.post_json("/api/v1/admin/metrics/reset", json!({}));
+    assert_eq!(reset.status, 200, "{}", String::from_utf8_lossy(&reset.body));
+    assert!(reset.json()["counters_cleared"].as_u64() > Some(0), "{}", reset.json());
+    // Job counters start over until the next job
+    let counters = server.get("/metrics?prefix=aperio_jobs_").json();
+    assert_eq!(counters["counters"], json!({}), "{counters}");
+
+    let audit = server.get("/api/v1/admin/audit").json();
+    let entries = audit["entries"].as_array().unwrap();
+    assert!(
+        entries.iter().any(|entry| entry["action"] == "reset_metrics" && entry["outcome"] == "success"),
+        "{audit}"
+    );
+}
+
+#[test]
+fn concat_options_that_need_a_single_source_are_rejected() {
+    let server = Server::start(FIXTURE, &[]);
+    let mut body = concat_body(2);
+    body["keep_original"] = json!(true);
+    let response = server.post_json("/api/v1/process/concat", body);
+    assert_eq!(response.status, 400, "{}", String::from_utf8_lossy(&response.body));
+    assert_eq!(response.json()["message"], "keep_original is not available when joining sources");
+
+    let mut body = concat_body(2);
+    body["options"] = json!({ "max_output_size_mb": 10 });
+    let response = server.post_json("/api/v1/process/concat", body);
+    assert_eq!(response.status, 400, "{}", String::from_utf8_lossy(&response.body));
+    assert_eq!(response.json()["message"], "options.max_output_size_mb is not available when joining sources");
+
+    let jobs = server.get("/api/v1/jobs").json();
+    assert_eq!(jobs["jobs"], json!([]), "{jobs}");
+}
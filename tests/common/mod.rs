@@ -0,0 +1,160 @@
+//! Runs the aperio binary against a scratch directory with the mock downloader and the
+//! passthrough processor, so the whole HTTP + queue + repository stack runs without yt-dlp,
+//! ffmpeg or network access.
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+pub struct Server {
+    child: Child,
+    port: u16,
+    dir: tempfile::TempDir,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|e| panic!("{} is not JSON ({e}): {}", self.status, String::from_utf8_lossy(&self.body)))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+impl Server {
+    /// Start a server whose every download yields `fixture`, with `env` applied on top of the
+    /// test defaults
+    pub fn start(fixture: &[u8], env: &[(&str, &str)]) -> Server {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture_path = dir.path().join("fixture.mp4");
+        std::fs::write(&fixture_path, fixture).unwrap();
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let log = std::fs::File::create(dir.path().join("server.log")).unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_aperio"));
+        // Settings from the environment running the tests must not leak into the server
+        for (key, _) in std::env::vars().filter(|(key, _)| key.starts_with("APERIO_")) {
+            command.env_remove(key);
+        }
+        command
+            .current_dir(dir.path())
+            .env("APERIO_HOST", "127.0.0.1")
+            .env("APERIO_PORT", port.to_string())
+            .env("APERIO_DATABASE_URL", format!("sqlite://{}", dir.path().join("aperio.db").display()))
+            .env("APERIO_STORAGE_PATH", dir.path().join("storage"))
+            .env("APERIO_WORKING_DIR", dir.path().join("working"))
+            .env("APERIO_DOWNLOADER", "mock")
+            .env("APERIO_MOCK_DOWNLOAD_FIXTURE", &fixture_path)
+            .env("APERIO_PROCESSOR", "passthrough")
+            .env("APERIO_MIN_DOWNLOAD_SIZE_KB", "0")
+            .env("APERIO_DB_MAINTENANCE_INTERVAL", "0")
+            .env("APERIO_LOG_FORMAT", "json")
+            .env("RUST_LOG", "aperio=info")
+            .envs(env.iter().copied())
+            .stdin(Stdio::null())
+            .stdout(log.try_clone().unwrap())
+            .stderr(log);
+
+        let mut server = Server { child: command.spawn().unwrap(), port, dir };
+        server.wait_until_live();
+        server
+    }
+
+    fn wait_until_live(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                panic!("server exited with {status} during startup:\n{}", self.log());
+            }
+            if TcpStream::connect(("127.0.0.1", self.port)).is_ok() && self.get("/health/live").status == 200 {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("server did not come up within 30 seconds:\n{}", self.log());
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn working_dir(&self) -> PathBuf {
+        self.dir.path().join("working")
+    }
+
+    pub fn log(&self) -> String {
+        std::fs::read_to_string(self.dir.path().join("server.log")).unwrap_or_default()
+    }
+
+    /// One HTTP/1.0 exchange, so the body is simply everything up to the close
+    pub fn request(&self, method: &str, path: &str, content_type: &str, body: &[u8]) -> Response {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.0\r\nHost: 127.0.0.1\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        ).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).unwrap();
+        let split = raw.windows(4).position(|window| window == b"\r\n\r\n").expect("no end of headers");
+        let head = String::from_utf8_lossy(&raw[..split]).into_owned();
+        let mut lines = head.lines();
+        let status = lines.next().and_then(|line| line.split_whitespace().nth(1)).and_then(|code| code.parse().ok())
+            .expect("no status line");
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Response { status, headers, body: raw[split + 4..].to_vec() }
+    }
+
+    pub fn get(&self, path: &str) -> Response {
+        self.request("GET", path, "application/json", b"")
+    }
+
+    pub fn post_json(&self, path: &str, body: serde_json::Value) -> Response {
+        self.request("POST", path, "application/json", body.to_string().as_bytes())
+    }
+
+    /// Submit a URL job and return its id
+    pub fn submit(&self, body: serde_json::Value) -> String {
+        let response = self.post_json("/api/v1/process", body);
+        assert!(matches!(response.status, 200..=202), "{}: {}", response.status, String::from_utf8_lossy(&response.body));
+        response.json()["id"].as_str().unwrap().to_string()
+    }
+
+    /// Poll the job's status until it is terminal
+    pub fn wait_for_job(&self, id: &str) -> serde_json::Value {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            let status = self.get(&format!("/api/v1/status/{id}")).json();
+            if matches!(status["status"].as_str(), Some("Completed" | "Failed" | "Cancelled" | "Expired")) {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "job {id} still {} after 30 seconds:\n{}", status["status"], self.log());
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
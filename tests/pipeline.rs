@@ -0,0 +1,291 @@
+//! Jobs through the real server with the mock downloader and passthrough processor
+
+mod common;
+
+use common::Server;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+const FIXTURE: &[u8] = b"\x00\x00\x00\x18ftypmp42 fixture standing in for a downloaded video";
+
+#[test]
+fn submitted_job_completes_and_its_file_is_served() {
+    let server = Server::start(FIXTURE, &[]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Completed", "{status}\n{}", server.log());
+    assert_eq!(status["output_available"], true, "{status}");
+
+    let video = server.get(&format!("/api/v1/video/{id}"));
+    assert_eq!(video.status, 200);
+    assert_eq!(video.header("content-type"), Some("video/mp4"));
+    assert_eq!(video.body, FIXTURE);
+
+    let checksum = server.get(&format!("/api/v1/video/{id}/checksum"));
+    assert_eq!(checksum.status, 200);
+    let expected: String = Sha256::digest(FIXTURE).iter().map(|byte| format!("{byte:02x}")).collect();
+    assert_eq!(checksum.json()["checksum"], expected.as_str());
+    assert_eq!(status["processed_sha256"], expected.as_str());
+
+    // Without keep_original only the output is left
+    assert_eq!(server.get(&format!("/api/v1/original/{id}")).status, 404);
+}
+
+#[test]
+fn kept_original_is_the_mock_download() {
+    let server = Server::start(FIXTURE, &[]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk", "keep_original": true }));
+
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Completed", "{status}\n{}", server.log());
+
+    let original = server.get(&format!("/api/v1/original/{id}"));
+    assert_eq!(original.status, 200);
+    assert_eq!(original.body, FIXTURE);
+
+    // The mock names the file as yt-dlp would, so it is kept next to the output like any original
+    let kept = server.path().join("storage").join(&id).join(format!("{id}_original.mp4"));
+    assert_eq!(std::fs::read(&kept).unwrap(), FIXTURE);
+}
+
+#[test]
+fn failed_mock_download_fails_the_job() {
+    let server = Server::start(FIXTURE, &[]);
+    std::fs::remove_file(server.path().join("fixture.mp4")).unwrap();
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Failed", "{status}");
+    let error = status["error_message"].as_str().unwrap_or_default();
+    assert!(error.contains("download fixture"), "{status}");
+}
+
+#[test]
+fn empty_download_fails_the_job_after_a_retry() {
+    let server = Server::start(b"", &[("APERIO_MIN_DOWNLOAD_SIZE_KB", "1")]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Failed", "{status}");
+    assert_eq!(status["failure_reason"], "incomplete_download", "{status}");
+
+    let logs = server.get(&format!("/api/v1/jobs/{id}/logs")).json().to_string();
+    assert!(logs.contains("Download attempt 1 failed"), "{logs}");
+    assert!(logs.contains("Download attempt 2 failed"), "{logs}");
+
+    // Each rejected file is removed, so no attempt found a stale one
+    let job_dir = server.working_dir().join(&id);
+    let leftovers: Vec<_> = std::fs::read_dir(&job_dir).map(|entries| entries.flatten().map(|entry| entry.path()).collect()).unwrap_or_default();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+/// An executable `#!/bin/sh` script in `dir`
+fn script(dir: &std::path::Path, name: &str, body: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[test]
+fn job_without_a_free_processing_slot_is_requeued_and_later_completes() {
+    // ffmpeg only runs for frame extraction here, and holds the one processing slot until the
+    // release file appears
+    let tools = tempfile::tempdir().unwrap();
+    let release = tools.path().join("release");
+    let ffmpeg = script(tools.path(), "ffmpeg", &format!("while [ ! -e '{}' ]; do sleep 0.1; done\nexit 1", release.display()));
+    let ffprobe = script(tools.path(), "ffprobe", "echo 10.0");
+    let server = Server::start(FIXTURE, &[
+        ("APERIO_FFMPEG_COMMAND", ffmpeg.to_str().unwrap()),
+        ("APERIO_FFPROBE_COMMAND", ffprobe.to_str().unwrap()),
+        ("APERIO_MAX_CONCURRENT_PROCESSING", "1"),
+        ("APERIO_SLOT_WAIT_TIMEOUT", "1"),
+    ]);
+
+    let first = server.submit(json!({ "url": "https://youtube.com/watch?v=aaaaaaaaaaa" }));
+    assert_eq!(server.wait_for_job(&first)["status"], "Completed", "{}", server.log());
+    let frame = std::thread::scope(|scope| {
+        let frame = scope.spawn(|| server.get(&format!("/api/v1/frame/{first}?t=1")));
+
+        let waiter = server.submit(json!({ "url": "https://youtube.com/watch?v=bbbbbbbbbbb" }));
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        loop {
+            let logs = server.get(&format!("/api/v1/jobs/{waiter}/logs")).json().to_string();
+            if logs.contains("No processing slot became free within 1s; requeued") {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "{waiter} was never requeued: {logs}\n{}", server.log());
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        // Waiting for a slot is not a failure; the job goes round the queue until one frees up
+        let status = server.get(&format!("/api/v1/status/{waiter}")).json();
+        assert!(matches!(status["status"].as_str(), Some("Pending" | "Claimed")), "{status}");
+        assert!(status["failure_reason"].is_null(), "{status}");
+
+        std::fs::write(&release, b"").unwrap();
+        let status = server.wait_for_job(&waiter);
+        assert_eq!(status["status"], "Completed", "{status}\n{}", server.log());
+        assert_eq!(server.get(&format!("/api/v1/video/{waiter}")).body, FIXTURE);
+        let logs = server.get(&format!("/api/v1/jobs/{waiter}/logs")).json().to_string();
+        assert_eq!(logs.matches("to Downloading").count(), 1, "downloaded once: {logs}");
+        frame.join().unwrap()
+    });
+    // The fake ffmpeg wrote no frame
+    assert_ne!(frame.status, 200);
+}
+
+#[test]
+fn strict_post_hook_fails_the_job_it_rejects() {
+    let tools = tempfile::tempdir().unwrap();
+    let hook = script(tools.path(), "hook.sh", "echo 'share is read-only' >&2\nexit 1");
+
+    let server = Server::start(FIXTURE, &[("APERIO_POST_HOOK_COMMAND", hook.to_str().unwrap()), ("APERIO_POST_HOOK_STRICT", "true")]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Failed", "{status}\n{}", server.log());
+    assert_eq!(status["error_message"], "Processing error: Post-completion hook exited with code 1", "{status}");
+    assert_eq!(status["failure_reason"], "processing_failed", "{status}");
+    let logs = server.get(&format!("/api/v1/jobs/{id}/logs")).json().to_string();
+    assert!(logs.contains("share is read-only"), "{logs}");
+    drop(server);
+
+    // Without strictness the same failure is only logged
+    let server = Server::start(FIXTURE, &[("APERIO_POST_HOOK_COMMAND", hook.to_str().unwrap())]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Completed", "{status}\n{}", server.log());
+}
+
+#[test]
+fn correlation_id_of_the_submission_is_on_every_stage_span() {
+    let server = Server::start(FIXTURE, &[]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=dQw4w9WgXcQ" }));
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Completed", "{status}");
+    let correlation_id = status["correlation_id"].as_str().expect("no correlation id").to_string();
+
+    let events: Vec<serde_json::Value> = server.log().lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    // The request that submitted the job logged under the same id
+    assert!(
+        events.iter().any(|event| event["span"]["name"] == "http_request" && event["span"]["correlation_id"] == correlation_id.as_str()),
+        "no request span with {correlation_id}:\n{}", server.log()
+    );
+    for stage in ["download_job", "process_job"] {
+        let stage_events: Vec<&serde_json::Value> = events.iter().filter(|event| event["span"]["name"] == stage).collect();
+        assert!(!stage_events.is_empty(), "nothing logged in {stage}:\n{}", server.log());
+        // The id is recorded on the span once the job is read, so lines before that go without it
+        let with_id = stage_events.iter()
+            .skip_while(|event| event["span"]["correlation_id"].is_null())
+            .collect::<Vec<_>>();
+        assert!(!with_id.is_empty(), "{stage} never logged {correlation_id}:\n{}", server.log());
+        for event in with_id {
+            assert_eq!(event["span"]["job_id"], id.as_str(), "{event}");
+            assert_eq!(event["span"]["correlation_id"], correlation_id.as_str(), "{event}");
+        }
+    }
+}
+
+#[test]
+fn rate_limited_download_is_deferred_without_another_attempt() {
+    // yt-dlp probes fine, then every download is turned away with a 429
+    let tools = tempfile::tempdir().unwrap();
+    let attempts = tools.path().join("attempts");
+    let yt_dlp = script(tools.path(), "yt-dlp", &format!(r#"case " $* " in
+    *" --dump-json "*) echo '{{"url":"https://93.184.216.34/v.mp4","protocol":"https","live_status":"not_live","duration":10}}'; exit 0;;
+esac
+echo attempt >> '{}'
+echo "ERROR: [youtube] abc: HTTP Error 429: Too Many Requests" >&2
+exit 1"#, attempts.display()));
+    let server = Server::start(FIXTURE, &[
+        ("APERIO_DOWNLOADER", "yt-dlp"),
+        ("APERIO_DOWNLOAD_COMMAND", yt_dlp.to_str().unwrap()),
+        ("APERIO_MEDIA_HOSTS", "93.184.216.34"),
+    ]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+    let logs = loop {
+        let logs = server.get(&format!("/api/v1/jobs/{id}/logs")).json().to_string();
+        if logs.contains("Rate limited by") {
+            break logs;
+        }
+        assert!(std::time::Instant::now() < deadline, "job was never deferred:\n{logs}\n{}", server.log());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    };
+    // Longer than the retry backoff, so a second attempt would have run by now
+    std::thread::sleep(std::time::Duration::from_millis(2500));
+
+    let status = server.get(&format!("/api/v1/status/{id}")).json();
+    assert_ne!(status["status"], "Failed", "{status}");
+    assert!(!logs.contains("Download attempt 2"), "{logs}");
+    assert_eq!(std::fs::read_to_string(&attempts).unwrap().lines().count(), 1, "{logs}");
+}
+
+#[test]
+fn timed_out_download_fails_the_job_as_a_timeout() {
+    let tools = tempfile::tempdir().unwrap();
+    let yt_dlp = script(tools.path(), "yt-dlp", r#"case " $* " in
+    *" --dump-json "*) echo '{"url":"https://93.184.216.34/v.mp4","protocol":"https","live_status":"not_live","duration":10}'; exit 0;;
+esac
+exec sleep 30"#);
+    let server = Server::start(FIXTURE, &[
+        ("APERIO_DOWNLOADER", "yt-dlp"),
+        ("APERIO_DOWNLOAD_COMMAND", yt_dlp.to_str().unwrap()),
+        ("APERIO_MEDIA_HOSTS", "93.184.216.34"),
+        ("APERIO_DOWNLOAD_TIMEOUT", "1"),
+    ]);
+    let id = server.submit(json!({ "url": "https://youtube.com/watch?v=abcdefghijk" }));
+
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Failed", "{status}");
+    assert_eq!(status["failure_reason"], "timeout", "{status}");
+    let error = status["error_message"].as_str().unwrap_or_default();
+    assert!(error.contains("timed out after 1 seconds"), "{status}");
+
+    // A timeout is transient, so it was retried before failing
+    let logs = server.get(&format!("/api/v1/jobs/{id}/logs")).json().to_string();
+    assert!(logs.contains("Download attempt 2 failed"), "{logs}");
+}
+
+#[test]
+fn concat_sources_are_joined_in_the_processing_encode() {
+    // ffmpeg records each run that reads inputs and writes its output; ffprobe describes every
+    // file as 720p with audio
+    let tools = tempfile::tempdir().unwrap();
+    let runs = tools.path().join("runs");
+    let ffmpeg = script(tools.path(), "ffmpeg", &format!(r#"case " $* " in
+    *" -i "*) echo "$*" >> '{}';;
+esac
+for last in "$@"; do :; done
+printf encoded > "$last""#, runs.display()));
+    let ffprobe = script(tools.path(), "ffprobe", r#"case " $* " in
+    *" stream=codec_type"*) echo '{"streams":[{"codec_type":"video","codec_name":"h264","width":1280,"height":720,"avg_frame_rate":"30/1"},{"codec_type":"audio","codec_name":"aac","channels":2}]}';;
+    *) echo 10.0;;
+esac"#);
+    let server = Server::start(FIXTURE, &[
+        ("APERIO_PROCESSOR", "ffmpeg"),
+        ("APERIO_FFMPEG_COMMAND", ffmpeg.to_str().unwrap()),
+        ("APERIO_FFPROBE_COMMAND", ffprobe.to_str().unwrap()),
+    ]);
+    let response = server.post_json("/api/v1/process/concat", json!({
+        "urls": ["https://youtube.com/watch?v=aaaaaaaaaaa", "https://youtube.com/watch?v=bbbbbbbbbbb"],
+        "mode": "concat",
+        "options": { "preview": false },
+    }));
+    assert_eq!(response.status, 202, "{}", String::from_utf8_lossy(&response.body));
+    let id = response.json()["id"].as_str().unwrap().to_string();
+
+    let status = server.wait_for_job(&id);
+    assert_eq!(status["status"], "Completed", "{status}\n{}", server.log());
+    assert_eq!(status["metadata"]["source_duration_seconds"], 20.0, "{status}");
+
+    // One encode reads both sources and joins them before the job's own filters
+    let runs = std::fs::read_to_string(&runs).unwrap();
+    let runs: Vec<&str> = runs.lines().collect();
+    assert_eq!(runs.len(), 1, "{runs:#?}");
+    assert!(runs[0].contains(&format!("{id}_source1.mp4")) && runs[0].contains(&format!("{id}_source2.mp4")), "{}", runs[0]);
+    assert!(runs[0].contains("-filter_complex"), "{}", runs[0]);
+    assert!(runs[0].contains("concat=n=2:v=1:a=1[joined][joined_a];[joined]"), "{}", runs[0]);
+}